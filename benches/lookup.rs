@@ -0,0 +1,76 @@
+//! Headword index benchmarks against a synthetic dictionary the size of a
+//! full English dataset.
+//!
+//! Run with `cargo bench --bench lookup`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+// The crate is binary-only, so pull the index module in directly
+#[allow(dead_code)]
+#[path = "../src/index.rs"]
+mod index;
+
+use index::HeadwordIndex;
+
+const HEADWORDS: usize = 300_000;
+
+/// Deterministic pseudo-random headwords of 3 to 12 lowercase letters
+fn synthetic_headwords(count: usize) -> Vec<String> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    (0..count)
+        .map(|_| {
+            let len = 3 + (next() % 10) as usize;
+            (0..len)
+                .map(|_| (b'a' + (next() % 26) as u8) as char)
+                .collect()
+        })
+        .collect()
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let words = synthetic_headwords(HEADWORDS);
+    let index = HeadwordIndex::build(words.iter().cloned());
+    let hit = words[HEADWORDS / 2].clone();
+    let prefix = hit[..3].to_string();
+
+    c.bench_function("build 300k", |b| {
+        b.iter_batched(
+            || words.clone(),
+            HeadwordIndex::build,
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("exact hit 300k", |b| {
+        b.iter(|| black_box(index.exact(black_box(&hit))).len())
+    });
+
+    c.bench_function("exact miss 300k", |b| {
+        b.iter(|| black_box(index.exact(black_box("zzzzzzzzzzzzz"))).len())
+    });
+
+    c.bench_function("prefix 300k", |b| {
+        b.iter(|| index.prefix(black_box(&prefix)).take(50).count())
+    });
+
+    // Baseline: the linear scan the index replaced
+    c.bench_function("linear prefix scan 300k", |b| {
+        b.iter(|| {
+            words
+                .iter()
+                .filter(|word| word.to_lowercase().starts_with(black_box(&prefix)))
+                .take(50)
+                .count()
+        })
+    });
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
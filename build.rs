@@ -1,3 +1,36 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+// The build script compiles the bundled dictionary with the app's own writer
+#[allow(dead_code)]
+#[path = "src/normalize.rs"]
+mod normalize;
+
+#[allow(dead_code)]
+#[path = "src/compact_layout.rs"]
+mod compact_layout;
+
+// ...and checks it against the app's own schema, through diagnostics.rs
+#[allow(dead_code)]
+#[path = "src/entry.rs"]
+mod entry;
+
+#[allow(dead_code)]
+#[path = "src/diagnostics.rs"]
+mod diagnostics;
+
+/// The one api.rs item diagnostics.rs needs
+mod api {
+    pub use crate::entry::DictionaryEntry;
+}
+
+use compact_layout::SerializedEntry;
+use diagnostics::Diagnostic;
+
+const BUNDLED_DICTIONARY: &str = "data/dictionary.json";
+
 fn main() {
     // No resources to compile for now
     // If you add GTK resource files later, uncomment:
@@ -6,5 +39,61 @@ fn main() {
     //     "data/resources.gresource.xml",
     //     "resources.gresource",
     // );
+
+    compile_bundled_dictionary();
 }
 
+/// Compile the bundled dictionary into `$OUT_DIR/dictionary.aydict`, which is
+/// included in the binary and memory-mapped like any compiled dictionary
+/// rather than parsed on every start
+fn compile_bundled_dictionary() {
+    for input in [
+        BUNDLED_DICTIONARY,
+        "src/normalize.rs",
+        "src/compact_layout.rs",
+        "src/entry.rs",
+        "src/diagnostics.rs",
+    ] {
+        println!("cargo:rerun-if-changed={}", input);
+    }
+
+    let text = fs::read_to_string(BUNDLED_DICTIONARY)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", BUNDLED_DICTIONARY, e));
+    // Like an installed dictionary, a broken entry leaves out only itself
+    let raw = diagnostics::split_entries(&text);
+    for problem in &raw.errors {
+        warn(problem);
+    }
+    let (parsed, skipped) = raw.parse();
+    for problem in &skipped {
+        warn(problem);
+    }
+    let entries: Vec<SerializedEntry> = parsed
+        .iter()
+        .map(|entry| SerializedEntry {
+            word: entry.word.clone(),
+            frequency: entry.frequency.unwrap_or(0),
+            json: serde_json::to_vec(entry).unwrap(),
+        })
+        .collect();
+
+    let output = Path::new(&env::var("OUT_DIR").unwrap()).join("dictionary.aydict");
+    let file = File::create(&output)
+        .unwrap_or_else(|e| panic!("Failed to create {}: {}", output.display(), e));
+    // The source id names it; the info has nothing to add
+    compact_layout::write(&entries, br#"{"name":""}"#, BufWriter::new(file))
+        .unwrap_or_else(|e| panic!("Failed to compile {}: {:#}", BUNDLED_DICTIONARY, e));
+}
+
+/// Report a problem in the bundled dictionary without failing the build
+fn warn(problem: &Diagnostic) {
+    let word = problem
+        .word
+        .as_ref()
+        .map(|word| format!(" ('{}')", word))
+        .unwrap_or_default();
+    println!(
+        "cargo:warning={}:{}:{}{}: {}",
+        BUNDLED_DICTIONARY, problem.line, problem.column, word, problem.message
+    );
+}
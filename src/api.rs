@@ -1,93 +1,593 @@
-use anyhow::{Context, Result};
+use crate::compact::{self, CompactSource};
+use crate::diagnostics::{self, LoadProblem};
+use crate::dict_client::{DictServerSource, DictSession};
+use crate::dsl;
+pub use crate::entry::{
+    Definition, DictionaryEntry, License, Meaning, Phonetic, Relation, RelationKind,
+};
+use crate::fuzzy;
+use crate::hunspell::Hunspell;
+use crate::lemma;
+use crate::markup;
+use crate::mdict::MDictSource;
+use crate::normalize;
+use crate::package::{self, Manifest, PackageSource};
+use crate::pattern::{Pattern, PatternError};
+use crate::settings::Settings;
+use crate::source::{self, DictionarySource, MemorySource};
+use crate::stardict::StarDictSource;
+use crate::wordnet::{self, WordNetSource};
+use crate::xdxf;
+use crate::zim::ZimSource;
+use anyhow::{bail, Context, Result};
 use once_cell::sync::Lazy;
-use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use thiserror::Error;
 
-static OFFLINE_ENTRIES: Lazy<Vec<DictionaryEntry>> = Lazy::new(load_dictionary);
+/// Dictionaries discovered at startup, highest priority first
+static DEFAULT_SOURCES: Lazy<InstalledSources> = Lazy::new(load_sources);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DictionaryEntry {
-    pub word: String,
-    pub phonetic: Option<String>,
-    pub phonetics: Vec<Phonetic>,
-    pub meanings: Vec<Meaning>,
-    pub license: Option<License>,
-    #[serde(rename = "sourceUrls")]
-    pub source_urls: Vec<String>,
+/// Spelling dictionaries for the languages in use, discovered at startup
+static DEFAULT_SPELLERS: Lazy<Arc<Vec<Hunspell>>> = Lazy::new(|| Arc::new(load_spellers()));
+
+/// Source id reported for the dictionary compiled into the binary
+const BUNDLED_SOURCE: &str = "bundled";
+
+/// `data/dictionary.json`, compiled by the build script. Used when no
+/// dictionaries are installed.
+static BUNDLED_DICTIONARY: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/dictionary.aydict"));
+
+/// Subdirectory of each XDG data directory that holds dictionary files
+const DICTIONARY_SUBDIR: &str = "aynary/dictionaries";
+
+/// Subdirectory of each XDG data directory where StarDict dictionaries are installed
+const STARDICT_SUBDIR: &str = "stardict/dic";
+
+/// Subdirectory of each XDG data directory where distributions install WordNet
+const WORDNET_SUBDIR: &str = "wordnet";
+
+/// Subdirectory of each XDG data directory where Hunspell dictionaries are installed
+const HUNSPELL_SUBDIR: &str = "hunspell";
+
+/// Extensions of the files that identify a dictionary; companion files such
+/// as StarDict's `.idx` and `.dict` or MDict's `.mdd` are found by the reader.
+/// Lingvo DSL files, which may end in `.dsl.dz`, are recognized by `dsl::is_dsl`.
+const DICTIONARY_EXTENSIONS: &[&str] = &["json", compact::EXTENSION, "ifo", "mdx", "xdxf", "zim"];
+
+/// Upper bound on entries returned by the prefix fallback, so short queries
+/// against a large dictionary stay cheap to clone and render
+const MAX_PREFIX_MATCHES: usize = 50;
+
+/// Number of "did you mean" suggestions attached to a miss or a fuzzy match
+const MAX_SUGGESTIONS: usize = 8;
+
+/// How the entries in a [`LookupResult`] relate to the query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// The headword equals the query (ignoring case and, unless strict, accents)
+    Exact,
+    /// The query is an inflected form of the headword ("running" → "run")
+    Lemma,
+    /// No headword equals the query; these start with it
+    Prefix,
+    /// No headword equals or starts with the query; this is the one unambiguous
+    /// close spelling
+    Fuzzy,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Phonetic {
-    pub text: Option<String>,
-    pub audio: Option<String>,
-    #[serde(rename = "sourceUrl")]
-    pub source_url: Option<String>,
-    pub license: Option<License>,
+impl MatchKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchKind::Exact => "exact",
+            MatchKind::Lemma => "lemma",
+            MatchKind::Prefix => "prefix",
+            MatchKind::Fuzzy => "fuzzy",
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Meaning {
-    #[serde(rename = "partOfSpeech")]
-    pub part_of_speech: String,
-    pub definitions: Vec<Definition>,
-    pub synonyms: Vec<String>,
-    pub antonyms: Vec<String>,
+/// An entry together with the dictionary it came from
+#[derive(Debug, Clone)]
+pub struct SourcedEntry {
+    /// Name of the source, from its `SourceMetadata`
+    pub source: String,
+    /// Id of the source, which unlike its name is unique
+    pub source_id: String,
+    pub entry: DictionaryEntry,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Definition {
-    pub definition: String,
-    pub synonyms: Vec<String>,
-    pub antonyms: Vec<String>,
-    pub example: Option<String>,
+/// A lookup result laid out for the definition view
+#[derive(Debug, Clone)]
+pub struct RenderedResult {
+    /// Formatted text, with a [`markup::IMAGE_PLACEHOLDER`] for each embedded image
+    pub text: String,
+    /// Contents of each placeholder's image, in order; `None` when it can't be read
+    pub images: Vec<Option<Vec<u8>>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct License {
-    pub name: String,
-    pub url: String,
+/// Entries found for a query
+#[derive(Debug, Clone)]
+pub struct LookupResult {
+    /// The query as typed, trimmed
+    pub query: String,
+    /// Matches from every source, highest-priority source first
+    pub entries: Vec<SourcedEntry>,
+    pub match_kind: MatchKind,
+    /// Set when the query was an inflected form and `entries` belong to this lemma
+    pub lemma: Option<String>,
+    /// Other close headwords, offered alongside prefix and fuzzy matches
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug, Error)]
+pub enum LookupError {
+    #[error("Please enter a word to look up.")]
+    EmptyQuery,
+    #[error("No offline entry found for '{query}'.")]
+    NotFound {
+        query: String,
+        /// Close headwords the user may have meant, best first
+        suggestions: Vec<Suggestion>,
+    },
+    #[error(transparent)]
+    InvalidPattern(#[from] PatternError),
+}
+
+/// A headword close to a query that had no match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub word: String,
+    /// Edit distance from the query
+    pub distance: usize,
+}
+
+/// An entry whose definitions matched a reverse-dictionary query
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub entry: DictionaryEntry,
+    pub source: String,
+    /// BM25 relevance divided by that of the source's best hit, so hits from
+    /// sources with different corpus statistics can be ranked together
+    pub score: f32,
 }
 
 #[derive(Clone)]
 pub struct DictionaryClient {
+    /// Queried in order; earlier sources take priority when merging results
+    sources: Arc<Vec<Arc<dyn DictionarySource>>>,
+    /// The DICT protocol server, asked only when `sources` have nothing
+    dict_server: Option<Arc<DictServerSource>>,
+    /// Hunspell dictionaries, for spelling corrections and stems
+    spellers: Arc<Vec<Hunspell>>,
+    // Shared between clones so a settings change applies everywhere
+    strict_diacritics: Arc<AtomicBool>,
 }
 
 impl DictionaryClient {
+    /// A client over the dictionaries installed in the XDG data directories,
+    /// falling back to the DICT server from the saved settings, with the
+    /// installed Hunspell dictionaries for spelling
     pub fn new() -> Self {
-        Self {}
+        // Discover and parse dictionaries up front so the first lookup doesn't stall
+        let sources = installed_sources();
+        let settings = Settings::load();
+
+        Self {
+            dict_server: Some(Arc::new(DictServerSource::new(settings.dict_server))),
+            spellers: DEFAULT_SPELLERS.clone(),
+            strict_diacritics: Arc::new(AtomicBool::new(settings.strict_diacritics)),
+            ..Self::with_sources(sources)
+        }
+    }
+
+    /// A client over `sources`, highest priority first
+    pub fn with_sources(sources: Vec<Arc<dyn DictionarySource>>) -> Self {
+        Self {
+            sources: Arc::new(sources),
+            dict_server: None,
+            spellers: Arc::new(Vec::new()),
+            strict_diacritics: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn sources(&self) -> &[Arc<dyn DictionarySource>] {
+        &self.sources
     }
 
-    pub fn lookup(&self, word: &str) -> Result<Vec<DictionaryEntry>> {
+    pub fn dict_server(&self) -> Option<&Arc<DictServerSource>> {
+        self.dict_server.as_ref()
+    }
+
+    /// Whether lookups require diacritics to match exactly ("cafe" won't find "café")
+    pub fn strict_diacritics(&self) -> bool {
+        self.strict_diacritics.load(Ordering::Relaxed)
+    }
+
+    pub fn set_strict_diacritics(&self, strict: bool) {
+        self.strict_diacritics.store(strict, Ordering::Relaxed);
+    }
+
+    /// Attribute entries from the DICT server to it
+    fn attribute_to_server(&self, entries: Vec<DictionaryEntry>) -> Vec<SourcedEntry> {
+        let Some(server) = &self.dict_server else {
+            return Vec::new();
+        };
+        let metadata = server.metadata();
+        entries
+            .into_iter()
+            .map(|entry| SourcedEntry {
+                source: metadata.name.clone(),
+                source_id: metadata.id.clone(),
+                entry,
+            })
+            .collect()
+    }
+
+    /// Run `query` against every source in priority order, attributing each entry
+    fn collect<F>(&self, query: F) -> Vec<SourcedEntry>
+    where
+        F: Fn(&dyn DictionarySource) -> Vec<DictionaryEntry>,
+    {
+        self.sources
+            .iter()
+            .flat_map(|source| {
+                let metadata = source.metadata();
+                query(source.as_ref())
+                    .into_iter()
+                    .map(move |entry| SourcedEntry {
+                        source: metadata.name.clone(),
+                        source_id: metadata.id.clone(),
+                        entry,
+                    })
+            })
+            .collect()
+    }
+
+    pub fn lookup(&self, word: &str) -> Result<LookupResult, LookupError> {
         let term = word.trim();
         if term.is_empty() {
-            anyhow::bail!("Please enter a word to look up.");
+            return Err(LookupError::EmptyQuery);
         }
 
-        let lower = term.to_lowercase();
+        let folded = normalize::fold(term);
+        let strict = self.strict_diacritics();
+        let result = |entries, match_kind, lemma, suggestions| LookupResult {
+            query: term.to_string(),
+            entries,
+            match_kind,
+            lemma,
+            suggestions,
+        };
 
         // Prefer exact matches first
-        let mut matches: Vec<DictionaryEntry> = OFFLINE_ENTRIES
+        let entries = self.collect(|source| source.lookup(&folded, strict));
+        if !entries.is_empty() {
+            return Ok(result(entries, MatchKind::Exact, None, Vec::new()));
+        }
+
+        // Then treat the query as an inflected form ("running" → "run"), by
+        // the English rules and then by the spelling dictionaries' affix rules
+        let lemmas = lemma::candidates(&folded)
+            .into_iter()
+            .chain(iter::once_with(|| self.stems(term, &folded)).flatten());
+        for lemma in lemmas {
+            let entries = self.collect(|source| source.lookup(&lemma, strict));
+            if let Some(first) = entries.first() {
+                let lemma = Some(first.entry.word.clone());
+                return Ok(result(entries, MatchKind::Lemma, lemma, Vec::new()));
+            }
+        }
+
+        // Only now ask the DICT server, over one connection for the rest of
+        // the lookup
+        let mut remote = self.dict_server.as_ref().and_then(|server| server.session());
+        if let Some(session) = remote.as_mut() {
+            let entries = self.attribute_to_server(session.lookup(&folded, strict));
+            if !entries.is_empty() {
+                return Ok(result(entries, MatchKind::Exact, None, Vec::new()));
+            }
+        }
+
+        let suggestions = self.suggest_with(term, MAX_SUGGESTIONS, remote.as_mut());
+
+        // Fall back to prefix matches if no exact entry exists
+        let mut entries = self.collect(|source| source.prefix(&folded, strict, MAX_PREFIX_MATCHES));
+        if entries.is_empty() {
+            if let Some(session) = remote.as_mut() {
+                entries = self.attribute_to_server(session.prefix(&folded, strict, MAX_PREFIX_MATCHES));
+            }
+        }
+        if !entries.is_empty() {
+            entries.truncate(MAX_PREFIX_MATCHES);
+            return Ok(result(entries, MatchKind::Prefix, None, suggestions));
+        }
+
+        // Correct the spelling only when a single headword is one edit away;
+        // otherwise let the user pick from the suggestions. A word the spelling
+        // dictionaries know is left alone: "cart" is no typo for "cat".
+        let unambiguous = match suggestions.as_slice() {
+            [best] => best.distance == 1,
+            [best, next, ..] => best.distance == 1 && next.distance > 1,
+            [] => false,
+        };
+        if unambiguous && !self.spelled_correctly(term) {
+            let best = normalize::fold(&suggestions[0].word);
+            let mut entries = self.collect(|source| source.lookup(&best, false));
+            if entries.is_empty() {
+                if let Some(session) = remote.as_mut() {
+                    entries = self.attribute_to_server(session.lookup(&best, false));
+                }
+            }
+            if !entries.is_empty() {
+                let suggestions = suggestions[1..].to_vec();
+                return Ok(result(entries, MatchKind::Fuzzy, None, suggestions));
+            }
+        }
+
+        Err(LookupError::NotFound {
+            query: term.to_string(),
+            suggestions,
+        })
+    }
+
+    /// Headwords within a few edits of `word` in any source, closest first and,
+    /// at equal distance, most frequent first, along with the spelling
+    /// dictionaries' corrections when none of them accepts `word`. Exact
+    /// matches are not included.
+    pub fn suggest(&self, word: &str, limit: usize) -> Vec<Suggestion> {
+        self.suggest_with(word, limit, None)
+    }
+
+    /// [`DictionaryClient::suggest`], also asking the DICT server when
+    /// a session with it is open
+    fn suggest_with(
+        &self,
+        word: &str,
+        limit: usize,
+        remote: Option<&mut DictSession>,
+    ) -> Vec<Suggestion> {
+        let key = normalize::loose(word);
+        if key.is_empty() {
+            return Vec::new();
+        }
+
+        // Allow more edits for longer words, where typos are more likely
+        let max_distance = match key.chars().count() {
+            0..=4 => 1,
+            5..=8 => 2,
+            _ => 3,
+        };
+
+        // The same headword may be in several sources; keep its best showing
+        let mut best: HashMap<String, (String, usize, u32)> = HashMap::new();
+        let local = self
+            .sources
             .iter()
-            .filter(|entry| entry.word.eq_ignore_ascii_case(term))
-            .cloned()
+            .flat_map(|source| source.fuzzy(&key, max_distance));
+        let remote = remote
+            .map(|session| session.fuzzy(&key, max_distance))
+            .unwrap_or_default();
+        for candidate in local.chain(remote) {
+            if candidate.distance == 0 {
+                continue;
+            }
+            let slot = best
+                .entry(normalize::loose(&candidate.word))
+                .or_insert_with(|| (candidate.word.clone(), candidate.distance, 0));
+            slot.1 = slot.1.min(candidate.distance);
+            slot.2 = slot.2.max(candidate.frequency);
+        }
+
+        if !self.spellers.is_empty() && !self.spelled_correctly(word) {
+            for speller in self.spellers.iter() {
+                for correction in speller.suggest(word, limit) {
+                    let loose = normalize::loose(&correction);
+                    let distance = fuzzy::levenshtein(&key, &loose);
+                    if distance == 0 {
+                        continue;
+                    }
+                    let slot = best
+                        .entry(loose)
+                        .or_insert_with(|| (correction, distance, 0));
+                    slot.1 = slot.1.min(distance);
+                }
+            }
+        }
+
+        let mut candidates: Vec<(String, usize, u32)> = best.into_values().collect();
+        candidates.sort_by(|a, b| a.1.cmp(&b.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+
+        candidates
+            .into_iter()
+            .take(limit)
+            .map(|(word, distance, _)| Suggestion { word, distance })
+            .collect()
+    }
+
+    /// Whether any of the Hunspell dictionaries accepts `word`
+    fn spelled_correctly(&self, word: &str) -> bool {
+        self.spellers.iter().any(|speller| speller.check(word))
+    }
+
+    /// Stems the Hunspell dictionaries find for `word` ("walked" → "walk"),
+    /// case-folded, other than `folded` itself
+    fn stems(&self, word: &str, folded: &str) -> Vec<String> {
+        let mut stems: Vec<String> = Vec::new();
+        for stem in self.spellers.iter().flat_map(|speller| speller.stems(word)) {
+            let stem = normalize::fold(&stem);
+            if stem != folded && !stems.contains(&stem) {
+                stems.push(stem);
+            }
+        }
+        stems
+    }
+
+    /// Headwords matching a crossword-style pattern such as `c?t`, `*ology`,
+    /// `[bc]at` or `s*:5`, in alphabetical order. See [`Pattern`] for the syntax.
+    pub fn match_pattern(&self, pattern: &str, limit: usize) -> Result<Vec<String>, LookupError> {
+        let pattern = Pattern::parse(&normalize::loose(pattern))?;
+
+        // Merge the sorted lists from each source, dropping duplicates
+        let mut words: Vec<(String, String)> = Vec::new();
+        let mut seen = HashSet::new();
+        for source in self.sources.iter() {
+            for word in source.pattern(&pattern, limit) {
+                let key = normalize::loose(&word);
+                if seen.insert(key.clone()) {
+                    words.push((key, word));
+                }
+            }
+        }
+        words.sort();
+
+        Ok(words.into_iter().take(limit).map(|(_, word)| word).collect())
+    }
+
+    pub fn format_pattern_matches(&self, pattern: &str, words: &[String]) -> String {
+        if words.is_empty() {
+            return format!("No words match '{}'.", pattern.trim());
+        }
+
+        let mut formatted = format!("Words matching '{}'\n\n", pattern.trim());
+        for word in words {
+            formatted.push_str(word);
+            formatted.push('\n');
+        }
+        formatted
+    }
+
+    /// Reverse lookup: entries whose definitions or examples best match
+    /// `query` (e.g. "fear of heights"), most relevant first
+    pub fn search_definitions(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let mut hits: Vec<SearchHit> = self
+            .sources
+            .iter()
+            .flat_map(|source| {
+                let name = source.metadata().name.clone();
+                let source_hits = source.search(query, limit);
+                let top = source_hits
+                    .iter()
+                    .map(|(_, score)| *score)
+                    .fold(0.0, f32::max);
+                source_hits
+                    .into_iter()
+                    .map(move |(entry, score)| SearchHit {
+                        entry,
+                        source: name.clone(),
+                        score: if top > 0.0 { score / top } else { 0.0 },
+                    })
+            })
             .collect();
 
-        // Fall back to prefix matches if no exact entry exists
-        if matches.is_empty() {
-            matches = OFFLINE_ENTRIES
+        // Stable, so equally relevant hits keep their sources' priority order
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        hits
+    }
+
+    /// One line per hit: the headword and its first definition
+    pub fn format_search_hits(&self, query: &str, hits: &[SearchHit]) -> String {
+        if hits.is_empty() {
+            return format!("No definitions mention '{}'.", query.trim());
+        }
+
+        let mut formatted = format!("Words matching '{}'\n\n", query.trim());
+        for hit in hits {
+            let first_definition = hit
+                .entry
+                .meanings
                 .iter()
-                .filter(|entry| entry.word.to_lowercase().starts_with(&lower))
-                .cloned()
-                .collect();
+                .flat_map(|meaning| &meaning.definitions)
+                .next()
+                .map(|definition| definition.definition.as_str())
+                .unwrap_or_default();
+            formatted.push_str(&format!("{}\n   {}\n\n", hit.entry.word, first_definition));
+        }
+        formatted
+    }
+
+    /// Format a lookup result, explaining how it relates to the query when it
+    /// isn't an exact match
+    pub fn format_result(&self, result: &LookupResult) -> String {
+        markup::strip_image_placeholders(&self.layout_result(result))
+    }
+
+    /// Format a lookup result for the definition view, which shows embedded
+    /// images in place of their placeholders
+    pub fn render_result(&self, result: &LookupResult) -> RenderedResult {
+        let images = result
+            .entries
+            .iter()
+            .flat_map(|sourced| {
+                let source = self
+                    .sources
+                    .iter()
+                    .find(|source| source.metadata().id == sourced.source_id);
+                sourced
+                    .entry
+                    .images
+                    .iter()
+                    .map(move |name| source.and_then(|source| source.resource(name)))
+            })
+            .collect();
+
+        RenderedResult {
+            text: self.layout_result(result),
+            images,
+        }
+    }
+
+    fn layout_result(&self, result: &LookupResult) -> String {
+        let mut formatted = match (result.match_kind, result.entries.first()) {
+            (MatchKind::Lemma, _) => format!(
+                "{} \u{2192} {}\n\n",
+                result.query,
+                result.lemma.as_deref().unwrap_or_default()
+            ),
+            (MatchKind::Prefix, _) => {
+                format!("No exact entry for '{}'; words starting with it:\n\n", result.query)
+            }
+            (MatchKind::Fuzzy, Some(first)) => format!(
+                "No entry for '{}'; showing results for '{}'.\n\n",
+                result.query, first.entry.word
+            ),
+            _ => String::new(),
+        };
+
+        // Attribute each run of entries from the same dictionary
+        for group in result.entries.chunk_by(|a, b| a.source_id == b.source_id) {
+            let entries: Vec<DictionaryEntry> =
+                group.iter().map(|sourced| sourced.entry.clone()).collect();
+            formatted.push_str(&self.layout_entries(&entries));
+            if self.sources.len() > 1 {
+                formatted.push_str(&format!("Source: {}\n\n", group[0].source));
+            }
         }
 
-        if matches.is_empty() {
-            anyhow::bail!("No offline entry found for '{}'.", term);
+        if !result.suggestions.is_empty() {
+            let words: Vec<&str> = result
+                .suggestions
+                .iter()
+                .map(|suggestion| suggestion.word.as_str())
+                .collect();
+            formatted.push_str(&format!("Did you mean: {}\n", words.join(", ")));
         }
 
-        Ok(matches)
+        formatted
     }
 
     pub fn format_entry(&self, entries: &[DictionaryEntry]) -> String {
+        markup::strip_image_placeholders(&self.layout_entries(entries))
+    }
+
+    fn layout_entries(&self, entries: &[DictionaryEntry]) -> String {
         if entries.is_empty() {
             return String::from("No definitions found.");
         }
@@ -102,13 +602,25 @@ impl DictionaryClient {
             }
 
             for meaning in &entry.meanings {
-                formatted.push_str(&format!(
-                    "{}\n",
-                    meaning.part_of_speech.replace('-', " ")
-                ));
+                // Imported dictionaries don't always tag a part of speech
+                if !meaning.part_of_speech.is_empty() {
+                    formatted.push_str(&format!(
+                        "{}\n",
+                        meaning.part_of_speech.replace('-', " ")
+                    ));
+                }
 
                 for (idx, definition) in meaning.definitions.iter().enumerate() {
-                    formatted.push_str(&format!("{}. {}\n", idx + 1, definition.definition));
+                    if definition.tags.is_empty() {
+                        formatted.push_str(&format!("{}. {}\n", idx + 1, definition.definition));
+                    } else {
+                        formatted.push_str(&format!(
+                            "{}. ({}) {}\n",
+                            idx + 1,
+                            definition.tags.join(", "),
+                            definition.definition
+                        ));
+                    }
 
                     if let Some(example) = &definition.example {
                         formatted.push_str(&format!("   Example: {}\n", example));
@@ -121,8 +633,31 @@ impl DictionaryClient {
                         meaning.synonyms.join(", ")
                     ));
                 }
+                if !meaning.antonyms.is_empty() {
+                    formatted.push_str(&format!(
+                        "   Antonyms: {}\n",
+                        meaning.antonyms.join(", ")
+                    ));
+                }
+                for relation in &meaning.relations {
+                    formatted.push_str(&format!(
+                        "   {}: {}\n",
+                        relation.kind.label(),
+                        relation.words.join(", ")
+                    ));
+                }
+
+                formatted.push('\n');
+            }
 
-                formatted.push_str("\n");
+            if !entry.forms.is_empty() {
+                formatted.push_str(&format!("Forms: {}\n", entry.forms.join(", ")));
+            }
+            if let Some(etymology) = &entry.etymology {
+                formatted.push_str(&format!("Etymology: {}\n", etymology));
+            }
+            if !entry.forms.is_empty() || entry.etymology.is_some() {
+                formatted.push('\n');
             }
         }
 
@@ -136,9 +671,348 @@ impl Default for DictionaryClient {
     }
 }
 
-fn load_dictionary() -> Vec<DictionaryEntry> {
-    let raw = include_str!("../data/dictionary.json");
-    serde_json::from_str(raw)
-        .context("Failed to parse bundled offline dictionary data")
-        .unwrap_or_else(|_| Vec::new())
+/// The dictionaries installed on this machine (or the bundled one), highest
+/// priority first. Loaded once and shared.
+pub fn installed_sources() -> Vec<Arc<dyn DictionarySource>> {
+    DEFAULT_SOURCES.sources.clone()
+}
+
+/// Dictionaries that failed to load, in whole or in part, when the installed
+/// sources were loaded
+pub fn load_problems() -> &'static [LoadProblem] {
+    &DEFAULT_SOURCES.problems
+}
+
+/// Directories searched for dictionary files, in priority order:
+/// `$XDG_DATA_HOME/aynary/dictionaries` first, then each entry of `$XDG_DATA_DIRS`,
+/// then the usual StarDict locations (`~/.stardict/dic` and `stardict/dic` under
+/// each data directory) so dictionaries installed for other readers are picked up,
+/// then WordNet's (`$WNSEARCHDIR` and `wordnet` under each data directory).
+pub fn dictionary_dirs() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let data_dirs = data_dirs();
+
+    let stardict_dirs = home
+        .map(|home| home.join(".stardict/dic"))
+        .into_iter()
+        .chain(data_dirs.iter().map(|dir| dir.join(STARDICT_SUBDIR)));
+
+    let wordnet_dirs = env::var_os("WNSEARCHDIR")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(data_dirs.iter().map(|dir| dir.join(WORDNET_SUBDIR)));
+
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for dir in data_dirs
+        .iter()
+        .map(|dir| dir.join(DICTIONARY_SUBDIR))
+        .chain(stardict_dirs)
+        .chain(wordnet_dirs)
+    {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// `$XDG_DATA_HOME` followed by each entry of `$XDG_DATA_DIRS`
+fn data_dirs() -> Vec<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+
+    let data_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+
+    // The spec says relative paths are invalid and must be ignored
+    data_home
+        .into_iter()
+        .chain(env::split_paths(&data_dirs).filter(|path| path.is_absolute()))
+        .collect()
+}
+
+/// The dictionaries found at startup and what went wrong loading them
+struct InstalledSources {
+    sources: Vec<Arc<dyn DictionarySource>>,
+    problems: Vec<LoadProblem>,
+}
+
+/// One source per dictionary file, searching the data directories in
+/// priority order. A file shadows any same-named file in a lower-priority
+/// directory. Falls back to the bundled dictionary when nothing usable is installed.
+fn load_sources() -> InstalledSources {
+    let mut sources: Vec<Arc<dyn DictionarySource>> = Vec::new();
+    let mut problems = Vec::new();
+    // Encyclopedia articles come after the dictionaries, so a lookup shows the
+    // definition first and the article summary below it
+    let mut encyclopedias: Vec<Arc<dyn DictionarySource>> = Vec::new();
+    let mut seen_names = HashSet::new();
+
+    for dir in dictionary_dirs() {
+        for path in dictionary_files(&dir) {
+            // Every package has a manifest.json, so packages go by their directory
+            let named = if package::is_manifest(&path) {
+                path.parent().unwrap_or(&path)
+            } else {
+                &path
+            };
+            let Some(name) = named.file_name().map(|name| name.to_os_string()) else {
+                continue;
+            };
+            if !seen_names.insert(name) {
+                continue;
+            }
+
+            let mut problem = LoadProblem::new(path.display().to_string());
+            let source = match load_source(&path, &mut problem) {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("Skipping dictionary {}: {:#}", path.display(), e);
+                    problems.push(LoadProblem::failed(path.display().to_string(), &e));
+                    continue;
+                }
+            };
+            if !problem.is_empty() {
+                problem.loaded_entries = source.metadata().entry_count.unwrap_or(0);
+                eprintln!("{}", problem.describe());
+                problems.push(problem);
+            }
+
+            if source.metadata().entry_count == Some(0) {
+                continue;
+            }
+            if is_zim(&path) {
+                encyclopedias.push(source);
+            } else {
+                sources.push(source);
+            }
+        }
+    }
+
+    if sources.is_empty() {
+        match CompactSource::from_static(BUNDLED_SOURCE, BUNDLED_DICTIONARY) {
+            Ok(source) => sources.push(Arc::new(source)),
+            Err(e) => {
+                eprintln!("Failed to load the bundled dictionary: {:#}", e);
+                problems.push(LoadProblem::failed(BUNDLED_SOURCE, &e));
+            }
+        }
+    }
+
+    sources.extend(encyclopedias);
+
+    let indexed = sources.clone();
+    thread::spawn(move || {
+        for source in indexed {
+            source.build_indexes();
+        }
+    });
+    InstalledSources { sources, problems }
+}
+
+/// Directories searched for Hunspell dictionaries: those in `$DICPATH`, then
+/// `hunspell` under each XDG data directory, which covers `/usr/share/hunspell`
+fn hunspell_dirs() -> Vec<PathBuf> {
+    let dicpath = env::var_os("DICPATH").unwrap_or_default();
+    env::split_paths(&dicpath)
+        .filter(|path| path.is_absolute())
+        .chain(data_dirs().into_iter().map(|dir| dir.join(HUNSPELL_SUBDIR)))
+        .collect()
+}
+
+/// A Hunspell dictionary for the locale's language and for the language of
+/// each installed dictionary. Distributions install a dozen regional variants
+/// of some languages, so only one is loaded: the locale's own (`en_GB` for
+/// `en_GB.UTF-8`) when there is one, otherwise the first found.
+fn load_spellers() -> Vec<Hunspell> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| value.split(['.', '@']).next().map(str::to_string))
+        .filter(|locale| locale != "C" && locale != "POSIX");
+
+    let mut languages: Vec<String> = locale
+        .iter()
+        .map(|locale| primary_language(locale))
+        .collect();
+    for source in &DEFAULT_SOURCES.sources {
+        if let Some(language) = &source.metadata().language {
+            let language = primary_language(language);
+            if !languages.contains(&language) {
+                languages.push(language);
+            }
+        }
+    }
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for dir in hunspell_dirs() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "dic"))
+            .filter(|path| path.with_extension("aff").is_file())
+            .collect();
+        found.sort();
+        files.extend(found);
+    }
+    let stem = |path: &Path| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    let mut spellers = Vec::new();
+    for language in languages {
+        let candidates: Vec<&PathBuf> = files
+            .iter()
+            .filter(|path| primary_language(&stem(path)) == language)
+            .collect();
+        let chosen = candidates
+            .iter()
+            .find(|path| locale.as_deref() == Some(stem(path).as_str()))
+            .or(candidates.first());
+        let Some(path) = chosen else {
+            continue;
+        };
+        match Hunspell::open(path) {
+            Ok(speller) => spellers.push(speller),
+            Err(e) => eprintln!("Skipping Hunspell dictionary {}: {:#}", path.display(), e),
+        }
+    }
+    spellers
+}
+
+/// Primary language subtag of a locale or language tag: `en` for `en_US`,
+/// `en-GB` or a three-letter `eng`
+fn primary_language(tag: &str) -> String {
+    source::language_tag(tag.split(['_', '-']).next().unwrap_or_default())
+}
+
+fn is_zim(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zim")
+}
+
+/// Open a dictionary file with the reader for its format. Entries of a JSON
+/// dictionary that can't be read are left out and described in `problem`.
+fn load_source(path: &Path, problem: &mut LoadProblem) -> Result<Arc<dyn DictionarySource>> {
+    if package::is_manifest(path) {
+        return Ok(Arc::new(PackageSource::open(path, problem)?));
+    }
+    if is_wordnet(path) {
+        let dir = path
+            .parent()
+            .context("WordNet data file has no directory")?;
+        return Ok(Arc::new(WordNetSource::open(dir)?));
+    }
+    if dsl::is_dsl(path) {
+        return Ok(Arc::new(dsl::open(path)?));
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ifo") => Ok(Arc::new(StarDictSource::open(path)?)),
+        Some("mdx") => Ok(Arc::new(MDictSource::open(path)?)),
+        Some("xdxf") => Ok(Arc::new(xdxf::open(path)?)),
+        Some("zim") => Ok(Arc::new(ZimSource::open(path)?)),
+        Some(compact::EXTENSION) => Ok(Arc::new(CompactSource::open(path)?)),
+        _ => {
+            // A bare array is the version 1 package format, read as the
+            // package `aynary package` would make of it
+            let entries = load_dictionary_file(path, problem)?;
+            let metadata = Manifest::for_entries(path, &entries).metadata(entries.len());
+            Ok(Arc::new(MemorySource::new(metadata, entries)))
+        }
+    }
+}
+
+/// Dictionary files inside `dir`, sorted by path. StarDict dictionaries are
+/// usually unpacked into a directory of their own, so subdirectories are
+/// searched one level deep; a subdirectory with a manifest is a package, which
+/// is loaded through its manifest alone. A JSON dictionary that has been
+/// compiled next to itself is left to the compiled copy.
+fn dictionary_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in read_dir_paths(dir) {
+        let manifest = path.join(package::MANIFEST_FILE);
+        if manifest.is_file() {
+            files.push(manifest);
+        } else if path.is_dir() {
+            files.extend(
+                read_dir_paths(&path)
+                    .into_iter()
+                    .filter(|path| is_dictionary(path)),
+            );
+        } else if is_dictionary(&path) {
+            files.push(path);
+        }
+    }
+    files.retain(|path| {
+        let json = path.extension().is_some_and(|ext| ext == "json");
+        !(json && path.with_extension(compact::EXTENSION).is_file())
+    });
+    files.sort();
+    files
+}
+
+fn read_dir_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Whether `path` is the main file of a dictionary in a supported format
+fn is_dictionary(path: &Path) -> bool {
+    path.is_file()
+        && (is_wordnet(path)
+            || dsl::is_dsl(path)
+            || path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| DICTIONARY_EXTENSIONS.contains(&ext)))
+}
+
+/// WordNet is a directory of files rather than one file, found by its noun
+/// data. They all share the name, so only the highest-priority copy is loaded.
+fn is_wordnet(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name == wordnet::MARKER_FILE)
+}
+
+/// The entries of a JSON dictionary that could be read. What was wrong with
+/// the others is added to `problem`.
+pub fn load_dictionary_file(
+    path: &Path,
+    problem: &mut LoadProblem,
+) -> Result<Vec<DictionaryEntry>> {
+    let raw = fs::read_to_string(path).context("Failed to read dictionary file")?;
+    parse_dictionary(&raw, problem)
+}
+
+/// Parse a JSON dictionary one entry at a time, so a malformed entry costs
+/// only itself. Fails only if the text isn't an array of entries at all.
+fn parse_dictionary(text: &str, problem: &mut LoadProblem) -> Result<Vec<DictionaryEntry>> {
+    let split = diagnostics::split_entries(text);
+    if split.entries.is_empty() {
+        if let Some(error) = split.errors.first() {
+            bail!(
+                "Failed to parse dictionary file: {} at line {}, column {}",
+                error.message,
+                error.line,
+                error.column
+            );
+        }
+    }
+    // Stray commas and the like lose no entries
+    problem.warnings.extend_from_slice(&split.errors);
+    let (entries, skipped) = split.parse();
+    problem.skipped_entries.extend(skipped);
+    Ok(entries)
 }
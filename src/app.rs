@@ -1,11 +1,22 @@
-use crate::api::DictionaryClient;
-use crate::ui::AppWindow;
+use crate::api::{self, DictionaryClient, LookupError, LookupResult, RenderedResult};
+use crate::dict_client::{self, DictServerSource};
+use crate::pattern::Pattern;
+use crate::settings::{DictServerSettings, Settings};
+use crate::ui::{AppWindow, SearchMode};
 use adw::prelude::*;
 use adw::Application;
 use anyhow::Result;
+use std::cell::Cell;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
 
+/// Number of words listed for a reverse-dictionary search
+const MAX_SEARCH_HITS: usize = 25;
+
+/// Number of words listed for a pattern search
+const MAX_PATTERN_MATCHES: usize = 200;
+
 pub struct App {
     application: Application,
     window: Arc<Mutex<Option<Arc<AppWindow>>>>,
@@ -33,6 +44,10 @@ impl App {
         &self.application
     }
 
+    pub fn client(&self) -> &DictionaryClient {
+        &self.client
+    }
+
     pub fn setup(&mut self) {
         let application = self.application.clone();
         let client = self.client.clone();
@@ -46,39 +61,62 @@ impl App {
             if window_guard.is_none() {
                 // Create window
                 let window = Arc::new(AppWindow::new(app));
+                window.set_load_problems(api::load_problems());
                 
+                // Keep the client's matching options in sync with the options menu
+                let client_for_options = client.clone();
+                window
+                    .strict_diacritics_check
+                    .set_active(client_for_options.strict_diacritics());
+                window.strict_diacritics_check.connect_toggled(move |check| {
+                    client_for_options.set_strict_diacritics(check.is_active());
+                    let mut settings = Settings::load();
+                    settings.strict_diacritics = check.is_active();
+                    if let Err(e) = settings.save() {
+                        eprintln!("Failed to save settings: {:#}", e);
+                    }
+                });
+
+                if let Some(dict_server) = client.dict_server() {
+                    setup_dict_server_options(&window, dict_server);
+                }
+
                 // Setup search entry handler
                 let search_entry = window.search_entry.clone();
                 let client_clone = client.clone();
                 let window_ref_for_search = window_ref.clone();
+                // Numbers searches, so a slow one finishing late doesn't
+                // replace the results of a newer one
+                let latest_search = Rc::new(Cell::new(0u64));
 
                 search_entry.connect_activate(move |entry| {
                     let word = entry.text().to_string();
                     if word.is_empty() {
                         return;
                     }
+                    let Some(window) = window_ref_for_search.lock().unwrap().clone() else {
+                        return;
+                    };
 
                     // Set loading state (switches to definition view)
-                    {
-                        let window_guard = window_ref_for_search.lock().unwrap();
-                        if let Some(window) = window_guard.as_ref() {
-                            window.set_loading(true);
-                        }
-                    }
+                    window.set_loading(true);
 
+                    let search = latest_search.get() + 1;
+                    latest_search.set(search);
+                    let latest_search = latest_search.clone();
                     let client = client_clone.clone();
-                    let window_ref_clone = window_ref_for_search.clone();
+                    let mode = window.search_mode();
 
-                    // Perform synchronous lookup and update UI
-                    let result = match client.lookup(&word) {
-                        Ok(entries) => client.format_entry(&entries),
-                        Err(e) => format!("Error: {}", e),
-                    };
-
-                    let window_guard = window_ref_clone.lock().unwrap();
-                    if let Some(window) = window_guard.as_ref() {
-                        window.set_definition(&result);
-                    }
+                    // Dictionaries, spelling and the DICT server can all take a
+                    // while, so search off the main thread
+                    run_in_background(
+                        move || search_in_mode(&client, mode, &word),
+                        move |found| {
+                            if latest_search.get() == search {
+                                show_found(&window, found);
+                            }
+                        },
+                    );
                 });
 
                 // Store window reference
@@ -92,7 +130,11 @@ impl App {
         });
     }
 
-    pub fn lookup_word(&mut self, word: &str) -> Result<String> {
+    /// Show a lookup already made elsewhere, such as by the D-Bus service
+    pub fn show_lookup_result(
+        &mut self,
+        result: Result<LookupResult, LookupError>,
+    ) -> Result<String> {
         let window_guard = self.window.lock().unwrap();
         let window = window_guard
             .as_ref()
@@ -101,13 +143,7 @@ impl App {
         window.set_loading(true);
         window.show();
 
-        // Perform synchronous lookup
-        let result = match self.client.lookup(word) {
-            Ok(entries) => self.client.format_entry(&entries),
-            Err(e) => format!("Error: {}", e),
-        };
-
-        window.definition_view.buffer().set_text(&result);
+        show_found(window, found_for_lookup(&self.client, result));
 
         Ok(String::from("Lookup completed"))
     }
@@ -119,13 +155,183 @@ impl App {
         }
     }
 
-    pub fn lookup_and_show(&mut self, word: &str) -> Result<()> {
+    pub fn lookup_and_show(&mut self, result: Result<LookupResult, LookupError>) -> Result<()> {
         self.show_window();
-        self.lookup_word(word)?;
+        self.show_lookup_result(result)?;
         Ok(())
     }
 }
 
+/// What a search found, ready to show
+enum Found {
+    Definition(RenderedResult),
+    /// "Did you mean" suggestions for a query that matched nothing
+    Suggestions(String, Vec<String>),
+    Text(String),
+}
+
+/// Run `work` on a worker thread and pass its result to `show` on the main
+/// thread, so slow sources don't freeze the window
+fn run_in_background<T, W, S>(work: W, show: S)
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+    S: FnOnce(T) + 'static,
+{
+    glib::MainContext::default().spawn_local(async move {
+        match gio::spawn_blocking(work).await {
+            Ok(value) => show(value),
+            Err(_) => eprintln!("Background task panicked"),
+        }
+    });
+}
+
+fn search_in_mode(client: &DictionaryClient, mode: SearchMode, text: &str) -> Found {
+    match mode {
+        // Wildcards in a word search switch to pattern search
+        SearchMode::Word if Pattern::looks_like_pattern(text) => pattern_matches(client, text),
+        SearchMode::Word => found_for_lookup(client, client.lookup(text)),
+        SearchMode::Meaning => definition_search(client, text),
+        SearchMode::Pattern => pattern_matches(client, text),
+    }
+}
+
+/// Either the definition or, when nothing matches, "did you mean" suggestions
+fn found_for_lookup(client: &DictionaryClient, result: Result<LookupResult, LookupError>) -> Found {
+    match result {
+        Ok(result) => Found::Definition(client.render_result(&result)),
+        Err(LookupError::NotFound { query, suggestions }) if !suggestions.is_empty() => {
+            let words: Vec<String> = suggestions
+                .into_iter()
+                .map(|suggestion| suggestion.word)
+                .collect();
+            Found::Suggestions(query, words)
+        }
+        Err(e) => Found::Text(format!("Error: {}", e)),
+    }
+}
+
+fn show_found(window: &AppWindow, found: Found) {
+    match found {
+        Found::Definition(rendered) => {
+            window.set_rendered_definition(&rendered.text, &rendered.images)
+        }
+        Found::Suggestions(query, words) => window.set_suggestions(&query, &words),
+        Found::Text(text) => window.set_definition(&text),
+    }
+}
+
+/// Show the DICT server settings in the options menu and apply changes to them
+fn setup_dict_server_options(window: &Arc<AppWindow>, dict_server: &Arc<DictServerSource>) {
+    let settings = dict_server.settings();
+    let host = dict_client::format_address(&settings.host, settings.port);
+    window.dict_host_entry.set_text(&host);
+    window.dict_enabled_check.set_active(settings.enabled);
+    refresh_dict_databases(window, dict_server);
+
+    let window_for_enabled = Arc::clone(window);
+    let server_for_enabled = Arc::clone(dict_server);
+    window.dict_enabled_check.connect_toggled(move |check| {
+        let mut settings = server_for_enabled.settings();
+        settings.enabled = check.is_active();
+        save_dict_server_settings(&server_for_enabled, settings);
+        refresh_dict_databases(&window_for_enabled, &server_for_enabled);
+    });
+
+    let window_for_host = Arc::clone(window);
+    let server_for_host = Arc::clone(dict_server);
+    window.dict_host_entry.connect_activate(move |entry| {
+        let text = entry.text();
+        let Some((host, port)) = dict_client::parse_address(text.trim()) else {
+            return;
+        };
+
+        let mut settings = server_for_host.settings();
+        settings.host = if host.is_empty() {
+            String::from("localhost")
+        } else {
+            host.to_string()
+        };
+        settings.port = port;
+        // Database names differ between servers
+        settings.database = String::from("*");
+        save_dict_server_settings(&server_for_host, settings);
+        refresh_dict_databases(&window_for_host, &server_for_host);
+    });
+
+    let window_for_refresh = Arc::clone(window);
+    let server_for_refresh = Arc::clone(dict_server);
+    window.dict_refresh_button.connect_clicked(move |_| {
+        refresh_dict_databases(&window_for_refresh, &server_for_refresh);
+    });
+
+    let window_for_database = Arc::clone(window);
+    let server_for_database = Arc::clone(dict_server);
+    window.dict_database_dropdown.connect_selected_notify(move |_| {
+        let Some(database) = window_for_database.selected_dict_database() else {
+            return;
+        };
+        let mut settings = server_for_database.settings();
+        if settings.database != database {
+            settings.database = database;
+            save_dict_server_settings(&server_for_database, settings);
+        }
+    });
+}
+
+/// Ask the server for its databases in the background and list them in the
+/// options menu. Nothing is asked while the server is turned off.
+fn refresh_dict_databases(window: &Arc<AppWindow>, dict_server: &Arc<DictServerSource>) {
+    if !dict_server.settings().enabled {
+        window.set_dict_databases(&[], &dict_server.settings().database);
+        return;
+    }
+
+    let server = Arc::clone(dict_server);
+    let server_for_list = Arc::clone(dict_server);
+    let window = Arc::clone(window);
+    run_in_background(
+        move || server.databases(),
+        move |databases| {
+            let databases: Vec<(String, String)> = match databases {
+                Ok(databases) => databases
+                    .into_iter()
+                    .map(|database| (database.name, database.description))
+                    .collect(),
+                Err(e) => {
+                    eprintln!("Failed to list DICT databases: {:#}", e);
+                    Vec::new()
+                }
+            };
+            window.set_dict_databases(&databases, &server_for_list.settings().database);
+        },
+    );
+}
+
+fn save_dict_server_settings(dict_server: &DictServerSource, dict_settings: DictServerSettings) {
+    dict_server.set_settings(dict_settings.clone());
+
+    let mut settings = Settings::load();
+    settings.dict_server = dict_settings;
+    if let Err(e) = settings.save() {
+        eprintln!("Failed to save settings: {:#}", e);
+    }
+}
+
+/// Reverse dictionary: list the words whose definitions match `query`
+fn definition_search(client: &DictionaryClient, query: &str) -> Found {
+    let hits = client.search_definitions(query, MAX_SEARCH_HITS);
+    Found::Text(client.format_search_hits(query, &hits))
+}
+
+/// List the headwords matching a crossword-style pattern
+fn pattern_matches(client: &DictionaryClient, pattern: &str) -> Found {
+    match client.match_pattern(pattern, MAX_PATTERN_MATCHES) {
+        Ok(words) => Found::Text(client.format_pattern_matches(pattern, &words)),
+        Err(e) => Found::Text(format!("Error: {}", e)),
+    }
+}
+
 impl Default for App {
     fn default() -> Self {
         Self::new()
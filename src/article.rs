@@ -0,0 +1,64 @@
+use crate::api::{Definition, DictionaryEntry, Meaning};
+
+/// `forms` in order without empty strings or repeats, such as the keys of a
+/// card or article
+pub fn unique_forms(forms: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::new();
+    for form in forms {
+        if !form.is_empty() && !unique.contains(&form) {
+            unique.push(form);
+        }
+    }
+    unique
+}
+
+/// One copy of `entry` per form, so each can be looked up, listing the other
+/// forms as its `forms`
+pub fn entry_per_form(forms: &[String], entry: DictionaryEntry) -> Vec<DictionaryEntry> {
+    forms
+        .iter()
+        .map(|word| DictionaryEntry {
+            word: word.clone(),
+            forms: forms.iter().filter(|form| *form != word).cloned().collect(),
+            ..entry.clone()
+        })
+        .collect()
+}
+
+pub fn current_meaning(meanings: &mut Vec<Meaning>) -> &mut Meaning {
+    if meanings.is_empty() {
+        meanings.push(Meaning::default());
+    }
+    meanings.last_mut().unwrap()
+}
+
+/// A line that is nothing but links, perhaps after a word such as "see"
+pub fn is_link_only(text: &str, refs: &[String]) -> bool {
+    let mut rest = text.to_string();
+    for target in refs {
+        rest = rest.replacen(target.as_str(), "", 1);
+    }
+    rest.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+        <= 1
+}
+
+/// Attach an example to the latest definition; several are joined with "; ".
+/// Without a definition to attach to, the example becomes one.
+pub fn add_example(meanings: &mut Vec<Meaning>, example: String) {
+    let meaning = current_meaning(meanings);
+    match meaning.definitions.last_mut() {
+        Some(definition) => match &mut definition.example {
+            Some(examples) => {
+                examples.push_str("; ");
+                examples.push_str(&example);
+            }
+            None => definition.example = Some(example),
+        },
+        None => meaning.definitions.push(Definition {
+            definition: example,
+            ..Definition::default()
+        }),
+    }
+}
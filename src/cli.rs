@@ -0,0 +1,589 @@
+use crate::api::{self, DictionaryEntry};
+use crate::compact;
+use crate::csv;
+use crate::dict_client;
+use crate::dict_server::DictServer;
+use crate::freedict::{self, LanguagePair};
+use crate::import::{self as importer, EntryWriter, ImportStats};
+use crate::kaikki;
+use crate::lint::{self, Report};
+use crate::package::{self, Manifest};
+use crate::xdxf::{self, ExportInfo};
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+Usage: aynary [COMMAND]
+
+Without a command, starts the dictionary window.
+
+Commands:
+  serve-dict [--listen ADDRESS] [--port PORT]
+      Serve the installed dictionaries over the DICT protocol (RFC 2229).
+      Listens on 127.0.0.1:2628 by default; use --listen 0.0.0.0 to
+      accept connections from other machines.
+  import kaikki FILE --lang LANGUAGE [--compile] [--output PATH]
+      Convert a Kaikki.org Wiktextract JSONL dump (optionally .gz), keeping
+      entries in LANGUAGE (a code such as \"en\" or a name such as
+      \"English\"). Writes to the user dictionary directory by default.
+  import freedict FILE [--from LANGUAGE] [--to LANGUAGE] [--compile]
+          [--output PATH]
+      Convert a FreeDict TEI dictionary (optionally .gz). The languages
+      default to those in the file name, e.g. eng-fra.tei.
+  import csv FILE [--compile] [--output PATH]
+      Convert a CSV or TSV word list (optionally .gz) whose header names
+      its columns: word and definition, and optionally pos, example,
+      synonyms, antonyms and phonetic. Rows for the same word are merged.
+      Imports are written as JSON, or with --compile in the compact
+      binary format of the compile command.
+  compile FILE [--title TITLE] [--output PATH]
+      Compile an Aynary JSON dictionary (optionally .gz) into the compact
+      binary format, which opens instantly and is read on demand. Writes
+      FILE with an .aydict extension by default.
+  package FILE [--id ID] [--title TITLE] [--version VERSION]
+          [--language CODE]... [--license LICENSE] [--attribution TEXT]
+          [--description TEXT] [--compile] [--output DIR]
+      Make a dictionary package from an Aynary JSON dictionary (optionally
+      .gz): a directory with a manifest, the entries (compiled with
+      --compile) and the audio and image files they refer to. Writes to
+      the user dictionary directory by default.
+  export xdxf FILE [--title TITLE] [--output PATH]
+      Write an Aynary JSON dictionary (optionally .gz) as logical-format
+      XDXF. Writes FILE with an .xdxf extension by default.
+  lint FILE... [--format text|json] [--strict]
+      Check Aynary JSON dictionaries (optionally .gz) or packages for schema errors,
+      duplicate entries, empty definitions, unknown parts of speech,
+      synonyms that aren't headwords, missing audio files and invalid IPA.
+      Fails if there are errors, or any problems at all with --strict.
+  help
+      Show this message.";
+
+/// Run a headless command given on the command line. Returns the process exit
+/// code, or `None` when there is no command and the window should start.
+pub fn run() -> Option<i32> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str)? {
+        "serve-dict" => serve_dict(&args[1..]),
+        "import" => import(&args[1..]),
+        "compile" => compile(&args[1..]),
+        "package" => package(&args[1..]),
+        "export" => export(&args[1..]),
+        "lint" => lint(&args[1..]),
+        "help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        // Leave anything else to GTK's own option handling
+        _ => return None,
+    };
+
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("aynary: {:#}", e);
+            1
+        }
+    })
+}
+
+fn serve_dict(args: &[String]) -> Result<()> {
+    let mut address = String::from("127.0.0.1");
+    let mut port = dict_client::DEFAULT_PORT;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => address = args.next().context("--listen needs an address")?.clone(),
+            "--port" => {
+                let value = args.next().context("--port needs a number")?;
+                port = value
+                    .parse()
+                    .with_context(|| format!("Invalid port '{}'", value))?;
+            }
+            other => bail!("Unknown option '{}'\n\n{}", other, USAGE),
+        }
+    }
+
+    let sources = api::installed_sources();
+    let listener = TcpListener::bind((address.as_str(), port))
+        .with_context(|| format!("Failed to listen on {}:{}", address, port))?;
+    eprintln!(
+        "Serving {} dictionaries over DICT on {}",
+        sources.len(),
+        listener.local_addr()?
+    );
+
+    DictServer::new(sources).serve(listener)
+}
+
+fn import(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("kaikki") => import_kaikki(&args[1..]),
+        Some("freedict") => import_freedict(&args[1..]),
+        Some("csv") => import_csv(&args[1..]),
+        Some(other) => bail!("Unknown import format '{}'\n\n{}", other, USAGE),
+        None => bail!("Missing import format\n\n{}", USAGE),
+    }
+}
+
+fn import_kaikki(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut language = None;
+    let mut compile = false;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--lang" => language = Some(args.next().context("--lang needs a language")?.clone()),
+            "--compile" => compile = true,
+            "--output" => {
+                output = Some(PathBuf::from(args.next().context("--output needs a path")?))
+            }
+            other if other.starts_with("--") => bail!("Unknown option '{}'\n\n{}", other, USAGE),
+            other => input = Some(PathBuf::from(other)),
+        }
+    }
+    let input = input.context("Missing input file")?;
+    let language = language.context("Missing --lang")?;
+    let output = match output {
+        Some(output) => output,
+        None => import_output(&format!("kaikki-{}", language.to_lowercase()), compile)?,
+    };
+
+    write_import(&output, compile, |writer| {
+        kaikki::import(importer::open_input(&input)?, &language, writer)
+    })
+}
+
+fn import_freedict(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut languages = LanguagePair::default();
+    let mut compile = false;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => {
+                languages.source = Some(args.next().context("--from needs a language")?.clone())
+            }
+            "--to" => {
+                languages.target = Some(args.next().context("--to needs a language")?.clone())
+            }
+            "--compile" => compile = true,
+            "--output" => {
+                output = Some(PathBuf::from(args.next().context("--output needs a path")?))
+            }
+            other if other.starts_with("--") => bail!("Unknown option '{}'\n\n{}", other, USAGE),
+            other => input = Some(PathBuf::from(other)),
+        }
+    }
+    let input = input.context("Missing input file")?;
+    let from_name = LanguagePair::from_file_name(&input);
+    languages.source = languages.source.or(from_name.source);
+    languages.target = languages.target.or(from_name.target);
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let name = input
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split('.').next())
+                .unwrap_or("dictionary");
+            import_output(&format!("freedict-{}", name), compile)?
+        }
+    };
+
+    write_import(&output, compile, |writer| {
+        freedict::import(importer::open_input(&input)?, &languages, writer)
+    })
+}
+
+fn import_csv(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut compile = false;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--compile" => compile = true,
+            "--output" => {
+                output = Some(PathBuf::from(args.next().context("--output needs a path")?))
+            }
+            other if other.starts_with("--") => bail!("Unknown option '{}'\n\n{}", other, USAGE),
+            other => input = Some(PathBuf::from(other)),
+        }
+    }
+    let input = input.context("Missing input file")?;
+    let name = input
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    // "words.tsv.gz" is tab-separated; other files are sniffed from their header
+    let delimiter = [".tsv", ".tab", ".tsv.gz", ".tab.gz"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+        .then_some('\t');
+    let output = match output {
+        Some(output) => output,
+        None => import_output(name.split('.').next().unwrap_or("dictionary"), compile)?,
+    };
+
+    write_import(&output, compile, |writer| {
+        csv::import(importer::open_input(&input)?, delimiter, writer)
+    })
+}
+
+/// Where an import named `name` goes by default, with the extension of the
+/// format it is written in
+fn import_output(name: &str, compile: bool) -> Result<PathBuf> {
+    let output = importer::default_output(name)?;
+    Ok(if compile {
+        output.with_extension(compact::EXTENSION)
+    } else {
+        output
+    })
+}
+
+/// Run an importer, streaming its entries to `output` as JSON or, with
+/// `compile`, gathering them to be compiled there
+fn write_import(
+    output: &Path,
+    compile: bool,
+    import: impl FnOnce(&mut EntryWriter<&mut dyn Write>) -> Result<ImportStats>,
+) -> Result<()> {
+    let stats = if compile {
+        let mut writer = EntryWriter::compiling();
+        let stats = import(&mut writer)?;
+        let name = file_name(&output.with_extension(""));
+        writer.finish_compiled(name, importer::create_output(output)?)?;
+        stats
+    } else {
+        let mut file = importer::create_output(output)?;
+        let mut writer = EntryWriter::new(&mut file as &mut dyn Write)?;
+        let stats = import(&mut writer)?;
+        writer.finish()?;
+        stats
+    };
+
+    report_import(output, stats.entries, stats.skipped);
+    Ok(())
+}
+
+fn compile(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut title = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--title" => title = Some(args.next().context("--title needs a title")?.clone()),
+            "--output" => {
+                output = Some(PathBuf::from(args.next().context("--output needs a path")?))
+            }
+            other if other.starts_with("--") => bail!("Unknown option '{}'\n\n{}", other, USAGE),
+            other => input = Some(PathBuf::from(other)),
+        }
+    }
+    let input = input.context("Missing input file")?;
+    let entries = read_entries(&input)?;
+
+    let stem = importer::json_stem(&input);
+    let output = output.unwrap_or_else(|| stem.with_extension(compact::EXTENSION));
+    write_compiled(&entries, title.unwrap_or_else(|| file_name(&stem)), &output)?;
+    eprintln!("Wrote {} entries to {}", entries.len(), output.display());
+    Ok(())
+}
+
+/// Compile `entries` to `output`, titled `name`
+fn write_compiled(entries: &[DictionaryEntry], name: String, output: &Path) -> Result<()> {
+    let info = compact::Info {
+        name,
+        description: None,
+        language: entries.iter().find_map(|entry| entry.language.clone()),
+    };
+    compact::write(entries, &info, importer::create_output(output)?)
+}
+
+fn package(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut id = None;
+    let mut title = None;
+    let mut version = None;
+    let mut languages = Vec::new();
+    let mut license = None;
+    let mut attribution = None;
+    let mut description = None;
+    let mut compile = false;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--id" => id = Some(args.next().context("--id needs an id")?.clone()),
+            "--title" => title = Some(args.next().context("--title needs a title")?.clone()),
+            "--version" => {
+                version = Some(args.next().context("--version needs a version")?.clone())
+            }
+            "--language" => {
+                languages.push(args.next().context("--language needs a language")?.clone())
+            }
+            "--license" => {
+                license = Some(args.next().context("--license needs a license")?.clone())
+            }
+            "--attribution" => {
+                attribution = Some(args.next().context("--attribution needs a text")?.clone())
+            }
+            "--description" => {
+                description = Some(args.next().context("--description needs a text")?.clone())
+            }
+            "--compile" => compile = true,
+            "--output" => {
+                output = Some(PathBuf::from(args.next().context("--output needs a path")?))
+            }
+            other if other.starts_with("--") => bail!("Unknown option '{}'\n\n{}", other, USAGE),
+            other => input = Some(PathBuf::from(other)),
+        }
+    }
+    let input = input.context("Missing input file")?;
+    let entries = read_entries(&input)?;
+
+    // Bare JSON is the format's first version, so packaging it is the upgrade
+    let mut manifest = Manifest::for_entries(&input, &entries);
+    if let Some(id) = id {
+        // The id names the package's directory when there is no --output
+        let is_name = package::resolve(Path::new(""), &id)
+            .is_some_and(|dir| dir.components().count() == 1 && dir.file_name().is_some());
+        if !is_name || id.trim().is_empty() {
+            bail!("The id '{}' can't name a package directory", id);
+        }
+        manifest.id = id;
+    }
+    manifest.title = title.unwrap_or_else(|| manifest.id.clone());
+    manifest.version = version;
+    if !languages.is_empty() {
+        manifest.languages = languages;
+    }
+    manifest.license = license;
+    manifest.attribution = attribution;
+    manifest.description = description;
+
+    let output = match output {
+        Some(output) => output,
+        None => importer::user_dictionary_dir()?.join(&manifest.id),
+    };
+    fs::create_dir_all(&output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+
+    if compile {
+        manifest.entries = format!("entries.{}", compact::EXTENSION);
+        let info = compact::Info {
+            name: manifest.title.clone(),
+            description: manifest.description.clone(),
+            language: manifest.languages.first().cloned(),
+        };
+        compact::write(
+            &entries,
+            &info,
+            importer::create_output(&output.join(&manifest.entries))?,
+        )?;
+    } else {
+        let mut writer =
+            EntryWriter::new(importer::create_output(&output.join(&manifest.entries))?)?;
+        for entry in &entries {
+            writer.write(entry)?;
+        }
+        writer.finish()?;
+    }
+
+    // Audio and images are referred to relative to the dictionary, so they
+    // keep their paths inside the package
+    let input_dir = input.parent().unwrap_or(Path::new(""));
+    let mut copied = HashSet::new();
+    for name in entries.iter().flat_map(package::referenced_files) {
+        if copied.contains(name) {
+            continue;
+        }
+        let (Some(from), Some(to)) = (
+            package::resolve(input_dir, name),
+            package::resolve(&output, name),
+        ) else {
+            eprintln!(
+                "Not copying '{}', which is outside the dictionary's directory",
+                name
+            );
+            continue;
+        };
+        if !from.is_file() {
+            eprintln!("Missing file '{}'", from.display());
+            continue;
+        }
+        if let Some(dir) = to.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        fs::copy(&from, &to).with_context(|| format!("Failed to copy {}", from.display()))?;
+        copied.insert(name);
+    }
+
+    let mut writer = importer::create_output(&output.join(package::MANIFEST_FILE))?;
+    serde_json::to_writer_pretty(&mut writer, &manifest)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    eprintln!(
+        "Wrote {} entries and {} files to {}",
+        entries.len(),
+        copied.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn export(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("xdxf") => export_xdxf(&args[1..]),
+        Some(other) => bail!("Unknown export format '{}'\n\n{}", other, USAGE),
+        None => bail!("Missing export format\n\n{}", USAGE),
+    }
+}
+
+fn export_xdxf(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut title = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--title" => title = Some(args.next().context("--title needs a title")?.clone()),
+            "--output" => {
+                output = Some(PathBuf::from(args.next().context("--output needs a path")?))
+            }
+            other if other.starts_with("--") => bail!("Unknown option '{}'\n\n{}", other, USAGE),
+            other => input = Some(PathBuf::from(other)),
+        }
+    }
+    let input = input.context("Missing input file")?;
+    let entries = read_entries(&input)?;
+
+    let stem = importer::json_stem(&input);
+    let output = output.unwrap_or_else(|| stem.with_extension("xdxf"));
+    let info = ExportInfo {
+        title: title.unwrap_or_else(|| file_name(&stem)),
+        description: None,
+        language: entries.iter().find_map(|entry| entry.language.clone()),
+        target_language: entries
+            .iter()
+            .find_map(|entry| entry.target_language.clone()),
+    };
+
+    xdxf::export(&entries, &info, importer::create_output(&output)?)?;
+    eprintln!("Wrote {} entries to {}", entries.len(), output.display());
+    Ok(())
+}
+
+fn lint(args: &[String]) -> Result<()> {
+    let mut inputs = Vec::new();
+    let mut json = false;
+    let mut strict = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(String::as_str) {
+                Some("text") => json = false,
+                Some("json") => json = true,
+                Some(other) => bail!("Unknown format '{}'; use text or json", other),
+                None => bail!("--format needs text or json"),
+            },
+            "--strict" => strict = true,
+            other if other.starts_with("--") => bail!("Unknown option '{}'\n\n{}", other, USAGE),
+            other => inputs.push(PathBuf::from(other)),
+        }
+    }
+    if inputs.is_empty() {
+        bail!("Missing input file");
+    }
+
+    let mut reports = Vec::new();
+    for input in &inputs {
+        if input.is_dir() || package::is_manifest(input) {
+            reports.extend(lint::lint_package(input)?);
+        } else {
+            reports.push(lint::lint(input)?);
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in &reports {
+            print_lint_report(report);
+        }
+    }
+
+    let errors: usize = reports.iter().map(|report| report.errors).sum();
+    let warnings: usize = reports.iter().map(|report| report.warnings).sum();
+    if errors > 0 || (strict && warnings > 0) {
+        bail!("{} errors and {} warnings", errors, warnings);
+    }
+    Ok(())
+}
+
+/// Print problems one per line in the `file:line:column:` form editors and
+/// CI logs recognize, with a summary on stderr
+fn print_lint_report(report: &Report) {
+    for problem in &report.problems {
+        let mut context = problem.word.clone().unwrap_or_default();
+        if !problem.path.is_empty() {
+            if !context.is_empty() {
+                context.push_str(", ");
+            }
+            context.push_str(&problem.path);
+        }
+        println!(
+            "{}:{}:{}: {}[{}]: {}{}",
+            report.file,
+            problem.line,
+            problem.column,
+            problem.severity.name(),
+            problem.code,
+            problem.message,
+            if context.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", context)
+            }
+        );
+    }
+    eprintln!(
+        "{}: {} entries, {} errors, {} warnings",
+        report.file, report.entries, report.errors, report.warnings
+    );
+}
+
+fn read_entries(input: &Path) -> Result<Vec<DictionaryEntry>> {
+    serde_json::from_reader(importer::open_input(input)?)
+        .with_context(|| format!("Failed to parse {}", input.display()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn report_import(output: &Path, entries: usize, skipped: usize) {
+    eprintln!("Wrote {} entries to {}", entries, output.display());
+    if skipped > 0 {
+        eprintln!("Skipped {} malformed entries", skipped);
+    }
+}
@@ -0,0 +1,475 @@
+use crate::api::DictionaryEntry;
+use crate::compact_layout::{self, SerializedEntry, HEADER_LEN, MAGIC, RECORD_LEN, VERSION};
+use crate::normalize;
+use crate::pattern::Pattern;
+use crate::search::FullTextIndex;
+use crate::source::{self, DictionarySource, FuzzyMatch, Headwords, SourceMetadata};
+use anyhow::{ensure, Context, Result};
+use flate2::read::DeflateDecoder;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Extension of compiled dictionaries
+pub const EXTENSION: &str = "aydict";
+
+/// Dictionary details stored as JSON after the header
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Info {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+/// Write `entries` as a compiled dictionary, laid out as described at
+/// [`compact_layout::write`]
+pub fn write(entries: &[DictionaryEntry], info: &Info, out: impl Write) -> Result<()> {
+    let entries = entries.iter().map(serialize).collect::<Result<Vec<_>>>()?;
+    write_serialized(&entries, info, out)
+}
+
+/// Like [`write`], for entries an importer has already serialized
+pub fn write_serialized(entries: &[SerializedEntry], info: &Info, out: impl Write) -> Result<()> {
+    compact_layout::write(entries, &serde_json::to_vec(info)?, out)
+}
+
+/// `entry` in the form [`compact_layout::write`] takes
+pub fn serialize(entry: &DictionaryEntry) -> Result<SerializedEntry> {
+    Ok(SerializedEntry {
+        word: entry.word.clone(),
+        frequency: entry.frequency.unwrap_or(0),
+        json: serde_json::to_vec(entry)?,
+    })
+}
+
+/// The bytes of a compiled dictionary: a mapped file, or the bundled
+/// dictionary compiled into the binary
+enum Data {
+    Mapped(Mmap),
+    Static(&'static [u8]),
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Data::Mapped(map) => map,
+            Data::Static(bytes) => bytes,
+        }
+    }
+}
+
+/// A compiled dictionary, memory-mapped.
+///
+/// Opening one reads only the header, and lookups binary search the index in
+/// place, so startup doesn't depend on the dictionary's size. Entries are
+/// decompressed and parsed when a lookup returns them. Fuzzy, pattern and
+/// full-text search need indexes built from the whole dictionary, which
+/// [`DictionarySource::build_indexes`] builds ahead of their first use.
+pub struct CompactSource {
+    metadata: SourceMetadata,
+    map: Data,
+    count: usize,
+    strings_offset: usize,
+    index_offset: usize,
+    headwords: OnceLock<Headwords>,
+    definitions: OnceLock<FullTextIndex>,
+}
+
+/// One index record, see [`write`]
+struct Record<'a> {
+    key: &'a str,
+    word: &'a str,
+    blob: &'a [u8],
+    frequency: u32,
+}
+
+impl CompactSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the map is read-only. Dictionaries are replaced by writing a
+        // new file rather than modified in place, which would be undefined
+        // behaviour while mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        let id = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::from_data(id, Data::Mapped(map))
+    }
+
+    /// A compiled dictionary included in the binary, named `id`
+    pub fn from_static(id: &str, bytes: &'static [u8]) -> Result<Self> {
+        Self::from_data(id.to_string(), Data::Static(bytes))
+    }
+
+    fn from_data(id: String, map: Data) -> Result<Self> {
+        ensure!(
+            map.len() >= HEADER_LEN && &map[..8] == MAGIC,
+            "Not a compiled Aynary dictionary"
+        );
+        let version = read_u32(&map, 8);
+        ensure!(
+            version == VERSION,
+            "Unsupported compiled dictionary version {}",
+            version
+        );
+        let count = read_u32(&map, 12) as usize;
+        let info_len = read_u64(&map, 16) as usize;
+        let strings_offset = read_u64(&map, 24) as usize;
+        let index_offset = read_u64(&map, 32) as usize;
+        let index_end = count
+            .checked_mul(RECORD_LEN)
+            .and_then(|len| len.checked_add(index_offset));
+        ensure!(
+            info_len <= strings_offset.saturating_sub(HEADER_LEN)
+                && strings_offset <= index_offset
+                && index_end.is_some_and(|end| end <= map.len()),
+            "Compiled dictionary is truncated"
+        );
+
+        let info: Info = serde_json::from_slice(&map[HEADER_LEN..HEADER_LEN + info_len])
+            .context("Invalid dictionary info")?;
+        let name = if info.name.is_empty() {
+            id.clone()
+        } else {
+            info.name
+        };
+        let mut metadata = SourceMetadata::new(id, name);
+        metadata.description = info.description;
+        metadata.language = info.language;
+        metadata.entry_count = Some(count);
+
+        Ok(Self {
+            metadata,
+            map,
+            count,
+            strings_offset,
+            index_offset,
+            headwords: OnceLock::new(),
+            definitions: OnceLock::new(),
+        })
+    }
+
+    /// Index record `i`. Records are checked as they are read rather than
+    /// all at once, which would mean touching the whole file on startup.
+    fn record(&self, i: usize) -> Option<Record<'_>> {
+        let record = self.read_record(i);
+        if let Err(e) = &record {
+            eprintln!(
+                "Corrupt index record {} in {}: {:#}",
+                i, self.metadata.id, e
+            );
+        }
+        record.ok()
+    }
+
+    fn read_record(&self, i: usize) -> Result<Record<'_>> {
+        let raw = &self.map[self.index_offset + i * RECORD_LEN..][..RECORD_LEN];
+        let string_start = self.strings_offset + read_u32(raw, 0) as usize;
+        let key_len = u16::from_le_bytes([raw[4], raw[5]]) as usize;
+        let word_len = u16::from_le_bytes([raw[6], raw[7]]) as usize;
+        let blob_start = read_u64(raw, 8) as usize;
+        let blob_len = read_u32(raw, 16) as usize;
+
+        // The ends are checked against the sections they belong in, which
+        // opening checked against the file
+        let strings = string_start
+            .checked_add(key_len + word_len)
+            .filter(|&end| end <= self.index_offset)
+            .and_then(|end| self.map.get(string_start..end))
+            .context("Headword out of range")?;
+        let blob = blob_start
+            .checked_add(blob_len)
+            .filter(|&end| end <= self.strings_offset)
+            .and_then(|end| self.map.get(blob_start..end))
+            .context("Entry out of range")?;
+        Ok(Record {
+            key: std::str::from_utf8(&strings[..key_len])?,
+            word: std::str::from_utf8(&strings[key_len..])?,
+            blob,
+            frequency: read_u32(raw, 20),
+        })
+    }
+
+    /// First record whose key is not less than `key`
+    fn lower_bound(&self, key: &str) -> usize {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.record(middle) {
+                Some(record) if record.key < key => low = middle + 1,
+                _ => high = middle,
+            }
+        }
+        low
+    }
+
+    fn entry(&self, i: usize) -> Option<DictionaryEntry> {
+        let record = self.record(i)?;
+        let mut json = Vec::new();
+        let decoded = DeflateDecoder::new(record.blob)
+            .read_to_end(&mut json)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(serde_json::from_slice(&json)?));
+        match decoded {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("Skipping corrupt entry for {}: {:#}", record.word, e);
+                None
+            }
+        }
+    }
+
+    fn entries(&self, ids: impl IntoIterator<Item = usize>) -> Vec<DictionaryEntry> {
+        ids.into_iter().filter_map(|i| self.entry(i)).collect()
+    }
+
+    /// Ids of the records whose key starts with (or, unless `prefix`, equals)
+    /// the loose form of `folded`, checked against its accents when `strict`
+    fn matching(
+        &self,
+        folded: &str,
+        strict: bool,
+        prefix: bool,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let key = normalize::strip_diacritics(folded);
+        let folded = folded.to_string();
+        (self.lower_bound(&key)..self.count)
+            .map_while(|i| Some((i, self.record(i)?)))
+            .take_while(move |(_, record)| {
+                if prefix {
+                    record.key.starts_with(key.as_str())
+                } else {
+                    record.key == key
+                }
+            })
+            .filter(move |(_, record)| {
+                if !strict {
+                    return true;
+                }
+                let word = normalize::fold(record.word);
+                if prefix {
+                    word.starts_with(folded.as_str())
+                } else {
+                    word == folded
+                }
+            })
+            .map(|(i, _)| i)
+    }
+
+    /// The full-text index, built from every entry on first use
+    fn definition_index(&self) -> &FullTextIndex {
+        self.definitions.get_or_init(|| {
+            FullTextIndex::build((0..self.count).map(|i| {
+                self.entry(i)
+                    .map(|entry| source::definition_text(&entry))
+                    .unwrap_or_default()
+            }))
+        })
+    }
+
+    /// Indexes for fuzzy and pattern search, built from the headwords on first use
+    fn headword_index(&self) -> &Headwords {
+        self.headwords.get_or_init(|| {
+            Headwords::new(
+                (0..self.count)
+                    .map(|i| {
+                        self.record(i)
+                            .map_or_else(String::new, |record| record.word.to_string())
+                    })
+                    .collect(),
+            )
+        })
+    }
+}
+
+impl DictionarySource for CompactSource {
+    fn metadata(&self) -> &SourceMetadata {
+        &self.metadata
+    }
+
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        self.entries(self.matching(folded, strict, false))
+    }
+
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        self.entries(self.matching(folded, strict, true).take(limit))
+    }
+
+    fn prefix_headwords(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.matching(folded, strict, true)
+            .take(limit)
+            .filter_map(|i| Some(self.record(i)?.word.to_string()))
+            .collect()
+    }
+
+    fn fuzzy(&self, loose: &str, max_distance: usize) -> Vec<FuzzyMatch> {
+        let headwords = self.headword_index();
+        headwords
+            .fuzzy(loose, max_distance)
+            .into_iter()
+            .map(|(ids, distance)| FuzzyMatch {
+                word: headwords.word(ids[0]).to_string(),
+                distance,
+                frequency: ids
+                    .iter()
+                    .filter_map(|&id| Some(self.record(id)?.frequency))
+                    .max()
+                    .unwrap_or(0),
+            })
+            .collect()
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<(DictionaryEntry, f32)> {
+        self.definition_index()
+            .search(query, limit)
+            .into_iter()
+            .filter_map(|(id, score)| Some((self.entry(id)?, score)))
+            .collect()
+    }
+
+    fn pattern(&self, pattern: &Pattern, limit: usize) -> Vec<String> {
+        self.headword_index().pattern(pattern, limit)
+    }
+
+    fn headwords(&self) -> Vec<&str> {
+        (0..self.count)
+            .filter_map(|i| Some(self.record(i)?.word))
+            .collect()
+    }
+
+    fn build_indexes(&self) {
+        // Suggestions are wanted on every miss, full-text search only on request
+        self.headword_index();
+        self.definition_index();
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{Definition, Meaning};
+    use crate::import::EntryWriter;
+
+    fn entry(word: &str, definition: &str) -> DictionaryEntry {
+        DictionaryEntry {
+            word: word.to_string(),
+            meanings: vec![Meaning {
+                part_of_speech: String::from("noun"),
+                definitions: vec![Definition {
+                    definition: definition.to_string(),
+                    ..Definition::default()
+                }],
+                ..Meaning::default()
+            }],
+            ..DictionaryEntry::default()
+        }
+    }
+
+    fn entries() -> [DictionaryEntry; 3] {
+        [
+            entry("Éclair", "a pastry filled with cream"),
+            entry("cat", "a small domesticated carnivore"),
+            entry("catalog", "a list of items"),
+        ]
+    }
+
+    fn compiled() -> Vec<u8> {
+        let info = Info {
+            name: String::from("Test"),
+            ..Info::default()
+        };
+        let mut bytes = Vec::new();
+        write(&entries(), &info, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn open_bytes(bytes: Vec<u8>) -> Result<CompactSource> {
+        CompactSource::from_static("test", Box::leak(bytes.into_boxed_slice()))
+    }
+
+    #[test]
+    fn imports_compile_to_the_same_bytes() {
+        let mut writer = EntryWriter::<Vec<u8>>::compiling();
+        for entry in entries() {
+            writer.write(&entry).unwrap();
+        }
+        let mut bytes = Vec::new();
+        let count = writer
+            .finish_compiled(String::from("Test"), &mut bytes)
+            .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(bytes, compiled());
+    }
+
+    #[test]
+    fn looks_up_compiled_entries() {
+        let source = open_bytes(compiled()).unwrap();
+        assert_eq!(source.metadata().name, "Test");
+        assert_eq!(source.metadata().entry_count, Some(3));
+        assert_eq!(source.lookup("eclair", false)[0].word, "Éclair");
+        assert!(source.lookup("eclair", true).is_empty());
+        assert_eq!(
+            source.prefix_headwords("cat", false, 10),
+            ["cat", "catalog"]
+        );
+
+        source.build_indexes();
+        assert_eq!(source.fuzzy("cot", 1)[0].word, "cat");
+        assert_eq!(source.search("pastry", 5)[0].0.word, "Éclair");
+    }
+
+    #[test]
+    fn rejects_truncated_and_foreign_files() {
+        let bytes = compiled();
+        assert!(open_bytes(bytes[..HEADER_LEN - 1].to_vec()).is_err());
+        assert!(open_bytes(bytes[..bytes.len() - 1].to_vec()).is_err());
+        let mut foreign = bytes.clone();
+        foreign[0] = b'X';
+        assert!(open_bytes(foreign).is_err());
+        let mut newer = bytes;
+        newer[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(open_bytes(newer).is_err());
+    }
+
+    #[test]
+    fn skips_records_pointing_outside_their_section() {
+        let mut bytes = compiled();
+        let index_offset = read_u64(&bytes, 32) as usize;
+        // First record ("cat"): a blob offset that overflows when its length
+        // is added, then a headword offset past the end
+        bytes[index_offset + 8..index_offset + 16].copy_from_slice(&u64::MAX.to_le_bytes());
+        let second = index_offset + RECORD_LEN;
+        bytes[second..second + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let source = open_bytes(bytes).unwrap();
+
+        assert!(source.lookup("cat", false).is_empty());
+        assert_eq!(source.headwords(), ["Éclair"]);
+    }
+
+    #[test]
+    fn bundled_dictionary_decodes() {
+        let bundled = include_bytes!(concat!(env!("OUT_DIR"), "/dictionary.aydict"));
+        let source = CompactSource::from_static("bundled", bundled).unwrap();
+        assert!(source.count > 0);
+        for i in 0..source.count {
+            assert!(source.entry(i).is_some(), "entry {} doesn't decode", i);
+        }
+    }
+}
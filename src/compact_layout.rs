@@ -0,0 +1,94 @@
+use crate::normalize;
+use anyhow::{ensure, Result};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+pub const MAGIC: &[u8; 8] = b"AYDICT\0\0";
+pub const VERSION: u32 = 1;
+pub const HEADER_LEN: usize = 40;
+pub const RECORD_LEN: usize = 24;
+
+/// An entry ready to be written: its headword and frequency for the index,
+/// and the entry itself as JSON
+pub struct SerializedEntry {
+    pub word: String,
+    pub frequency: u32,
+    pub json: Vec<u8>,
+}
+
+/// Write a compiled dictionary. Kept apart from the reader, with nothing but
+/// [`normalize`] to depend on, so the build script can compile the bundled
+/// dictionary with it too.
+///
+/// The file starts with a header (magic, version, entry count and the offsets
+/// of the sections that follow), then `info_json`, each entry as a
+/// deflate-compressed JSON blob, the headwords, and finally one fixed-size
+/// index record per entry sorted by accent- and case-insensitive key:
+///
+/// | bytes | field                                         |
+/// |-------|-----------------------------------------------|
+/// | 4     | offset of the key in the headword section     |
+/// | 2     | key length; the headword follows the key      |
+/// | 2     | headword length                               |
+/// | 8     | offset of the entry blob                      |
+/// | 4     | blob length                                   |
+/// | 4     | frequency, 0 when unknown                     |
+///
+/// All numbers are little-endian.
+pub fn write(entries: &[SerializedEntry], info_json: &[u8], mut out: impl Write) -> Result<()> {
+    let mut order: Vec<(String, usize)> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (normalize::loose(&entry.word), i))
+        .collect();
+    order.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then(entries[a.1].word.cmp(&entries[b.1].word))
+    });
+
+    let blobs_offset = (HEADER_LEN + info_json.len()) as u64;
+
+    let mut blobs = Vec::new();
+    let mut strings = Vec::new();
+    let mut records = Vec::with_capacity(order.len() * RECORD_LEN);
+    for (key, i) in &order {
+        let entry = &entries[*i];
+        let blob_start = blobs.len();
+        let mut encoder = DeflateEncoder::new(&mut blobs, Compression::default());
+        encoder.write_all(&entry.json)?;
+        encoder.finish()?;
+
+        let string_start = strings.len();
+        strings.extend_from_slice(key.as_bytes());
+        strings.extend_from_slice(entry.word.as_bytes());
+
+        ensure!(
+            key.len() <= u16::MAX as usize && entry.word.len() <= u16::MAX as usize,
+            "Headword too long: {}",
+            entry.word
+        );
+        records.extend_from_slice(&u32::try_from(string_start)?.to_le_bytes());
+        records.extend_from_slice(&(key.len() as u16).to_le_bytes());
+        records.extend_from_slice(&(entry.word.len() as u16).to_le_bytes());
+        records.extend_from_slice(&(blobs_offset + blob_start as u64).to_le_bytes());
+        records.extend_from_slice(&u32::try_from(blobs.len() - blob_start)?.to_le_bytes());
+        records.extend_from_slice(&entry.frequency.to_le_bytes());
+    }
+
+    let strings_offset = blobs_offset + blobs.len() as u64;
+    let index_offset = strings_offset + strings.len() as u64;
+
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&u32::try_from(entries.len())?.to_le_bytes())?;
+    out.write_all(&(info_json.len() as u64).to_le_bytes())?;
+    out.write_all(&strings_offset.to_le_bytes())?;
+    out.write_all(&index_offset.to_le_bytes())?;
+    out.write_all(info_json)?;
+    out.write_all(&blobs)?;
+    out.write_all(&strings)?;
+    out.write_all(&records)?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,499 @@
+use crate::api::{Definition, DictionaryEntry, Meaning, Phonetic};
+use crate::import::{EntryWriter, ImportStats};
+use anyhow::{bail, Result};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{BufRead, Write};
+
+/// Malformed rows reported individually before only being counted
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Separators accepted between the words of a synonyms or antonyms cell
+const LIST_SEPARATORS: &[char] = &[';', '|', ','];
+
+/// A column the importer understands, with the header names it goes by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Word,
+    Definition,
+    PartOfSpeech,
+    Example,
+    Synonyms,
+    Antonyms,
+    Phonetic,
+}
+
+const COLUMN_NAMES: &[(Column, &[&str])] = &[
+    (Column::Word, &["word", "headword", "term", "lemma"]),
+    (
+        Column::Definition,
+        &["definition", "meaning", "gloss", "translation"],
+    ),
+    (
+        Column::PartOfSpeech,
+        &["pos", "part of speech", "partofspeech", "part_of_speech"],
+    ),
+    (Column::Example, &["example", "examples"]),
+    (Column::Synonyms, &["synonyms", "synonym"]),
+    (Column::Antonyms, &["antonyms", "antonym"]),
+    (Column::Phonetic, &["phonetic", "pronunciation", "ipa"]),
+];
+
+/// Convert a CSV or TSV word list with a header row naming its columns.
+///
+/// `word` and `definition` columns are required; `pos`, `example`, `synonyms`,
+/// `antonyms` and `phonetic` are optional and other columns are ignored. Each
+/// headword becomes one entry however many rows it has, with a meaning per
+/// part of speech holding the definitions in file order. The delimiter is
+/// guessed from the header unless given.
+pub fn import<W: Write>(
+    input: impl BufRead,
+    delimiter: Option<char>,
+    output: &mut EntryWriter<W>,
+) -> Result<ImportStats> {
+    let mut stats = ImportStats::default();
+    let mut reader = RecordReader::new(input, delimiter);
+
+    let Some((_, header)) = reader.next_record()? else {
+        bail!("The file is empty");
+    };
+    let columns: Vec<Option<Column>> = header.iter().map(|name| column(name)).collect();
+    for (name, column) in header.iter().zip(&columns) {
+        if column.is_none() && !name.trim().is_empty() {
+            eprintln!("Ignoring unknown column '{}'", name.trim());
+        }
+    }
+    if !columns.contains(&Some(Column::Word)) {
+        bail!("No 'word' column in the header");
+    }
+    if !columns.contains(&Some(Column::Definition)) {
+        bail!("No 'definition' column in the header");
+    }
+
+    // Rows for the same word needn't be next to each other, so entries are
+    // kept until the end
+    let mut entries: Vec<DictionaryEntry> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    loop {
+        let (line, fields) = match reader.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            // Reading goes on after the record; anything else is fatal
+            Err(e) if e.is::<InvalidRecord>() => {
+                stats.skipped += 1;
+                if stats.skipped <= MAX_REPORTED_ERRORS {
+                    eprintln!("{}", e);
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if fields.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        stats.records += 1;
+
+        let row = match Row::parse(&columns, &fields) {
+            Ok(row) => row,
+            Err(e) => {
+                stats.skipped += 1;
+                if stats.skipped <= MAX_REPORTED_ERRORS {
+                    eprintln!("Line {}: {}", line, e);
+                }
+                continue;
+            }
+        };
+
+        let position = *positions.entry(row.word.clone()).or_insert_with(|| {
+            entries.push(DictionaryEntry {
+                word: row.word.clone(),
+                ..DictionaryEntry::default()
+            });
+            entries.len() - 1
+        });
+        add_row(&mut entries[position], row);
+    }
+
+    for entry in &entries {
+        output.write(entry)?;
+    }
+    stats.entries = output.count();
+    Ok(stats)
+}
+
+/// The values of one row, trimmed
+#[derive(Debug, Default)]
+struct Row {
+    word: String,
+    definition: String,
+    part_of_speech: String,
+    example: Option<String>,
+    synonyms: Vec<String>,
+    antonyms: Vec<String>,
+    phonetic: Option<String>,
+}
+
+impl Row {
+    fn parse(columns: &[Option<Column>], fields: &[String]) -> Result<Self, String> {
+        if fields.len() > columns.len() {
+            return Err(format!(
+                "{} fields, but the header has {} columns",
+                fields.len(),
+                columns.len()
+            ));
+        }
+
+        // Missing trailing fields are empty, as spreadsheets often leave them out
+        let mut row = Row::default();
+        for (column, field) in columns.iter().zip(fields) {
+            let value = field.trim();
+            let optional = (!value.is_empty()).then(|| value.to_string());
+            match column {
+                Some(Column::Word) => row.word = value.to_string(),
+                Some(Column::Definition) => row.definition = value.to_string(),
+                Some(Column::PartOfSpeech) => row.part_of_speech = part_of_speech(value),
+                Some(Column::Example) => row.example = optional,
+                Some(Column::Synonyms) => row.synonyms = list(value),
+                Some(Column::Antonyms) => row.antonyms = list(value),
+                Some(Column::Phonetic) => row.phonetic = optional,
+                None => {}
+            }
+        }
+
+        if row.word.is_empty() {
+            return Err(String::from("Empty word"));
+        }
+        if row.definition.is_empty() {
+            return Err(format!("No definition for '{}'", row.word));
+        }
+        Ok(row)
+    }
+}
+
+/// Add a row's definition to the meaning for its part of speech, unless the
+/// same definition is already there
+fn add_row(entry: &mut DictionaryEntry, row: Row) {
+    if let Some(phonetic) = row.phonetic {
+        if !entry
+            .phonetics
+            .iter()
+            .any(|known| known.text.as_deref() == Some(phonetic.as_str()))
+        {
+            entry.phonetic.get_or_insert_with(|| phonetic.clone());
+            entry.phonetics.push(Phonetic {
+                text: Some(phonetic),
+                ..Phonetic::default()
+            });
+        }
+    }
+
+    let index = match entry
+        .meanings
+        .iter()
+        .position(|meaning| meaning.part_of_speech == row.part_of_speech)
+    {
+        Some(index) => index,
+        None => {
+            entry.meanings.push(Meaning {
+                part_of_speech: row.part_of_speech,
+                ..Meaning::default()
+            });
+            entry.meanings.len() - 1
+        }
+    };
+    let meaning = &mut entry.meanings[index];
+
+    match meaning
+        .definitions
+        .iter_mut()
+        .find(|definition| definition.definition == row.definition)
+    {
+        Some(definition) => {
+            if definition.example.is_none() {
+                definition.example = row.example;
+            }
+            extend_unique(&mut definition.synonyms, row.synonyms);
+            extend_unique(&mut definition.antonyms, row.antonyms);
+        }
+        None => meaning.definitions.push(Definition {
+            definition: row.definition,
+            synonyms: row.synonyms,
+            antonyms: row.antonyms,
+            example: row.example,
+            ..Definition::default()
+        }),
+    }
+}
+
+fn extend_unique(words: &mut Vec<String>, more: Vec<String>) {
+    for word in more {
+        if !words.contains(&word) {
+            words.push(word);
+        }
+    }
+}
+
+fn column(name: &str) -> Option<Column> {
+    let name = name.trim().to_lowercase();
+    COLUMN_NAMES
+        .iter()
+        .find(|(_, names)| names.contains(&name.as_str()))
+        .map(|(column, _)| *column)
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(LIST_SEPARATORS)
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Common abbreviations ("n.", "adj") spelled out like the bundled data
+fn part_of_speech(pos: &str) -> String {
+    let pos = pos.trim_end_matches('.').to_lowercase();
+    let full = match pos.as_str() {
+        "n" => "noun",
+        "v" | "vb" => "verb",
+        "adj" | "a" => "adjective",
+        "adv" => "adverb",
+        "prep" => "preposition",
+        "conj" => "conjunction",
+        "pron" => "pronoun",
+        "interj" | "intj" => "interjection",
+        "det" => "determiner",
+        "num" => "numeral",
+        "abbr" | "abbrev" => "abbreviation",
+        _ => return pos,
+    };
+    full.to_string()
+}
+
+/// A record that couldn't be read, such as one with invalid UTF-8. Reading
+/// can carry on with the line after it.
+#[derive(Debug)]
+struct InvalidRecord {
+    line: usize,
+    reason: String,
+}
+
+impl fmt::Display for InvalidRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidRecord {}
+
+/// Splits CSV text into records: fields separated by the delimiter, optionally
+/// in double quotes, in which case they may contain delimiters, line breaks
+/// and doubled quotes (RFC 4180)
+struct RecordReader<R> {
+    input: R,
+    delimiter: Option<char>,
+    /// Lines read so far
+    line: usize,
+    /// Lines given back after an unterminated quoted field, to be read again
+    pending: VecDeque<String>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    fn new(input: R, delimiter: Option<char>) -> Self {
+        Self {
+            input,
+            delimiter,
+            line: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The next record and the line it starts on. A record that can't be
+    /// read is an [`InvalidRecord`] error, after which the next call goes on
+    /// with the following line.
+    fn next_record(&mut self) -> Result<Option<(usize, Vec<String>)>> {
+        let mut text = String::new();
+        if !self.read_line(&mut text)? {
+            return Ok(None);
+        }
+        let start = self.line;
+        if start == 1 {
+            if let Some(stripped) = text.strip_prefix('\u{feff}') {
+                text = stripped.to_string();
+            }
+        }
+        // Tab-separated files have tabs in their header; anything else is
+        // taken for comma-separated unless the caller said otherwise
+        let delimiter = *self
+            .delimiter
+            .get_or_insert(if text.contains('\t') { '\t' } else { ',' });
+
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        // Where each line after the first starts in `text`
+        let mut line_starts = Vec::new();
+        loop {
+            let offset = line_starts.last().copied().unwrap_or(0);
+            let mut chars = text[offset..].chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' if in_quotes => {
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                            field.push('"');
+                        } else {
+                            in_quotes = false;
+                        }
+                    }
+                    '"' if field.is_empty() && !quoted => {
+                        quoted = true;
+                        in_quotes = true;
+                    }
+                    c if c == delimiter && !in_quotes => {
+                        fields.push(std::mem::take(&mut field));
+                        quoted = false;
+                    }
+                    c => field.push(c),
+                }
+            }
+            if !in_quotes {
+                break;
+            }
+            // A quoted field goes on to the next line
+            field.push('\n');
+            line_starts.push(text.len());
+            if !self.read_line(&mut text)? {
+                // The quote was never closed, so read the lines after the
+                // first again as records of their own
+                line_starts.pop();
+                let mut ends = line_starts.iter().skip(1).copied().collect::<Vec<_>>();
+                ends.push(text.len());
+                for (&from, &to) in line_starts.iter().zip(&ends).rev() {
+                    self.pending.push_front(text[from..to].to_string());
+                }
+                self.line = start;
+                return Err(InvalidRecord {
+                    line: start,
+                    reason: String::from("Unterminated quoted field"),
+                }
+                .into());
+            }
+        }
+        fields.push(field);
+        Ok(Some((start, fields)))
+    }
+
+    /// Append the next line to `text` without its line ending; false at the end
+    fn read_line(&mut self, text: &mut String) -> Result<bool> {
+        let line = match self.pending.pop_front() {
+            None => {
+                let mut bytes = Vec::new();
+                if self.input.read_until(b'\n', &mut bytes)? == 0 {
+                    return Ok(false);
+                }
+                while let Some(b'\n' | b'\r') = bytes.last() {
+                    bytes.pop();
+                }
+                self.line += 1;
+                match String::from_utf8(bytes) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        return Err(InvalidRecord {
+                            line: self.line,
+                            reason: format!(
+                                "Invalid UTF-8 at byte {}",
+                                e.utf8_error().valid_up_to() + 1
+                            ),
+                        }
+                        .into())
+                    }
+                }
+            }
+            Some(line) => {
+                self.line += 1;
+                line
+            }
+        };
+        text.push_str(&line);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The entries imported from `input`, and the import's counts
+    fn import_bytes(input: &[u8]) -> (Vec<DictionaryEntry>, ImportStats) {
+        let mut json = Vec::new();
+        let mut writer = EntryWriter::new(&mut json).unwrap();
+        let stats = import(input, None, &mut writer).unwrap();
+        writer.finish().unwrap();
+        (serde_json::from_slice(&json).unwrap(), stats)
+    }
+
+    fn words(entries: &[DictionaryEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.word.as_str()).collect()
+    }
+
+    #[test]
+    fn merges_rows_by_word_and_part_of_speech() {
+        let input = "word,pos,definition,synonyms\n\
+                     run,v.,\"to move fast, on foot\",sprint;dash\n\
+                     run,n,a spell of running,\n\
+                     run,v,\"to move fast, on foot\",jog\n";
+        let (entries, stats) = import_bytes(input.as_bytes());
+        assert_eq!((stats.records, stats.skipped), (3, 0));
+        assert_eq!(entries.len(), 1);
+        let meanings = &entries[0].meanings;
+        assert_eq!(meanings[0].part_of_speech, "verb");
+        assert_eq!(
+            meanings[0].definitions[0].synonyms,
+            ["sprint", "dash", "jog"]
+        );
+        assert_eq!(meanings[1].part_of_speech, "noun");
+    }
+
+    #[test]
+    fn skips_invalid_utf8_and_carries_on() {
+        let input = b"word\tdefinition\ncat\ta small animal\nbad\t\xff\xfe\ndog\ta loyal animal\n";
+        let (entries, stats) = import_bytes(input);
+        assert_eq!(words(&entries), ["cat", "dog"]);
+        assert_eq!(stats.skipped, 1);
+
+        let mut reader = RecordReader::new(&input[..], None);
+        reader.next_record().unwrap();
+        reader.next_record().unwrap();
+        let error = reader.next_record().unwrap_err();
+        assert_eq!(error.to_string(), "Line 3: Invalid UTF-8 at byte 5");
+        assert_eq!(reader.next_record().unwrap().unwrap().0, 4);
+    }
+
+    #[test]
+    fn rereads_the_lines_after_an_unterminated_quote() {
+        let input = "word,definition\n\
+                     cat,\"a small animal\n\
+                     dog,a loyal animal\n\
+                     fish,lives in water\n";
+        let (entries, stats) = import_bytes(input.as_bytes());
+        assert_eq!(words(&entries), ["dog", "fish"]);
+        assert_eq!(stats.skipped, 1);
+    }
+
+    #[test]
+    fn reports_rows_with_the_line_they_start_on() {
+        let mut reader = RecordReader::new(&b"a,b\n\"x\ny\",z\n,\n\"open"[..], None);
+        assert_eq!(reader.next_record().unwrap().unwrap().0, 1);
+        assert_eq!(
+            reader.next_record().unwrap().unwrap(),
+            (2, vec!["x\ny".into(), "z".into()])
+        );
+        assert_eq!(reader.next_record().unwrap().unwrap().0, 4);
+        let error = reader.next_record().unwrap_err();
+        assert_eq!(error.to_string(), "Line 5: Unterminated quoted field");
+        assert!(reader.next_record().unwrap().is_none());
+    }
+}
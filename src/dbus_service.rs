@@ -1,6 +1,10 @@
+use crate::api::{self, DictionaryClient, LookupError, LookupResult};
 use dbus::blocking::Connection;
-use dbus::channel::Sender as DbusSender;
+use dbus::channel::{MatchingReceiver, Sender as DbusSender};
+use dbus::message::MatchRule;
+use dbus::strings::ErrorName;
 use dbus::Message;
+use std::ffi::CString;
 use std::sync::mpsc::Sender;
 use std::time::Duration;
 
@@ -8,46 +12,226 @@ const DBUS_SERVICE_NAME: &str = "com.aynary.Dictionary";
 const DBUS_OBJECT_PATH: &str = "/com/aynary/Dictionary";
 const DBUS_INTERFACE: &str = "com.aynary.Dictionary";
 
-#[derive(Clone)]
+// D-Bus error names for each `LookupError`, so callers can tell them apart
+// without matching on the message text
+pub const ERROR_EMPTY_QUERY: &str = "com.aynary.Dictionary.Error.EmptyQuery";
+pub const ERROR_NOT_FOUND: &str = "com.aynary.Dictionary.Error.NotFound";
+pub const ERROR_INVALID_PATTERN: &str = "com.aynary.Dictionary.Error.InvalidPattern";
+const ERROR_UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+const ERROR_UNKNOWN_OBJECT: &str = "org.freedesktop.DBus.Error.UnknownObject";
+const ERROR_INVALID_ARGS: &str = "org.freedesktop.DBus.Error.InvalidArgs";
+const ERROR_FAILED: &str = "org.freedesktop.DBus.Error.Failed";
+
+// Standard interfaces every object answers, so busctl and gdbus can inspect us
+const INTROSPECTABLE_INTERFACE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER_INTERFACE: &str = "org.freedesktop.DBus.Peer";
+
+const INTROSPECTION_HEADER: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+"#;
+
+const STANDARD_INTERFACES_XML: &str = r#"  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml_data" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+    <method name="GetMachineId">
+      <arg name="machine_uuid" type="s" direction="out"/>
+    </method>
+  </interface>
+"#;
+
+const DICTIONARY_INTERFACE_XML: &str = r#"  <interface name="com.aynary.Dictionary">
+    <method name="LookupWord">
+      <arg name="word" type="s" direction="in"/>
+      <arg name="definition" type="s" direction="out"/>
+    </method>
+    <method name="LookupAndShow">
+      <arg name="word" type="s" direction="in"/>
+    </method>
+    <method name="ShowWindow"/>
+    <method name="GetLoadStatus">
+      <arg name="status" type="s" direction="out"/>
+    </method>
+  </interface>
+"#;
+
+/// Where the D-Bus machine id is kept, newest location first
+const MACHINE_ID_FILES: &[&str] = &["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Work for the main thread. Lookups carry the service's result, so the
+/// window shows it without looking the word up again.
 pub enum DbusCommand {
-    LookupWord(String),
+    LookupWord(Result<LookupResult, LookupError>),
     ShowWindow,
-    LookupAndShow(String),
+    LookupAndShow(Result<LookupResult, LookupError>),
 }
 
 pub struct DictionaryService {
     sender: Sender<DbusCommand>,
+    client: DictionaryClient,
 }
 
 impl DictionaryService {
-    pub fn new(sender: Sender<DbusCommand>) -> Self {
-        Self { sender }
+    pub fn new(sender: Sender<DbusCommand>, client: DictionaryClient) -> Self {
+        Self { sender, client }
     }
 
-    pub fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn start(self) -> Result<(), Box<dyn std::error::Error>> {
         let conn = Connection::new_session()?;
 
         // Request the service name
         conn.request_name(DBUS_SERVICE_NAME, false, true, false)?;
 
-        // Simple message handling loop
-        // Note: This is a simplified implementation. For production use,
-        // consider using dbus-tokio for async or dbus::tree for structured message handling
+        // Method calls addressed to us are delivered without an explicit match
+        conn.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(move |msg, conn| {
+                if let Some(reply) = self.handle_method_call(&msg) {
+                    let _ = conn.send(reply);
+                }
+                true
+            }),
+        );
+
         loop {
             conn.process(Duration::from_millis(1000))?;
-            // The DBus service will handle messages via the system's message bus
-            // In a full implementation, you'd register object paths and methods
-            // For now, we'll use the helper functions that call this service
-            std::thread::sleep(Duration::from_millis(100));
         }
     }
-    
-    // Placeholder - actual message handling would be done via registered methods
-    // For now, external callers use the helper functions that send messages directly
 
-    // Message handling removed - simplified implementation
-    // The DBus service loop keeps the service registered
-    // Actual method calls are handled via the helper functions that use the proxy API
+    /// Build the reply to a method call, or `None` for calls without a
+    /// method name, which are malformed
+    fn handle_method_call(&self, msg: &Message) -> Option<Message> {
+        let path = msg.path()?;
+        let member = msg.member()?;
+        // The interface is optional in a method call; the method names don't clash
+        let interface = msg.interface();
+        let interface = interface.as_deref();
+
+        let reply = match (interface, &*member) {
+            (None | Some(PEER_INTERFACE), "Ping") => msg.method_return(),
+            (None | Some(PEER_INTERFACE), "GetMachineId") => match machine_id() {
+                Some(id) => msg.method_return().append1(id),
+                None => error_reply(msg, ERROR_FAILED, "No machine id"),
+            },
+            (None | Some(INTROSPECTABLE_INTERFACE), "Introspect") => match introspect(&path) {
+                Some(xml) => msg.method_return().append1(xml),
+                None => unknown_object_reply(msg, &path),
+            },
+            _ if &*path != DBUS_OBJECT_PATH => unknown_object_reply(msg, &path),
+            (None | Some(DBUS_INTERFACE), "LookupWord") => match msg.read1::<&str>() {
+                Ok(word) => {
+                    let result = self.client.lookup(word);
+                    let reply = match &result {
+                        Ok(result) => msg
+                            .method_return()
+                            .append1(self.client.format_result(result)),
+                        Err(e) => lookup_error_reply(msg, e),
+                    };
+                    let _ = self.sender.send(DbusCommand::LookupWord(result));
+                    reply
+                }
+                Err(e) => error_reply(msg, ERROR_INVALID_ARGS, &e.to_string()),
+            },
+            (None | Some(DBUS_INTERFACE), "LookupAndShow") => match msg.read1::<&str>() {
+                // The window is shown either way, with suggestions on a miss;
+                // the error only tells the caller that nothing matched exactly
+                Ok(word) => {
+                    let result = self.client.lookup(word);
+                    let reply = match &result {
+                        Ok(_) => msg.method_return(),
+                        Err(e) => lookup_error_reply(msg, e),
+                    };
+                    let _ = self.sender.send(DbusCommand::LookupAndShow(result));
+                    reply
+                }
+                Err(e) => error_reply(msg, ERROR_INVALID_ARGS, &e.to_string()),
+            },
+            (None | Some(DBUS_INTERFACE), "ShowWindow") => {
+                let _ = self.sender.send(DbusCommand::ShowWindow);
+                msg.method_return()
+            }
+            (None | Some(DBUS_INTERFACE), "GetLoadStatus") => {
+                msg.method_return().append1(load_status())
+            }
+            (_, other) => error_reply(msg, ERROR_UNKNOWN_METHOD, &format!("No method '{}'", other)),
+        };
+
+        Some(reply)
+    }
+}
+
+/// Introspection data for `path`: our object, or a node on the way to it
+fn introspect(path: &str) -> Option<String> {
+    let mut xml = String::from(INTROSPECTION_HEADER);
+    xml.push_str("<node>\n");
+    if path == DBUS_OBJECT_PATH {
+        xml.push_str(DICTIONARY_INTERFACE_XML);
+        xml.push_str(STANDARD_INTERFACES_XML);
+    } else {
+        let prefix = if path == "/" {
+            String::from("/")
+        } else {
+            format!("{}/", path)
+        };
+        let rest = DBUS_OBJECT_PATH.strip_prefix(&prefix)?;
+        let child = rest.split('/').next()?;
+        xml.push_str(STANDARD_INTERFACES_XML);
+        xml.push_str(&format!("  <node name=\"{}\"/>\n", child));
+    }
+    xml.push_str("</node>\n");
+    Some(xml)
+}
+
+fn machine_id() -> Option<String> {
+    MACHINE_ID_FILES
+        .iter()
+        .find_map(|file| std::fs::read_to_string(file).ok())
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+}
+
+fn unknown_object_reply(msg: &Message, path: &str) -> Message {
+    error_reply(msg, ERROR_UNKNOWN_OBJECT, &format!("No object at '{}'", path))
+}
+
+/// JSON describing the dictionaries that loaded and those that failed to,
+/// in whole or in part
+fn load_status() -> String {
+    let status = serde_json::json!({
+        "dictionaries": api::installed_sources()
+            .iter()
+            .map(|source| {
+                let metadata = source.metadata();
+                serde_json::json!({
+                    "id": metadata.id,
+                    "title": metadata.name,
+                    "version": metadata.version,
+                    "language": metadata.language,
+                    "license": metadata.license,
+                    "entryCount": metadata.entry_count,
+                })
+            })
+            .collect::<Vec<_>>(),
+        "problems": api::load_problems(),
+    });
+    status.to_string()
+}
+
+fn lookup_error_reply(msg: &Message, error: &LookupError) -> Message {
+    let name = match error {
+        LookupError::EmptyQuery => ERROR_EMPTY_QUERY,
+        LookupError::NotFound { .. } => ERROR_NOT_FOUND,
+        LookupError::InvalidPattern(_) => ERROR_INVALID_PATTERN,
+    };
+    error_reply(msg, name, &error.to_string())
+}
+
+fn error_reply(msg: &Message, name: &'static str, text: &str) -> Message {
+    let text = CString::new(text.replace('\0', "")).unwrap_or_default();
+    msg.error(&ErrorName::from(name), &text)
 }
 
 // Helper function to make DBus calls from other components
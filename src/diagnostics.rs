@@ -0,0 +1,643 @@
+use crate::api::DictionaryEntry;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Skipped entries listed per file in the load problems summary
+const MAX_LISTED_ENTRIES: usize = 10;
+
+/// A place in a file, 1-based
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    /// Turn a position serde_json reports within a piece of the file that
+    /// starts here into one in the whole file
+    pub fn offset_by(self, line: usize, column: usize) -> Self {
+        if line <= 1 {
+            Self {
+                line: self.line,
+                column: self.column + column.saturating_sub(1),
+            }
+        } else {
+            // serde_json counts the end of the input as column 0
+            Self {
+                line: self.line + line - 1,
+                column: column.max(1),
+            }
+        }
+    }
+}
+
+/// Something wrong at a place in a dictionary file
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    /// Headword of the entry, when it could be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub word: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(at: Position, message: impl Into<String>) -> Self {
+        Self {
+            line: at.line,
+            column: at.column,
+            word: None,
+            message: message.into(),
+        }
+    }
+}
+
+/// One top-level element of a JSON dictionary, not yet parsed
+#[derive(Clone, Copy)]
+pub struct RawEntry<'a> {
+    /// Where the element starts
+    pub at: Position,
+    pub text: &'a str,
+}
+
+impl RawEntry<'_> {
+    pub fn parse(&self) -> serde_json::Result<DictionaryEntry> {
+        serde_json::from_str(self.text)
+    }
+
+    /// Describe an error from [`RawEntry::parse`], located in the whole file
+    pub fn diagnostic(&self, e: &serde_json::Error) -> Diagnostic {
+        // The headword can usually still be read unless the JSON is broken
+        let word = serde_json::from_str::<Value>(self.text)
+            .ok()
+            .and_then(|value| Some(value.get("word")?.as_str()?.to_string()));
+        Diagnostic {
+            word,
+            ..Diagnostic::new(self.at.offset_by(e.line(), e.column()), error_message(e))
+        }
+    }
+
+    /// Where the value at `path`, such as `meanings[0].definitions[2]`,
+    /// starts in the file. When it isn't there, the deepest value on the way
+    /// to it that is; the entry itself for an empty path.
+    pub fn locate(&self, path: &str) -> Position {
+        let bytes = self.text.as_bytes();
+        let mut offset = 0;
+        let steps = path
+            .split('.')
+            .filter(|part| !part.is_empty())
+            .flat_map(|part| {
+                let (name, indexes) = part.split_once('[').unwrap_or((part, ""));
+                let indexes = indexes
+                    .split('[')
+                    .filter_map(|index| index.trim_end_matches(']').parse().ok());
+                std::iter::once(Step::Field(name)).chain(indexes.map(Step::Index))
+            });
+        for step in steps {
+            let found = match step {
+                Step::Field(name) => member(bytes, offset, name),
+                Step::Index(index) => element(bytes, offset, index),
+            };
+            match found {
+                Some(start) => offset = start,
+                None => break,
+            }
+        }
+
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let column = before[line_start..].chars().count() + 1;
+        self.at.offset_by(line, column)
+    }
+}
+
+/// One step of a path into an entry
+enum Step<'a> {
+    Field(&'a str),
+    Index(usize),
+}
+
+/// Start of the value of the member `name` of the object at `start`
+fn member(bytes: &[u8], start: usize, name: &str) -> Option<usize> {
+    if bytes.get(start) != Some(&b'{') {
+        return None;
+    }
+    let mut i = skip_whitespace(bytes, start + 1);
+    while bytes.get(i) == Some(&b'"') {
+        let key_end = skip_value(bytes, i)?;
+        let key: String = serde_json::from_slice(&bytes[i..key_end]).ok()?;
+        i = skip_whitespace(bytes, key_end);
+        if bytes.get(i) != Some(&b':') {
+            return None;
+        }
+        let value = skip_whitespace(bytes, i + 1);
+        if key == name {
+            return Some(value);
+        }
+        i = skip_whitespace(bytes, skip_value(bytes, value)?);
+        if bytes.get(i) != Some(&b',') {
+            return None;
+        }
+        i = skip_whitespace(bytes, i + 1);
+    }
+    None
+}
+
+/// Start of element `index` of the array at `start`
+fn element(bytes: &[u8], start: usize, index: usize) -> Option<usize> {
+    if bytes.get(start) != Some(&b'[') {
+        return None;
+    }
+    let mut i = skip_whitespace(bytes, start + 1);
+    for _ in 0..index {
+        i = skip_whitespace(bytes, skip_value(bytes, i)?);
+        if bytes.get(i) != Some(&b',') {
+            return None;
+        }
+        i = skip_whitespace(bytes, i + 1);
+    }
+    (i < bytes.len() && bytes[i] != b']').then_some(i)
+}
+
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+        i += 1;
+    }
+    i
+}
+
+/// Just past the value starting at `start`, tracking only strings and
+/// brackets like [`split_entries`]
+fn skip_value(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &byte) in bytes.iter().enumerate().skip(start) {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => depth += 1,
+            b']' | b'}' if depth == 0 => return Some(i),
+            b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            b',' | b' ' | b'\t' | b'\n' | b'\r' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    (depth == 0 && !in_string).then_some(bytes.len())
+}
+
+/// The elements of a JSON dictionary and anything wrong with the array
+/// around them
+pub struct RawEntries<'a> {
+    pub entries: Vec<RawEntry<'a>>,
+    pub errors: Vec<Diagnostic>,
+}
+
+/// Split a JSON array of entries into its elements without parsing them, so
+/// that a malformed entry, even one with a syntax error, costs only itself.
+/// Only strings and brackets are tracked. An entry missing its closing brace
+/// ends where a `{` follows it at the same depth, and one with an unterminated
+/// string ends before the next line that starts with `{` no further indented
+/// than the entry. Any other element that leaves a bracket or string open runs
+/// on to the end of the file and fails alone.
+pub fn split_entries(text: &str) -> RawEntries<'_> {
+    let mut lines = LineCounter::new(text);
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+
+    let bytes = text.as_bytes();
+    let open = text.trim_start_matches(['\u{feff}', ' ', '\t', '\n', '\r']);
+    let mut i = text.len() - open.len();
+    if !open.starts_with('[') {
+        let message = if open.is_empty() {
+            "The file is empty"
+        } else {
+            "Expected a JSON array of entries"
+        };
+        errors.push(Diagnostic::new(lines.position(i), message));
+        return RawEntries { entries, errors };
+    }
+    i += 1;
+
+    let mut start = None;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut after_comma = false;
+    let mut closed = false;
+    // Just past the last character of the current element
+    let mut end = i;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        match start.filter(|&start| bytes[start] == b'{') {
+            // JSON strings can't span lines, so this one is unterminated
+            Some(entry_start) if in_string && byte == b'\n' => {
+                let column = lines.position(entry_start).column;
+                if let Some(next) = next_entry_line(text, i + 1, column) {
+                    entries.push(RawEntry {
+                        at: lines.position(entry_start),
+                        text: cut_entry(&text[entry_start..next]),
+                    });
+                    start = None;
+                    depth = 0;
+                    in_string = false;
+                    escaped = false;
+                    after_comma = true;
+                    i = next;
+                    continue;
+                }
+            }
+            // Inside an entry an object only follows a key, so the entry
+            // before this one is missing its closing brace
+            Some(entry_start)
+                if !in_string && byte == b'{' && depth == 1 && bytes[end - 1] != b':' =>
+            {
+                entries.push(RawEntry {
+                    at: lines.position(entry_start),
+                    text: cut_entry(&text[entry_start..end]),
+                });
+                start = None;
+                depth = 0;
+                after_comma = true;
+            }
+            _ => {}
+        }
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else if matches!(byte, b',' | b']') && depth == 0 {
+            match start.take() {
+                Some(start) => entries.push(RawEntry {
+                    at: lines.position(start),
+                    text: &text[start..end],
+                }),
+                // "[]" is fine, but "[,", ",," and ",]" aren't
+                None if byte == b',' || after_comma => {
+                    errors.push(Diagnostic::new(lines.position(i), "Missing entry"))
+                }
+                None => {}
+            }
+            after_comma = byte == b',';
+            if byte == b']' {
+                closed = true;
+                i += 1;
+                break;
+            }
+        } else if !byte.is_ascii_whitespace() {
+            start.get_or_insert(i);
+            match byte {
+                b'"' => in_string = true,
+                b'[' | b'{' => depth += 1,
+                b']' | b'}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        if !byte.is_ascii_whitespace() {
+            end = i + 1;
+        }
+        i += 1;
+    }
+
+    if !closed {
+        if let Some(start) = start {
+            entries.push(RawEntry {
+                at: lines.position(start),
+                text: &text[start..],
+            });
+        }
+        errors.push(Diagnostic::new(
+            lines.position(text.len()),
+            "The array of entries isn't closed",
+        ));
+    } else if let Some(extra) = text[i..].find(|c: char| !c.is_whitespace()) {
+        errors.push(Diagnostic::new(
+            lines.position(i + extra),
+            "Trailing characters after the array of entries",
+        ));
+    }
+    RawEntries { entries, errors }
+}
+
+/// Start of the first line from `from` on that begins with `{` at or before
+/// `column`
+fn next_entry_line(text: &str, from: usize, column: usize) -> Option<usize> {
+    let mut line_start = from;
+    for line in text[from..].split_inclusive('\n') {
+        let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+        if line[indent..].starts_with('{') && line[..indent].chars().count() < column {
+            return Some(line_start + indent);
+        }
+        line_start += line.len();
+    }
+    None
+}
+
+/// A cut-off entry without the whitespace and comma that separated it from
+/// the next
+fn cut_entry(text: &str) -> &str {
+    let text = text.trim_end();
+    text.strip_suffix(',').unwrap_or(text).trim_end()
+}
+
+impl RawEntries<'_> {
+    /// Parse the elements, keeping every entry that parses and describing
+    /// the ones that don't
+    pub fn parse(self) -> (Vec<DictionaryEntry>, Vec<Diagnostic>) {
+        let mut entries = Vec::with_capacity(self.entries.len());
+        let mut skipped = Vec::new();
+        for raw in self.entries {
+            match raw.parse() {
+                Ok(entry) => entries.push(entry),
+                Err(e) => skipped.push(raw.diagnostic(&e)),
+            }
+        }
+        (entries, skipped)
+    }
+}
+
+/// The text of a serde_json error without the location it appends, which
+/// is reported separately
+pub fn error_message(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+    let location = format!(" at line {} column {}", e.line(), e.column());
+    let message = message.strip_suffix(&location).unwrap_or(&message);
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Converts increasing byte offsets into positions without rescanning the file
+struct LineCounter<'a> {
+    text: &'a str,
+    offset: usize,
+    line: usize,
+    line_start: usize,
+}
+
+impl<'a> LineCounter<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            offset: 0,
+            line: 1,
+            line_start: 0,
+        }
+    }
+
+    fn position(&mut self, offset: usize) -> Position {
+        let skipped = &self.text[self.offset..offset];
+        if let Some(last) = skipped.rfind('\n') {
+            self.line += skipped.matches('\n').count();
+            self.line_start = self.offset + last + 1;
+        }
+        self.offset = offset;
+        Position {
+            line: self.line,
+            column: self.text[self.line_start..offset].chars().count() + 1,
+        }
+    }
+}
+
+/// What went wrong loading one dictionary
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadProblem {
+    /// Path of the dictionary file, or "bundled" for the built-in data
+    pub file: String,
+    /// Why the dictionary couldn't be loaded at all, if it couldn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Entries left out; the rest of the dictionary was loaded
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_entries: Vec<Diagnostic>,
+    /// Mistakes that cost no entries, such as a stray comma between two
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Diagnostic>,
+    pub loaded_entries: usize,
+}
+
+impl LoadProblem {
+    /// Nothing wrong yet with `file`; readers add what they find
+    pub fn new(file: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            error: None,
+            skipped_entries: Vec::new(),
+            warnings: Vec::new(),
+            loaded_entries: 0,
+        }
+    }
+
+    pub fn failed(file: impl Into<String>, error: &anyhow::Error) -> Self {
+        Self {
+            error: Some(format!("{:#}", error)),
+            ..Self::new(file)
+        }
+    }
+
+    /// Whether anything was found wrong
+    pub fn is_empty(&self) -> bool {
+        self.error.is_none() && self.skipped_entries.is_empty() && self.warnings.is_empty()
+    }
+
+    /// A line or a few for logs and the details dialog
+    pub fn describe(&self) -> String {
+        if let Some(error) = &self.error {
+            return format!("{}: {}", self.file, error);
+        }
+        let mut text = if self.skipped_entries.is_empty() {
+            format!("{}:", self.file)
+        } else {
+            format!(
+                "{}: skipped {} of {} entries",
+                self.file,
+                self.skipped_entries.len(),
+                self.skipped_entries.len() + self.loaded_entries
+            )
+        };
+        let listed = self
+            .skipped_entries
+            .iter()
+            .take(MAX_LISTED_ENTRIES)
+            .chain(&self.warnings);
+        for diagnostic in listed {
+            text.push_str(&format!(
+                "\n  line {}, column {}",
+                diagnostic.line, diagnostic.column
+            ));
+            if let Some(word) = &diagnostic.word {
+                text.push_str(&format!(" ('{}')", word));
+            }
+            text.push_str(&format!(": {}", diagnostic.message));
+        }
+        if self.skipped_entries.len() > MAX_LISTED_ENTRIES {
+            text.push_str(&format!(
+                "\n  and {} more",
+                self.skipped_entries.len() - MAX_LISTED_ENTRIES
+            ));
+        }
+        text
+    }
+}
+
+/// One sentence on what failed to load, for the window's banner
+pub fn summarize(problems: &[LoadProblem]) -> Option<String> {
+    let failed = problems
+        .iter()
+        .filter(|problem| problem.error.is_some())
+        .count();
+    let skipped: usize = problems
+        .iter()
+        .map(|problem| problem.skipped_entries.len())
+        .sum();
+    let plural = |count: usize, one: &str, many: &str| {
+        format!("{} {}", count, if count == 1 { one } else { many })
+    };
+    match (failed, skipped) {
+        (0, 0) => None,
+        (0, skipped) => Some(format!(
+            "{} couldn't be read and {} left out",
+            plural(skipped, "dictionary entry", "dictionary entries"),
+            if skipped == 1 { "was" } else { "were" }
+        )),
+        (failed, 0) => Some(format!(
+            "{} couldn't be loaded",
+            plural(failed, "dictionary", "dictionaries")
+        )),
+        (failed, skipped) => Some(format!(
+            "{} couldn't be loaded and {} {} left out",
+            plural(failed, "dictionary", "dictionaries"),
+            plural(skipped, "entry", "entries"),
+            if skipped == 1 { "was" } else { "were" }
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(text: &str) -> Vec<&str> {
+        let split = split_entries(text);
+        assert!(split.errors.is_empty(), "{:?}", split.errors);
+        split.entries.iter().map(|raw| raw.text).collect()
+    }
+
+    #[test]
+    fn entry_missing_its_brace_costs_only_itself() {
+        let text = r#"[
+  {"word": "a", "meanings": [{"definitions": []}] ,
+  {"word": "b", "meanings": []},
+  {"word": "c", "x": {"y": 1}{"word": "d"}
+]"#;
+        assert_eq!(
+            texts(text),
+            [
+                r#"{"word": "a", "meanings": [{"definitions": []}]"#,
+                r#"{"word": "b", "meanings": []}"#,
+                r#"{"word": "c", "x": {"y": 1}"#,
+                r#"{"word": "d"}"#,
+            ]
+        );
+        let (entries, skipped) = split_entries(text).parse();
+        assert_eq!(entries.len(), 0);
+        assert_eq!(
+            skipped
+                .iter()
+                .map(|skipped| skipped.line)
+                .collect::<Vec<_>>(),
+            [2, 3, 4, 4]
+        );
+    }
+
+    #[test]
+    fn unterminated_string_ends_before_the_next_entry() {
+        let text = r#"[
+  {
+    "word": "a",
+    "meanings": [
+      {"partOfSpeech": "noun},
+      {"partOfSpeech": "verb"}
+    ]
+  },
+  {"word": "b"}
+]"#;
+        let split = split_entries(text);
+        assert_eq!(split.entries.len(), 2);
+        assert!(split.entries[0].text.ends_with("}\n    ]\n  }"));
+        assert_eq!(split.entries[1].text, r#"{"word": "b"}"#);
+        assert_eq!(split.entries[1].at, Position { line: 9, column: 3 });
+    }
+
+    #[test]
+    fn nested_objects_at_the_margin_are_not_entries() {
+        let text = "[\n{\n\"word\": \"a\",\n\"meanings\": [\n{\n\"partOfSpeech\": \"noun\"\n},\n{\n}\n]\n},\n{\n\"word\": \"b\"\n}\n]";
+        assert_eq!(texts(text).len(), 2);
+    }
+
+    #[test]
+    fn stray_commas_are_reported() {
+        let split = split_entries("[{\"word\": \"a\"},,{\"word\": \"b\"},]");
+        assert_eq!(split.entries.len(), 2);
+        let columns: Vec<_> = split.errors.iter().map(|error| error.column).collect();
+        assert_eq!(columns, [16, 31]);
+    }
+
+    #[test]
+    fn describes_what_was_lost() {
+        let mut problem = LoadProblem::new("a.json");
+        assert!(problem.is_empty());
+        problem.warnings.push(Diagnostic::new(
+            Position { line: 3, column: 4 },
+            "Missing entry",
+        ));
+        problem.loaded_entries = 2;
+        assert_eq!(
+            problem.describe(),
+            "a.json:\n  line 3, column 4: Missing entry"
+        );
+        assert_eq!(summarize(std::slice::from_ref(&problem)), None);
+
+        problem.skipped_entries.push(Diagnostic {
+            word: Some(String::from("b")),
+            ..Diagnostic::new(Position { line: 5, column: 1 }, "Missing field `word`")
+        });
+        let failed = LoadProblem::failed("c.json", &anyhow::anyhow!("Not a dictionary"));
+        assert_eq!(
+            problem.describe(),
+            "a.json: skipped 1 of 3 entries\n  \
+             line 5, column 1 ('b'): Missing field `word`\n  \
+             line 3, column 4: Missing entry"
+        );
+        assert_eq!(
+            summarize(&[problem, failed]).unwrap(),
+            "1 dictionary couldn't be loaded and 1 entry was left out"
+        );
+    }
+}
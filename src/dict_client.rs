@@ -0,0 +1,631 @@
+use crate::api::{Definition, DictionaryEntry, Meaning};
+use crate::fuzzy;
+use crate::normalize;
+use crate::settings::DictServerSettings;
+use crate::source::{DictionarySource, FuzzyMatch, SourceMetadata};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Port assigned to the DICT protocol
+pub const DEFAULT_PORT: u16 = 2628;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to stop contacting a server after it couldn't be reached, so an
+/// absent `dictd` doesn't slow every lookup down
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Prefix matches are each fetched with a DEFINE, so fetch fewer than for
+/// local sources
+const MAX_REMOTE_PREFIX: usize = 10;
+
+/// A database offered by a DICT server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteDatabase {
+    pub name: String,
+    pub description: String,
+}
+
+/// One definition from a DEFINE response
+#[derive(Debug, Clone)]
+pub struct RemoteDefinition {
+    pub word: String,
+    pub database: String,
+    pub description: String,
+    pub text: String,
+}
+
+/// An open connection to a DICT server (RFC 2229)
+pub struct DictConnection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl DictConnection {
+    pub fn connect(host: &str, port: u16) -> Result<Self> {
+        let address = (host, port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {}", host))?
+            .next()
+            .with_context(|| format!("No address for {}", host))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+            .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let mut connection = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+
+        let (code, text) = connection.status()?;
+        if code != 220 {
+            bail!("Server refused connection: {} {}", code, text);
+        }
+        // Identifying the client is optional; servers may reject it
+        connection.command(&format!("CLIENT aynary {}", env!("CARGO_PKG_VERSION")))?;
+        Ok(connection)
+    }
+
+    /// Definitions of `word` in `database` (`*` for all, `!` for the first that has it)
+    pub fn define(&mut self, database: &str, word: &str) -> Result<Vec<RemoteDefinition>> {
+        let (code, text) = self.command(&format!("DEFINE {} {}", database, quote(word)))?;
+        match code {
+            150 => {}
+            552 => return Ok(Vec::new()),
+            _ => bail!("DEFINE failed: {} {}", code, text),
+        }
+
+        let mut definitions = Vec::new();
+        loop {
+            let (code, text) = self.status()?;
+            match code {
+                151 => {
+                    let params = split_params(&text);
+                    let [word, database, description] = params.as_slice() else {
+                        bail!("Malformed definition header: {}", text);
+                    };
+                    definitions.push(RemoteDefinition {
+                        word: word.clone(),
+                        database: database.clone(),
+                        description: description.clone(),
+                        text: self.text_block()?.join("\n"),
+                    });
+                }
+                250 => return Ok(definitions),
+                _ => bail!("DEFINE failed: {} {}", code, text),
+            }
+        }
+    }
+
+    /// `(database, word)` pairs matching `word` under `strategy`
+    pub fn match_words(
+        &mut self,
+        database: &str,
+        strategy: &str,
+        word: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let (code, text) =
+            self.command(&format!("MATCH {} {} {}", database, strategy, quote(word)))?;
+        match code {
+            152 => {}
+            552 => return Ok(Vec::new()),
+            _ => bail!("MATCH failed: {} {}", code, text),
+        }
+
+        let matches = self
+            .text_block()?
+            .iter()
+            .filter_map(|line| match split_params(line).as_slice() {
+                [database, word] => Some((database.clone(), word.clone())),
+                _ => None,
+            })
+            .collect();
+        self.expect(250)?;
+        Ok(matches)
+    }
+
+    /// The databases the server offers (SHOW DB)
+    pub fn databases(&mut self) -> Result<Vec<RemoteDatabase>> {
+        let (code, text) = self.command("SHOW DB")?;
+        match code {
+            110 => {}
+            554 => return Ok(Vec::new()),
+            _ => bail!("SHOW DB failed: {} {}", code, text),
+        }
+
+        let databases = self
+            .text_block()?
+            .iter()
+            .filter_map(|line| match split_params(line).as_slice() {
+                [name, description] => Some(RemoteDatabase {
+                    name: name.clone(),
+                    description: description.clone(),
+                }),
+                _ => None,
+            })
+            .collect();
+        self.expect(250)?;
+        Ok(databases)
+    }
+
+    fn command(&mut self, line: &str) -> Result<(u16, String)> {
+        self.writer.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.status()
+    }
+
+    fn expect(&mut self, expected: u16) -> Result<()> {
+        let (code, text) = self.status()?;
+        if code != expected {
+            bail!("Unexpected response: {} {}", code, text);
+        }
+        Ok(())
+    }
+
+    /// Read a status line: a three-digit code and its text
+    fn status(&mut self) -> Result<(u16, String)> {
+        parse_status(&self.line()?)
+    }
+
+    /// Read a text response up to its terminating `.` line, undoing dot-stuffing
+    fn text_block(&mut self) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.line()?;
+            if line == "." {
+                return Ok(lines);
+            }
+            let line = line.strip_prefix('.').unwrap_or(&line).to_string();
+            lines.push(line);
+        }
+    }
+
+    fn line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            bail!("Connection closed by server");
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+impl Drop for DictConnection {
+    fn drop(&mut self) {
+        let _ = self.writer.write_all(b"QUIT\r\n");
+    }
+}
+
+/// Split a status line into its three-digit code and text
+fn parse_status(line: &str) -> Result<(u16, String)> {
+    let code = line
+        .get(..3)
+        .filter(|code| code.bytes().all(|b| b.is_ascii_digit()))
+        .ok_or_else(|| anyhow!("Malformed response: {}", line))?;
+    Ok((code.parse()?, line[3..].trim().to_string()))
+}
+
+/// Host and port from `host`, `host:port` or `[address]:port`. A bare address
+/// with several colons is an IPv6 address without a port.
+pub fn parse_address(text: &str) -> Option<(&str, u16)> {
+    let (host, port) = match text.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once(']')?;
+            match port {
+                "" => (host, None),
+                port => (host, Some(port.strip_prefix(':')?)),
+            }
+        }
+        None => match text.split_once(':') {
+            Some((host, port)) if !port.contains(':') => (host, Some(port)),
+            _ => (text, None),
+        },
+    };
+
+    match port {
+        Some(port) => Some((host, port.parse().ok()?)),
+        None => Some((host, DEFAULT_PORT)),
+    }
+}
+
+/// The inverse of [`parse_address`], leaving out the default port
+pub fn format_address(host: &str, port: u16) -> String {
+    match (port == DEFAULT_PORT, host.contains(':')) {
+        (true, _) => host.to_string(),
+        (false, true) => format!("[{}]:{}", host, port),
+        (false, false) => format!("{}:{}", host, port),
+    }
+}
+
+/// Quote a word for a command, so phrases with spaces stay one parameter
+pub(crate) fn quote(word: &str) -> String {
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Split a command or response line into atoms and quoted strings
+pub(crate) fn split_params(line: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut param = String::new();
+        if c == '"' || c == '\'' {
+            chars.next();
+            while let Some(c2) = chars.next() {
+                match c2 {
+                    '\\' => param.extend(chars.next()),
+                    _ if c2 == c => break,
+                    _ => param.push(c2),
+                }
+            }
+        } else {
+            while let Some(&c2) = chars.peek() {
+                if c2.is_whitespace() {
+                    break;
+                }
+                param.push(c2);
+                chars.next();
+            }
+        }
+        params.push(param);
+    }
+    params
+}
+
+/// A DICT server. The client asks it only once the local dictionaries have
+/// nothing, over one connection per lookup (see [`DictSession`]).
+///
+/// Connection errors are logged and treated as "no results", and the server
+/// is left alone for a while afterwards.
+pub struct DictServerSource {
+    metadata: SourceMetadata,
+    settings: Mutex<DictServerSettings>,
+    retry_at: Mutex<Option<Instant>>,
+}
+
+impl DictServerSource {
+    pub fn new(settings: DictServerSettings) -> Self {
+        let mut metadata = SourceMetadata::new("dict", "DICT server");
+        metadata.description = Some(String::from("Definitions from a DICT protocol server"));
+
+        Self {
+            metadata,
+            settings: Mutex::new(settings),
+            retry_at: Mutex::new(None),
+        }
+    }
+
+    pub fn settings(&self) -> DictServerSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Change the server or database; takes effect on the next lookup
+    pub fn set_settings(&self, settings: DictServerSettings) {
+        *self.settings.lock().unwrap() = settings;
+        *self.retry_at.lock().unwrap() = None;
+    }
+
+    /// List the server's databases, so the user can pick one to query
+    pub fn databases(&self) -> Result<Vec<RemoteDatabase>> {
+        let settings = self.settings();
+        DictConnection::connect(&settings.host, settings.port)?.databases()
+    }
+
+    /// Connect for one lookup, or return `None` if the source is disabled,
+    /// backing off, or the server can't be reached
+    pub fn session(&self) -> Option<DictSession<'_>> {
+        let settings = self.settings();
+        if !settings.enabled {
+            return None;
+        }
+        if self
+            .retry_at
+            .lock()
+            .unwrap()
+            .is_some_and(|retry_at| Instant::now() < retry_at)
+        {
+            return None;
+        }
+
+        match DictConnection::connect(&settings.host, settings.port) {
+            Ok(connection) => Some(DictSession {
+                source: self,
+                connection: Some(connection),
+                settings,
+            }),
+            Err(e) => {
+                self.back_off(&settings, &e);
+                None
+            }
+        }
+    }
+
+    fn back_off(&self, settings: &DictServerSettings, error: &anyhow::Error) {
+        eprintln!("DICT server {}:{}: {:#}", settings.host, settings.port, error);
+        *self.retry_at.lock().unwrap() = Some(Instant::now() + RETRY_DELAY);
+    }
+}
+
+/// A connection to the server shared by the queries of one lookup. After a
+/// failed request the session returns nothing more.
+pub struct DictSession<'a> {
+    source: &'a DictServerSource,
+    connection: Option<DictConnection>,
+    settings: DictServerSettings,
+}
+
+impl DictSession<'_> {
+    /// Run `request` on the connection, or return the default if it failed now
+    /// or earlier in the session
+    fn request<T: Default>(
+        &mut self,
+        request: impl FnOnce(&mut DictConnection, &str) -> Result<T>,
+    ) -> T {
+        let Some(connection) = self.connection.as_mut() else {
+            return T::default();
+        };
+        match request(connection, &self.settings.database) {
+            Ok(value) => value,
+            Err(e) => {
+                self.source.back_off(&self.settings, &e);
+                self.connection = None;
+                T::default()
+            }
+        }
+    }
+
+    /// Entries whose headword equals `folded`
+    pub fn lookup(&mut self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        let definitions = self.request(|connection, database| connection.define(database, folded));
+
+        definitions
+            .into_iter()
+            .filter(|definition| !strict || normalize::fold(&definition.word) == folded)
+            .map(|definition| self.to_entry(definition))
+            .collect()
+    }
+
+    /// Entries whose headword starts with `folded`
+    pub fn prefix(&mut self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        let definitions = self.request(|connection, database| {
+            let mut seen = HashSet::new();
+            let words: Vec<(String, String)> = connection
+                .match_words(database, "prefix", folded)?
+                .into_iter()
+                .filter(|(_, word)| !strict || normalize::fold(word).starts_with(folded))
+                .filter(|pair| seen.insert(pair.clone()))
+                .take(limit.min(MAX_REMOTE_PREFIX))
+                .collect();
+
+            let mut definitions = Vec::new();
+            for (database, word) in words {
+                definitions.extend(connection.define(&database, &word)?);
+            }
+            Ok(definitions)
+        });
+
+        definitions
+            .into_iter()
+            .map(|definition| self.to_entry(definition))
+            .collect()
+    }
+
+    /// Headwords within `max_distance` edits of `loose`
+    pub fn fuzzy(&mut self, loose: &str, max_distance: usize) -> Vec<FuzzyMatch> {
+        // "lev" finds words one edit away, which is as far as servers go
+        let matches =
+            self.request(|connection, database| connection.match_words(database, "lev", loose));
+
+        let mut seen = HashSet::new();
+        matches
+            .into_iter()
+            .filter(|(_, word)| seen.insert(word.clone()))
+            .filter_map(|(_, word)| {
+                let distance = fuzzy::levenshtein(&normalize::loose(&word), loose);
+                (distance <= max_distance).then_some(FuzzyMatch {
+                    word,
+                    distance,
+                    frequency: 0,
+                })
+            })
+            .collect()
+    }
+
+    fn to_entry(&self, definition: RemoteDefinition) -> DictionaryEntry {
+        to_entry(
+            definition,
+            &format!("dict://{}:{}", self.settings.host, self.settings.port),
+        )
+    }
+}
+
+/// Each query on its own connection; [`DictionaryClient`] uses a
+/// [`DictSession`] instead to make one per lookup
+///
+/// [`DictionaryClient`]: crate::api::DictionaryClient
+impl DictionarySource for DictServerSource {
+    fn metadata(&self) -> &SourceMetadata {
+        &self.metadata
+    }
+
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        self.session()
+            .map(|mut session| session.lookup(folded, strict))
+            .unwrap_or_default()
+    }
+
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        self.session()
+            .map(|mut session| session.prefix(folded, strict, limit))
+            .unwrap_or_default()
+    }
+
+    fn fuzzy(&self, loose: &str, max_distance: usize) -> Vec<FuzzyMatch> {
+        self.session()
+            .map(|mut session| session.fuzzy(loose, max_distance))
+            .unwrap_or_default()
+    }
+}
+
+/// Map a DICT definition onto an entry. The text conventionally starts with
+/// the headword, often followed by a pronunciation; the remaining paragraphs
+/// become definitions, with their hard line wraps undone.
+fn to_entry(definition: RemoteDefinition, server: &str) -> DictionaryEntry {
+    let mut lines: Vec<&str> = definition.text.lines().collect();
+    let mut phonetic = None;
+
+    if let Some(first) = lines.first() {
+        let first = first.trim();
+        let rest = first
+            .get(..definition.word.len())
+            .filter(|head| normalize::fold(head) == normalize::fold(&definition.word))
+            .map(|_| first[definition.word.len()..].trim());
+        if let Some(rest) = rest {
+            if rest.starts_with(['/', '[']) {
+                phonetic = Some(rest.to_string());
+            }
+            if phonetic.is_some() || rest.is_empty() {
+                lines.remove(0);
+            }
+        }
+    }
+
+    let definitions = lines
+        .split(|line| line.trim().is_empty())
+        .map(|paragraph| {
+            paragraph
+                .iter()
+                .map(|line| line.trim())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| Definition {
+            // Definitions are numbered when shown
+            definition: strip_numbering(&paragraph).to_string(),
+            ..Definition::default()
+        })
+        .collect();
+
+    DictionaryEntry {
+        source_urls: vec![format!(
+            "{}/d:{}:{}",
+            server, definition.word, definition.database
+        )],
+        word: definition.word,
+        phonetic,
+        meanings: vec![Meaning {
+            definitions,
+            ..Meaning::default()
+        }],
+        ..DictionaryEntry::default()
+    }
+}
+
+/// `"2. n. A piece of work"` → `"n. A piece of work"`
+fn strip_numbering(paragraph: &str) -> &str {
+    let digits = paragraph.len()
+        - paragraph
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .len();
+    match paragraph[digits..].strip_prefix(['.', ')']) {
+        Some(rest) if digits > 0 && rest.starts_with(' ') => rest.trim_start(),
+        _ => paragraph,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Connect to a server that sends `script` whatever it is asked
+    fn connect(script: &'static str) -> DictConnection {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(script.as_bytes()).unwrap();
+            // Keep the connection open until the client quits
+            let _ = BufReader::new(stream)
+                .lines()
+                .find(|line| line.as_ref().map_or(true, |line| line == "QUIT"));
+        });
+        DictConnection::connect("127.0.0.1", port).unwrap()
+    }
+
+    #[test]
+    fn parses_status_lines() {
+        assert_eq!(parse_status("250 ok").unwrap(), (250, "ok".to_string()));
+        assert_eq!(parse_status("552").unwrap(), (552, String::new()));
+        assert!(parse_status("2é0 odd").is_err());
+        assert!(parse_status("é").is_err());
+        assert!(parse_status("-12 negative").is_err());
+    }
+
+    #[test]
+    fn reads_definitions_and_matches() {
+        let mut connection = connect(concat!(
+            "220 test server\r\n",
+            "250 ok\r\n",
+            "150 2 definitions retrieved\r\n",
+            "151 \"cat\" wn \"WordNet\"\r\n",
+            "cat\r\n",
+            "  n. a small feline\r\n",
+            "..and more\r\n",
+            ".\r\n",
+            "151 \"cat\" gcide \"GCIDE\"\r\n",
+            "Cat /kat/\r\n",
+            ".\r\n",
+            "250 ok\r\n",
+            "152 2 matches found\r\n",
+            "wn \"cat\"\r\n",
+            "wn \"cat's-claw\"\r\n",
+            ".\r\n",
+            "250 ok\r\n",
+            "552 no match\r\n",
+            "5é1 broken\r\n",
+        ));
+
+        let definitions = connection.define("*", "cat").unwrap();
+        assert_eq!(definitions.len(), 2);
+        assert_eq!(definitions[0].database, "wn");
+        assert_eq!(definitions[0].description, "WordNet");
+        assert_eq!(definitions[0].text, "cat\n  n. a small feline\n.and more");
+        assert_eq!(definitions[1].text, "Cat /kat/");
+
+        assert_eq!(
+            connection.match_words("*", "prefix", "cat").unwrap(),
+            [
+                ("wn".to_string(), "cat".to_string()),
+                ("wn".to_string(), "cat's-claw".to_string())
+            ]
+        );
+        assert!(connection.define("*", "dgo").unwrap().is_empty());
+        assert!(connection.define("*", "cat").is_err());
+    }
+
+    #[test]
+    fn parses_server_addresses() {
+        assert_eq!(parse_address("dict.org"), Some(("dict.org", DEFAULT_PORT)));
+        assert_eq!(parse_address("localhost:2629"), Some(("localhost", 2629)));
+        assert_eq!(parse_address("[::1]:2629"), Some(("::1", 2629)));
+        assert_eq!(parse_address("[::1]"), Some(("::1", DEFAULT_PORT)));
+        assert_eq!(parse_address("fe80::1"), Some(("fe80::1", DEFAULT_PORT)));
+        assert_eq!(parse_address("localhost:port"), None);
+        assert_eq!(parse_address("[::1]2629"), None);
+
+        assert_eq!(format_address("::1", 2629), "[::1]:2629");
+        assert_eq!(format_address("::1", DEFAULT_PORT), "::1");
+        assert_eq!(format_address("localhost", 2629), "localhost:2629");
+    }
+}
@@ -0,0 +1,643 @@
+use crate::api::{DictionaryClient, DictionaryEntry};
+use crate::dict_client::{quote, split_params};
+use crate::fuzzy;
+use crate::normalize;
+use crate::source::DictionarySource;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Connections idle for longer than this are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Clients served at once; further connections are turned away with a 420
+const MAX_CONNECTIONS: usize = 64;
+
+/// RFC 2229 limits command lines to 1024 octets
+const MAX_LINE_LEN: u64 = 1024;
+
+/// Upper bound on the matches returned per database for one MATCH
+const MAX_MATCHES: usize = 200;
+
+/// Strategy used for MATCH with the `.` (server default) strategy
+const DEFAULT_STRATEGY: &str = "lev";
+
+const STRATEGIES: &[(&str, &str)] = &[
+    ("exact", "Match headwords exactly"),
+    ("prefix", "Match prefixes"),
+    ("soundex", "Match using SOUNDEX algorithm"),
+    ("lev", "Match headwords within Levenshtein distance one"),
+];
+
+const HELP: &str = "\
+DEFINE database word         -- look up word in database
+MATCH database strategy word -- match word in database using strategy
+SHOW DB                      -- list all accessible databases
+SHOW STRAT                   -- list available matching strategies
+SHOW INFO database           -- provide information about the database
+SHOW SERVER                  -- provide site-specific information
+OPTION MIME                  -- use MIME headers
+CLIENT info                  -- identify client to server
+STATUS                       -- display timing information
+HELP                         -- display this help information
+QUIT                         -- terminate connection";
+
+/// Header sent before each text response once a client asks for `OPTION MIME`
+const MIME_HEADER: &str =
+    "Content-type: text/plain; charset=utf-8\nContent-transfer-encoding: 8bit\n";
+
+/// One loaded dictionary, served as a DICT database
+struct Database {
+    /// Database name used in commands; the source id without whitespace
+    name: String,
+    source: Arc<dyn DictionarySource>,
+    /// Headwords by Soundex code
+    soundex: HashMap<String, Vec<String>>,
+}
+
+/// A `dictd`-compatible server (RFC 2229) over Aynary's dictionaries
+pub struct DictServer {
+    databases: Vec<Database>,
+    /// Formats entries the same way the app does
+    client: DictionaryClient,
+    /// Source of unique message ids for connection banners
+    connections: AtomicU64,
+    /// Connections being served right now
+    active: AtomicUsize,
+    max_connections: usize,
+}
+
+impl DictServer {
+    /// Serve `sources` as databases, highest priority first
+    pub fn new(sources: Vec<Arc<dyn DictionarySource>>) -> Self {
+        let mut names = HashSet::new();
+        let databases = sources
+            .iter()
+            .map(|source| {
+                let mut soundex: HashMap<String, Vec<String>> = HashMap::new();
+                for word in source.headwords() {
+                    if let Some(code) = fuzzy::soundex(&normalize::loose(word)) {
+                        soundex.entry(code).or_default().push(word.to_string());
+                    }
+                }
+
+                Database {
+                    name: unique_name(&source.metadata().id, &mut names),
+                    source: Arc::clone(source),
+                    soundex,
+                }
+            })
+            .collect();
+
+        Self {
+            databases,
+            client: DictionaryClient::with_sources(sources),
+            connections: AtomicU64::new(0),
+            active: AtomicUsize::new(0),
+            max_connections: MAX_CONNECTIONS,
+        }
+    }
+
+    /// Accept connections until the listener fails, one thread per client
+    pub fn serve(self, listener: TcpListener) -> Result<()> {
+        let server = Arc::new(self);
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Failed to accept DICT connection: {}", e);
+                    continue;
+                }
+            };
+
+            if server.active.fetch_add(1, Ordering::SeqCst) >= server.max_connections {
+                server.active.fetch_sub(1, Ordering::SeqCst);
+                // The client is turned away whether or not this arrives
+                let _ = stream.write_all(b"420 Server temporarily unavailable\r\n");
+                continue;
+            }
+
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                let _slot = ConnectionSlot(&server.active);
+                let peer = stream
+                    .peer_addr()
+                    .map(|address| address.to_string())
+                    .unwrap_or_default();
+                if let Err(e) = server.session(stream) {
+                    eprintln!("DICT connection {} closed: {:#}", peer, e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn session(&self, stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut session = Session {
+            server: self,
+            writer: stream,
+            mime: false,
+        };
+
+        let id = self.connections.fetch_add(1, Ordering::Relaxed);
+        session.status(
+            220,
+            &format!(
+                "aynary {} <mime> <{}.{}@aynary>",
+                env!("CARGO_PKG_VERSION"),
+                std::process::id(),
+                id
+            ),
+        )?;
+
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = (&mut reader).take(MAX_LINE_LEN).read_until(b'\n', &mut line)?;
+            if read == 0 {
+                return Ok(());
+            }
+            if !line.ends_with(b"\n") && read as u64 == MAX_LINE_LEN {
+                // Answer the whole line once rather than running its pieces
+                session.status(500, "line too long")?;
+                skip_line(&mut reader)?;
+                continue;
+            }
+            if !session.command(String::from_utf8_lossy(&line).trim())? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Databases named by a command: all of them for `*` and `!`
+    fn databases(&self, name: &str) -> Option<Vec<&Database>> {
+        match name {
+            "*" | "!" => Some(self.databases.iter().collect()),
+            _ => self
+                .databases
+                .iter()
+                .find(|database| database.name == name)
+                .map(|database| vec![database]),
+        }
+    }
+}
+
+/// Frees a connection slot when its session thread ends, however it ends
+struct ConnectionSlot<'a>(&'a AtomicUsize);
+
+impl Drop for ConnectionSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Discard input up to and including the next newline
+fn skip_line(reader: &mut impl BufRead) -> std::io::Result<()> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        match buffer.iter().position(|&byte| byte == b'\n') {
+            Some(end) => {
+                reader.consume(end + 1);
+                return Ok(());
+            }
+            None => {
+                let len = buffer.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// State of one client connection
+struct Session<'a> {
+    server: &'a DictServer,
+    writer: TcpStream,
+    /// Whether the client asked for MIME headers on text responses
+    mime: bool,
+}
+
+impl Session<'_> {
+    /// Handle one command line; `false` ends the session
+    fn command(&mut self, line: &str) -> Result<bool> {
+        let params = split_params(line);
+        let Some(command) = params.first() else {
+            return Ok(true);
+        };
+        let args: Vec<&str> = params[1..].iter().map(String::as_str).collect();
+
+        match (command.to_ascii_uppercase().as_str(), args.as_slice()) {
+            ("DEFINE", [database, word]) => self.define(database, word)?,
+            ("MATCH", [database, strategy, word]) => self.match_words(database, strategy, word)?,
+            ("SHOW", [what]) if is_any(what, &["DB", "DATABASES"]) => self.show_databases()?,
+            ("SHOW", [what]) if is_any(what, &["STRAT", "STRATEGIES"]) => self.show_strategies()?,
+            ("SHOW", [what, database]) if is_any(what, &["INFO"]) => self.show_info(database)?,
+            ("SHOW", [what]) if is_any(what, &["SERVER"]) => self.show_server()?,
+            ("OPTION", [option]) if is_any(option, &["MIME"]) => {
+                self.mime = true;
+                self.status(250, "ok - using MIME headers")?;
+            }
+            ("CLIENT", _) => self.status(250, "ok")?,
+            ("STATUS", []) => self.status(210, "status [d/m/c = 0/0/0; 0.000r 0.000u 0.000s]")?,
+            ("HELP", []) => {
+                self.status(113, "help text follows")?;
+                self.text(HELP)?;
+                self.status(250, "ok")?;
+            }
+            ("QUIT", []) => {
+                self.status(221, "bye")?;
+                return Ok(false);
+            }
+            ("AUTH" | "SASLAUTH", _) => self.status(502, "Command not implemented")?,
+            ("DEFINE" | "MATCH" | "SHOW" | "OPTION" | "STATUS" | "HELP" | "QUIT", _) => {
+                self.status(501, "Syntax error, illegal parameters")?
+            }
+            _ => self.status(500, "Syntax error, command not recognized")?,
+        }
+        Ok(true)
+    }
+
+    fn define(&mut self, database: &str, word: &str) -> Result<()> {
+        let Some(databases) = self.server.databases(database) else {
+            return self.status(
+                550,
+                "Invalid database, use \"SHOW DB\" for list of databases",
+            );
+        };
+
+        let folded = normalize::fold(word);
+        let mut definitions: Vec<(&Database, DictionaryEntry)> = Vec::new();
+        for db in databases {
+            let entries = db.source.lookup(&folded, false);
+            let found = !entries.is_empty();
+            definitions.extend(entries.into_iter().map(|entry| (db, entry)));
+            // "!" stops at the first database with a match
+            if found && database == "!" {
+                break;
+            }
+        }
+
+        if definitions.is_empty() {
+            return self.status(552, "No match");
+        }
+
+        self.status(150, &format!("{} definitions retrieved", definitions.len()))?;
+        for (db, entry) in definitions {
+            self.status(
+                151,
+                &format!(
+                    "{} {} {}",
+                    quote(&entry.word),
+                    db.name,
+                    quote(&db.source.metadata().name)
+                ),
+            )?;
+            let text = self
+                .server
+                .client
+                .format_entry(std::slice::from_ref(&entry));
+            self.text(text.trim_end())?;
+        }
+        self.status(250, "ok")
+    }
+
+    fn match_words(&mut self, database: &str, strategy: &str, word: &str) -> Result<()> {
+        let Some(databases) = self.server.databases(database) else {
+            return self.status(
+                550,
+                "Invalid database, use \"SHOW DB\" for list of databases",
+            );
+        };
+        let strategy = if strategy == "." {
+            DEFAULT_STRATEGY
+        } else {
+            strategy
+        };
+        if !STRATEGIES.iter().any(|(name, _)| *name == strategy) {
+            return self.status(
+                551,
+                "Invalid strategy, use \"SHOW STRAT\" for a list of strategies",
+            );
+        }
+
+        let mut matches: Vec<(&str, String)> = Vec::new();
+        for db in databases {
+            let words = match_database(db, strategy, word);
+            let found = !words.is_empty();
+            matches.extend(words.into_iter().map(|word| (db.name.as_str(), word)));
+            if found && database == "!" {
+                break;
+            }
+        }
+
+        if matches.is_empty() {
+            return self.status(552, "No match");
+        }
+
+        self.status(152, &format!("{} matches found", matches.len()))?;
+        let lines: Vec<String> = matches
+            .iter()
+            .map(|(database, word)| format!("{} {}", database, quote(word)))
+            .collect();
+        self.text(&lines.join("\n"))?;
+        self.status(250, "ok")
+    }
+
+    fn show_databases(&mut self) -> Result<()> {
+        if self.server.databases.is_empty() {
+            return self.status(554, "No databases present");
+        }
+
+        let lines: Vec<String> = self
+            .server
+            .databases
+            .iter()
+            .map(|db| format!("{} {}", db.name, quote(&db.source.metadata().name)))
+            .collect();
+        self.status(110, &format!("{} databases present", lines.len()))?;
+        self.text(&lines.join("\n"))?;
+        self.status(250, "ok")
+    }
+
+    fn show_strategies(&mut self) -> Result<()> {
+        let lines: Vec<String> = STRATEGIES
+            .iter()
+            .map(|(name, description)| format!("{} {}", name, quote(description)))
+            .collect();
+        self.status(111, &format!("{} strategies present", lines.len()))?;
+        self.text(&lines.join("\n"))?;
+        self.status(250, "ok")
+    }
+
+    fn show_info(&mut self, database: &str) -> Result<()> {
+        let Some(db) = self.server.databases.iter().find(|db| db.name == database) else {
+            return self.status(
+                550,
+                "Invalid database, use \"SHOW DB\" for list of databases",
+            );
+        };
+
+        let metadata = db.source.metadata();
+        let mut info = format!("{}\n\nDatabase: {}\n", metadata.name, db.name);
+        if let Some(language) = &metadata.language {
+            info.push_str(&format!("Language: {}\n", language));
+        }
+        if let Some(count) = metadata.entry_count {
+            info.push_str(&format!("Entries: {}\n", count));
+        }
+        if let Some(version) = &metadata.version {
+            info.push_str(&format!("Version: {}\n", version));
+        }
+        if let Some(license) = &metadata.license {
+            info.push_str(&format!("License: {}\n", license));
+        }
+        if let Some(description) = &metadata.description {
+            info.push_str(&format!("\n{}\n", description));
+        }
+        if let Some(attribution) = &metadata.attribution {
+            info.push_str(&format!("\n{}\n", attribution));
+        }
+
+        self.status(112, &format!("information for {}", db.name))?;
+        self.text(info.trim_end())?;
+        self.status(250, "ok")
+    }
+
+    fn show_server(&mut self) -> Result<()> {
+        let info = format!(
+            "Aynary {}\n\nServing {} databases.",
+            env!("CARGO_PKG_VERSION"),
+            self.server.databases.len()
+        );
+        self.status(114, "server information")?;
+        self.text(&info)?;
+        self.status(250, "ok")
+    }
+
+    fn status(&mut self, code: u16, text: &str) -> Result<()> {
+        self.writer
+            .write_all(format!("{} {}\r\n", code, text).as_bytes())
+            .context("Failed to write response")
+    }
+
+    /// Send a text response: CRLF line endings, dot-stuffed, ending with `.`
+    fn text(&mut self, text: &str) -> Result<()> {
+        let mut out = String::new();
+        if self.mime {
+            for line in MIME_HEADER.lines() {
+                out.push_str(line);
+                out.push_str("\r\n");
+            }
+            out.push_str("\r\n");
+        }
+        for line in text.lines() {
+            if line.starts_with('.') {
+                out.push('.');
+            }
+            out.push_str(line);
+            out.push_str("\r\n");
+        }
+        out.push_str(".\r\n");
+        self.writer
+            .write_all(out.as_bytes())
+            .context("Failed to write response")
+    }
+}
+
+/// Headwords in `db` matching `word` under `strategy`
+fn match_database(db: &Database, strategy: &str, word: &str) -> Vec<String> {
+    let folded = normalize::fold(word);
+    let mut words: Vec<String> = match strategy {
+        "exact" => db
+            .source
+            .lookup(&folded, false)
+            .into_iter()
+            .map(|entry| entry.word)
+            .collect(),
+        "prefix" => db.source.prefix_headwords(&folded, false, MAX_MATCHES),
+        "soundex" => fuzzy::soundex(&normalize::loose(word))
+            .and_then(|code| db.soundex.get(&code))
+            .cloned()
+            .unwrap_or_default(),
+        "lev" => db
+            .source
+            .fuzzy(&normalize::loose(word), 1)
+            .into_iter()
+            .map(|candidate| candidate.word)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    // Several entries can share a headword
+    let mut seen = HashSet::new();
+    words.retain(|word| seen.insert(word.clone()));
+    words.truncate(MAX_MATCHES);
+    words
+}
+
+fn is_any(param: &str, names: &[&str]) -> bool {
+    names.iter().any(|name| param.eq_ignore_ascii_case(name))
+}
+
+/// A database name for `id` that is a single atom and not taken yet
+fn unique_name(id: &str, taken: &mut HashSet<String>) -> String {
+    let base: String = id
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c == '"' {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let base = if base.is_empty() || base == "*" || base == "!" {
+        String::from("dictionary")
+    } else {
+        base
+    };
+
+    let mut name = base.clone();
+    let mut suffix = 2;
+    while !taken.insert(name.clone()) {
+        name = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{Definition, Meaning};
+    use crate::dict_client::DictConnection;
+    use crate::source::{MemorySource, SourceMetadata};
+
+    fn entry(word: &str) -> DictionaryEntry {
+        DictionaryEntry {
+            word: word.to_string(),
+            meanings: vec![Meaning {
+                definitions: vec![Definition {
+                    definition: format!("The word {}", word),
+                    ..Definition::default()
+                }],
+                ..Meaning::default()
+            }],
+            ..DictionaryEntry::default()
+        }
+    }
+
+    /// Serve one test dictionary in the background, returning its port
+    fn start(max_connections: usize) -> u16 {
+        let entries = ["cat", "cot", "cart", "catalog", "dog"].map(entry).to_vec();
+        let source = MemorySource::new(SourceMetadata::new("test dict", "Test"), entries);
+        let mut server = DictServer::new(vec![Arc::new(source)]);
+        server.max_connections = max_connections;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || server.serve(listener));
+        port
+    }
+
+    /// Send `line` and return the status line it gets back
+    fn send(reader: &mut BufReader<TcpStream>, line: &str) -> String {
+        reader
+            .get_mut()
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        response.trim_end().to_string()
+    }
+
+    /// Words matched in the test dictionary, sorted
+    fn matches(connection: &mut DictConnection, strategy: &str, word: &str) -> Vec<String> {
+        let mut words: Vec<String> = connection
+            .match_words("*", strategy, word)
+            .unwrap()
+            .into_iter()
+            .map(|(database, word)| {
+                assert_eq!(database, "test-dict");
+                word
+            })
+            .collect();
+        words.sort();
+        words
+    }
+
+    #[test]
+    fn answers_commands_by_syntax() {
+        let mut reader = BufReader::new(TcpStream::connect(("127.0.0.1", start(4))).unwrap());
+        let mut banner = String::new();
+        reader.read_line(&mut banner).unwrap();
+        assert!(banner.starts_with("220 aynary"));
+
+        assert_eq!(send(&mut reader, "client \"test client\""), "250 ok");
+        assert!(send(&mut reader, "DEFINE test-dict").starts_with("501 "));
+        assert!(send(&mut reader, "SHOW NOTHING").starts_with("501 "));
+        assert!(send(&mut reader, "AUTH user secret").starts_with("502 "));
+        assert!(send(&mut reader, "FROB").starts_with("500 "));
+        assert!(send(&mut reader, "DEFINE other cat").starts_with("550 "));
+        assert!(send(&mut reader, "MATCH * regexp c.t").starts_with("551 "));
+        assert!(send(&mut reader, "DEFINE * \"no such word\"").starts_with("552 "));
+        assert!(send(&mut reader, &"x".repeat(2000)).starts_with("500 line too long"));
+        assert!(send(&mut reader, "STATUS").starts_with("210 "));
+        assert_eq!(send(&mut reader, "QUIT"), "221 bye");
+    }
+
+    #[test]
+    fn matches_with_each_strategy() {
+        let mut connection = DictConnection::connect("127.0.0.1", start(4)).unwrap();
+        assert_eq!(matches(&mut connection, "exact", "CAT"), ["cat"]);
+        assert_eq!(
+            matches(&mut connection, "prefix", "ca"),
+            ["cart", "cat", "catalog"]
+        );
+        assert_eq!(matches(&mut connection, "soundex", "cad"), ["cat", "cot"]);
+        assert_eq!(
+            matches(&mut connection, "lev", "cat"),
+            ["cart", "cat", "cot"]
+        );
+        // The server default is "lev"
+        assert_eq!(matches(&mut connection, ".", "dig"), ["dog"]);
+        assert!(connection
+            .match_words("*", "prefix", "x")
+            .unwrap()
+            .is_empty());
+
+        let definitions = connection.define("!", "cat").unwrap();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].database, "test-dict");
+        assert!(definitions[0].text.contains("The word cat"));
+    }
+
+    #[test]
+    fn turns_away_clients_over_the_limit() {
+        let port = start(1);
+        let first = DictConnection::connect("127.0.0.1", port).unwrap();
+
+        let mut refused = String::new();
+        BufReader::new(TcpStream::connect(("127.0.0.1", port)).unwrap())
+            .read_line(&mut refused)
+            .unwrap();
+        assert_eq!(refused, "420 Server temporarily unavailable\r\n");
+
+        // The slot frees up once the first session has ended
+        drop(first);
+        let connected = (0..100).any(|_| {
+            thread::sleep(Duration::from_millis(10));
+            DictConnection::connect("127.0.0.1", port).is_ok()
+        });
+        assert!(connected);
+    }
+}
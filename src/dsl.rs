@@ -0,0 +1,666 @@
+use crate::api::{Definition, DictionaryEntry, Meaning, Phonetic, Relation, RelationKind};
+use crate::article::{self, current_meaning, is_link_only};
+use crate::markup;
+use crate::normalize;
+use crate::source::{MemorySource, SourceMetadata};
+use anyhow::{Context, Result};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Name suffix of the abbreviation table shipped with a dictionary, e.g.
+/// `En-Ru_abrv.dsl` next to `En-Ru.dsl`
+const ABBREVIATIONS_SUFFIX: &str = "_abrv";
+
+/// Lingvo's language names, matched by prefix so variants such as
+/// `GermanNewSpelling` or `PortugueseBrazilian` are covered
+const LANGUAGES: &[(&str, &str)] = &[
+    ("Arabic", "ar"),
+    ("Belarusian", "be"),
+    ("Bulgarian", "bg"),
+    ("Chinese", "zh"),
+    ("Czech", "cs"),
+    ("Danish", "da"),
+    ("Dutch", "nl"),
+    ("English", "en"),
+    ("Esperanto", "eo"),
+    ("Estonian", "et"),
+    ("Finnish", "fi"),
+    ("French", "fr"),
+    ("German", "de"),
+    ("Greek", "el"),
+    ("Hebrew", "he"),
+    ("Hungarian", "hu"),
+    ("Italian", "it"),
+    ("Japanese", "ja"),
+    ("Kazakh", "kk"),
+    ("Korean", "ko"),
+    ("Latin", "la"),
+    ("Latvian", "lv"),
+    ("Lithuanian", "lt"),
+    ("Norwegian", "no"),
+    ("Polish", "pl"),
+    ("Portuguese", "pt"),
+    ("Romanian", "ro"),
+    ("Russian", "ru"),
+    ("Serbian", "sr"),
+    ("Slovak", "sk"),
+    ("Slovenian", "sl"),
+    ("Spanish", "es"),
+    ("Swedish", "sv"),
+    ("Tatar", "tt"),
+    ("Turkish", "tr"),
+    ("Ukrainian", "uk"),
+];
+
+/// Whether `path` is an ABBYY Lingvo DSL dictionary, plain or
+/// dictzip-compressed. Abbreviation tables aren't dictionaries of their own.
+pub fn is_dsl(path: &Path) -> bool {
+    dsl_stem(path).is_some_and(|stem| !stem.ends_with(ABBREVIATIONS_SUFFIX))
+}
+
+/// Load a `.dsl` or `.dsl.dz` dictionary.
+///
+/// Each card becomes an entry per headword. Lines labelled only with `[p]`
+/// start a meaning with that part of speech, `[trn]` lines are definitions,
+/// `[ex]` examples go to the definition before them and so do `[com]`
+/// comments on a line of their own. Lines that only point elsewhere with
+/// `[ref]` become "see also" links. Labels are spelled out with the `_abrv.dsl` table
+/// when there is one, and a `.ann` annotation becomes the description.
+pub fn open(path: &Path) -> Result<MemorySource> {
+    let text = strip_comments(&read_text(path)?);
+    let headers = headers(&text);
+    let id = dsl_stem(path).unwrap_or_default().to_string();
+
+    let abbreviations = companion(path, &format!("{}{}", id, ABBREVIATIONS_SUFFIX))
+        .map(|path| match read_text(&path) {
+            Ok(text) => abbreviation_table(&strip_comments(&text)),
+            Err(e) => {
+                eprintln!("Skipping abbreviations {}: {:#}", path.display(), e);
+                HashMap::new()
+            }
+        })
+        .unwrap_or_default();
+
+    let language = headers
+        .get("INDEX_LANGUAGE")
+        .and_then(|name| language_code(name));
+    let target_language = headers
+        .get("CONTENTS_LANGUAGE")
+        .and_then(|name| language_code(name));
+
+    let mut entries = Vec::new();
+    for card in cards(&text) {
+        for mut entry in convert(&card, &abbreviations) {
+            entry.language = language.clone();
+            entry.target_language = target_language.clone();
+            entries.push(entry);
+        }
+    }
+
+    let name = headers
+        .get("NAME")
+        .filter(|name| !name.is_empty())
+        .cloned()
+        .unwrap_or_else(|| id.clone());
+    let mut metadata = SourceMetadata::new(id, name);
+    metadata.language = language;
+    metadata.description = annotation(path);
+
+    Ok(MemorySource::new(metadata, entries))
+}
+
+/// File name without `.dsl` or `.dsl.dz`
+fn dsl_stem(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_suffix(".dsl")
+        .or_else(|| name.strip_suffix(".dsl.dz"))
+}
+
+/// `stem.dsl` or `stem.dsl.dz` in the same directory as `path`
+fn companion(path: &Path, stem: &str) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    [".dsl", ".dsl.dz"]
+        .iter()
+        .map(|extension| dir.join(format!("{}{}", stem, extension)))
+        .find(|path| path.is_file())
+}
+
+/// The `.ann` file describing the dictionary, if any
+fn annotation(path: &Path) -> Option<String> {
+    let ann = path.parent()?.join(format!("{}.ann", dsl_stem(path)?));
+    let text = read_text(&ann).ok()?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Read a DSL file, decompressing `.dz` files. Lingvo writes UTF-16 with a
+/// byte order mark; UTF-8 files are accepted too.
+fn read_text(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut bytes = Vec::new();
+    if path.extension().is_some_and(|ext| ext == "dz") {
+        MultiGzDecoder::new(file).read_to_end(&mut bytes)
+    } else {
+        file.read_to_end(&mut bytes)
+    }
+    .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let encoding = match Encoding::for_bom(&bytes) {
+        Some((encoding, _)) => encoding,
+        // Without a byte order mark, UTF-16 shows up as zero bytes in the ASCII header
+        None if bytes.len() >= 2 && bytes[1] == 0 => UTF_16LE,
+        None if bytes.len() >= 2 && bytes[0] == 0 => UTF_16BE,
+        None => UTF_8,
+    };
+    let (text, _, _) = encoding.decode(&bytes);
+    Ok(text.into_owned())
+}
+
+/// Remove `{{comments}}`, which may span lines
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        stripped.push_str(&rest[..start]);
+        match rest[start..].find("}}") {
+            Some(end) => rest = &rest[start + end + 2..],
+            None => rest = "",
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// `#NAME "English-Russian"` style directives at the top of the file
+fn headers(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .map_while(|line| line.strip_prefix('#'))
+        .filter_map(|directive| {
+            let (key, value) = directive.split_once(char::is_whitespace)?;
+            Some((key.to_string(), value.trim().trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+fn language_code(name: &str) -> Option<String> {
+    LANGUAGES
+        .iter()
+        .find(|(language, _)| name.starts_with(language))
+        .map(|(_, code)| code.to_string())
+}
+
+/// Headword lines followed by their indented body lines
+#[derive(Debug, Default)]
+struct Card {
+    headwords: Vec<String>,
+    body: Vec<String>,
+}
+
+/// Split the text after the headers into cards. A body line starting with `@`
+/// opens a subentry, a card of its own, which a bare `@` closes.
+fn cards(text: &str) -> Vec<Card> {
+    let mut cards = Vec::new();
+    let mut card = Card::default();
+    let mut subentry: Option<Card> = None;
+
+    let lines = text.lines().skip_while(|line| line.starts_with('#'));
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if !line.starts_with([' ', '\t']) {
+            // Headwords after a body start the next card
+            if !card.body.is_empty() {
+                cards.push(std::mem::take(&mut card));
+                cards.extend(subentry.take());
+            }
+            card.headwords.push(trimmed.to_string());
+        } else if card.headwords.is_empty() {
+            continue;
+        } else if let Some(headword) = trimmed.strip_prefix('@') {
+            cards.extend(subentry.take());
+            let headword = headword.trim();
+            if !headword.is_empty() {
+                subentry = Some(Card {
+                    headwords: vec![headword.to_string()],
+                    body: Vec::new(),
+                });
+            }
+        } else if let Some(subentry) = &mut subentry {
+            subentry.body.push(trimmed.to_string());
+        } else {
+            card.body.push(trimmed.to_string());
+        }
+    }
+    cards.push(card);
+    cards.extend(subentry);
+    cards
+}
+
+/// Lookup forms of a headword line. `{...}` parts are shown by Lingvo but not
+/// indexed, so they are dropped; `(...)` parts are optional, giving one form
+/// with them and one without.
+fn headword_forms(line: &str) -> Vec<String> {
+    let (full, short) = headword_variants(line);
+    article::unique_forms([full, short])
+}
+
+/// A headword line with and without its optional parts
+fn headword_variants(line: &str) -> (String, String) {
+    let mut full = String::new();
+    let mut short = String::new();
+    let mut unsorted = false;
+    let mut optional = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    if !unsorted {
+                        full.push(escaped);
+                        if !optional {
+                            short.push(escaped);
+                        }
+                    }
+                }
+            }
+            '{' => unsorted = true,
+            '}' => unsorted = false,
+            '(' if !unsorted => optional = true,
+            ')' if !unsorted => optional = false,
+            _ if unsorted => {}
+            _ => {
+                full.push(c);
+                if !optional {
+                    short.push(c);
+                }
+            }
+        }
+    }
+
+    (collapse(&full), collapse(&short))
+}
+
+/// What one body line holds, with the markup resolved
+#[derive(Debug, Default)]
+struct Line {
+    /// Text outside examples, labels, transcriptions and media
+    text: String,
+    example: String,
+    /// `[p]` labels
+    labels: Vec<String>,
+    /// Targets of `[ref]` and `<<...>>` links
+    refs: Vec<String>,
+    /// `[t]` transcription
+    transcription: String,
+    /// Whether the line holds a `[trn]` translation
+    translated: bool,
+    /// `[com]` comments, which are also part of `text`
+    comment: String,
+}
+
+/// Resolve the markup of a body line; `~` stands for `headword`
+fn parse_line(line: &str, headword: &str) -> Line {
+    let mut parser = LineParser::default();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\\' => {
+                if let Some(escaped) = rest.chars().next() {
+                    rest = &rest[escaped.len_utf8()..];
+                    parser.push(escaped.encode_utf8(&mut [0; 4]));
+                }
+            }
+            '~' => parser.push(headword),
+            '<' if rest.starts_with('<') => {
+                rest = &rest[1..];
+                parser.tag("ref", false);
+            }
+            '>' if rest.starts_with('>') && parser.linked > 0 => {
+                rest = &rest[1..];
+                parser.tag("ref", true);
+            }
+            '[' => match rest.find(']') {
+                Some(end) => {
+                    let tag = &rest[..end];
+                    rest = &rest[end + 1..];
+                    let name = tag
+                        .trim_start_matches('/')
+                        .trim_start_matches('!')
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default();
+                    parser.tag(name, tag.starts_with('/'));
+                }
+                None => parser.push("["),
+            },
+            _ => parser.push(c.encode_utf8(&mut [0; 4])),
+        }
+    }
+
+    let mut parsed = parser.line;
+    parsed.text = collapse(&parsed.text);
+    parsed.example = collapse(&parsed.example);
+    parsed.transcription = collapse(&parsed.transcription);
+    parsed.comment = collapse(&parsed.comment);
+    parsed
+}
+
+/// Routes the text of a line by the tags open around it
+#[derive(Default)]
+struct LineParser {
+    line: Line,
+    label: String,
+    link: String,
+    example: usize,
+    labelled: usize,
+    transcribed: usize,
+    linked: usize,
+    media: usize,
+    commented: usize,
+}
+
+impl LineParser {
+    fn push(&mut self, text: &str) {
+        if self.media > 0 {
+            // Sound and picture file names
+        } else if self.transcribed > 0 {
+            self.line.transcription.push_str(text);
+        } else if self.example > 0 {
+            self.line.example.push_str(text);
+        } else if self.labelled > 0 {
+            self.label.push_str(text);
+        } else {
+            self.line.text.push_str(text);
+            if self.linked > 0 {
+                self.link.push_str(text);
+            }
+            if self.commented > 0 {
+                self.line.comment.push_str(text);
+            }
+        }
+    }
+
+    fn tag(&mut self, name: &str, closing: bool) {
+        let depth = match name {
+            "ex" => &mut self.example,
+            "p" => &mut self.labelled,
+            "t" => &mut self.transcribed,
+            "ref" => &mut self.linked,
+            "s" | "video" => &mut self.media,
+            "com" => &mut self.commented,
+            "trn" => {
+                self.line.translated = true;
+                return;
+            }
+            // Formatting, indentation and the rest only style their text
+            _ => return,
+        };
+        if !closing {
+            *depth += 1;
+            return;
+        }
+        *depth = depth.saturating_sub(1);
+        match name {
+            "p" => finish(&mut self.label, &mut self.line.labels),
+            "ref" => finish(&mut self.link, &mut self.line.refs),
+            _ => {}
+        }
+    }
+}
+
+/// Move a finished label or link into `list`
+fn finish(buffer: &mut String, list: &mut Vec<String>) {
+    let text = collapse(buffer);
+    if !text.is_empty() {
+        list.push(text);
+    }
+    buffer.clear();
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Roman numerals on a line of their own separate homonyms
+fn is_homonym_number(text: &str) -> bool {
+    !text.is_empty() && text.len() <= 4 && text.chars().all(|c| matches!(c, 'I' | 'V' | 'X'))
+}
+
+fn convert(card: &Card, abbreviations: &HashMap<String, String>) -> Vec<DictionaryEntry> {
+    let forms = article::unique_forms(card.headwords.iter().flat_map(|line| headword_forms(line)));
+    let Some(headword) = forms.first() else {
+        return Vec::new();
+    };
+    let folded_headword = normalize::fold(headword);
+    // `~` stands for the headword without its optional parts
+    let tilde = card
+        .headwords
+        .first()
+        .map(|line| headword_variants(line).1)
+        .filter(|short| !short.is_empty())
+        .unwrap_or_else(|| headword.clone());
+
+    let expand = |label: &String| abbreviations.get(label).unwrap_or(label).clone();
+
+    let mut meanings: Vec<Meaning> = Vec::new();
+    let mut phonetic: Option<String> = None;
+    for raw in &card.body {
+        let line = parse_line(raw, &tilde);
+        if phonetic.is_none() && !line.transcription.is_empty() {
+            phonetic = Some(line.transcription.clone());
+        }
+        let labels: Vec<String> = line.labels.iter().map(expand).collect();
+        let text = markup::strip_sense_number(&line.text);
+
+        if line.translated {
+            // Translations are definitions even when they match the headword
+            add_definition(&mut meanings, text, &line.refs, labels);
+        } else if text.is_empty() || normalize::fold(text) == folded_headword {
+            // A heading: the headword repeated, or a part of speech
+            if !labels.is_empty() {
+                start_meaning(&mut meanings, labels.join(", "));
+            }
+        } else if text == line.comment {
+            add_comment(&mut meanings, text);
+        } else if is_homonym_number(text) {
+            start_meaning(&mut meanings, labels.join(", "));
+        } else if !line.refs.is_empty() && is_link_only(text, &line.refs) {
+            add_link(current_meaning(&mut meanings), &line.refs);
+        } else {
+            add_definition(&mut meanings, text, &line.refs, labels);
+        }
+
+        if !line.example.is_empty() {
+            article::add_example(&mut meanings, line.example);
+        }
+    }
+
+    meanings.retain(|meaning| !meaning.definitions.is_empty() || !meaning.relations.is_empty());
+    if meanings.is_empty() {
+        return Vec::new();
+    }
+
+    let phonetics: Vec<Phonetic> = phonetic
+        .iter()
+        .map(|text| Phonetic {
+            text: Some(text.clone()),
+            ..Phonetic::default()
+        })
+        .collect();
+
+    article::entry_per_form(
+        &forms,
+        DictionaryEntry {
+            phonetic,
+            phonetics,
+            meanings,
+            ..DictionaryEntry::default()
+        },
+    )
+}
+
+/// Start a meaning with the given part of speech, reusing the current one if
+/// nothing has been added to it yet
+fn start_meaning(meanings: &mut Vec<Meaning>, part_of_speech: String) {
+    match meanings.last_mut() {
+        Some(meaning) if meaning.definitions.is_empty() && meaning.relations.is_empty() => {
+            if !part_of_speech.is_empty() {
+                meaning.part_of_speech = part_of_speech;
+            }
+        }
+        _ => meanings.push(Meaning {
+            part_of_speech,
+            ..Meaning::default()
+        }),
+    }
+}
+
+fn add_definition(meanings: &mut Vec<Meaning>, text: &str, refs: &[String], tags: Vec<String>) {
+    let meaning = current_meaning(meanings);
+    if !refs.is_empty() {
+        add_link(meaning, refs);
+    }
+    meaning.definitions.push(Definition {
+        definition: text.to_string(),
+        tags,
+        ..Definition::default()
+    });
+}
+
+/// Append a comment line to the latest definition, in parentheses
+fn add_comment(meanings: &mut Vec<Meaning>, comment: &str) {
+    match current_meaning(meanings).definitions.last_mut() {
+        Some(definition) => {
+            definition.definition.push_str(" (");
+            definition
+                .definition
+                .push_str(comment.trim_matches(['(', ')']));
+            definition.definition.push(')');
+        }
+        None => add_definition(meanings, comment, &[], Vec::new()),
+    }
+}
+
+fn add_link(meaning: &mut Meaning, refs: &[String]) {
+    match meaning
+        .relations
+        .iter_mut()
+        .find(|relation| relation.kind == RelationKind::AlsoSee)
+    {
+        Some(relation) => relation.words.extend(refs.iter().cloned()),
+        None => meaning.relations.push(Relation {
+            kind: RelationKind::AlsoSee,
+            words: refs.to_vec(),
+        }),
+    }
+}
+
+/// Abbreviations and what they stand for, from an `_abrv.dsl` file whose cards
+/// each hold one abbreviation
+fn abbreviation_table(text: &str) -> HashMap<String, String> {
+    let mut table = HashMap::new();
+    for card in cards(text) {
+        let Some(expansion) = card
+            .body
+            .iter()
+            .map(|line| parse_line(line, "").text)
+            .find(|text| !text.is_empty())
+        else {
+            continue;
+        };
+        for headword in &card.headwords {
+            for form in headword_forms(headword) {
+                table.insert(form, expansion.clone());
+            }
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(headwords: &[&str], body: &[&str]) -> Card {
+        Card {
+            headwords: headwords.iter().map(|line| line.to_string()).collect(),
+            body: body.iter().map(|line| line.to_string()).collect(),
+        }
+    }
+
+    fn definitions(entry: &DictionaryEntry) -> Vec<&str> {
+        entry.meanings[0]
+            .definitions
+            .iter()
+            .map(|definition| definition.definition.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn headword_forms_drop_unsorted_parts_and_expand_optional_ones() {
+        assert_eq!(headword_forms("{to }run (away)"), ["run away", "run"]);
+        assert_eq!(headword_forms("a\\(b\\)"), ["a(b)"]);
+    }
+
+    #[test]
+    fn tilde_is_the_headword_without_optional_parts() {
+        let entries = convert(
+            &card(
+                &["run (away)"],
+                &["[m1][trn]бежать[/trn]", "[m2][ex]~ fast[/ex]"],
+            ),
+            &HashMap::new(),
+        );
+        let definition = &entries[0].meanings[0].definitions[0];
+        assert_eq!(definition.example.as_deref(), Some("run fast"));
+    }
+
+    #[test]
+    fn translations_matching_the_headword_are_definitions() {
+        let entries = convert(
+            &card(&["taxi"], &["[m1][p]n[/p]", "[m1]1) [trn]taxi[/trn]"]),
+            &HashMap::new(),
+        );
+        assert_eq!(entries[0].meanings[0].part_of_speech, "n");
+        assert_eq!(definitions(&entries[0]), ["taxi"]);
+    }
+
+    #[test]
+    fn comment_lines_annotate_the_definition_before_them() {
+        let entries = convert(
+            &card(
+                &["cat"],
+                &["[m1][trn]кошка[/trn]", "[m2][com](informal)[/com]"],
+            ),
+            &HashMap::new(),
+        );
+        assert_eq!(definitions(&entries[0]), ["кошка (informal)"]);
+    }
+
+    #[test]
+    fn subentries_become_cards_of_their_own() {
+        let cards =
+            cards("#NAME \"Test\"\ncat\n\t[trn]кошка[/trn]\n\t@ cat's eye\n\tкатафот\n\t@\n");
+        assert_eq!(cards.len(), 2);
+        let subentry = cards
+            .iter()
+            .find(|card| card.headwords == ["cat's eye"])
+            .unwrap();
+        assert_eq!(subentry.body, ["катафот"]);
+    }
+
+    #[test]
+    fn unclosed_markup_keeps_the_text() {
+        let line = parse_line("[b]bold [i", "x");
+        assert_eq!(line.text, "bold [i");
+        assert_eq!(strip_comments("a {{unterminated"), "a ");
+    }
+}
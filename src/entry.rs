@@ -0,0 +1,115 @@
+// The entry schema of JSON and compiled dictionaries, kept apart from api.rs
+// so the build script can check the bundled dictionary against it
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DictionaryEntry {
+    pub word: String,
+    pub phonetic: Option<String>,
+    pub phonetics: Vec<Phonetic>,
+    pub meanings: Vec<Meaning>,
+    pub license: Option<License>,
+    #[serde(rename = "sourceUrls")]
+    pub source_urls: Vec<String>,
+    /// Relative usage frequency (higher is more common), used to rank suggestions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etymology: Option<String>,
+    /// Inflected and alternative forms of the headword ("ran", "running")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forms: Vec<String>,
+    /// Language of the headword, as a BCP 47 or ISO 639 code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Images embedded in the definitions, as resource names for the source's
+    /// `resource`, in the order of their placeholders in the text
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// Language the definitions are translations into, for bilingual dictionaries
+    #[serde(
+        default,
+        rename = "targetLanguage",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub target_language: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Phonetic {
+    pub text: Option<String>,
+    pub audio: Option<String>,
+    #[serde(rename = "sourceUrl")]
+    pub source_url: Option<String>,
+    pub license: Option<License>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Meaning {
+    #[serde(rename = "partOfSpeech")]
+    pub part_of_speech: String,
+    pub definitions: Vec<Definition>,
+    pub synonyms: Vec<String>,
+    pub antonyms: Vec<String>,
+    /// Links to semantically related words, such as WordNet's hypernyms
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relations: Vec<Relation>,
+}
+
+/// Words related to a meaning in one particular way
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relation {
+    pub kind: RelationKind,
+    pub words: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelationKind {
+    /// A more general term ("dog" → "canine")
+    Hypernym,
+    /// A more specific term ("dog" → "puppy")
+    Hyponym,
+    /// A part or member ("tree" → "trunk")
+    Meronym,
+    /// The whole this is part of ("trunk" → "tree")
+    Holonym,
+    /// An adjective with a similar meaning ("wet" → "damp")
+    SimilarTo,
+    /// A word from the same root in another part of speech ("run" → "runner")
+    Derivation,
+    /// A loosely related word worth looking up
+    AlsoSee,
+}
+
+impl RelationKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Hypernym => "Type of",
+            Self::Hyponym => "Types",
+            Self::Meronym => "Parts",
+            Self::Holonym => "Part of",
+            Self::SimilarTo => "Similar to",
+            Self::Derivation => "Related forms",
+            Self::AlsoSee => "See also",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Definition {
+    pub definition: String,
+    pub synonyms: Vec<String>,
+    pub antonyms: Vec<String>,
+    pub example: Option<String>,
+    /// Usage labels such as "archaic", "slang" or "US"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct License {
+    pub name: String,
+    pub url: String,
+}
@@ -0,0 +1,371 @@
+use crate::api::{Definition, DictionaryEntry, Meaning, Phonetic};
+use crate::import::{EntryWriter, ImportStats};
+use crate::xml::{Element, ElementReader, InvalidElement};
+use anyhow::Result;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// Malformed entries reported individually before only being counted
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Languages of a bilingual dictionary
+#[derive(Debug, Clone, Default)]
+pub struct LanguagePair {
+    /// Language of the headwords
+    pub source: Option<String>,
+    /// Language of the translations
+    pub target: Option<String>,
+}
+
+impl LanguagePair {
+    /// FreeDict names its files after the pair, e.g. `eng-fra.tei` or `deu-eng.tei.gz`
+    pub fn from_file_name(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let stem = name.split('.').next().unwrap_or_default();
+        match stem.split_once('-') {
+            Some((source, target)) if !source.is_empty() && !target.is_empty() => Self {
+                source: Some(source.to_string()),
+                target: Some(target.to_string()),
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// Convert a FreeDict TEI document, streaming one `<entry>` at a time.
+///
+/// Both TEI P5 (`<cit type="trans"><quote>`) and the older P4 layout
+/// (`<trans><tr>`) are understood. Each sense becomes a definition listing its
+/// translations, grouped into meanings by part of speech.
+pub fn import<W: Write>(
+    input: impl BufRead,
+    languages: &LanguagePair,
+    output: &mut EntryWriter<W>,
+) -> Result<ImportStats> {
+    let mut stats = ImportStats::default();
+    let mut reader = ElementReader::new(input, "entry");
+
+    loop {
+        let element = match reader.next_element() {
+            Ok(Some(element)) => element,
+            Ok(None) => break,
+            // e.g. an entity the document doesn't declare
+            Err(e) if e.is::<InvalidElement>() => {
+                stats.records += 1;
+                stats.skipped += 1;
+                if stats.skipped <= MAX_REPORTED_ERRORS {
+                    eprintln!("Skipping entry: {}", e);
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        stats.records += 1;
+        match convert(&element, languages) {
+            Some(entry) => output.write(&entry)?,
+            None => {
+                stats.skipped += 1;
+                if stats.skipped <= MAX_REPORTED_ERRORS {
+                    eprintln!(
+                        "Skipping entry without a headword or senses before byte {}",
+                        reader.position()
+                    );
+                }
+            }
+        }
+    }
+
+    stats.entries = output.count();
+    Ok(stats)
+}
+
+fn convert(entry: &Element, languages: &LanguagePair) -> Option<DictionaryEntry> {
+    // Only the entry's own forms: <cit> and <re> hold the orths of
+    // translations and related entries
+    let forms_of_entry: Vec<&Element> = std::iter::once(entry)
+        .chain(entry.children_named("form"))
+        .collect();
+    let mut orths = forms_of_entry
+        .iter()
+        .flat_map(|form| form.children_named("orth"))
+        .map(Element::text);
+    let word = orths.next().filter(|word| !word.is_empty())?;
+    let forms: Vec<String> = orths.filter(|orth| *orth != word).collect();
+
+    let phonetics: Vec<Phonetic> = forms_of_entry
+        .iter()
+        .flat_map(|form| form.children_named("pron"))
+        .map(Element::text)
+        .filter(|pron| !pron.is_empty())
+        .map(|pron| Phonetic {
+            text: Some(pron),
+            ..Phonetic::default()
+        })
+        .collect();
+
+    let grammar = Grammar::default().refine(entry.child("gramGrp"));
+    let mut senses = Vec::new();
+    if entry.child("sense").is_some() {
+        collect_senses(entry, &grammar, &[], &mut senses);
+    } else {
+        // Some older dictionaries put the translations directly in the entry
+        senses
+            .extend(definition(entry, Vec::new()).map(|definition| (grammar.clone(), definition)));
+    }
+    if senses.is_empty() {
+        return None;
+    }
+
+    let mut meanings: Vec<Meaning> = Vec::new();
+    for (grammar, definition) in senses {
+        let part_of_speech = grammar.describe();
+        match meanings
+            .iter_mut()
+            .find(|meaning| meaning.part_of_speech == part_of_speech)
+        {
+            Some(meaning) => meaning.definitions.push(definition),
+            None => meanings.push(Meaning {
+                part_of_speech,
+                definitions: vec![definition],
+                ..Meaning::default()
+            }),
+        }
+    }
+
+    Some(DictionaryEntry {
+        word,
+        phonetic: phonetics.first().and_then(|phonetic| phonetic.text.clone()),
+        phonetics,
+        meanings,
+        forms,
+        language: languages.source.clone(),
+        target_language: languages.target.clone(),
+        ..DictionaryEntry::default()
+    })
+}
+
+/// Leaf senses under `parent`, each with the grammar and usage labels it inherits
+fn collect_senses(
+    parent: &Element,
+    grammar: &Grammar,
+    labels: &[String],
+    senses: &mut Vec<(Grammar, Definition)>,
+) {
+    for sense in parent.children_named("sense") {
+        let grammar = grammar.clone().refine(sense.child("gramGrp"));
+        let mut labels = labels.to_vec();
+        labels.extend(usage_labels(sense));
+
+        if sense.child("sense").is_some() {
+            collect_senses(sense, &grammar, &labels, senses);
+        } else if let Some(definition) = definition(sense, labels) {
+            senses.push((grammar, definition));
+        }
+    }
+}
+
+fn definition(sense: &Element, tags: Vec<String>) -> Option<Definition> {
+    let mut translations: Vec<String> = sense
+        .children_named("cit")
+        .filter(|cit| cit.attribute("type") == Some("trans"))
+        .filter_map(translation)
+        .collect();
+    // TEI P4: <trans><tr>chat</tr></trans>
+    translations.extend(
+        sense
+            .children_named("trans")
+            .flat_map(|trans| trans.find_all("tr"))
+            .map(Element::text)
+            .filter(|tr| !tr.is_empty()),
+    );
+
+    let gloss = sense
+        .child("def")
+        .map(Element::text)
+        .filter(|def| !def.is_empty());
+    let definition = match (translations.is_empty(), gloss) {
+        (false, Some(gloss)) => format!("{} ({})", translations.join(", "), gloss),
+        (false, None) => translations.join(", "),
+        (true, Some(gloss)) => gloss,
+        (true, None) => return None,
+    };
+
+    let example = sense
+        .children_named("cit")
+        .find(|cit| cit.attribute("type") == Some("example"))
+        .and_then(|cit| {
+            let text = cit.child("quote")?.text();
+            Some(match cit.child("cit").and_then(translation) {
+                Some(translated) => format!("{} ({})", text, translated),
+                None => text,
+            })
+        });
+
+    Some(Definition {
+        definition,
+        synonyms: cross_references(sense, "syn"),
+        antonyms: cross_references(sense, "ant"),
+        example,
+        tags,
+    })
+}
+
+/// The quoted text of a `<cit>`, with the translation's gender when given
+fn translation(cit: &Element) -> Option<String> {
+    let quote = cit.child("quote")?.text();
+    if quote.is_empty() {
+        return None;
+    }
+    match cit.child("gramGrp").and_then(|grammar| grammar.find("gen")) {
+        Some(gender) => Some(format!("{} ({})", quote, gender.text())),
+        None => Some(quote),
+    }
+}
+
+fn usage_labels(sense: &Element) -> Vec<String> {
+    sense
+        .children_named("usg")
+        .map(Element::text)
+        .filter(|label| !label.is_empty())
+        .collect()
+}
+
+/// Headwords of `<xr type="...">` cross-references
+fn cross_references(sense: &Element, kind: &str) -> Vec<String> {
+    sense
+        .children_named("xr")
+        .filter(|xr| xr.attribute("type") == Some(kind))
+        .flat_map(|xr| xr.find_all("ref"))
+        .map(Element::text)
+        .collect()
+}
+
+/// Part of speech and gender from a `<gramGrp>`, inherited by nested senses
+#[derive(Debug, Clone, Default)]
+struct Grammar {
+    pos: Option<String>,
+    gender: Option<String>,
+}
+
+impl Grammar {
+    fn refine(mut self, group: Option<&Element>) -> Self {
+        if let Some(group) = group {
+            if let Some(pos) = group.find("pos") {
+                self.pos = Some(pos.text());
+            }
+            if let Some(gender) = group.find("gen") {
+                self.gender = Some(gender.text());
+            }
+        }
+        self
+    }
+
+    /// e.g. "noun (feminine)"
+    fn describe(&self) -> String {
+        let pos = self.pos.as_deref().map(part_of_speech).unwrap_or_default();
+        match self.gender.as_deref().map(gender) {
+            Some(gender) if pos.is_empty() => gender.to_string(),
+            Some(gender) => format!("{} ({})", pos, gender),
+            None => pos.to_string(),
+        }
+    }
+}
+
+/// FreeDict's abbreviated parts of speech, spelled out like the bundled data
+fn part_of_speech(pos: &str) -> &str {
+    match pos {
+        "n" => "noun",
+        "v" | "vi" | "vt" => "verb",
+        "adj" => "adjective",
+        "adv" => "adverb",
+        "pn" => "proper noun",
+        "prep" => "preposition",
+        "conj" => "conjunction",
+        "pron" => "pronoun",
+        "int" | "intj" => "interjection",
+        "num" => "numeral",
+        "art" => "article",
+        "ptcl" => "particle",
+        "abbr" => "abbreviation",
+        other => other,
+    }
+}
+
+fn gender(gen: &str) -> &str {
+    match gen {
+        "m" | "masc" => "masculine",
+        "f" | "fem" => "feminine",
+        "n" | "neut" => "neuter",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The entries imported from `document`, and the import's counts
+    fn import_document(document: &str) -> (Vec<DictionaryEntry>, ImportStats) {
+        let languages = LanguagePair::from_file_name(Path::new("fra-eng.tei.gz"));
+        let mut json = Vec::new();
+        let mut writer = EntryWriter::new(&mut json).unwrap();
+        let stats = import(document.as_bytes(), &languages, &mut writer).unwrap();
+        writer.finish().unwrap();
+        (serde_json::from_slice(&json).unwrap(), stats)
+    }
+
+    #[test]
+    fn converts_p5_and_p4_entries() {
+        let document = r#"<TEI><text><body>
+            <entry>
+                <form><orth>chat</orth><orth>chatte</orth><pron>ʃa</pron></form>
+                <gramGrp><pos>n</pos><gen>m</gen></gramGrp>
+                <sense>
+                    <usg>zoology</usg>
+                    <sense>
+                        <cit type="trans"><quote>cat</quote></cit>
+                        <cit type="trans"><quote>tomcat</quote></cit>
+                        <cit type="example"><quote>le chat dort</quote><cit type="trans"><quote>the cat sleeps</quote></cit></cit>
+                        <xr type="syn"><ref>matou</ref></xr>
+                    </sense>
+                </sense>
+                <sense><gramGrp><pos>v</pos></gramGrp><def>to chat online</def></sense>
+            </entry>
+            <entry><orth>chien</orth><trans><tr>dog</tr><tr>hound</tr></trans></entry>
+            <entry><form><orth>vide</orth></form></entry>
+            <entry><form><orth>&nbsp;</orth></form><sense><def>x</def></sense></entry>
+        </body></text></TEI>"#;
+        let (entries, stats) = import_document(document);
+        assert_eq!((stats.records, stats.entries, stats.skipped), (4, 2, 2));
+
+        let chat = &entries[0];
+        assert_eq!(chat.word, "chat");
+        assert_eq!(chat.forms, ["chatte"]);
+        assert_eq!(chat.phonetic.as_deref(), Some("ʃa"));
+        assert_eq!(chat.language.as_deref(), Some("fra"));
+        assert_eq!(chat.target_language.as_deref(), Some("eng"));
+
+        assert_eq!(chat.meanings.len(), 2);
+        assert_eq!(chat.meanings[0].part_of_speech, "noun (masculine)");
+        let definition = &chat.meanings[0].definitions[0];
+        assert_eq!(definition.definition, "cat, tomcat");
+        assert_eq!(definition.tags, ["zoology"]);
+        assert_eq!(definition.synonyms, ["matou"]);
+        assert_eq!(
+            definition.example.as_deref(),
+            Some("le chat dort (the cat sleeps)")
+        );
+        // A nested <gramGrp> overrides the part of speech but keeps the gender
+        assert_eq!(chat.meanings[1].part_of_speech, "verb (masculine)");
+        assert_eq!(chat.meanings[1].definitions[0].definition, "to chat online");
+
+        assert_eq!(entries[1].word, "chien");
+        assert_eq!(
+            entries[1].meanings[0].definitions[0].definition,
+            "dog, hound"
+        );
+    }
+}
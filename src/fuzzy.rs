@@ -0,0 +1,183 @@
+/// BK-tree over headword keys for edit-distance queries.
+///
+/// Each child edge is labelled with the distance between parent and child,
+/// so by the triangle inequality a query only needs to descend into edges
+/// within `max_distance` of its own distance to the parent.
+#[derive(Debug, Default)]
+pub struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+#[derive(Debug)]
+struct BkNode {
+    key: String,
+    children: Vec<(usize, usize)>,
+}
+
+impl BkTree {
+    pub fn build<I>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut tree = Self::default();
+        for key in keys {
+            tree.insert(key);
+        }
+        tree
+    }
+
+    pub fn insert(&mut self, key: String) {
+        if self.nodes.is_empty() {
+            self.nodes.push(BkNode { key, children: Vec::new() });
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let distance = levenshtein(&self.nodes[current].key, &key);
+            if distance == 0 {
+                return;
+            }
+
+            let next = self.nodes[current]
+                .children
+                .iter()
+                .find(|(edge, _)| *edge == distance)
+                .map(|(_, child)| *child);
+
+            match next {
+                Some(child) => current = child,
+                None => {
+                    let id = self.nodes.len();
+                    self.nodes.push(BkNode { key, children: Vec::new() });
+                    self.nodes[current].children.push((distance, id));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Every key within `max_distance` edits of `query`, with its distance, unordered
+    pub fn find(&self, query: &str, max_distance: usize) -> Vec<(&str, usize)> {
+        let mut found = Vec::new();
+        if self.nodes.is_empty() {
+            return found;
+        }
+
+        let mut pending = vec![0];
+        while let Some(id) = pending.pop() {
+            let node = &self.nodes[id];
+            let distance = levenshtein(&node.key, query);
+            if distance <= max_distance {
+                found.push((node.key.as_str(), distance));
+            }
+
+            let low = distance.saturating_sub(max_distance);
+            let high = distance + max_distance;
+            pending.extend(
+                node.children
+                    .iter()
+                    .filter(|(edge, _)| (low..=high).contains(edge))
+                    .map(|(_, child)| *child),
+            );
+        }
+        found
+    }
+}
+
+/// Levenshtein distance counted in Unicode scalar values
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// American Soundex code of a word: its first letter followed by three digits
+/// for the consonant sounds after it, e.g. "Robert" and "Rupert" → "R163".
+/// Non-ASCII letters are ignored, so pass accent-stripped text. Returns `None`
+/// for words without any ASCII letter.
+pub fn soundex(word: &str) -> Option<String> {
+    fn digit(c: char) -> Option<char> {
+        match c {
+            'b' | 'f' | 'p' | 'v' => Some('1'),
+            'c' | 'g' | 'j' | 'k' | 'q' | 's' | 'x' | 'z' => Some('2'),
+            'd' | 't' => Some('3'),
+            'l' => Some('4'),
+            'm' | 'n' => Some('5'),
+            'r' => Some('6'),
+            _ => None,
+        }
+    }
+
+    let mut letters = word
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase());
+    let first = letters.next()?;
+
+    let mut code = String::from(first.to_ascii_uppercase());
+    let mut previous = digit(first);
+    for c in letters {
+        let current = digit(c);
+        if current.is_some() && current != previous {
+            code.extend(current);
+            if code.len() == 4 {
+                break;
+            }
+        }
+        // 'h' and 'w' don't separate letters with the same code; vowels do
+        if c != 'h' && c != 'w' {
+            previous = current;
+        }
+    }
+
+    while code.len() < 4 {
+        code.push('0');
+    }
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_edits_in_characters() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("café", "cafe"), 1);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+
+    #[test]
+    fn finds_keys_within_the_distance() {
+        let keys = ["cat", "cart", "dog", "coat", "cat", "catalog"];
+        let tree = BkTree::build(keys.map(String::from));
+        let mut found = tree.find("cat", 1);
+        found.sort();
+        assert_eq!(found, [("cart", 1), ("cat", 0), ("coat", 1)]);
+        assert_eq!(tree.find("dig", 1), [("dog", 1)]);
+        assert!(BkTree::default().find("cat", 2).is_empty());
+    }
+
+    #[test]
+    fn soundex_codes_consonant_sounds() {
+        assert_eq!(soundex("Robert").as_deref(), Some("R163"));
+        assert_eq!(soundex("Rupert").as_deref(), Some("R163"));
+        // 'h' doesn't separate the 's' and 'c' sounds around it
+        assert_eq!(soundex("Ashcraft").as_deref(), Some("A261"));
+        assert_eq!(soundex("Tymczak").as_deref(), Some("T522"));
+        assert_eq!(soundex("Lee").as_deref(), Some("L000"));
+        assert_eq!(soundex("123"), None);
+    }
+}
@@ -0,0 +1,1095 @@
+use crate::fuzzy;
+use anyhow::{Context, Result};
+use encoding_rs::{Encoding, WINDOWS_1252};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Fewest characters in each part of a compound word, unless `COMPOUNDMIN` says otherwise
+const DEFAULT_COMPOUND_MIN: usize = 3;
+
+/// Most parts considered when splitting a compound word
+const MAX_COMPOUND_PARTS: usize = 4;
+
+/// Characters tried by suggestions when the affix file has no `TRY` line
+const MAX_TRY_CHARS: usize = 40;
+
+/// Edits allowed between a misspelling and a dictionary word found by
+/// comparing it against every word, after the cheaper edits found nothing
+const MAX_NEAR_MISS_DISTANCE: usize = 2;
+
+/// Longest word checked, in characters, as in Hunspell. Anything longer is
+/// no word, and trying edits of it would take far too long.
+const MAX_WORD_CHARS: usize = 100;
+
+/// Most candidate spellings checked against the dictionary for one
+/// suggestion request
+const MAX_SUGGESTION_CANDIDATES: usize = 5000;
+
+/// Time one suggestion request may take before settling for what it found
+const SUGGESTION_TIME_LIMIT: Duration = Duration::from_millis(250);
+
+/// A flag on a dictionary word or an affix rule. Depending on the `FLAG`
+/// setting flags are written as single characters, two-character codes or
+/// comma-separated numbers; all three fit in a number.
+type Flag = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlagFormat {
+    Char,
+    Long,
+    Numeric,
+}
+
+/// One way of adding a prefix or suffix, from a `PFX` or `SFX` rule line
+#[derive(Debug)]
+struct Affix {
+    flag: Flag,
+    /// Whether the affix combines with an affix of the other kind
+    cross_product: bool,
+    strip: String,
+    add: String,
+    condition: Condition,
+    /// Flags of the derived word, allowing a second suffix after this one
+    continuation: Vec<Flag>,
+}
+
+/// A rule condition: characters, `.` or `[...]`/`[^...]` classes, matched
+/// against the end of the stem for suffixes and its start for prefixes
+#[derive(Debug, Default)]
+struct Condition(Vec<CharClass>);
+
+#[derive(Debug)]
+enum CharClass {
+    Any,
+    Char(char),
+    Set(Vec<char>),
+    NotSet(Vec<char>),
+}
+
+/// A word of the `.dic` file. The same word may be listed more than once
+/// with different flags, e.g. as a noun and as a verb.
+#[derive(Debug)]
+struct Homonym {
+    flags: Vec<Flag>,
+    /// The `st:` field, for words whose stem isn't the word itself
+    stem: Option<String>,
+}
+
+/// A dictionary word that a checked word was derived from
+struct Derivation<'a> {
+    root: &'a str,
+    homonym: &'a Homonym,
+    affixed: bool,
+}
+
+/// Flags the affix file gives a special meaning
+#[derive(Debug, Default)]
+struct SpecialFlags {
+    forbidden: Option<Flag>,
+    need_affix: Option<Flag>,
+    only_in_compound: Option<Flag>,
+    keep_case: Option<Flag>,
+    no_suggest: Option<Flag>,
+    compound: Option<Flag>,
+    compound_begin: Option<Flag>,
+    compound_middle: Option<Flag>,
+    compound_end: Option<Flag>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CompoundPart {
+    Begin,
+    Middle,
+    End,
+}
+
+/// A Hunspell spelling dictionary: a `.dic` word list and the `.aff` rules
+/// for deriving inflected forms from it.
+///
+/// Words are checked by undoing up to one prefix and two suffixes and looking
+/// up what remains, the way Hunspell does, so the same rules also give the
+/// stem of an inflected word. Compounding is supported in its basic form.
+pub struct Hunspell {
+    words: HashMap<String, Vec<Homonym>>,
+    prefixes: Vec<Affix>,
+    suffixes: Vec<Affix>,
+    /// Indexes into `prefixes` by the first character they add
+    prefix_index: HashMap<Option<char>, Vec<usize>>,
+    /// Indexes into `suffixes` by the last character they add
+    suffix_index: HashMap<Option<char>, Vec<usize>>,
+    special: SpecialFlags,
+    compound_min: usize,
+    /// Characters tried by suggestions, most common first
+    try_chars: Vec<char>,
+    /// Common misspellings and their corrections, from `REP`
+    replacements: Vec<(String, String)>,
+    /// Rows of neighbouring keys, from `KEY`
+    keyboard: Vec<Vec<char>>,
+    /// Characters removed from words before checking, such as Hebrew points
+    ignore: Vec<char>,
+    /// Words that may be suggested, in lowercase and as listed, by their
+    /// length in characters, so near misses are only compared against words
+    /// of about the right length
+    suggestable_by_length: Vec<Vec<(String, String)>>,
+}
+
+impl Hunspell {
+    /// Load `path`, a `.dic` file, and the `.aff` file next to it
+    pub fn open(path: &Path) -> Result<Self> {
+        let aff_path = path.with_extension("aff");
+        let aff = fs::read(&aff_path)
+            .with_context(|| format!("Failed to read {}", aff_path.display()))?;
+        let encoding = declared_encoding(&aff);
+        let aff = decode(&aff, encoding);
+
+        let mut speller = Self {
+            words: HashMap::new(),
+            prefixes: Vec::new(),
+            suffixes: Vec::new(),
+            prefix_index: HashMap::new(),
+            suffix_index: HashMap::new(),
+            special: SpecialFlags::default(),
+            compound_min: DEFAULT_COMPOUND_MIN,
+            try_chars: Vec::new(),
+            replacements: Vec::new(),
+            keyboard: Vec::new(),
+            ignore: Vec::new(),
+            suggestable_by_length: Vec::new(),
+        };
+        let mut parser = AffixParser::default();
+        for line in aff.lines() {
+            parser.line(&mut speller, line);
+        }
+
+        let dic = fs::read(path)?;
+        let dic = decode(&dic, encoding);
+        speller.read_words(&dic, &parser);
+
+        if speller.try_chars.is_empty() {
+            speller.try_chars = common_chars(speller.words.keys());
+        }
+        for (index, prefix) in speller.prefixes.iter().enumerate() {
+            let key = prefix.add.chars().next();
+            speller.prefix_index.entry(key).or_default().push(index);
+        }
+        for (index, suffix) in speller.suffixes.iter().enumerate() {
+            let key = suffix.add.chars().next_back();
+            speller.suffix_index.entry(key).or_default().push(index);
+        }
+        speller.suggestable_by_length = speller.length_buckets();
+        Ok(speller)
+    }
+
+    /// Whether `word` is spelled correctly. Capitalization is forgiven, so
+    /// "paris" passes when the dictionary has "Paris", unless the word is
+    /// marked to keep its case.
+    pub fn check(&self, word: &str) -> bool {
+        let word = self.clean(word);
+        if !word.chars().any(char::is_alphabetic) {
+            return true;
+        }
+        if word.chars().count() > MAX_WORD_CHARS {
+            return false;
+        }
+        case_variants(&word)
+            .iter()
+            .enumerate()
+            .any(|(i, variant)| self.check_exact(variant, i > 0, false))
+    }
+
+    /// Dictionary words that `word` is an inflected or derived form of, such
+    /// as "walk" for "walked". A correctly spelled uninflected word is its
+    /// own stem.
+    pub fn stems(&self, word: &str) -> Vec<String> {
+        let word = self.clean(word);
+        let mut stems: Vec<String> = Vec::new();
+        if word.chars().count() > MAX_WORD_CHARS {
+            return stems;
+        }
+        for (i, variant) in case_variants(&word).iter().enumerate() {
+            for derivation in self.derivations(variant) {
+                let keeps_case = i > 0 && self.has(derivation.homonym, self.special.keep_case);
+                if !self.accepts(&derivation) || keeps_case {
+                    continue;
+                }
+                let stem = derivation
+                    .homonym
+                    .stem
+                    .clone()
+                    .unwrap_or_else(|| derivation.root.to_string());
+                if !stems.contains(&stem) {
+                    stems.push(stem);
+                }
+            }
+            // An exact-case match is better than one found by changing case
+            if !stems.is_empty() {
+                break;
+            }
+        }
+        stems
+    }
+
+    /// Corrections for a misspelled word, best first; empty when it is
+    /// spelled correctly. The search stops after [`MAX_SUGGESTION_CANDIDATES`]
+    /// candidates or [`SUGGESTION_TIME_LIMIT`], keeping what it found by then.
+    pub fn suggest(&self, word: &str, limit: usize) -> Vec<String> {
+        let word = self.clean(word);
+        if word.chars().count() > MAX_WORD_CHARS || self.check(&word) {
+            return Vec::new();
+        }
+        let lower = word.to_lowercase();
+        let deadline = Instant::now() + SUGGESTION_TIME_LIMIT;
+
+        let mut suggestions: Vec<String> = Vec::new();
+        for (tried, candidate) in self.edits(&lower).into_iter().enumerate() {
+            if suggestions.len() >= limit
+                || tried >= MAX_SUGGESTION_CANDIDATES
+                || Instant::now() >= deadline
+            {
+                break;
+            }
+            if !suggestions.contains(&candidate) && self.suggestable(&candidate) {
+                suggestions.push(candidate);
+            }
+        }
+        if suggestions.is_empty() {
+            for candidate in self.near_misses(&lower, deadline) {
+                if suggestions.len() >= limit {
+                    break;
+                }
+                if !suggestions.contains(&candidate) {
+                    suggestions.push(candidate);
+                }
+            }
+        }
+
+        // Keep the capitalization of the query
+        if word.chars().next().is_some_and(char::is_uppercase) {
+            for suggestion in &mut suggestions {
+                *suggestion = capitalize(suggestion);
+            }
+        }
+        suggestions
+    }
+
+    fn clean(&self, word: &str) -> String {
+        word.trim()
+            .chars()
+            .filter(|c| !self.ignore.contains(c))
+            .collect()
+    }
+
+    /// Check `word` in exactly this capitalization. `case_changed` is set
+    /// when it is a variant of what was typed, which words marked to keep
+    /// their case don't match.
+    fn check_exact(&self, word: &str, case_changed: bool, suggesting: bool) -> bool {
+        let derivations = self.derivations(word);
+        let forbidden = derivations.iter().any(|derivation| {
+            !derivation.affixed && self.has(derivation.homonym, self.special.forbidden)
+        });
+        if forbidden {
+            return false;
+        }
+        let accepted = derivations.iter().any(|derivation| {
+            self.accepts(derivation)
+                && !(case_changed && self.has(derivation.homonym, self.special.keep_case))
+                && !(suggesting && self.has(derivation.homonym, self.special.no_suggest))
+        });
+        accepted || self.is_compound(word, 0)
+    }
+
+    /// Whether a derivation stands as a word of its own
+    fn accepts(&self, derivation: &Derivation) -> bool {
+        let homonym = derivation.homonym;
+        !self.has(homonym, self.special.forbidden)
+            && !self.has(homonym, self.special.only_in_compound)
+            && (derivation.affixed || !self.has(homonym, self.special.need_affix))
+    }
+
+    fn has(&self, homonym: &Homonym, flag: Option<Flag>) -> bool {
+        flag.is_some_and(|flag| homonym.flags.contains(&flag))
+    }
+
+    /// Every way `word` can be derived from a dictionary word: as it is, by a
+    /// prefix, by one or two suffixes, or by a prefix and a suffix
+    fn derivations(&self, word: &str) -> Vec<Derivation<'_>> {
+        let mut found = Vec::new();
+        if let Some((root, homonyms)) = self.words.get_key_value(word) {
+            found.extend(homonyms.iter().map(|homonym| Derivation {
+                root,
+                homonym,
+                affixed: false,
+            }));
+        }
+        self.suffixed(word, None, &mut found);
+
+        let first = word.chars().next();
+        for &index in self.prefix_index_entries(first) {
+            let prefix = &self.prefixes[index];
+            let Some(rest) = word.strip_prefix(prefix.add.as_str()) else {
+                continue;
+            };
+            if rest.is_empty() && prefix.strip.is_empty() {
+                continue;
+            }
+            let base = format!("{}{}", prefix.strip, rest);
+            if !prefix.condition.matches_start(&base) {
+                continue;
+            }
+            if !self.needs_more(prefix) {
+                self.push_with_flag(&base, prefix.flag, &mut found);
+            }
+            if prefix.cross_product {
+                self.suffixed(&base, Some(prefix), &mut found);
+            }
+        }
+        found
+    }
+
+    /// Add the derivations of `word` by a suffix, or by two when the inner one
+    /// allows the outer. With `prefix`, only suffixes that combine with it.
+    fn suffixed<'a>(&'a self, word: &str, prefix: Option<&Affix>, found: &mut Vec<Derivation<'a>>) {
+        let last = word.chars().next_back();
+        for &index in self.suffix_index_entries(last) {
+            let suffix = &self.suffixes[index];
+            if prefix.is_some() && !suffix.cross_product {
+                continue;
+            }
+            let Some(base) = suffix.unsuffix(word) else {
+                continue;
+            };
+
+            match prefix {
+                Some(prefix) => {
+                    for (root, homonym) in self.homonyms(&base) {
+                        let takes_prefix = homonym.flags.contains(&prefix.flag)
+                            || suffix.continuation.contains(&prefix.flag);
+                        if homonym.flags.contains(&suffix.flag) && takes_prefix {
+                            found.push(Derivation {
+                                root,
+                                homonym,
+                                affixed: true,
+                            });
+                        }
+                    }
+                }
+                None => {
+                    if !self.needs_more(suffix) {
+                        self.push_with_flag(&base, suffix.flag, found);
+                    }
+                    // "Twofold" suffixes: the inner suffix lists the outer among its flags
+                    let inner_last = base.chars().next_back();
+                    for &inner_index in self.suffix_index_entries(inner_last) {
+                        let inner = &self.suffixes[inner_index];
+                        if !inner.continuation.contains(&suffix.flag) {
+                            continue;
+                        }
+                        if let Some(root) = inner.unsuffix(&base) {
+                            self.push_with_flag(&root, inner.flag, found);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Affixes whose own flags say another affix must follow
+    fn needs_more(&self, affix: &Affix) -> bool {
+        self.special
+            .need_affix
+            .is_some_and(|flag| affix.continuation.contains(&flag))
+    }
+
+    fn push_with_flag<'a>(&'a self, base: &str, flag: Flag, found: &mut Vec<Derivation<'a>>) {
+        for (root, homonym) in self.homonyms(base) {
+            if homonym.flags.contains(&flag) {
+                found.push(Derivation {
+                    root,
+                    homonym,
+                    affixed: true,
+                });
+            }
+        }
+    }
+
+    fn homonyms(&self, word: &str) -> impl Iterator<Item = (&str, &Homonym)> {
+        self.words
+            .get_key_value(word)
+            .into_iter()
+            .flat_map(|(root, homonyms)| {
+                homonyms.iter().map(move |homonym| (root.as_str(), homonym))
+            })
+    }
+
+    /// Affixes that could have produced a word starting or ending with `c`:
+    /// those adding that character and those adding nothing
+    fn prefix_index_entries(&self, c: Option<char>) -> impl Iterator<Item = &usize> {
+        let adding = c.and_then(|c| self.prefix_index.get(&Some(c)));
+        adding
+            .into_iter()
+            .chain(self.prefix_index.get(&None))
+            .flatten()
+    }
+
+    fn suffix_index_entries(&self, c: Option<char>) -> impl Iterator<Item = &usize> {
+        let adding = c.and_then(|c| self.suffix_index.get(&Some(c)));
+        adding
+            .into_iter()
+            .chain(self.suffix_index.get(&None))
+            .flatten()
+    }
+
+    /// Whether `word` splits into dictionary words allowed in compounds
+    fn is_compound(&self, word: &str, parts: usize) -> bool {
+        let special = &self.special;
+        if special.compound.is_none() && special.compound_begin.is_none() {
+            return false;
+        }
+        let min = self.compound_min.max(1);
+        let position = if parts == 0 {
+            CompoundPart::Begin
+        } else {
+            CompoundPart::Middle
+        };
+
+        for (split, _) in word.char_indices().skip(min) {
+            let (head, tail) = word.split_at(split);
+            if tail.chars().count() < min {
+                break;
+            }
+            if !self.is_compound_part(head, position) {
+                continue;
+            }
+            if self.is_compound_part(tail, CompoundPart::End)
+                || (parts + 2 < MAX_COMPOUND_PARTS && self.is_compound(tail, parts + 1))
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn is_compound_part(&self, part: &str, position: CompoundPart) -> bool {
+        let special = &self.special;
+        let position_flag = match position {
+            CompoundPart::Begin => special.compound_begin,
+            CompoundPart::Middle => special.compound_middle,
+            CompoundPart::End => special.compound_end,
+        };
+        self.derivations(part).iter().any(|derivation| {
+            let homonym = derivation.homonym;
+            (self.has(homonym, special.compound) || self.has(homonym, position_flag))
+                && !self.has(homonym, special.forbidden)
+                && (derivation.affixed || !self.has(homonym, special.need_affix))
+        })
+    }
+
+    /// Whether `candidate`, one word or several separated by spaces, may be
+    /// offered as a suggestion
+    fn suggestable(&self, candidate: &str) -> bool {
+        candidate.split(' ').all(|part| {
+            case_variants(part)
+                .iter()
+                .enumerate()
+                .any(|(i, variant)| self.check_exact(variant, i > 0, true))
+        })
+    }
+
+    /// Spellings one change away from `word`, most likely first: known
+    /// misspellings, neighbouring keys, swapped, missing, extra and wrong
+    /// letters, and finally a missing space
+    fn edits(&self, word: &str) -> Vec<String> {
+        let chars: Vec<char> = word.chars().collect();
+        // `word` is in lowercase, and so are the corrections tried
+        let try_chars: Vec<char> = self
+            .try_chars
+            .iter()
+            .copied()
+            .filter(|c| !c.is_uppercase())
+            .collect();
+        let mut edits = Vec::new();
+
+        for (from, to) in &self.replacements {
+            for (position, _) in word.match_indices(from.as_str()) {
+                edits.push(format!(
+                    "{}{}{}",
+                    &word[..position],
+                    to,
+                    &word[position + from.len()..]
+                ));
+            }
+        }
+
+        let with = |i: usize, c: char| {
+            let mut changed = chars.clone();
+            changed[i] = c;
+            changed.iter().collect::<String>()
+        };
+        for (i, &c) in chars.iter().enumerate() {
+            for row in &self.keyboard {
+                for (k, &key) in row.iter().enumerate() {
+                    if key != c {
+                        continue;
+                    }
+                    let neighbours = [k.checked_sub(1), Some(k + 1)];
+                    for neighbour in neighbours.into_iter().flatten().filter_map(|k| row.get(k)) {
+                        edits.push(with(i, *neighbour));
+                    }
+                }
+            }
+        }
+
+        for i in 1..chars.len() {
+            let mut swapped = chars.clone();
+            swapped.swap(i - 1, i);
+            edits.push(swapped.into_iter().collect());
+        }
+        for i in 0..chars.len() {
+            let mut shorter = chars.clone();
+            shorter.remove(i);
+            edits.push(shorter.into_iter().collect());
+        }
+        for i in 0..=chars.len() {
+            for &c in &try_chars {
+                let mut longer = chars.clone();
+                longer.insert(i, c);
+                edits.push(longer.into_iter().collect());
+            }
+        }
+        for (i, &original) in chars.iter().enumerate() {
+            for &c in &try_chars {
+                if c != original {
+                    edits.push(with(i, c));
+                }
+            }
+        }
+        for i in 2..chars.len().saturating_sub(1) {
+            let (head, tail) = chars.split_at(i);
+            edits.push(format!(
+                "{} {}",
+                head.iter().collect::<String>(),
+                tail.iter().collect::<String>()
+            ));
+        }
+        edits
+    }
+
+    /// Dictionary words within a couple of edits of `word`, closest first.
+    /// Slower than [`Hunspell::edits`] since it compares every word of about
+    /// the same length, as many as it can before `deadline`.
+    fn near_misses(&self, word: &str, deadline: Instant) -> Vec<String> {
+        let length = word.chars().count();
+        let max_distance = if length <= 3 {
+            1
+        } else {
+            MAX_NEAR_MISS_DISTANCE
+        };
+
+        let lengths = length.saturating_sub(max_distance)..=length + max_distance;
+        let buckets = lengths.filter_map(|length| self.suggestable_by_length.get(length));
+        let mut found: Vec<(usize, &str)> = Vec::new();
+        'buckets: for bucket in buckets {
+            for (lower, root) in bucket {
+                if Instant::now() >= deadline {
+                    break 'buckets;
+                }
+                let distance = fuzzy::levenshtein(word, lower);
+                if distance <= max_distance {
+                    found.push((distance, root));
+                }
+            }
+        }
+        found.sort();
+        found
+            .into_iter()
+            .map(|(_, root)| root.to_string())
+            .collect()
+    }
+
+    /// The words near misses are looked for among, by length: those without
+    /// a flag keeping them from being suggested on their own
+    fn length_buckets(&self) -> Vec<Vec<(String, String)>> {
+        let special = &self.special;
+        let hiding = [
+            special.forbidden,
+            special.need_affix,
+            special.only_in_compound,
+            special.no_suggest,
+        ];
+        let mut buckets: Vec<Vec<(String, String)>> = Vec::new();
+        for (root, homonyms) in &self.words {
+            let shown = homonyms
+                .iter()
+                .any(|homonym| hiding.iter().all(|&flag| !self.has(homonym, flag)));
+            let length = root.chars().count();
+            if !shown || length > MAX_WORD_CHARS {
+                continue;
+            }
+            if buckets.len() <= length {
+                buckets.resize_with(length + 1, Vec::new);
+            }
+            buckets[length].push((root.to_lowercase(), root.clone()));
+        }
+        buckets
+    }
+
+    fn read_words(&mut self, dic: &str, parser: &AffixParser) {
+        let mut lines = dic.lines();
+        // The first line is the approximate number of words
+        if let Some(first) = lines.next() {
+            if !first.trim().chars().all(|c| c.is_ascii_digit()) {
+                self.read_word(first, parser);
+            }
+        }
+        for line in lines {
+            self.read_word(line, parser);
+        }
+    }
+
+    fn read_word(&mut self, line: &str, parser: &AffixParser) {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with(['\t', '#']) {
+            return;
+        }
+        let (entry, morphology) = line
+            .split_once(['\t', ' '])
+            .map_or((line, ""), |(entry, morphology)| (entry, morphology.trim()));
+
+        // A slash inside the word is escaped as "\/"
+        let mut word = String::new();
+        let mut flags = "";
+        let mut chars = entry.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' if entry[i + 1..].starts_with('/') => {
+                    word.push('/');
+                    chars.next();
+                }
+                '/' => {
+                    flags = &entry[i + 1..];
+                    break;
+                }
+                _ => word.push(c),
+            }
+        }
+        let word: String = word.chars().filter(|c| !self.ignore.contains(c)).collect();
+        if word.is_empty() {
+            return;
+        }
+
+        let morphology = parser.morphology_alias(morphology);
+        let stem = morphology
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix("st:"))
+            .map(str::to_string);
+        let mut flags = parser.flags(flags);
+        flags.sort_unstable();
+        self.words
+            .entry(word)
+            .or_default()
+            .push(Homonym { flags, stem });
+    }
+}
+
+impl Affix {
+    /// The stem this suffix would have been added to, if it fits `word`
+    fn unsuffix(&self, word: &str) -> Option<String> {
+        let rest = word.strip_suffix(self.add.as_str())?;
+        if rest.is_empty() && self.strip.is_empty() {
+            return None;
+        }
+        let base = format!("{}{}", rest, self.strip);
+        self.condition.matches_end(&base).then_some(base)
+    }
+}
+
+impl Condition {
+    fn parse(text: &str) -> Self {
+        if text == "." {
+            return Self::default();
+        }
+        let mut classes = Vec::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            let class = match c {
+                '.' => CharClass::Any,
+                '[' => {
+                    let mut set: Vec<char> = chars.by_ref().take_while(|&c| c != ']').collect();
+                    if set.first() == Some(&'^') {
+                        set.remove(0);
+                        CharClass::NotSet(set)
+                    } else {
+                        CharClass::Set(set)
+                    }
+                }
+                c => CharClass::Char(c),
+            };
+            classes.push(class);
+        }
+        Self(classes)
+    }
+
+    fn matches_start(&self, word: &str) -> bool {
+        let mut chars = word.chars();
+        self.0
+            .iter()
+            .all(|class| chars.next().is_some_and(|c| class.matches(c)))
+    }
+
+    fn matches_end(&self, word: &str) -> bool {
+        let mut chars = word.chars().rev();
+        self.0
+            .iter()
+            .rev()
+            .all(|class| chars.next().is_some_and(|c| class.matches(c)))
+    }
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            CharClass::Any => true,
+            CharClass::Char(expected) => c == *expected,
+            CharClass::Set(set) => set.contains(&c),
+            CharClass::NotSet(set) => !set.contains(&c),
+        }
+    }
+}
+
+/// State carried between the lines of an affix file
+#[derive(Default)]
+struct AffixParser {
+    format: Option<FlagFormat>,
+    /// Flag sets numbered by `AF`, which words and rules may refer to by number
+    flag_aliases: Vec<Vec<Flag>>,
+    /// Morphological fields numbered by `AM`
+    morphology_aliases: Vec<String>,
+    /// Rule lines still expected after each `PFX`/`SFX` header, with its
+    /// cross product setting
+    pending: HashMap<(bool, Flag), (bool, usize)>,
+    /// Alias tables whose length line has been seen
+    tables_started: Vec<&'static str>,
+}
+
+impl AffixParser {
+    fn line(&mut self, speller: &mut Hunspell, line: &str) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (&keyword, args) = match fields.split_first() {
+            Some(split) if !split.0.starts_with('#') => split,
+            _ => return,
+        };
+        let arg = args.first().copied().unwrap_or_default();
+
+        // Tables start with a line giving their length, which for the alias
+        // tables looks like an entry
+        if keyword == "REP" && args.len() == 1 {
+            return;
+        }
+        let table = ["AF", "AM"].into_iter().find(|&name| name == keyword);
+        if let Some(table) = table {
+            if !self.tables_started.contains(&table) {
+                self.tables_started.push(table);
+                return;
+            }
+        }
+
+        let flag = |parser: &Self| parser.flags(arg).first().copied();
+        match keyword {
+            "FLAG" => {
+                self.format = Some(match arg {
+                    "long" => FlagFormat::Long,
+                    "num" => FlagFormat::Numeric,
+                    _ => FlagFormat::Char,
+                })
+            }
+            "TRY" => speller.try_chars = arg.chars().collect(),
+            "KEY" => speller.keyboard = arg.split('|').map(|row| row.chars().collect()).collect(),
+            "IGNORE" => speller.ignore = arg.chars().collect(),
+            "REP" if args.len() >= 2 => speller
+                .replacements
+                .push((args[0].replace('_', " "), args[1].replace('_', " "))),
+            "AF" => self.flag_aliases.push(self.parse_flags(arg)),
+            "AM" => self.morphology_aliases.push(args.join(" ")),
+            "COMPOUNDMIN" => {
+                if let Ok(min) = arg.parse() {
+                    speller.compound_min = min;
+                }
+            }
+            "FORBIDDENWORD" => speller.special.forbidden = flag(self),
+            "NEEDAFFIX" | "PSEUDOROOT" => speller.special.need_affix = flag(self),
+            "ONLYINCOMPOUND" => speller.special.only_in_compound = flag(self),
+            "KEEPCASE" => speller.special.keep_case = flag(self),
+            "NOSUGGEST" => speller.special.no_suggest = flag(self),
+            "COMPOUNDFLAG" => speller.special.compound = flag(self),
+            "COMPOUNDBEGIN" => speller.special.compound_begin = flag(self),
+            "COMPOUNDMIDDLE" => speller.special.compound_middle = flag(self),
+            "COMPOUNDEND" => speller.special.compound_end = flag(self),
+            "PFX" | "SFX" => self.affix(speller, keyword == "PFX", args),
+            _ => {}
+        }
+    }
+
+    /// A `PFX`/`SFX` header (`SFX D Y 4`) or rule (`SFX D y ied [^aeiou]y`)
+    fn affix(&mut self, speller: &mut Hunspell, is_prefix: bool, args: &[&str]) {
+        let Some(flag) = args
+            .first()
+            .and_then(|flag| self.flags(flag).first().copied())
+        else {
+            return;
+        };
+        let key = (is_prefix, flag);
+        let pending = self.pending.get(&key).copied();
+
+        // A header when no rule lines are expected for the flag
+        let Some((cross_product, remaining @ 1..)) = pending else {
+            if let (Some(cross), Some(Ok(count))) =
+                (args.get(1), args.get(2).map(|count| count.parse()))
+            {
+                self.pending.insert(key, (*cross == "Y", count));
+            }
+            return;
+        };
+        self.pending.insert(key, (cross_product, remaining - 1));
+
+        let (Some(&strip), Some(&add)) = (args.get(1), args.get(2)) else {
+            return;
+        };
+        let (add, continuation) = add.split_once('/').unwrap_or((add, ""));
+        let empty = |text: &str| {
+            if text == "0" {
+                String::new()
+            } else {
+                text.to_string()
+            }
+        };
+        let affix = Affix {
+            flag,
+            cross_product,
+            strip: empty(strip),
+            add: empty(add)
+                .chars()
+                .filter(|c| !speller.ignore.contains(c))
+                .collect(),
+            condition: Condition::parse(args.get(3).copied().unwrap_or(".")),
+            continuation: self.flags(continuation),
+        };
+        if is_prefix {
+            speller.prefixes.push(affix);
+        } else {
+            speller.suffixes.push(affix);
+        }
+    }
+
+    /// Flags as written after a word or affix, or an `AF` alias number
+    fn flags(&self, text: &str) -> Vec<Flag> {
+        if !self.flag_aliases.is_empty() {
+            if let Ok(alias) = text.parse::<usize>() {
+                return alias
+                    .checked_sub(1)
+                    .and_then(|i| self.flag_aliases.get(i))
+                    .cloned()
+                    .unwrap_or_default();
+            }
+        }
+        self.parse_flags(text)
+    }
+
+    fn parse_flags(&self, text: &str) -> Vec<Flag> {
+        match self.format.unwrap_or(FlagFormat::Char) {
+            FlagFormat::Char => text.chars().map(|c| c as Flag).collect(),
+            FlagFormat::Long => {
+                let chars: Vec<char> = text.chars().collect();
+                chars
+                    .chunks(2)
+                    .map(|pair| {
+                        pair.iter()
+                            .fold(0, |flag, &c| (flag << 16) | (c as Flag & 0xffff))
+                    })
+                    .collect()
+            }
+            FlagFormat::Numeric => text
+                .split(',')
+                .filter_map(|number| number.trim().parse().ok())
+                .collect(),
+        }
+    }
+
+    /// Morphological fields, or the `AM` entry they refer to by number
+    fn morphology_alias<'a>(&'a self, text: &'a str) -> &'a str {
+        match text.parse::<usize>() {
+            Ok(alias) if !self.morphology_aliases.is_empty() => alias
+                .checked_sub(1)
+                .and_then(|i| self.morphology_aliases.get(i))
+                .map_or(text, String::as_str),
+            _ => text,
+        }
+    }
+}
+
+/// The encoding named by the affix file's `SET` line. Both files use it;
+/// Hunspell's default is ISO 8859-1.
+fn declared_encoding(aff: &[u8]) -> &'static Encoding {
+    let label = aff
+        .split(|&byte| byte == b'\n')
+        .filter_map(|line| line.strip_prefix(b"SET"))
+        .map(|rest| String::from_utf8_lossy(rest).trim().to_ascii_lowercase())
+        .next();
+    let Some(label) = label else {
+        return WINDOWS_1252;
+    };
+    // Some dictionaries write "microsoft-cp1251" for windows-1251
+    let label = label.strip_prefix("microsoft-").unwrap_or(&label);
+    Encoding::for_label(label.as_bytes()).unwrap_or_else(|| {
+        eprintln!("Unknown Hunspell encoding {}, assuming ISO 8859-1", label);
+        WINDOWS_1252
+    })
+}
+
+fn decode(bytes: &[u8], encoding: &'static Encoding) -> String {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    encoding.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+/// Letters of the dictionary's words, most frequent first, for suggestions
+/// when the affix file doesn't list them
+fn common_chars<'a>(words: impl Iterator<Item = &'a String>) -> Vec<char> {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in words.flat_map(|word| word.chars()) {
+        if c.is_alphabetic() {
+            *counts
+                .entry(c.to_lowercase().next().unwrap_or(c))
+                .or_default() += 1;
+        }
+    }
+    let mut chars: Vec<(char, usize)> = counts.into_iter().collect();
+    chars.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    chars
+        .into_iter()
+        .take(MAX_TRY_CHARS)
+        .map(|(c, _)| c)
+        .collect()
+}
+
+/// `word` as typed, in lowercase, capitalized and in uppercase
+fn case_variants(word: &str) -> Vec<String> {
+    let lower = word.to_lowercase();
+    let mut variants = vec![word.to_string()];
+    for variant in [lower.clone(), capitalize(&lower), word.to_uppercase()] {
+        if !variants.contains(&variant) {
+            variants.push(variant);
+        }
+    }
+    variants
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const AFF: &str = "SET UTF-8
+TRY esianrtolcdugmphbyfvkw
+REP 1
+REP f ph
+COMPOUNDFLAG X
+COMPOUNDMIN 3
+FORBIDDENWORD F
+NEEDAFFIX N
+
+PFX U Y 1
+PFX U 0 un .
+
+SFX S Y 1
+SFX S 0 s [^sxy]
+
+SFX D Y 2
+SFX D 0 ed [^ey]
+SFX D y ied [^aeiou]y
+
+# A header without a count and a rule without its fields
+SFX Q Y
+SFX Q 0
+SFX G Y 3
+SFX G 0 ing .
+";
+
+    const DIC: &str = "8
+walk/SDU
+carry/D
+Paris
+phone/S
+foot/X
+ball/X
+walkd/F
+bound/N
+";
+
+    /// A speller from the fixture files, written next to each other as Hunspell expects
+    fn speller(name: &str, aff: &str, dic: &str) -> Hunspell {
+        let base = std::env::temp_dir().join(format!("aynary-{}-{}", std::process::id(), name));
+        let dic_path: PathBuf = base.with_extension("dic");
+        fs::write(base.with_extension("aff"), aff).unwrap();
+        fs::write(&dic_path, dic).unwrap();
+        let speller = Hunspell::open(&dic_path).unwrap();
+        fs::remove_file(base.with_extension("aff")).unwrap();
+        fs::remove_file(&dic_path).unwrap();
+        speller
+    }
+
+    #[test]
+    fn checks_affixed_forms() {
+        let speller = speller("affixes", AFF, DIC);
+        for word in [
+            "walk", "walks", "walked", "unwalked", "carried", "Walked", "paris",
+        ] {
+            assert!(speller.check(word), "{} should be accepted", word);
+        }
+        for word in ["walkd", "carryed", "unphone", "bound", "walking"] {
+            assert!(!speller.check(word), "{} should be rejected", word);
+        }
+        assert_eq!(speller.stems("unwalked"), ["walk"]);
+        assert_eq!(speller.stems("Carried"), ["carry"]);
+    }
+
+    #[test]
+    fn checks_compounds() {
+        let speller = speller("compounds", AFF, DIC);
+        assert!(speller.check("football"));
+        assert!(speller.check("ballfootball"));
+        assert!(!speller.check("footbal"));
+        // Each part needs COMPOUNDMIN characters and the compound flag
+        assert!(!speller.check("footwalk"));
+    }
+
+    #[test]
+    fn suggests_edits_replacements_and_near_misses() {
+        let speller = speller("suggest", AFF, DIC);
+        assert!(speller.suggest("walked", 5).is_empty());
+        assert_eq!(speller.suggest("walkde", 1), ["walked"]);
+        assert_eq!(speller.suggest("Walkde", 1), ["Walked"]);
+        assert_eq!(speller.suggest("fone", 1), ["phone"]);
+        // Two edits away, so only found by comparing against the words
+        assert_eq!(speller.suggest("xalkk", 5), ["walk"]);
+        // A forbidden word is never suggested
+        assert!(!speller.suggest("walkdd", 5).contains(&"walkd".to_string()));
+    }
+
+    #[test]
+    fn gives_up_on_overlong_words() {
+        let speller = speller("overlong", AFF, DIC);
+        let long = "walk".repeat(1000);
+        assert!(!speller.check(&long));
+        assert!(speller.stems(&long).is_empty());
+        assert!(speller.suggest(&long, 5).is_empty());
+    }
+}
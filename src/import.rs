@@ -0,0 +1,151 @@
+use crate::api::{self, DictionaryEntry};
+use crate::compact::{self, Info};
+use crate::compact_layout::SerializedEntry;
+use anyhow::{bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes entries as a JSON array one at a time, so an import never holds the
+/// whole dictionary in memory. Made with [`EntryWriter::compiling`], it
+/// gathers them for a compiled dictionary instead.
+pub struct EntryWriter<W: Write> {
+    output: Output<W>,
+    count: usize,
+}
+
+enum Output<W: Write> {
+    Json(W),
+    /// Entries serialized for [`compact::write_serialized`], and the first
+    /// language any of them names
+    Compiled {
+        entries: Vec<SerializedEntry>,
+        language: Option<String>,
+    },
+}
+
+impl<W: Write> EntryWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(b"[")?;
+        Ok(Self {
+            output: Output::Json(writer),
+            count: 0,
+        })
+    }
+
+    /// Gather entries to be written by [`EntryWriter::finish_compiled`]
+    pub fn compiling() -> Self {
+        Self {
+            output: Output::Compiled {
+                entries: Vec::new(),
+                language: None,
+            },
+            count: 0,
+        }
+    }
+
+    pub fn write(&mut self, entry: &DictionaryEntry) -> Result<()> {
+        match &mut self.output {
+            Output::Json(writer) => {
+                writer.write_all(if self.count == 0 { b"\n" } else { b",\n" })?;
+                serde_json::to_writer(writer, entry)?;
+            }
+            Output::Compiled { entries, language } => {
+                if language.is_none() {
+                    language.clone_from(&entry.language);
+                }
+                entries.push(compact::serialize(entry)?);
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Close the array and flush, returning the number of entries written
+    pub fn finish(self) -> Result<usize> {
+        let Output::Json(mut writer) = self.output else {
+            bail!("Compiled imports are finished with finish_compiled");
+        };
+        writer.write_all(b"\n]\n")?;
+        writer.flush()?;
+        Ok(self.count)
+    }
+
+    /// Write the gathered entries to `out` as a compiled dictionary named
+    /// `name`, returning their number
+    pub fn finish_compiled(self, name: String, out: impl Write) -> Result<usize> {
+        let Output::Compiled { entries, language } = self.output else {
+            bail!("JSON imports are finished with finish");
+        };
+        let info = Info {
+            name,
+            description: None,
+            language,
+        };
+        compact::write_serialized(&entries, &info, out)?;
+        Ok(self.count)
+    }
+}
+
+/// Counts reported after an import
+#[derive(Debug, Default)]
+pub struct ImportStats {
+    /// Records read from the input, after filtering
+    pub records: usize,
+    /// Entries written, after merging records that describe the same entry
+    pub entries: usize,
+    /// Records that couldn't be read or converted
+    pub skipped: usize,
+}
+
+/// Open `path` for reading, decompressing it if it ends in `.gz`
+pub fn open_input(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// `path` without its `.json` and `.gz` extensions: "glossary.json.gz"
+/// becomes "glossary", for naming converted copies
+pub fn json_stem(path: &Path) -> PathBuf {
+    let mut stem = path.to_path_buf();
+    while stem
+        .extension()
+        .is_some_and(|ext| ext == "gz" || ext == "json")
+    {
+        stem.set_extension("");
+    }
+    stem
+}
+
+/// Create `path` for writing, along with its parent directories
+pub fn create_output(path: &Path) -> Result<BufWriter<File>> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Where an imported dictionary goes by default: the user's dictionary
+/// directory, so it is loaded on the next start
+pub fn default_output(name: &str) -> Result<PathBuf> {
+    Ok(user_dictionary_dir()?.join(format!("{}.json", name)))
+}
+
+/// The highest-priority dictionary directory, which belongs to the user
+pub fn user_dictionary_dir() -> Result<PathBuf> {
+    api::dictionary_dirs()
+        .into_iter()
+        .next()
+        .context("No data directory; pass --output")
+}
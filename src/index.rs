@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// In-memory index over dictionary headwords.
+///
+/// Keys are expected to be normalized by the caller (see `normalize::loose`);
+/// each key maps to the ids of the entries that share it, where an id is the
+/// entry's position in the iterator passed to [`HeadwordIndex::build`].
+#[derive(Debug, Default)]
+pub struct HeadwordIndex {
+    exact: HashMap<String, Vec<usize>>,
+    // Distinct keys in byte order, so every key sharing a prefix is contiguous
+    sorted: Vec<String>,
+}
+
+impl HeadwordIndex {
+    pub fn build<I>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut exact: HashMap<String, Vec<usize>> = HashMap::new();
+        for (id, key) in keys.into_iter().enumerate() {
+            exact.entry(key).or_default().push(id);
+        }
+
+        let mut sorted: Vec<String> = exact.keys().cloned().collect();
+        sorted.sort_unstable();
+
+        Self { exact, sorted }
+    }
+
+    /// Number of distinct keys
+    pub fn len(&self) -> usize {
+        self.sorted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sorted.is_empty()
+    }
+
+    /// Every distinct key, in sorted order
+    pub fn keys(&self) -> &[String] {
+        &self.sorted
+    }
+
+    /// Ids of the entries whose key is exactly `key`
+    pub fn exact(&self, key: &str) -> &[usize] {
+        self.exact.get(key).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Positions in [`keys`](Self::keys) of the keys starting with `prefix`
+    pub fn prefix_range(&self, prefix: &str) -> Range<usize> {
+        let start = self.sorted.partition_point(|key| key.as_str() < prefix);
+        let len = self.sorted[start..].partition_point(|key| key.starts_with(prefix));
+        start..start + len
+    }
+
+    /// Distinct keys starting with `prefix`, in sorted order
+    pub fn prefix_keys(&self, prefix: &str) -> &[String] {
+        &self.sorted[self.prefix_range(prefix)]
+    }
+
+    /// Ids of the entries whose key starts with `prefix`, ordered by key
+    pub fn prefix(&self, prefix: &str) -> impl Iterator<Item = usize> + '_ {
+        self.prefix_keys(prefix)
+            .iter()
+            .flat_map(move |key| self.exact(key).iter().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> HeadwordIndex {
+        HeadwordIndex::build(["cat", "catalog", "car", "cat", "dog"].map(String::from))
+    }
+
+    #[test]
+    fn entries_sharing_a_key_share_its_ids() {
+        let index = index();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.exact("cat"), [0, 3]);
+        assert_eq!(index.exact("ca"), [] as [usize; 0]);
+    }
+
+    #[test]
+    fn prefixes_find_keys_in_order() {
+        let index = index();
+        assert_eq!(index.prefix_keys("ca"), ["car", "cat", "catalog"]);
+        assert_eq!(index.prefix("cat").collect::<Vec<_>>(), [0, 3, 1]);
+        assert!(index.prefix_keys("e").is_empty());
+        assert_eq!(index.prefix_range("").len(), 4);
+    }
+}
@@ -0,0 +1,345 @@
+use crate::api::{Definition, DictionaryEntry, License, Meaning, Phonetic};
+use crate::import::{EntryWriter, ImportStats};
+use anyhow::Result;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::io::{BufRead, Write};
+
+/// Malformed lines reported individually before only being counted
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Sense tags that describe the record structure rather than usage
+const STRUCTURAL_TAGS: &[&str] = &["form-of", "alt-of", "no-gloss"];
+
+/// Form tags marking inflection-table metadata instead of actual forms
+const FORM_METADATA_TAGS: &[&str] = &["table-tags", "inflection-template", "class"];
+
+/// Just enough of a record to decide whether to import it. The fields are
+/// only copied when they contain JSON escapes.
+#[derive(Deserialize)]
+struct LanguageProbe<'a> {
+    #[serde(default, borrow)]
+    lang_code: Option<Text<'a>>,
+    #[serde(default, borrow)]
+    lang: Option<Text<'a>>,
+}
+
+/// A string borrowed from the input where possible. serde only borrows a
+/// bare `Cow` field, not one inside an `Option`, hence the wrapper.
+#[derive(Deserialize)]
+struct Text<'a>(#[serde(borrow)] Cow<'a, str>);
+
+/// One Wiktextract record: a word in one language, part of speech and etymology
+#[derive(Deserialize)]
+struct Record {
+    #[serde(default)]
+    word: String,
+    #[serde(default)]
+    pos: String,
+    #[serde(default)]
+    lang_code: Option<String>,
+    #[serde(default)]
+    etymology_text: Option<String>,
+    #[serde(default)]
+    senses: Vec<Sense>,
+    #[serde(default)]
+    sounds: Vec<Sound>,
+    #[serde(default)]
+    forms: Vec<Form>,
+    #[serde(default)]
+    synonyms: Vec<Related>,
+    #[serde(default)]
+    antonyms: Vec<Related>,
+}
+
+#[derive(Deserialize)]
+struct Sense {
+    #[serde(default)]
+    glosses: Vec<String>,
+    #[serde(default)]
+    examples: Vec<Example>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    synonyms: Vec<Related>,
+    #[serde(default)]
+    antonyms: Vec<Related>,
+}
+
+#[derive(Deserialize)]
+struct Example {
+    #[serde(default)]
+    text: String,
+    /// Translation, for examples in other languages
+    #[serde(default)]
+    english: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Sound {
+    #[serde(default)]
+    ipa: Option<String>,
+    #[serde(default)]
+    mp3_url: Option<String>,
+    #[serde(default)]
+    ogg_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Form {
+    form: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Related {
+    word: String,
+}
+
+/// Convert a Kaikki.org Wiktextract JSONL dump, one record per line, keeping
+/// records whose `lang_code` or `lang` is `language` (e.g. "en" or "English").
+///
+/// The input is read a line at a time, so dumps of any size can be imported.
+/// Dumps list a word's records together, so consecutive records for the same
+/// headword and etymology are merged into one entry with several meanings.
+pub fn import<W: Write>(
+    input: impl BufRead,
+    language: &str,
+    output: &mut EntryWriter<W>,
+) -> Result<ImportStats> {
+    let mut stats = ImportStats::default();
+    let mut pending: Option<DictionaryEntry> = None;
+
+    for (number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = serde_json::from_str::<LanguageProbe>(&line).and_then(|probe| {
+            let matches = [probe.lang_code, probe.lang]
+                .into_iter()
+                .flatten()
+                .any(|Text(lang)| lang.eq_ignore_ascii_case(language));
+            matches
+                .then(|| serde_json::from_str::<Record>(&line))
+                .transpose()
+        });
+
+        let record = match record {
+            Ok(Some(record)) if !record.word.is_empty() => record,
+            Ok(_) => continue,
+            Err(e) => {
+                stats.skipped += 1;
+                if stats.skipped <= MAX_REPORTED_ERRORS {
+                    eprintln!("Line {}: {}", number + 1, e);
+                }
+                continue;
+            }
+        };
+        stats.records += 1;
+
+        let entry = convert(record);
+        match &mut pending {
+            Some(current) if current.word == entry.word && current.etymology == entry.etymology => {
+                merge(current, entry)
+            }
+            _ => {
+                if let Some(done) = pending.replace(entry) {
+                    output.write(&done)?;
+                }
+            }
+        }
+    }
+
+    if let Some(done) = pending {
+        output.write(&done)?;
+    }
+    stats.entries = output.count();
+    Ok(stats)
+}
+
+fn convert(record: Record) -> DictionaryEntry {
+    let definitions = record
+        .senses
+        .into_iter()
+        .filter_map(|sense| {
+            // Sub-senses repeat their parent's gloss first; the last one is specific
+            let gloss = sense.glosses.last()?.trim().to_string();
+            let example = sense.examples.into_iter().next().map(|example| {
+                match example.english.filter(|english| !english.is_empty()) {
+                    Some(english) => format!("{} ({})", example.text, english),
+                    None => example.text,
+                }
+            });
+
+            Some(Definition {
+                definition: gloss,
+                synonyms: words(sense.synonyms),
+                antonyms: words(sense.antonyms),
+                example,
+                tags: sense
+                    .tags
+                    .into_iter()
+                    .filter(|tag| !STRUCTURAL_TAGS.contains(&tag.as_str()))
+                    .collect(),
+            })
+        })
+        .collect();
+
+    let phonetics: Vec<Phonetic> = record
+        .sounds
+        .into_iter()
+        .filter_map(|sound| {
+            let audio = sound.mp3_url.or(sound.ogg_url);
+            (sound.ipa.is_some() || audio.is_some()).then(|| Phonetic {
+                text: sound.ipa,
+                audio,
+                ..Phonetic::default()
+            })
+        })
+        .collect();
+
+    let mut forms: Vec<String> = Vec::new();
+    for form in record.forms {
+        let metadata = form
+            .tags
+            .iter()
+            .any(|tag| FORM_METADATA_TAGS.contains(&tag.as_str()));
+        if !metadata && form.form != record.word && !forms.contains(&form.form) {
+            forms.push(form.form);
+        }
+    }
+
+    DictionaryEntry {
+        phonetic: phonetics.iter().find_map(|phonetic| phonetic.text.clone()),
+        phonetics,
+        meanings: vec![Meaning {
+            part_of_speech: part_of_speech(&record.pos).to_string(),
+            definitions,
+            synonyms: words(record.synonyms),
+            antonyms: words(record.antonyms),
+            ..Meaning::default()
+        }],
+        // Wiktionary text is available under CC BY-SA
+        license: Some(License {
+            name: String::from("CC BY-SA 4.0"),
+            url: String::from("https://creativecommons.org/licenses/by-sa/4.0/"),
+        }),
+        source_urls: vec![format!(
+            "https://en.wiktionary.org/wiki/{}",
+            record.word.replace(' ', "_")
+        )],
+        etymology: record.etymology_text.filter(|text| !text.trim().is_empty()),
+        forms,
+        language: record.lang_code,
+        word: record.word,
+        ..DictionaryEntry::default()
+    }
+}
+
+/// Fold another record for the same headword and etymology into `entry`
+fn merge(entry: &mut DictionaryEntry, other: DictionaryEntry) {
+    entry.meanings.extend(other.meanings);
+    for phonetic in other.phonetics {
+        if !entry
+            .phonetics
+            .iter()
+            .any(|known| known.text == phonetic.text && known.audio == phonetic.audio)
+        {
+            entry.phonetics.push(phonetic);
+        }
+    }
+    for form in other.forms {
+        if !entry.forms.contains(&form) {
+            entry.forms.push(form);
+        }
+    }
+    if entry.phonetic.is_none() {
+        entry.phonetic = other.phonetic;
+    }
+}
+
+fn words(related: Vec<Related>) -> Vec<String> {
+    related.into_iter().map(|related| related.word).collect()
+}
+
+/// Wiktextract's abbreviated parts of speech, spelled out like the bundled data
+fn part_of_speech(pos: &str) -> &str {
+    match pos {
+        "adj" => "adjective",
+        "adv" => "adverb",
+        "conj" => "conjunction",
+        "det" => "determiner",
+        "intj" => "interjection",
+        "name" => "proper noun",
+        "num" => "numeral",
+        "prep" => "preposition",
+        "postp" => "postposition",
+        "pron" => "pronoun",
+        "abbrev" => "abbreviation",
+        "prep_phrase" => "prepositional phrase",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The entries imported from `input`, and the import's counts
+    fn import_lines(input: &str, language: &str) -> (Vec<DictionaryEntry>, ImportStats) {
+        let mut json = Vec::new();
+        let mut writer = EntryWriter::new(&mut json).unwrap();
+        let stats = import(input.as_bytes(), language, &mut writer).unwrap();
+        writer.finish().unwrap();
+        (serde_json::from_slice(&json).unwrap(), stats)
+    }
+
+    #[test]
+    fn probes_language_without_copying() {
+        let probe: LanguageProbe = serde_json::from_str(r#"{"lang_code": "en"}"#).unwrap();
+        assert!(matches!(probe.lang_code, Some(Text(Cow::Borrowed("en")))));
+        assert!(probe.lang.is_none());
+
+        let probe: LanguageProbe = serde_json::from_str(r#"{"lang": "Espa\u00f1ol"}"#).unwrap();
+        assert!(matches!(probe.lang, Some(Text(Cow::Owned(lang))) if lang == "Español"));
+    }
+
+    #[test]
+    fn merges_records_per_etymology_and_skips_bad_lines() {
+        let input = r#"{"word": "bank", "pos": "noun", "lang_code": "en", "etymology_text": "From Italian banca.", "senses": [{"glosses": ["An institution", "A financial institution"], "tags": ["form-of"]}], "sounds": [{"ipa": "/bæŋk/"}]}
+{"word": "bank", "pos": "verb", "lang_code": "en", "etymology_text": "From Italian banca.", "senses": [{"glosses": ["To deposit money"]}], "sounds": [{"ipa": "/bæŋk/"}]}
+{"word": "bank", "pos": "noun", "lang_code": "en", "etymology_text": "From Old Norse.", "senses": [{"glosses": ["The edge of a river"]}]}
+{"word": "Bank", "pos": "noun", "lang_code": "de", "senses": [{"glosses": ["bench"]}]}
+{"word": "broken", "lang_code": "en",
+
+{"word": "bay", "pos": "adj", "lang_code": "EN", "senses": [{"glosses": ["Reddish brown"]}]}
+"#;
+        let (entries, stats) = import_lines(input, "en");
+        assert_eq!((stats.records, stats.entries, stats.skipped), (4, 3, 1));
+
+        let bank = &entries[0];
+        assert_eq!(bank.etymology.as_deref(), Some("From Italian banca."));
+        assert_eq!(bank.phonetics.len(), 1);
+        let parts: Vec<&str> = bank
+            .meanings
+            .iter()
+            .map(|meaning| meaning.part_of_speech.as_str())
+            .collect();
+        assert_eq!(parts, ["noun", "verb"]);
+        assert_eq!(
+            bank.meanings[0].definitions[0].definition,
+            "A financial institution"
+        );
+        assert!(bank.meanings[0].definitions[0].tags.is_empty());
+
+        assert_eq!(
+            entries[1].meanings[0].definitions[0].definition,
+            "The edge of a river"
+        );
+        assert_eq!(entries[2].word, "bay");
+        assert_eq!(entries[2].meanings[0].part_of_speech, "adjective");
+    }
+}
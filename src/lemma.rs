@@ -0,0 +1,293 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+/// Inflected forms that suffix stripping can't recover, mapped to their lemma
+static IRREGULAR_FORMS: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
+    [
+        // Nouns
+        ("children", "child"),
+        ("dice", "die"),
+        ("feet", "foot"),
+        ("geese", "goose"),
+        ("lice", "louse"),
+        ("men", "man"),
+        ("mice", "mouse"),
+        ("oxen", "ox"),
+        ("people", "person"),
+        ("teeth", "tooth"),
+        ("women", "woman"),
+        ("knives", "knife"),
+        ("leaves", "leaf"),
+        ("lives", "life"),
+        ("halves", "half"),
+        ("selves", "self"),
+        ("shelves", "shelf"),
+        ("wives", "wife"),
+        ("wolves", "wolf"),
+        ("analyses", "analysis"),
+        ("crises", "crisis"),
+        ("criteria", "criterion"),
+        ("phenomena", "phenomenon"),
+        ("theses", "thesis"),
+        ("cacti", "cactus"),
+        ("fungi", "fungus"),
+        ("nuclei", "nucleus"),
+        ("radii", "radius"),
+        ("indices", "index"),
+        ("matrices", "matrix"),
+        ("vertices", "vertex"),
+        // Adjectives and adverbs
+        ("better", "good"),
+        ("best", "good"),
+        ("worse", "bad"),
+        ("worst", "bad"),
+        ("further", "far"),
+        ("furthest", "far"),
+        ("farther", "far"),
+        ("farthest", "far"),
+        ("less", "little"),
+        ("least", "little"),
+        ("more", "much"),
+        ("most", "much"),
+        // Verbs
+        ("am", "be"),
+        ("are", "be"),
+        ("is", "be"),
+        ("was", "be"),
+        ("were", "be"),
+        ("been", "be"),
+        ("being", "be"),
+        ("has", "have"),
+        ("had", "have"),
+        ("does", "do"),
+        ("did", "do"),
+        ("done", "do"),
+        ("went", "go"),
+        ("gone", "go"),
+        ("goes", "go"),
+        ("ate", "eat"),
+        ("eaten", "eat"),
+        ("began", "begin"),
+        ("begun", "begin"),
+        ("bought", "buy"),
+        ("brought", "bring"),
+        ("built", "build"),
+        ("caught", "catch"),
+        ("chose", "choose"),
+        ("chosen", "choose"),
+        ("came", "come"),
+        ("drew", "draw"),
+        ("drawn", "draw"),
+        ("drank", "drink"),
+        ("drunk", "drink"),
+        ("drove", "drive"),
+        ("driven", "drive"),
+        ("fell", "fall"),
+        ("fallen", "fall"),
+        ("felt", "feel"),
+        ("fought", "fight"),
+        ("found", "find"),
+        ("flew", "fly"),
+        ("flown", "fly"),
+        ("forgot", "forget"),
+        ("forgotten", "forget"),
+        ("froze", "freeze"),
+        ("frozen", "freeze"),
+        ("gave", "give"),
+        ("given", "give"),
+        ("got", "get"),
+        ("gotten", "get"),
+        ("grew", "grow"),
+        ("grown", "grow"),
+        ("heard", "hear"),
+        ("held", "hold"),
+        ("kept", "keep"),
+        ("knew", "know"),
+        ("known", "know"),
+        ("laid", "lay"),
+        ("led", "lead"),
+        ("left", "leave"),
+        ("lent", "lend"),
+        ("lay", "lie"),
+        ("lain", "lie"),
+        ("lost", "lose"),
+        ("made", "make"),
+        ("meant", "mean"),
+        ("met", "meet"),
+        ("paid", "pay"),
+        ("ran", "run"),
+        ("rang", "ring"),
+        ("rung", "ring"),
+        ("rode", "ride"),
+        ("ridden", "ride"),
+        ("rose", "rise"),
+        ("risen", "rise"),
+        ("said", "say"),
+        ("saw", "see"),
+        ("seen", "see"),
+        ("sold", "sell"),
+        ("sent", "send"),
+        ("shook", "shake"),
+        ("shaken", "shake"),
+        ("shot", "shoot"),
+        ("sang", "sing"),
+        ("sung", "sing"),
+        ("sank", "sink"),
+        ("sunk", "sink"),
+        ("sat", "sit"),
+        ("slept", "sleep"),
+        ("spoke", "speak"),
+        ("spoken", "speak"),
+        ("spent", "spend"),
+        ("stood", "stand"),
+        ("stole", "steal"),
+        ("stolen", "steal"),
+        ("swam", "swim"),
+        ("swum", "swim"),
+        ("took", "take"),
+        ("taken", "take"),
+        ("taught", "teach"),
+        ("tore", "tear"),
+        ("torn", "tear"),
+        ("told", "tell"),
+        ("thought", "think"),
+        ("threw", "throw"),
+        ("thrown", "throw"),
+        ("understood", "understand"),
+        ("woke", "wake"),
+        ("woken", "wake"),
+        ("wore", "wear"),
+        ("worn", "wear"),
+        ("won", "win"),
+        ("wrote", "write"),
+        ("written", "write"),
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// Regular English inflection suffixes and what replaces them, tried in order.
+/// More specific rules come first so e.g. "studies" yields "study" before "studi".
+const SUFFIX_RULES: &[(&str, &str)] = &[
+    ("ies", "y"),
+    ("ied", "y"),
+    ("iest", "y"),
+    ("ier", "y"),
+    ("ches", "ch"),
+    ("shes", "sh"),
+    ("sses", "ss"),
+    ("xes", "x"),
+    ("zes", "z"),
+    ("ing", "e"),
+    ("ing", ""),
+    ("ed", "e"),
+    ("ed", ""),
+    ("est", "e"),
+    ("est", ""),
+    ("er", "e"),
+    ("er", ""),
+    ("es", "e"),
+    ("es", ""),
+    ("s", ""),
+];
+
+/// Suffixes after which a doubled final consonant is undone ("running" → "run")
+const DOUBLING_SUFFIXES: &[&str] = &["ing", "ed", "er", "est"];
+
+/// Shortest stem a suffix rule may leave behind
+const MIN_STEM_LEN: usize = 2;
+
+/// Candidate lemmas for an inflected, lowercase English word, most likely first.
+///
+/// Candidates are not checked against any dictionary; callers try each in turn
+/// and keep the first that exists. The word itself is never included.
+pub fn candidates(word: &str) -> Vec<String> {
+    let mut lemmas: Vec<String> = Vec::new();
+    let mut push = |lemma: String| {
+        if lemma != word && !lemmas.contains(&lemma) {
+            lemmas.push(lemma);
+        }
+    };
+
+    if let Some(lemma) = IRREGULAR_FORMS.get(word) {
+        push(lemma.to_string());
+    }
+
+    for (suffix, replacement) in SUFFIX_RULES {
+        let Some(stem) = word.strip_suffix(suffix) else {
+            continue;
+        };
+        if stem.chars().count() < MIN_STEM_LEN {
+            continue;
+        }
+
+        let undoubled = if replacement.is_empty() && DOUBLING_SUFFIXES.contains(suffix) {
+            undouble(stem)
+        } else {
+            None
+        };
+
+        // Lemmas commonly end in a double "ll", "ss", "ff" or "zz" ("falling" → "fall"),
+        // so only prefer the undoubled stem for other consonants ("running" → "run")
+        match undoubled {
+            Some(short) if !stem.ends_with(['l', 's', 'f', 'z']) => {
+                push(short.to_string());
+                push(stem.to_string());
+            }
+            Some(short) => {
+                push(stem.to_string());
+                push(short.to_string());
+            }
+            None => push(format!("{}{}", stem, replacement)),
+        }
+    }
+
+    lemmas
+}
+
+/// `stem` without its last letter if it ends in a doubled consonant
+fn undouble(stem: &str) -> Option<&str> {
+    let mut chars = stem.char_indices().rev();
+    let (last_idx, last) = chars.next()?;
+    let (_, previous) = chars.next()?;
+
+    let is_consonant = last.is_alphabetic() && !"aeiou".contains(last);
+    (last == previous && is_consonant).then_some(&stem[..last_idx])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where `lemma` appears among the candidates for `word`
+    fn rank(word: &str, lemma: &str) -> Option<usize> {
+        candidates(word)
+            .iter()
+            .position(|candidate| candidate == lemma)
+    }
+
+    #[test]
+    fn irregular_forms_come_first() {
+        assert_eq!(rank("mice", "mouse"), Some(0));
+        assert_eq!(rank("went", "go"), Some(0));
+        // Suffix rules would leave a stem too short to try
+        assert_eq!(candidates("is"), ["be"]);
+    }
+
+    #[test]
+    fn strips_regular_suffixes() {
+        assert_eq!(candidates("cats"), ["cat"]);
+        assert_eq!(rank("studies", "study"), Some(0));
+        assert_eq!(rank("boxes", "box"), Some(0));
+        assert!(rank("hoped", "hope").is_some());
+        assert!(candidates("cat").is_empty());
+    }
+
+    #[test]
+    fn undoes_doubled_consonants_except_common_doubles() {
+        let running = rank("running", "run").unwrap();
+        assert!(running < rank("running", "runn").unwrap());
+        let falling = rank("falling", "fall").unwrap();
+        assert!(falling < rank("falling", "fal").unwrap());
+    }
+}
@@ -0,0 +1,806 @@
+use crate::api::DictionaryEntry;
+use crate::compact::{self, CompactSource};
+use crate::diagnostics::{self, RawEntries, RawEntry};
+use crate::import as importer;
+use crate::normalize;
+use crate::package::{self, Manifest};
+use crate::source::DictionarySource;
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::error::Category;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+/// Parts of speech written by the importers and readers, besides which a
+/// label is probably a typo or an abbreviation that should be spelled out
+const PARTS_OF_SPEECH: &[&str] = &[
+    "noun",
+    "proper noun",
+    "verb",
+    "auxiliary verb",
+    "modal verb",
+    "phrasal verb",
+    "adjective",
+    "adverb",
+    "pronoun",
+    "preposition",
+    "postposition",
+    "conjunction",
+    "interjection",
+    "determiner",
+    "article",
+    "numeral",
+    "particle",
+    "classifier",
+    "abbreviation",
+    "acronym",
+    "initialism",
+    "contraction",
+    "phrase",
+    "prepositional phrase",
+    "idiom",
+    "proverb",
+    "prefix",
+    "suffix",
+    "infix",
+    "affix",
+    "symbol",
+    "character",
+    "letter",
+];
+
+/// The fields of an object in the dictionary schema, and the schemas of the
+/// objects and arrays of objects among them
+struct Schema {
+    fields: &'static [&'static str],
+    nested: &'static [(&'static str, &'static Schema)],
+}
+
+const LICENSE: Schema = Schema {
+    fields: &["name", "url"],
+    nested: &[],
+};
+
+const PHONETIC: Schema = Schema {
+    fields: &["text", "audio", "sourceUrl", "license"],
+    nested: &[("license", &LICENSE)],
+};
+
+const RELATION: Schema = Schema {
+    fields: &["kind", "words"],
+    nested: &[],
+};
+
+const DEFINITION: Schema = Schema {
+    fields: &["definition", "synonyms", "antonyms", "example", "tags"],
+    nested: &[],
+};
+
+const MEANING: Schema = Schema {
+    fields: &[
+        "partOfSpeech",
+        "definitions",
+        "synonyms",
+        "antonyms",
+        "relations",
+    ],
+    nested: &[("definitions", &DEFINITION), ("relations", &RELATION)],
+};
+
+/// Matches [`DictionaryEntry`]; serde ignores fields it doesn't know, so a
+/// misspelt optional field would otherwise vanish without a trace
+const ENTRY: Schema = Schema {
+    fields: &[
+        "word",
+        "phonetic",
+        "phonetics",
+        "meanings",
+        "license",
+        "sourceUrls",
+        "frequency",
+        "etymology",
+        "forms",
+        "language",
+        "images",
+        "targetLanguage",
+    ],
+    nested: &[
+        ("phonetics", &PHONETIC),
+        ("meanings", &MEANING),
+        ("license", &LICENSE),
+    ],
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The data is unusable or certainly wrong
+    Error,
+    /// The data loads but is probably not what was meant
+    Warning,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+/// One finding, located by the line and column of the offending value, or of
+/// its entry when the value is missing, and the path to it inside the entry
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    /// Stable identifier of the check, such as `unresolved-synonym`
+    pub code: &'static str,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub word: Option<String>,
+    /// Such as `meanings[0].definitions[2]`; empty for the entry itself.
+    /// For an unknown field, the field.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub path: String,
+}
+
+/// Everything found in one dictionary file
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub file: String,
+    pub entries: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// Check an Aynary JSON dictionary (optionally .gz) against the
+/// [`DictionaryEntry`] schema and for common content mistakes. Only failing
+/// to read the file is an `Err`; everything wrong with its contents is
+/// reported as a [`Problem`].
+pub fn lint(path: &Path) -> Result<Report> {
+    let mut text = String::new();
+    importer::open_input(path)?
+        .read_to_string(&mut text)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let mut linter = Linter::new(base);
+    linter.check_file(&text);
+
+    Ok(Report::new(path, linter.entries, linter.problems))
+}
+
+/// Check a package, given its directory or manifest: the manifest, then the
+/// entries file it names. A compiled entries file is only checked to open.
+pub fn lint_package(path: &Path) -> Result<Vec<Report>> {
+    let manifest_path = if path.is_dir() {
+        path.join(package::MANIFEST_FILE)
+    } else {
+        path.to_path_buf()
+    };
+    let mut problems = Vec::new();
+    let mut problem = |severity, code, message: String, line: usize, column: usize| {
+        problems.push(Problem {
+            severity,
+            code,
+            message,
+            line,
+            column,
+            word: None,
+            path: String::new(),
+        })
+    };
+    let mut reports = Vec::new();
+
+    match Manifest::read(&manifest_path) {
+        Err(e) => {
+            let (line, column) = e
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<serde_json::Error>())
+                .map_or((1, 1), |e| (e.line(), e.column().max(1)));
+            problem(
+                Severity::Error,
+                "manifest",
+                format!("{:#}", e),
+                line,
+                column,
+            );
+        }
+        Ok(manifest) => {
+            let dir = manifest_path.parent().unwrap_or(Path::new(""));
+            let entries = match package::resolve(dir, &manifest.entries) {
+                None => Err(anyhow!(
+                    "The entries file '{}' is outside the package",
+                    manifest.entries
+                )),
+                Some(entries_path)
+                    if entries_path
+                        .extension()
+                        .is_some_and(|ext| ext == compact::EXTENSION) =>
+                {
+                    CompactSource::open(&entries_path)
+                        .map(|source| source.metadata().entry_count.unwrap_or(0))
+                }
+                Some(entries_path) => lint(&entries_path).map(|report| {
+                    let count = report.entries;
+                    reports.push(report);
+                    count
+                }),
+            };
+            match entries {
+                Ok(count) if count != manifest.entry_count => {
+                    let at = package::entry_count_position(&manifest_path);
+                    problem(
+                        Severity::Warning,
+                        "entry-count",
+                        format!(
+                            "The manifest lists {} entries, but the package has {}",
+                            manifest.entry_count, count
+                        ),
+                        at.line,
+                        at.column,
+                    )
+                }
+                Ok(_) => {}
+                Err(e) => problem(Severity::Error, "manifest", format!("{:#}", e), 1, 1),
+            }
+        }
+    }
+
+    reports.insert(0, Report::new(&manifest_path, 0, problems));
+    Ok(reports)
+}
+
+impl Report {
+    fn new(path: &Path, entries: usize, mut problems: Vec<Problem>) -> Self {
+        problems.sort_by_key(|problem| (problem.line, problem.column));
+        let count = |severity| {
+            problems
+                .iter()
+                .filter(|problem| problem.severity == severity)
+                .count()
+        };
+        Self {
+            file: path.display().to_string(),
+            entries,
+            errors: count(Severity::Error),
+            warnings: count(Severity::Warning),
+            problems,
+        }
+    }
+}
+
+struct Linter<'a> {
+    /// Audio paths are relative to the dictionary file
+    base: &'a Path,
+    entries: usize,
+    problems: Vec<Problem>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(base: &'a Path) -> Self {
+        Self {
+            base,
+            entries: 0,
+            problems: Vec::new(),
+        }
+    }
+
+    fn check_file(&mut self, text: &str) {
+        let RawEntries {
+            entries: raw_entries,
+            errors,
+        } = diagnostics::split_entries(text);
+        for error in errors {
+            self.problems.push(Problem {
+                severity: Severity::Error,
+                code: "syntax",
+                message: error.message,
+                line: error.line,
+                column: error.column,
+                word: None,
+                path: String::new(),
+            });
+        }
+        self.entries = raw_entries.len();
+
+        let mut entries = Vec::new();
+        for raw in &raw_entries {
+            match raw.parse() {
+                Ok(entry) => {
+                    if let Ok(value) = serde_json::from_str::<Value>(raw.text) {
+                        self.check_fields(*raw, &entry.word, &value, &ENTRY, String::new());
+                    }
+                    entries.push((*raw, entry));
+                }
+                Err(e) => {
+                    let diagnostic = raw.diagnostic(&e);
+                    self.problems.push(Problem {
+                        severity: Severity::Error,
+                        code: match e.classify() {
+                            Category::Data => "schema",
+                            Category::Io | Category::Syntax | Category::Eof => "syntax",
+                        },
+                        message: diagnostic.message,
+                        line: diagnostic.line,
+                        column: diagnostic.column,
+                        word: diagnostic.word,
+                        path: String::new(),
+                    });
+                }
+            }
+        }
+
+        let headwords: HashSet<String> = entries
+            .iter()
+            .flat_map(|(_, entry)| std::iter::once(&entry.word).chain(&entry.forms))
+            .map(|word| normalize::fold(word))
+            .collect();
+        let mut first_lines: HashMap<(String, Option<&str>), usize> = HashMap::new();
+        for (raw, entry) in &entries {
+            let key = (normalize::fold(&entry.word), entry.language.as_deref());
+            match first_lines.get(&key) {
+                Some(&line) => self.report(
+                    Severity::Warning,
+                    "duplicate-entry",
+                    format!("'{}' already has an entry at line {}", entry.word, line),
+                    *raw,
+                    &entry.word,
+                    String::new(),
+                ),
+                None => {
+                    first_lines.insert(key, raw.at.line);
+                }
+            }
+            self.check_entry(*raw, entry, &headwords);
+        }
+    }
+
+    fn check_fields(
+        &mut self,
+        raw: RawEntry,
+        word: &str,
+        value: &Value,
+        schema: &Schema,
+        path: String,
+    ) {
+        let Some(object) = value.as_object() else {
+            return;
+        };
+        for key in object.keys() {
+            if !schema.fields.contains(&key.as_str()) {
+                self.report(
+                    Severity::Warning,
+                    "unknown-field",
+                    format!("Unknown field '{}'", key),
+                    raw,
+                    word,
+                    join(&path, key),
+                );
+            }
+        }
+        for (name, nested) in schema.nested {
+            match object.get(*name) {
+                Some(Value::Array(items)) => {
+                    for (i, item) in items.iter().enumerate() {
+                        self.check_fields(
+                            raw,
+                            word,
+                            item,
+                            nested,
+                            join(&path, &format!("{}[{}]", name, i)),
+                        );
+                    }
+                }
+                Some(item) => self.check_fields(raw, word, item, nested, join(&path, name)),
+                None => {}
+            }
+        }
+    }
+
+    fn check_entry(&mut self, raw: RawEntry, entry: &DictionaryEntry, headwords: &HashSet<String>) {
+        let word = entry.word.as_str();
+        if word.trim().is_empty() {
+            self.report(
+                Severity::Error,
+                "empty-word",
+                String::from("Entry has no headword"),
+                raw,
+                word,
+                String::from("word"),
+            );
+        }
+        if entry.meanings.is_empty() {
+            self.report(
+                Severity::Error,
+                "no-meanings",
+                String::from("Entry has no meanings"),
+                raw,
+                word,
+                String::from("meanings"),
+            );
+        }
+
+        if let Some(message) = entry.phonetic.as_deref().and_then(ipa_problem) {
+            self.report(
+                Severity::Warning,
+                "invalid-ipa",
+                message,
+                raw,
+                word,
+                String::from("phonetic"),
+            );
+        }
+        for (i, phonetic) in entry.phonetics.iter().enumerate() {
+            let path = format!("phonetics[{}]", i);
+            if let Some(message) = phonetic.text.as_deref().and_then(ipa_problem) {
+                self.report(
+                    Severity::Warning,
+                    "invalid-ipa",
+                    message,
+                    raw,
+                    word,
+                    join(&path, "text"),
+                );
+            }
+            if let Some(message) = phonetic
+                .audio
+                .as_deref()
+                .and_then(|audio| audio_problem(audio, self.base))
+            {
+                self.report(
+                    Severity::Error,
+                    "broken-audio",
+                    message,
+                    raw,
+                    word,
+                    join(&path, "audio"),
+                );
+            }
+        }
+
+        // The synonyms of a bilingual entry are in the other language, so
+        // they aren't expected among the headwords
+        let mut links = Links {
+            headwords,
+            reported: HashSet::new(),
+            enabled: entry.target_language.is_none(),
+        };
+
+        for (i, meaning) in entry.meanings.iter().enumerate() {
+            let path = format!("meanings[{}]", i);
+            let pos = meaning.part_of_speech.trim().to_lowercase();
+            if !pos.is_empty() && !PARTS_OF_SPEECH.contains(&pos.as_str()) {
+                self.report(
+                    Severity::Warning,
+                    "unknown-part-of-speech",
+                    format!("Unknown part of speech '{}'", meaning.part_of_speech),
+                    raw,
+                    word,
+                    join(&path, "partOfSpeech"),
+                );
+            }
+            if meaning.definitions.is_empty() {
+                self.report(
+                    Severity::Error,
+                    "no-definitions",
+                    String::from("Meaning has no definitions"),
+                    raw,
+                    word,
+                    join(&path, "definitions"),
+                );
+            }
+
+            let mut seen = HashSet::new();
+            for (j, definition) in meaning.definitions.iter().enumerate() {
+                let path = join(&path, &format!("definitions[{}]", j));
+                let text = definition.definition.trim();
+                if text.is_empty() {
+                    self.report(
+                        Severity::Error,
+                        "empty-definition",
+                        String::from("Empty definition"),
+                        raw,
+                        word,
+                        path.clone(),
+                    );
+                } else if !seen.insert(text) {
+                    self.report(
+                        Severity::Warning,
+                        "duplicate-definition",
+                        format!(
+                            "Definition '{}' appears twice in this meaning",
+                            shorten(text)
+                        ),
+                        raw,
+                        word,
+                        path.clone(),
+                    );
+                }
+                links.check(self, raw, word, &definition.synonyms, Link::Synonym, &path);
+                links.check(self, raw, word, &definition.antonyms, Link::Antonym, &path);
+            }
+            links.check(self, raw, word, &meaning.synonyms, Link::Synonym, &path);
+            links.check(self, raw, word, &meaning.antonyms, Link::Antonym, &path);
+        }
+    }
+
+    fn report(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        message: String,
+        raw: RawEntry,
+        word: &str,
+        path: String,
+    ) {
+        let at = raw.locate(&path);
+        self.problems.push(Problem {
+            severity,
+            code,
+            message,
+            line: at.line,
+            column: at.column,
+            word: (!word.is_empty()).then(|| word.to_string()),
+            path,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Link {
+    Synonym,
+    Antonym,
+}
+
+/// Checks that an entry's synonyms and antonyms are headwords, reporting each
+/// missing word once even when it is listed for several definitions
+struct Links<'a> {
+    headwords: &'a HashSet<String>,
+    reported: HashSet<(Link, String)>,
+    enabled: bool,
+}
+
+impl Links<'_> {
+    fn check(
+        &mut self,
+        linter: &mut Linter,
+        raw: RawEntry,
+        word: &str,
+        linked: &[String],
+        link: Link,
+        path: &str,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let (code, label, field) = match link {
+            Link::Synonym => ("unresolved-synonym", "Synonym", "synonyms"),
+            Link::Antonym => ("unresolved-antonym", "Antonym", "antonyms"),
+        };
+        for linked in linked {
+            let folded = normalize::fold(linked);
+            if !self.headwords.contains(&folded) && self.reported.insert((link, folded)) {
+                linter.report(
+                    Severity::Warning,
+                    code,
+                    format!("{} '{}' is not a headword", label, linked),
+                    raw,
+                    word,
+                    join(path, field),
+                );
+            }
+        }
+    }
+}
+
+/// What's wrong with a transcription, if anything: it should be IPA, in
+/// slashes or square brackets if delimited at all
+fn ipa_problem(text: &str) -> Option<String> {
+    let inner = match text.chars().next() {
+        Some('/') => text
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/')),
+        Some('[') => text
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']')),
+        _ => Some(text),
+    };
+    let Some(inner) = inner else {
+        return Some(format!("Unbalanced delimiters in transcription '{}'", text));
+    };
+    if inner.trim().is_empty() {
+        return Some(String::from("Empty transcription"));
+    }
+    inner
+        .chars()
+        .find(|&c| !is_ipa(c))
+        .map(|c| format!("'{}' in transcription '{}' is not an IPA symbol", c, text))
+}
+
+fn is_ipa(c: char) -> bool {
+    matches!(c,
+        'a'..='z'
+        | 'æ' | 'ç' | 'ð' | 'ø' | 'ħ' | 'ŋ' | 'œ' | 'β' | 'θ' | 'χ'
+        // Clicks
+        | '\u{1c0}'..='\u{1c3}'
+        // IPA Extensions, Spacing Modifier Letters and Combining Diacritical Marks
+        | '\u{250}'..='\u{36f}'
+        // Phonetic Extensions, such as ᵻ and ᵊ
+        | '\u{1d00}'..='\u{1dbf}'
+        // Tone letters
+        | '\u{a700}'..='\u{a71f}'
+        // Global rise and fall, linking and major group marks
+        | '↗' | '↘' | '‿' | '‖'
+        | ' ' | '.' | '|' | '(' | ')' | '-'
+    )
+}
+
+/// What's wrong with an audio reference, if anything. URLs can't be checked
+/// offline beyond their form; local paths are relative to the dictionary.
+fn audio_problem(audio: &str, base: &Path) -> Option<String> {
+    let audio = audio.trim();
+    if audio.is_empty() {
+        return Some(String::from("Empty audio path"));
+    }
+    let path = match audio.split_once("://") {
+        Some(("http" | "https", rest)) => {
+            return rest
+                .split(['/', '?', '#'])
+                .next()
+                .unwrap_or_default()
+                .is_empty()
+                .then(|| format!("Audio URL '{}' has no host", audio));
+        }
+        Some(("file", path)) => path,
+        Some((scheme, _)) => {
+            return Some(format!(
+                "Unsupported scheme '{}' in audio URL '{}'",
+                scheme, audio
+            ))
+        }
+        None => audio,
+    };
+    (!base.join(path).is_file()).then(|| format!("Audio file '{}' not found", path))
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+/// The start of a long text, for quoting it in a message
+fn shorten(text: &str) -> String {
+    const MAX_CHARS: usize = 60;
+    match text.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The problems found in `text` as (code, line, column, path)
+    fn problems(text: &str) -> Vec<(&'static str, usize, usize, String)> {
+        let mut linter = Linter::new(Path::new(""));
+        linter.check_file(text);
+        linter
+            .problems
+            .into_iter()
+            .map(|problem| (problem.code, problem.line, problem.column, problem.path))
+            .collect()
+    }
+
+    /// Line and column of the only occurrence of `needle` in `text`
+    fn position_of(text: &str, needle: &str) -> (usize, usize) {
+        assert_eq!(text.matches(needle).count(), 1, "{}", needle);
+        let before = &text[..text.find(needle).unwrap()];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+
+    #[test]
+    fn locates_nested_values() {
+        let text = r#"[
+  {
+    "word": "run", "phonetics": [], "sourceUrls": [],
+    "meanings": [
+      {
+        "partOfSpeech": "verb", "synonyms": [], "antonyms": [],
+        "definitions": [{"definition": "to move", "synonyms": [], "antonyms": []}]
+      },
+      {
+        "partOfSpeech": "vrb", "synonyms": [], "antonyms": [],
+        "definitions": [
+          {"definition": "a \"race\", or [a] {spell}", "synonyms": [], "antonyms": []},
+          {"definition": "", "synonyms": [], "antonyms": [], "colour": "red"}
+        ]
+      }
+    ]
+  }
+]"#;
+        let at = |needle| position_of(text, needle);
+        let problem = |code, (line, column), path: &str| (code, line, column, path.to_string());
+        assert_eq!(
+            problems(text),
+            [
+                problem(
+                    "unknown-field",
+                    at(r#""red""#),
+                    "meanings[1].definitions[1].colour"
+                ),
+                problem(
+                    "unknown-part-of-speech",
+                    at(r#""vrb""#),
+                    "meanings[1].partOfSpeech"
+                ),
+                problem(
+                    "empty-definition",
+                    at(r#"{"definition": """#),
+                    "meanings[1].definitions[1]"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn entry_problems_are_reported_at_the_entry() {
+        let text = r#"[
+  {"word": "a", "phonetics": [], "sourceUrls": [], "meanings": []},
+  {"word": "A", "phonetics": [], "sourceUrls": [], "meanings": []}
+]"#;
+        assert_eq!(
+            problems(text),
+            [
+                ("no-meanings", 2, 64, String::from("meanings")),
+                ("duplicate-entry", 3, 3, String::new()),
+                ("no-meanings", 3, 64, String::from("meanings")),
+            ]
+        );
+    }
+
+    #[test]
+    fn unresolved_links_are_reported_once() {
+        let text = r#"[{"word": "big", "phonetics": [], "sourceUrls": [], "meanings": [
+  {"partOfSpeech": "adjective", "synonyms": [], "antonyms": [], "definitions": [
+    {"definition": "large", "synonyms": ["huge"], "antonyms": []},
+    {"definition": "grown up", "synonyms": ["huge", "big"], "antonyms": []}]}]}]"#;
+        let (line, column) = position_of(text, r#"["huge"]"#);
+        assert_eq!(
+            problems(text),
+            [(
+                "unresolved-synonym",
+                line,
+                column,
+                String::from("meanings[0].definitions[0].synonyms")
+            )]
+        );
+    }
+
+    #[test]
+    fn checks_transcriptions_and_audio() {
+        assert_eq!(ipa_problem("/ˈkæt/"), None);
+        assert!(ipa_problem("/kat").is_some());
+        assert!(ipa_problem("k@t").is_some());
+
+        let base = Path::new("");
+        assert_eq!(audio_problem("https://example.org/cat.ogg", base), None);
+        assert!(audio_problem("https:///cat.ogg", base).is_some());
+        assert!(audio_problem("ftp://example.org/cat.ogg", base).is_some());
+        assert!(audio_problem("no-such-file.ogg", base).is_some());
+    }
+}
@@ -0,0 +1,224 @@
+use anyhow::{bail, ensure, Result};
+
+/// Largest distance an M2 match can reach
+const M2_MAX_OFFSET: usize = 0x0800;
+
+/// Most bytes one input byte can expand to: each zero byte of a length
+/// adds 255
+const MAX_RATIO: usize = 255;
+
+/// Decompress an LZO1X stream, as produced by any of the LZO1X compressors.
+///
+/// `expected_len` is the decompressed size, which LZO streams don't record
+/// themselves; output beyond it is an error. It comes from the file, so the
+/// preallocation is also capped at the most `input` could expand to.
+pub fn decompress(input: &[u8], expected_len: usize) -> Result<Vec<u8>> {
+    let mut out = Output {
+        bytes: Vec::with_capacity(expected_len.min(input.len().saturating_mul(MAX_RATIO))),
+        limit: expected_len,
+    };
+    let mut ip = 0;
+
+    let byte = |ip: &mut usize| -> Result<usize> {
+        let value = *input.get(*ip).ok_or_else(truncated)?;
+        *ip += 1;
+        Ok(value as usize)
+    };
+
+    // A first byte above 17 is a literal run without the usual instruction
+    let mut state = State::Instruction;
+    if input.first().is_some_and(|&first| first > 17) {
+        let count = byte(&mut ip)? - 17;
+        copy_literals(input, &mut ip, &mut out, count)?;
+        state = if count < 4 {
+            State::Match
+        } else {
+            State::AfterLiteralRun
+        };
+    }
+
+    loop {
+        let mut instruction = byte(&mut ip)?;
+
+        match state {
+            State::Instruction if instruction < 16 => {
+                // Literal run of 3 or more bytes
+                let count = if instruction == 0 {
+                    15 + zero_run(input, &mut ip)? + byte(&mut ip)?
+                } else {
+                    instruction
+                };
+                copy_literals(input, &mut ip, &mut out, count + 3)?;
+                instruction = byte(&mut ip)?;
+                if instruction < 16 {
+                    // Three-byte match just beyond M2 range
+                    let distance = 1 + M2_MAX_OFFSET + (instruction >> 2) + (byte(&mut ip)? << 2);
+                    copy_match(&mut out, distance, 3)?;
+                    state = trailing_literals(input, &mut ip, &mut out)?;
+                    continue;
+                }
+            }
+            State::AfterLiteralRun if instruction < 16 => {
+                let distance = 1 + M2_MAX_OFFSET + (instruction >> 2) + (byte(&mut ip)? << 2);
+                copy_match(&mut out, distance, 3)?;
+                state = trailing_literals(input, &mut ip, &mut out)?;
+                continue;
+            }
+            _ => {}
+        }
+
+        // Matches, each followed by 0–3 literals encoded in its last byte
+        if instruction >= 64 {
+            // M2: 3–8 bytes within 2 KiB
+            let distance = 1 + ((instruction >> 2) & 7) + (byte(&mut ip)? << 3);
+            copy_match(&mut out, distance, (instruction >> 5) + 1)?;
+        } else if instruction >= 32 {
+            // M3: any length within 16 KiB
+            let mut length = instruction & 31;
+            if length == 0 {
+                length = 31 + zero_run(input, &mut ip)? + byte(&mut ip)?;
+            }
+            let distance = 1 + (byte(&mut ip)? >> 2) + (byte(&mut ip)? << 6);
+            copy_match(&mut out, distance, length + 2)?;
+        } else if instruction >= 16 {
+            // M4: any length within 48 KiB; distance 0 marks the end of the stream
+            let mut length = instruction & 7;
+            if length == 0 {
+                length = 7 + zero_run(input, &mut ip)? + byte(&mut ip)?;
+            }
+            let distance =
+                ((instruction & 8) << 11) + (byte(&mut ip)? >> 2) + (byte(&mut ip)? << 6);
+            if distance == 0 {
+                break;
+            }
+            copy_match(&mut out, distance + 0x4000, length + 2)?;
+        } else {
+            // M1: two bytes, only possible directly after a match
+            let distance = 1 + (instruction >> 2) + (byte(&mut ip)? << 2);
+            copy_match(&mut out, distance, 2)?;
+        }
+        state = trailing_literals(input, &mut ip, &mut out)?;
+    }
+
+    ensure!(
+        out.bytes.len() == expected_len,
+        "LZO data decompressed to {} bytes instead of {}",
+        out.bytes.len(),
+        expected_len
+    );
+    Ok(out.bytes)
+}
+
+/// Decompressed data, which mustn't grow past the expected length
+struct Output {
+    bytes: Vec<u8>,
+    limit: usize,
+}
+
+impl Output {
+    fn make_room(&self, count: usize) -> Result<()> {
+        ensure!(
+            count <= self.limit - self.bytes.len(),
+            "LZO data decompresses to more than {} bytes",
+            self.limit
+        );
+        Ok(())
+    }
+}
+
+/// What the next instruction byte can encode
+#[derive(Clone, Copy)]
+enum State {
+    /// A literal run or a match
+    Instruction,
+    /// A match; values below 16 are M1 matches
+    Match,
+    /// A match, or a three-byte match for values below 16
+    AfterLiteralRun,
+}
+
+/// Copy the 0–3 literals encoded in the low bits of the byte two back, and
+/// return what may follow them
+fn trailing_literals(input: &[u8], ip: &mut usize, out: &mut Output) -> Result<State> {
+    let count = (input[*ip - 2] & 3) as usize;
+    if count == 0 {
+        return Ok(State::Instruction);
+    }
+    copy_literals(input, ip, out, count)?;
+    Ok(State::Match)
+}
+
+/// Lengths above a field's range are stored as a run of zero bytes, each
+/// worth 255, then a final byte
+fn zero_run(input: &[u8], ip: &mut usize) -> Result<usize> {
+    let mut total = 0;
+    while *input.get(*ip).ok_or_else(truncated)? == 0 {
+        total += 255;
+        *ip += 1;
+    }
+    Ok(total)
+}
+
+fn copy_literals(input: &[u8], ip: &mut usize, out: &mut Output, count: usize) -> Result<()> {
+    let literals = input.get(*ip..*ip + count).ok_or_else(truncated)?;
+    out.make_room(count)?;
+    out.bytes.extend_from_slice(literals);
+    *ip += count;
+    Ok(())
+}
+
+/// Copy `length` bytes from `distance` back; the source may overlap the
+/// bytes being written, repeating a short pattern
+fn copy_match(out: &mut Output, distance: usize, length: usize) -> Result<()> {
+    let out_len = out.bytes.len();
+    if distance == 0 || distance > out_len {
+        bail!("LZO match reaches {} bytes back from {}", distance, out_len);
+    }
+    out.make_room(length)?;
+    let start = out_len - distance;
+    for index in start..start + length {
+        out.bytes.push(out.bytes[index]);
+    }
+    Ok(())
+}
+
+fn truncated() -> anyhow::Error {
+    anyhow::anyhow!("LZO data ends unexpectedly")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "abc" as a literal run, an M2 match repeating it for 8 bytes, then
+    /// the end-of-stream marker
+    const REPEATED: &[u8] = &[20, b'a', b'b', b'c', 0xe8, 0, 0x11, 0, 0];
+
+    #[test]
+    fn decompresses_literals_and_matches() {
+        assert_eq!(decompress(REPEATED, 11).unwrap(), b"abcabcabcab");
+    }
+
+    #[test]
+    fn rejects_output_past_the_expected_length() {
+        let error = decompress(REPEATED, 5).unwrap_err();
+        assert!(error.to_string().contains("more than 5 bytes"));
+    }
+
+    #[test]
+    fn huge_expected_length_is_a_mismatch_not_an_allocation() {
+        let error = decompress(REPEATED, usize::MAX).unwrap_err();
+        assert!(error.to_string().contains("instead of"));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        assert!(decompress(&REPEATED[..5], 11).is_err());
+    }
+
+    #[test]
+    fn rejects_matches_before_the_start() {
+        // A match three bytes back after a single literal
+        assert!(decompress(&[18, b'a', 0xe8, 0, 0x11, 0, 0], 9).is_err());
+    }
+}
@@ -1,4 +1,36 @@
 mod api;
+mod article;
+mod cli;
+mod compact;
+mod compact_layout;
+mod csv;
+mod diagnostics;
+mod dict_client;
+mod dict_server;
+mod dsl;
+mod entry;
+mod freedict;
+mod fuzzy;
+mod hunspell;
+mod import;
+mod index;
+mod kaikki;
+mod lemma;
+mod lint;
+mod lzo;
+mod markup;
+mod mdict;
+mod normalize;
+mod package;
+mod pattern;
+mod search;
+mod settings;
+mod source;
+mod stardict;
+mod wordnet;
+mod xdxf;
+mod xml;
+mod zim;
 mod app;
 mod ui;
 mod dbus_service;
@@ -11,6 +43,11 @@ use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
 
 fn main() {
+    // Headless commands such as `aynary serve-dict` run without a display
+    if let Some(code) = cli::run() {
+        std::process::exit(code);
+    }
+
     // Initialize GTK
     adw::init().expect("Failed to initialize Adwaita");
     load_css();
@@ -21,13 +58,16 @@ fn main() {
     // Create a channel for DBus to communicate with the app
     let (sender, receiver) = mpsc::channel::<dbus_service::DbusCommand>();
     
+    // The DBus service answers lookups itself with a client sharing the app's settings
+    let client_for_dbus = app.client().clone();
+
     // Store app in Arc for DBus service
     let app_arc = Arc::new(Mutex::new(app));
     
     // Start DBus service in a separate thread
     let sender_for_dbus = sender.clone();
     thread::spawn(move || {
-        let service = dbus_service::DictionaryService::new(sender_for_dbus);
+        let service = dbus_service::DictionaryService::new(sender_for_dbus, client_for_dbus);
         if let Err(e) = service.start() {
             eprintln!("DBus service error: {}", e);
         }
@@ -42,14 +82,14 @@ fn main() {
         while let Ok(cmd) = receiver.try_recv() {
             let mut app_guard = app_main.lock().unwrap();
             match cmd {
-                dbus_service::DbusCommand::LookupWord(word) => {
-                    let _ = app_guard.lookup_word(&word);
+                dbus_service::DbusCommand::LookupWord(result) => {
+                    let _ = app_guard.show_lookup_result(result);
                 }
                 dbus_service::DbusCommand::ShowWindow => {
                     app_guard.show_window();
                 }
-                dbus_service::DbusCommand::LookupAndShow(word) => {
-                    let _ = app_guard.lookup_and_show(&word);
+                dbus_service::DbusCommand::LookupAndShow(result) => {
+                    let _ = app_guard.lookup_and_show(result);
                 }
             }
         }
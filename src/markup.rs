@@ -0,0 +1,226 @@
+/// Elements whose start or end begins a new line of text
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// Elements whose content is never shown
+const HIDDEN_TAGS: &[&str] = &["head", "script", "style", "title"];
+
+/// Stands for an embedded image in converted text. The definition view shows
+/// the picture in its place; plain-text output drops it.
+pub const IMAGE_PLACEHOLDER: char = '\u{fffc}';
+
+/// Plain text of an HTML or XML fragment: tags dropped, block elements on their
+/// own lines, entities decoded and runs of whitespace collapsed. Empty lines are
+/// removed.
+pub fn html_to_text(html: &str) -> String {
+    convert(html, None)
+}
+
+/// Like [`html_to_text`], but each `<img>` is kept as an [`IMAGE_PLACEHOLDER`].
+/// Returns the text and the `src` of each image, in order.
+pub fn html_to_text_with_images(html: &str) -> (String, Vec<String>) {
+    let mut images = Vec::new();
+    let text = convert(html, Some(&mut images));
+    (text, images)
+}
+
+/// Remove image placeholders, for output that can't show pictures
+pub fn strip_image_placeholders(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if !line.contains(IMAGE_PLACEHOLDER) {
+            stripped.push_str(line);
+            continue;
+        }
+        let kept = line.replace(IMAGE_PLACEHOLDER, "");
+        let words: Vec<&str> = kept.split_whitespace().collect();
+        // Drop lines that held nothing but images
+        if words.is_empty() {
+            continue;
+        }
+        stripped.push_str(&words.join(" "));
+        if line.ends_with('\n') {
+            stripped.push('\n');
+        }
+    }
+    stripped
+}
+
+/// Drop sense numbering such as `1)`, `2.` or `a)` from the start of a line of
+/// plain text
+pub fn strip_sense_number(text: &str) -> &str {
+    let (first, rest) = text.split_once(' ').unwrap_or((text, ""));
+    let Some(marker) = first.strip_suffix([')', '.', '>']) else {
+        return text;
+    };
+    let numbered = !marker.is_empty() && marker.chars().all(|c| c.is_ascii_digit());
+    let lettered = marker.chars().count() == 1 && marker.chars().all(char::is_alphabetic);
+    if numbered || lettered {
+        rest.trim_start()
+    } else {
+        text
+    }
+}
+
+fn convert(html: &str, mut images: Option<&mut Vec<String>>) -> String {
+    let mut text = String::new();
+    let mut hidden: Option<String> = None;
+    let mut rest = html;
+
+    while let Some(open) = rest.find('<') {
+        if hidden.is_none() {
+            text.push_str(&decode_entities(&rest[..open]));
+        }
+        rest = &rest[open..];
+
+        // Comments may contain '>'
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let Some(close) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match &hidden {
+            Some(hidden_name) if closing && *hidden_name == name => hidden = None,
+            Some(_) => {}
+            None if !closing && !tag.ends_with('/') && HIDDEN_TAGS.contains(&name.as_str()) => {
+                hidden = Some(name)
+            }
+            None if BLOCK_TAGS.contains(&name.as_str()) => text.push('\n'),
+            None if name == "img" && !closing => {
+                if let (Some(images), Some(src)) = (images.as_deref_mut(), attribute(tag, "src")) {
+                    images.push(src);
+                    text.push(IMAGE_PLACEHOLDER);
+                }
+            }
+            None => {}
+        }
+    }
+    if hidden.is_none() {
+        text.push_str(&decode_entities(rest));
+    }
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Value of attribute `name` in the inside of a start tag, e.g. `img src="a.png"`
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(found) = rest.to_ascii_lowercase().find(name) {
+        let preceded_by_space = rest[..found].ends_with(char::is_whitespace);
+        rest = &rest[found + name.len()..];
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            continue;
+        };
+        if !preceded_by_space {
+            continue;
+        }
+        let value = value.trim_start();
+        let raw = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default(),
+        };
+        return Some(decode_entities(raw));
+    }
+    None
+}
+
+/// Decode named XML/HTML entities commonly found in dictionaries and numeric
+/// character references; anything unrecognized is kept as written
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end + 1])?, end + 2)));
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '\u{2013}',
+        "mdash" => '\u{2014}',
+        "hellip" => '\u{2026}',
+        "laquo" => '\u{ab}',
+        "raquo" => '\u{bb}',
+        "middot" => '\u{b7}',
+        "copy" => '\u{a9}',
+        _ => return None,
+    };
+    Some(c)
+}
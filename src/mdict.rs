@@ -0,0 +1,690 @@
+use crate::api::{Definition, DictionaryEntry, Meaning};
+use crate::lzo;
+use crate::markup;
+use crate::normalize;
+use crate::pattern::Pattern;
+use crate::source::{DictionarySource, FuzzyMatch, Headwords, SourceMetadata};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use encoding_rs::{Encoding, UTF_16LE, UTF_8};
+use flate2::read::ZlibDecoder;
+use ripemd::{Digest, Ripemd128};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A record consisting of this followed by a headword redirects to that entry
+const LINK_PREFIX: &str = "@@@LINK=";
+
+/// Redirects followed before giving up, in case of a cycle
+const MAX_LINKS: usize = 5;
+
+/// Compression type in the first four bytes of every block
+const STORED: u32 = 0;
+const LZO: u32 = 1;
+const ZLIB: u32 = 2;
+
+/// `Encrypted` header bits: the keyword header needs the owner's registration
+/// key; the key index is scrambled with a key derived from its own checksum
+const ENCRYPTED_KEYWORD_HEADER: u32 = 1;
+const ENCRYPTED_KEY_INDEX: u32 = 2;
+
+/// Title and description MdxBuilder fills in when none are given
+const PLACEHOLDER_TITLE: &str = "Title (No HTML code allowed)";
+const PLACEHOLDER_DESCRIPTION: &str = "Description";
+
+/// An MDict dictionary: a `.mdx` file of HTML articles, plus the `.mdd`
+/// resource archives next to it (`name.mdd`, `name.1.mdd`, ...) holding the
+/// images, sounds and stylesheets the articles refer to.
+///
+/// Headwords are kept in memory and articles are decompressed on demand, one
+/// record block at a time. Articles are shown as text, with their images
+/// available through [`DictionarySource::resource`].
+pub struct MDictSource {
+    metadata: SourceMetadata,
+    headwords: Headwords,
+    articles: MDictFile,
+    encoding: &'static Encoding,
+    archives: Vec<MDictFile>,
+    /// Resource name, normalized with `resource_key`, to archive and record
+    resources: HashMap<String, (usize, usize)>,
+}
+
+impl MDictSource {
+    /// Open the `.mdx` file at `path` and any `.mdd` archives beside it
+    pub fn open(path: &Path) -> Result<Self> {
+        let header = read_header(&mut File::open(path)?)?;
+        let encoding = match header.get("Encoding").map(|label| label.trim()) {
+            None | Some("") => UTF_8,
+            Some(label) => Encoding::for_label(label.as_bytes())
+                .with_context(|| format!("Unsupported encoding {}", label))?,
+        };
+
+        let (articles, keys) = MDictFile::open(path, encoding)?;
+
+        let mut archives = Vec::new();
+        let mut resources = HashMap::new();
+        for archive_path in archive_paths(path) {
+            match MDictFile::open(&archive_path, UTF_16LE) {
+                Ok((archive, names)) => {
+                    for (record, name) in names.iter().enumerate() {
+                        resources.insert(resource_key(name), (archives.len(), record));
+                    }
+                    archives.push(archive);
+                }
+                Err(e) => eprintln!(
+                    "Skipping MDict resources {}: {:#}",
+                    archive_path.display(),
+                    e
+                ),
+            }
+        }
+
+        let id = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = header
+            .get("Title")
+            .map(|title| markup::html_to_text(title))
+            .filter(|title| !title.is_empty() && title != PLACEHOLDER_TITLE)
+            .unwrap_or_else(|| id.clone());
+        let mut metadata = SourceMetadata::new(id, name);
+        metadata.description = header
+            .get("Description")
+            .map(|description| markup::html_to_text(description))
+            .filter(|description| {
+                !description.is_empty() && description != PLACEHOLDER_DESCRIPTION
+            });
+        metadata.entry_count = Some(keys.len());
+
+        Ok(Self {
+            metadata,
+            headwords: Headwords::new(keys),
+            articles,
+            encoding,
+            archives,
+            resources,
+        })
+    }
+
+    /// Articles for the given headword ids, skipping ones that fail to read
+    fn entries(&self, ids: Vec<usize>) -> Vec<DictionaryEntry> {
+        // Several headwords often redirect to the same article
+        let mut seen = HashSet::new();
+        ids.into_iter()
+            .filter_map(|id| match self.resolve(id) {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    eprintln!("Skipping MDict entry {}: {:#}", self.headwords.word(id), e);
+                    None
+                }
+            })
+            .filter(|(id, _)| seen.insert(*id))
+            .map(|(id, html)| self.to_entry(id, &html))
+            .collect()
+    }
+
+    /// Follow `@@@LINK=` redirects from headword `id` to an article, returning
+    /// its headword id and HTML
+    fn resolve(&self, mut id: usize) -> Result<(usize, String)> {
+        for _ in 0..=MAX_LINKS {
+            let record = self.articles.record(id)?;
+            let (html, _, _) = self.encoding.decode(&record);
+            let html = html.trim_end_matches(['\0', '\r', '\n']);
+            let Some(target) = html.strip_prefix(LINK_PREFIX) else {
+                return Ok((id, html.to_string()));
+            };
+            let target = target.trim();
+            id = *self
+                .headwords
+                .exact(&normalize::fold(target), true)
+                .first()
+                .with_context(|| format!("Redirect to missing entry {}", target))?;
+        }
+        bail!("Too many redirects")
+    }
+
+    fn to_entry(&self, id: usize, html: &str) -> DictionaryEntry {
+        let (text, images) = markup::html_to_text_with_images(html);
+        DictionaryEntry {
+            word: self.headwords.word(id).to_string(),
+            meanings: vec![Meaning {
+                definitions: vec![Definition {
+                    definition: text,
+                    ..Definition::default()
+                }],
+                ..Meaning::default()
+            }],
+            images,
+            ..DictionaryEntry::default()
+        }
+    }
+}
+
+impl DictionarySource for MDictSource {
+    fn metadata(&self) -> &SourceMetadata {
+        &self.metadata
+    }
+
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        self.entries(self.headwords.exact(folded, strict))
+    }
+
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        self.entries(self.headwords.prefix(folded, strict, limit))
+    }
+
+    fn prefix_headwords(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.headwords.prefix_words(folded, strict, limit)
+    }
+
+    fn fuzzy(&self, loose: &str, max_distance: usize) -> Vec<FuzzyMatch> {
+        self.headwords
+            .fuzzy(loose, max_distance)
+            .into_iter()
+            .map(|(ids, distance)| FuzzyMatch {
+                word: self.headwords.word(ids[0]).to_string(),
+                distance,
+                frequency: 0,
+            })
+            .collect()
+    }
+
+    fn pattern(&self, pattern: &Pattern, limit: usize) -> Vec<String> {
+        self.headwords.pattern(pattern, limit)
+    }
+
+    fn headwords(&self) -> Vec<&str> {
+        self.headwords.words().iter().map(String::as_str).collect()
+    }
+
+    fn resource(&self, name: &str) -> Option<Vec<u8>> {
+        let &(archive, record) = self.resources.get(&resource_key(name))?;
+        match self.archives[archive].record(record) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                eprintln!("Failed to read MDict resource {}: {:#}", name, e);
+                None
+            }
+        }
+    }
+}
+
+/// `name.mdd`, then `name.1.mdd`, `name.2.mdd`, ... while they exist
+fn archive_paths(mdx: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let first = mdx.with_extension("mdd");
+    if first.is_file() {
+        paths.push(first);
+    }
+    for number in 1.. {
+        let path = mdx.with_extension(format!("{}.mdd", number));
+        if !path.is_file() {
+            break;
+        }
+        paths.push(path);
+    }
+    paths
+}
+
+/// Archives name resources like `\images\cat.png`; articles refer to them as
+/// `images/cat.png`, `/images/cat.png` or `file://images/cat.png`
+fn resource_key(name: &str) -> String {
+    let name = name.strip_prefix("file://").unwrap_or(name);
+    let name = name.replace('/', "\\").to_lowercase();
+    format!("\\{}", name.trim_start_matches('\\'))
+}
+
+/// Where a record block sits in the file and in the decompressed record data
+struct RecordBlock {
+    file_offset: u64,
+    compressed_size: u64,
+    start: u64,
+    size: u64,
+}
+
+/// The keyword and record sections common to `.mdx` and `.mdd` files
+struct MDictFile {
+    /// Start of each key's record in the decompressed record data, by key
+    offsets: Vec<u64>,
+    /// Distinct record starts, sorted, to find where each record ends
+    boundaries: Vec<u64>,
+    blocks: Vec<RecordBlock>,
+    reader: Mutex<BlockReader>,
+}
+
+/// The open file and the most recently decompressed record block
+struct BlockReader {
+    file: File,
+    cached: Option<(usize, Arc<Vec<u8>>)>,
+}
+
+impl MDictFile {
+    /// Parse the file's key index, returning it with its keys in file order.
+    /// Keys are in `encoding`; `.mdd` archives always use UTF-16.
+    fn open(path: &Path, encoding: &'static Encoding) -> Result<(Self, Vec<String>)> {
+        let mut file = BufReader::new(File::open(path)?);
+        let header = read_header(&mut file)?;
+
+        let version: f32 = header
+            .get("GeneratedByEngineVersion")
+            .and_then(|version| version.trim().parse().ok())
+            .unwrap_or(2.0);
+        ensure!(version < 3.0, "MDict {} files aren't supported", version);
+        // Version 1 uses 4-byte numbers and plainer key blocks
+        let wide = version >= 2.0;
+
+        let encrypted = match header.get("Encrypted").map(|value| value.trim()) {
+            Some("Yes") => ENCRYPTED_KEYWORD_HEADER,
+            Some(value) => value.parse().unwrap_or(0),
+            None => 0,
+        };
+        ensure!(
+            encrypted & ENCRYPTED_KEYWORD_HEADER == 0,
+            "Dictionary is locked to a registered user"
+        );
+
+        let key_block_count = read_number(&mut file, wide)?;
+        let _entry_count = read_number(&mut file, wide)?;
+        let index_decompressed_size = if wide {
+            read_number(&mut file, wide)?
+        } else {
+            0
+        };
+        let index_size = read_number(&mut file, wide)?;
+        let _key_blocks_size = read_number(&mut file, wide)?;
+        if wide {
+            let _checksum = read_u32(&mut file)?;
+        }
+
+        let index = read_bytes(&mut file, index_size)?;
+        let index = if wide {
+            if encrypted & ENCRYPTED_KEY_INDEX != 0 {
+                decompress_block(&decrypt_key_index(&index)?, index_decompressed_size)?
+            } else {
+                decompress_block(&index, index_decompressed_size)?
+            }
+        } else {
+            index
+        };
+        let unit = if encoding == UTF_16LE { 2 } else { 1 };
+        let key_blocks = parse_key_index(&index, wide, unit, key_block_count)?;
+
+        let mut keys = Vec::new();
+        let mut offsets = Vec::new();
+        for (compressed_size, decompressed_size) in key_blocks {
+            let block = read_bytes(&mut file, compressed_size)?;
+            let block = decompress_block(&block, decompressed_size)?;
+            parse_key_block(&block, wide, encoding, &mut keys, &mut offsets)?;
+        }
+
+        let record_block_count = read_number(&mut file, wide)?;
+        let _entry_count = read_number(&mut file, wide)?;
+        let record_index_size = read_number(&mut file, wide)?;
+        let _record_blocks_size = read_number(&mut file, wide)?;
+
+        let mut blocks = Vec::new();
+        let mut file_offset = file.stream_position()? + record_index_size;
+        let mut start = 0;
+        for _ in 0..record_block_count {
+            let compressed_size = read_number(&mut file, wide)?;
+            let size = read_number(&mut file, wide)?;
+            blocks.push(RecordBlock {
+                file_offset,
+                compressed_size,
+                start,
+                size,
+            });
+            file_offset += compressed_size;
+            start += size;
+        }
+
+        let mut boundaries = offsets.clone();
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let file = MDictFile {
+            offsets,
+            boundaries,
+            blocks,
+            reader: Mutex::new(BlockReader {
+                file: file.into_inner(),
+                cached: None,
+            }),
+        };
+        Ok((file, keys))
+    }
+
+    /// The record for key `index`
+    fn record(&self, index: usize) -> Result<Vec<u8>> {
+        let start = self.offsets[index];
+        let block_index = self
+            .blocks
+            .partition_point(|block| block.start + block.size <= start);
+        let block = self
+            .blocks
+            .get(block_index)
+            .context("Record is past the end of the data")?;
+
+        // A record runs to the next one's start or the end of its block
+        let next = self.boundaries.partition_point(|&offset| offset <= start);
+        let end = self
+            .boundaries
+            .get(next)
+            .copied()
+            .unwrap_or(u64::MAX)
+            .min(block.start + block.size);
+
+        let data = self.block(block_index)?;
+        let from = (start - block.start) as usize;
+        let to = (end - block.start) as usize;
+        data.get(from..to)
+            .map(<[u8]>::to_vec)
+            .context("Record is past the end of its block")
+    }
+
+    fn block(&self, index: usize) -> Result<Arc<Vec<u8>>> {
+        let mut reader = self
+            .reader
+            .lock()
+            .map_err(|_| anyhow!("MDict file lock poisoned"))?;
+        if let Some((cached, data)) = &reader.cached {
+            if *cached == index {
+                return Ok(Arc::clone(data));
+            }
+        }
+
+        let block = &self.blocks[index];
+        reader.file.seek(SeekFrom::Start(block.file_offset))?;
+        let compressed = read_bytes(&mut reader.file, block.compressed_size)?;
+        let data = Arc::new(decompress_block(&compressed, block.size)?);
+        reader.cached = Some((index, Arc::clone(&data)));
+        Ok(data)
+    }
+}
+
+/// Read the header: a length, an XML tag in UTF-16 whose attributes describe
+/// the dictionary, and a checksum
+fn read_header(file: &mut impl Read) -> Result<HashMap<String, String>> {
+    let length = read_u32(file)? as u64;
+    let raw = read_bytes(file, length)?;
+    let _checksum = read_u32(file)?;
+
+    let (text, _, _) = UTF_16LE.decode(&raw);
+    ensure!(
+        text.trim_start().starts_with('<'),
+        "Not an MDict file: header isn't an XML tag"
+    );
+    Ok(parse_attributes(&text))
+}
+
+/// `name="value"` pairs of the header tag
+fn parse_attributes(tag: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = tag;
+    while let Some(equals) = rest.find("=\"") {
+        let name = rest[..equals]
+            .rsplit(|c: char| c.is_whitespace() || c == '<')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        rest = &rest[equals + 2..];
+        let Some(end) = rest.find('"') else {
+            break;
+        };
+        attributes.insert(name, markup::decode_entities(&rest[..end]));
+        rest = &rest[end + 1..];
+    }
+    attributes
+}
+
+/// Undo the scrambling of an encrypted key index: everything after the first
+/// eight bytes is XORed with a RIPEMD-128 key derived from the checksum
+fn decrypt_key_index(index: &[u8]) -> Result<Vec<u8>> {
+    ensure!(index.len() >= 8, "Key index is truncated");
+    let mut seed = index[4..8].to_vec();
+    seed.extend_from_slice(&0x3695u32.to_le_bytes());
+    let key = Ripemd128::digest(&seed);
+
+    let mut decrypted = index[..8].to_vec();
+    let mut previous = 0x36u8;
+    for (i, &byte) in index[8..].iter().enumerate() {
+        let swapped = byte.rotate_left(4);
+        decrypted.push(swapped ^ previous ^ (i as u8) ^ key[i % key.len()]);
+        previous = byte;
+    }
+    Ok(decrypted)
+}
+
+/// Sizes of each key block from the key index:
+/// `(compressed size, decompressed size)`
+fn parse_key_index(index: &[u8], wide: bool, unit: usize, count: u64) -> Result<Vec<(u64, u64)>> {
+    let mut bytes = ByteReader::new(index);
+    let mut blocks = Vec::new();
+    for _ in 0..count {
+        let _entries = bytes.number(wide)?;
+        // First and last key of the block; version 2 adds a terminator
+        for _ in 0..2 {
+            let length = if wide {
+                bytes.u16()? as usize + 1
+            } else {
+                bytes.u8()? as usize
+            };
+            bytes.skip(length * unit)?;
+        }
+        blocks.push((bytes.number(wide)?, bytes.number(wide)?));
+    }
+    Ok(blocks)
+}
+
+/// Keys of a decompressed key block: each a record offset, then the key text
+/// ending in a NUL character
+fn parse_key_block(
+    block: &[u8],
+    wide: bool,
+    encoding: &'static Encoding,
+    keys: &mut Vec<String>,
+    offsets: &mut Vec<u64>,
+) -> Result<()> {
+    let mut bytes = ByteReader::new(block);
+    let unit = if encoding == UTF_16LE { 2 } else { 1 };
+    while !bytes.is_empty() {
+        offsets.push(bytes.number(wide)?);
+        let text = bytes.until_nul(unit)?;
+        let (key, _) = encoding.decode_without_bom_handling(text);
+        keys.push(key.into_owned());
+    }
+    Ok(())
+}
+
+/// Decompress a block: a little-endian compression type, the big-endian
+/// Adler-32 of the decompressed data, then the data. Sizes come from the
+/// file, so a block that inflates past `expected_size` is an error rather
+/// than an allocation of whatever it expands to.
+fn decompress_block(block: &[u8], expected_size: u64) -> Result<Vec<u8>> {
+    ensure!(block.len() >= 8, "Block is truncated");
+    let compression = u32::from_le_bytes(block[0..4].try_into()?);
+    let checksum = u32::from_be_bytes(block[4..8].try_into()?);
+    let data = &block[8..];
+
+    let decompressed = match compression {
+        STORED => data.to_vec(),
+        LZO => lzo::decompress(data, usize::try_from(expected_size)?)?,
+        ZLIB => {
+            let mut decompressed = Vec::new();
+            // One byte over is enough to tell the size is wrong
+            ZlibDecoder::new(data)
+                .take(expected_size.saturating_add(1))
+                .read_to_end(&mut decompressed)?;
+            decompressed
+        }
+        other => bail!("Unknown block compression {}", other),
+    };
+
+    ensure!(
+        decompressed.len() as u64 == expected_size,
+        "Block decompressed to {} bytes instead of {}",
+        decompressed.len(),
+        expected_size
+    );
+    ensure!(
+        adler32(&decompressed) == checksum,
+        "Block checksum mismatch"
+    );
+    Ok(decompressed)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // Sums stay below u32::MAX for chunks this size
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+fn read_u32(file: &mut impl Read) -> Result<u32> {
+    let mut buf = [0; 4];
+    file.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+/// A big-endian count or size: 8 bytes in version 2 files, 4 in version 1
+fn read_number(file: &mut impl Read, wide: bool) -> Result<u64> {
+    if wide {
+        let mut buf = [0; 8];
+        file.read_exact(&mut buf)?;
+        Ok(u64::from_be_bytes(buf))
+    } else {
+        Ok(read_u32(file)? as u64)
+    }
+}
+
+fn read_bytes(file: &mut impl Read, length: u64) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    file.take(length).read_to_end(&mut buf)?;
+    ensure!(buf.len() as u64 == length, "File is truncated");
+    Ok(buf)
+}
+
+/// Big-endian fields of an in-memory index or block
+struct ByteReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        ensure!(self.data.len() >= length, "Index is truncated");
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn skip(&mut self, length: usize) -> Result<()> {
+        self.take(length).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn number(&mut self, wide: bool) -> Result<u64> {
+        if wide {
+            Ok(u64::from_be_bytes(self.take(8)?.try_into()?))
+        } else {
+            Ok(u32::from_be_bytes(self.take(4)?.try_into()?) as u64)
+        }
+    }
+
+    /// Text up to a NUL character `unit` bytes wide, consuming the NUL
+    fn until_nul(&mut self, unit: usize) -> Result<&'a [u8]> {
+        let end = self
+            .data
+            .chunks(unit)
+            .position(|chunk| chunk.iter().all(|&byte| byte == 0))
+            .context("Key without a terminator")?
+            * unit;
+        let text = self.take(end)?;
+        self.skip(unit)?;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn block(compression: u32, data: &[u8], decompressed: &[u8]) -> Vec<u8> {
+        let mut block = compression.to_le_bytes().to_vec();
+        block.extend_from_slice(&adler32(decompressed).to_be_bytes());
+        block.extend_from_slice(data);
+        block
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn decompresses_stored_and_zlib_blocks() {
+        let text = b"a small domesticated carnivore";
+        assert_eq!(
+            decompress_block(&block(STORED, text, text), text.len() as u64).unwrap(),
+            text
+        );
+        assert_eq!(
+            decompress_block(&block(ZLIB, &zlib(text), text), text.len() as u64).unwrap(),
+            text
+        );
+    }
+
+    #[test]
+    fn zlib_block_stops_at_the_expected_size() {
+        let bomb = vec![0; 1 << 20];
+        let error = decompress_block(&block(ZLIB, &zlib(&bomb), &bomb), 16).unwrap_err();
+        assert!(error.to_string().contains("17 bytes instead of 16"));
+    }
+
+    #[test]
+    fn rejects_bad_checksums_and_truncated_blocks() {
+        let mut stored = block(STORED, b"data", b"data");
+        stored[4] ^= 1;
+        assert!(decompress_block(&stored, 4).is_err());
+        assert!(decompress_block(&[STORED as u8, 0, 0], 0).is_err());
+    }
+
+    #[test]
+    fn lzo_block_with_a_huge_size_fails_cleanly() {
+        let data = [20, b'a', b'b', b'c', 0x11, 0, 0];
+        assert!(decompress_block(&block(LZO, &data, b"abc"), u64::MAX >> 1).is_err());
+        assert_eq!(
+            decompress_block(&block(LZO, &data, b"abc"), 3).unwrap(),
+            b"abc"
+        );
+    }
+}
@@ -2,7 +2,6 @@
 // This binary communicates with the browser extension via stdin/stdout
 
 use dbus::blocking::Connection;
-use serde_json;
 use std::io::{self, BufRead, Write};
 use std::time::Duration;
 
@@ -10,6 +9,11 @@ const DBUS_SERVICE_NAME: &str = "com.aynary.Dictionary";
 const DBUS_OBJECT_PATH: &str = "/com/aynary/Dictionary";
 const DBUS_INTERFACE: &str = "com.aynary.Dictionary";
 
+// Error names returned by the Aynary DBus service (see dbus_service.rs)
+const DBUS_ERROR_EMPTY_QUERY: &str = "com.aynary.Dictionary.Error.EmptyQuery";
+const DBUS_ERROR_NOT_FOUND: &str = "com.aynary.Dictionary.Error.NotFound";
+const DBUS_ERROR_INVALID_PATTERN: &str = "com.aynary.Dictionary.Error.InvalidPattern";
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
@@ -32,13 +36,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                             Duration::from_millis(5000),
                         );
 
-                        let _reply: () = proxy.method_call(DBUS_INTERFACE, "LookupAndShow", (word,))?;
+                        let reply: Result<(), dbus::Error> =
+                            proxy.method_call(DBUS_INTERFACE, "LookupAndShow", (word,));
 
                         // Send response to extension
-                        let response = serde_json::json!({
-                            "success": true,
-                            "word": word
-                        });
+                        let response = match reply {
+                            Ok(()) => serde_json::json!({
+                                "success": true,
+                                "word": word
+                            }),
+                            Err(e) => serde_json::json!({
+                                "success": false,
+                                "word": word,
+                                "error": error_code(e.name()),
+                                "message": e.message().unwrap_or_default()
+                            }),
+                        };
                         writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
                         stdout.flush()?;
                     }
@@ -58,3 +71,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Stable, machine-readable code for a DBus error reply
+fn error_code(dbus_error_name: Option<&str>) -> &'static str {
+    match dbus_error_name {
+        Some(DBUS_ERROR_EMPTY_QUERY) => "empty_query",
+        Some(DBUS_ERROR_NOT_FOUND) => "not_found",
+        Some(DBUS_ERROR_INVALID_PATTERN) => "invalid_pattern",
+        _ => "unavailable",
+    }
+}
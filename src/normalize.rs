@@ -0,0 +1,49 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Fold `text` for caseless comparison while keeping its diacritics.
+///
+/// Applies compatibility decomposition (so ligatures and width variants compare
+/// equal to their plain forms), full Unicode case folding ("Straße" and "STRASSE"
+/// both become "strasse") and recomposes the result to NFC.
+pub fn fold(text: &str) -> String {
+    let decomposed: String = text.trim().nfkd().collect();
+    caseless::default_case_fold_str(&decomposed).nfc().collect()
+}
+
+/// Remove combining marks from already folded text ("café" → "cafe")
+pub fn strip_diacritics(folded: &str) -> String {
+    folded
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .nfc()
+        .collect()
+}
+
+/// Accent- and case-insensitive form of `text`, used as the index key for
+/// headwords and queries alike
+pub fn loose(text: &str) -> String {
+    strip_diacritics(&fold(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_case_and_compatibility_forms() {
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("STRASSE"), "strasse");
+        assert_eq!(fold(" ﬁle "), "file");
+        assert_eq!(fold("Café"), "café");
+    }
+
+    #[test]
+    fn strips_diacritics_after_folding() {
+        assert_eq!(strip_diacritics("café"), "cafe");
+        assert_eq!(strip_diacritics("naïve"), "naive");
+        assert_eq!(loose("CAFÉ"), "cafe");
+        // Letters without a decomposition are kept as they are
+        assert_eq!(loose("Øre"), "øre");
+    }
+}
@@ -0,0 +1,385 @@
+use crate::api::{self, DictionaryEntry};
+use crate::compact::{self, CompactSource};
+use crate::diagnostics::{Diagnostic, LoadProblem, Position, RawEntry};
+use crate::import as importer;
+use crate::pattern::Pattern;
+use crate::source::{DictionarySource, FuzzyMatch, MemorySource, SourceMetadata};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Names the directory it is in as a dictionary package
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Version of the package format written by this build. Version 1 is the
+/// bare JSON array of entries, which carries no metadata and is still read,
+/// with a manifest made up from the file; version 2 put it in a directory
+/// with a manifest.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Entries file of a package whose manifest doesn't name one
+const DEFAULT_ENTRIES_FILE: &str = "entries.json";
+
+/// Describes a dictionary package: a directory holding this manifest, the
+/// entries and any audio and images they refer to by relative path
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub schema_version: u32,
+    /// Stable identifier, such as "team-glossary"
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Version of the dictionary's content, such as "2024.1" or "1.3.0"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Languages of the headwords, as BCP 47 or ISO 639 codes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    /// Name or SPDX identifier of the license, such as "CC-BY-SA-4.0"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Credit the license asks for, such as the authors and the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+    pub entry_count: usize,
+    /// Entries file, relative to the package: a JSON array of entries or a
+    /// compiled `.aydict`
+    #[serde(default = "default_entries_file")]
+    pub entries: String,
+}
+
+fn default_entries_file() -> String {
+    String::from(DEFAULT_ENTRIES_FILE)
+}
+
+impl Manifest {
+    /// Read and check a manifest. Packages from a newer Aynary are refused
+    /// rather than misread.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let value: Value = serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let version = value
+            .get("schemaVersion")
+            .and_then(Value::as_u64)
+            .context("The manifest has no schemaVersion")?;
+        if version > u64::from(SCHEMA_VERSION) {
+            bail!(
+                "The package uses schema version {}, but this version of Aynary reads up to {}",
+                version,
+                SCHEMA_VERSION
+            );
+        }
+        let manifest: Self = serde_json::from_value(migrate(value, version)?)
+            .with_context(|| format!("Invalid manifest {}", path.display()))?;
+        if manifest.id.trim().is_empty() {
+            bail!("The manifest has an empty id");
+        }
+        Ok(manifest)
+    }
+
+    /// A manifest for the bare array of entries in `path`, the version 1
+    /// format, named after the file and with the languages of its entries
+    pub fn for_entries(path: &Path, entries: &[DictionaryEntry]) -> Self {
+        let id = importer::json_stem(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("dictionary")
+            .to_string();
+        let mut languages: Vec<String> = Vec::new();
+        for language in entries.iter().filter_map(|entry| entry.language.as_ref()) {
+            if !languages.contains(language) {
+                languages.push(language.clone());
+            }
+        }
+        Self {
+            schema_version: SCHEMA_VERSION,
+            title: id.clone(),
+            id,
+            description: None,
+            version: None,
+            languages,
+            license: None,
+            attribution: None,
+            entry_count: entries.len(),
+            entries: default_entries_file(),
+        }
+    }
+
+    /// What the package's source reports about itself
+    pub fn metadata(&self, entry_count: usize) -> SourceMetadata {
+        SourceMetadata {
+            description: self.description.clone(),
+            language: self.languages.first().cloned(),
+            entry_count: Some(entry_count),
+            version: self.version.clone(),
+            license: self.license.clone(),
+            attribution: self.attribution.clone(),
+            ..SourceMetadata::new(self.id.clone(), self.title.clone())
+        }
+    }
+}
+
+/// Bring a manifest written for schema version `from` up to
+/// [`SCHEMA_VERSION`]. A version that changes the manifest adds an arm here
+/// that upgrades `value` from the version before it and migrates the result
+/// on from there. Version 1 had no manifest to migrate: its bare arrays are
+/// upgraded when loaded, through [`Manifest::for_entries`].
+fn migrate(value: Value, from: u64) -> Result<Value> {
+    match from {
+        2 => Ok(value),
+        _ => bail!("Unknown schema version {}", from),
+    }
+}
+
+/// Where the manifest at `path` gives its entry count, for pointing at a
+/// count that doesn't match the entries
+pub fn entry_count_position(path: &Path) -> Position {
+    let text = fs::read_to_string(path).unwrap_or_default();
+    let manifest = RawEntry {
+        at: Position { line: 1, column: 1 },
+        text: &text,
+    };
+    manifest.locate("entryCount")
+}
+
+/// Whether `path` is the manifest of a package
+pub fn is_manifest(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == MANIFEST_FILE)
+}
+
+/// A dictionary package, with its entries in memory or, when compiled,
+/// memory-mapped
+pub struct PackageSource {
+    dir: PathBuf,
+    metadata: SourceMetadata,
+    entries: Box<dyn DictionarySource>,
+}
+
+impl PackageSource {
+    /// Open the package whose manifest is at `path`. Entries of a JSON
+    /// entries file that can't be read are left out and described in `problem`.
+    pub fn open(path: &Path, problem: &mut LoadProblem) -> Result<Self> {
+        let manifest = Manifest::read(path)?;
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let entries_path =
+            resolve(&dir, &manifest.entries).context("The entries file is outside the package")?;
+
+        let entries: Box<dyn DictionarySource> = if entries_path
+            .extension()
+            .is_some_and(|ext| ext == compact::EXTENSION)
+        {
+            Box::new(CompactSource::open(&entries_path)?)
+        } else {
+            let entries = api::load_dictionary_file(&entries_path, problem)?;
+            let metadata = SourceMetadata::new(manifest.id.clone(), manifest.title.clone());
+            Box::new(MemorySource::new(metadata, entries))
+        };
+
+        let entry_count = entries.metadata().entry_count.unwrap_or(0);
+        let skipped = problem.skipped_entries.len();
+        if entry_count + skipped != manifest.entry_count {
+            let at = entry_count_position(path);
+            problem.warnings.push(Diagnostic {
+                line: at.line,
+                column: at.column,
+                word: None,
+                message: format!(
+                    "The manifest lists {} entries, but the package has {}",
+                    manifest.entry_count,
+                    entry_count + skipped
+                ),
+            });
+        }
+
+        Ok(Self {
+            dir,
+            metadata: manifest.metadata(entry_count),
+            entries,
+        })
+    }
+}
+
+impl DictionarySource for PackageSource {
+    fn metadata(&self) -> &SourceMetadata {
+        &self.metadata
+    }
+
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        self.entries.lookup(folded, strict)
+    }
+
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        self.entries.prefix(folded, strict, limit)
+    }
+
+    fn prefix_headwords(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.entries.prefix_headwords(folded, strict, limit)
+    }
+
+    fn fuzzy(&self, loose: &str, max_distance: usize) -> Vec<FuzzyMatch> {
+        self.entries.fuzzy(loose, max_distance)
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<(DictionaryEntry, f32)> {
+        self.entries.search(query, limit)
+    }
+
+    fn pattern(&self, pattern: &Pattern, limit: usize) -> Vec<String> {
+        self.entries.pattern(pattern, limit)
+    }
+
+    fn headwords(&self) -> Vec<&str> {
+        self.entries.headwords()
+    }
+
+    /// Images are files in the package, named by their path inside it
+    fn resource(&self, name: &str) -> Option<Vec<u8>> {
+        let path = resolve(&self.dir, name.strip_prefix("file://").unwrap_or(name))?;
+        match fs::read(&path) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    fn build_indexes(&self) {
+        self.entries.build_indexes()
+    }
+}
+
+/// `relative` inside `dir`, unless it is absolute or climbs out with ".."
+pub fn resolve(dir: &Path, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative);
+    relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        .then(|| dir.join(relative))
+}
+
+/// Files in the package an entry refers to: local audio and images
+pub fn referenced_files(entry: &DictionaryEntry) -> impl Iterator<Item = &str> {
+    entry
+        .phonetics
+        .iter()
+        .filter_map(|phonetic| phonetic.audio.as_deref())
+        .filter(|audio| !audio.contains("://"))
+        .chain(entry.images.iter().map(String::as_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A package directory under the temporary directory holding `manifest`
+    /// and `entries` as its entries file
+    fn package_dir(name: &str, manifest: &str, entries: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aynary-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(MANIFEST_FILE), manifest).unwrap();
+        fs::write(dir.join(DEFAULT_ENTRIES_FILE), entries).unwrap();
+        dir
+    }
+
+    const ENTRIES: &str = r#"[{"word": "a", "phonetics": [], "sourceUrls": [], "meanings": []}]"#;
+
+    #[test]
+    fn reads_manifests() {
+        let dir = package_dir(
+            "manifest",
+            r#"{"schemaVersion": 2, "id": "terms", "title": "Terms", "entryCount": 1}"#,
+            ENTRIES,
+        );
+        let manifest = Manifest::read(&dir.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(manifest.id, "terms");
+        assert_eq!(manifest.entries, DEFAULT_ENTRIES_FILE);
+
+        let mut problem = LoadProblem::new("terms");
+        let source = PackageSource::open(&dir.join(MANIFEST_FILE), &mut problem).unwrap();
+        assert_eq!(source.metadata().entry_count, Some(1));
+        assert!(problem.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        for (version, message) in [
+            (1, "Unknown schema version 1"),
+            (0, "Unknown schema version 0"),
+            (3, "reads up to 2"),
+        ] {
+            let dir = package_dir(
+                &format!("version-{}", version),
+                &format!(
+                    r#"{{"schemaVersion": {}, "id": "a", "title": "A", "entryCount": 1}}"#,
+                    version
+                ),
+                ENTRIES,
+            );
+            let e = Manifest::read(&dir.join(MANIFEST_FILE)).unwrap_err();
+            assert!(e.to_string().contains(message), "{:#}", e);
+            fs::remove_dir_all(dir).unwrap();
+        }
+        assert!(migrate(Value::Null, u64::from(SCHEMA_VERSION)).is_ok());
+    }
+
+    #[test]
+    fn reports_a_wrong_entry_count() {
+        let dir = package_dir(
+            "entry-count",
+            "{\n  \"schemaVersion\": 2,\n  \"id\": \"a\",\n  \"title\": \"A\",\n  \"entryCount\": 3\n}",
+            ENTRIES,
+        );
+        let mut problem = LoadProblem::new("a");
+        PackageSource::open(&dir.join(MANIFEST_FILE), &mut problem).unwrap();
+        assert_eq!(problem.warnings.len(), 1);
+        let warning = &problem.warnings[0];
+        assert_eq!((warning.line, warning.column), (5, 17));
+        assert_eq!(
+            warning.message,
+            "The manifest lists 3 entries, but the package has 1"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn entries_outside_the_package_are_refused() {
+        let dir = package_dir(
+            "outside",
+            r#"{"schemaVersion": 2, "id": "a", "title": "A", "entryCount": 1, "entries": "../a.json"}"#,
+            ENTRIES,
+        );
+        let mut problem = LoadProblem::new("a");
+        assert!(PackageSource::open(&dir.join(MANIFEST_FILE), &mut problem).is_err());
+        assert_eq!(resolve(&dir, "/etc/passwd"), None);
+        assert_eq!(resolve(&dir, "audio/a.ogg"), Some(dir.join("audio/a.ogg")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn names_bare_dictionaries_after_the_whole_stem() {
+        let manifest = Manifest::for_entries(Path::new("data/en.wiktionary.json.gz"), &[]);
+        assert_eq!(manifest.id, "en.wiktionary");
+        assert_eq!(manifest.schema_version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn bare_dictionaries_report_what_a_package_would() {
+        let entries: Vec<DictionaryEntry> = serde_json::from_str(
+            r#"[{"word": "Haus", "language": "de", "phonetics": [], "sourceUrls": [], "meanings": []}]"#,
+        )
+        .unwrap();
+        let metadata = Manifest::for_entries(Path::new("dicts/german.json"), &entries).metadata(1);
+        assert_eq!(metadata.id, "german");
+        assert_eq!(metadata.name, "german");
+        assert_eq!(metadata.language.as_deref(), Some("de"));
+        assert_eq!(metadata.entry_count, Some(1));
+    }
+}
@@ -0,0 +1,449 @@
+use crate::index::HeadwordIndex;
+use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+use thiserror::Error;
+
+/// Longest key covered by the per-letter position index; longer keys are
+/// still found through the length buckets
+const MAX_POSITIONAL_LEN: usize = 32;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PatternError {
+    #[error("The pattern is empty.")]
+    Empty,
+    #[error("Unclosed '[' in pattern.")]
+    UnclosedClass,
+    #[error("Empty character class '[]' in pattern.")]
+    EmptyClass,
+    #[error("Invalid length constraint ':{0}'; use ':5', ':4-6' or ':4-'.")]
+    InvalidLength(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    /// `?`: exactly one character
+    AnyChar,
+    /// `*`: any run of characters, including none
+    AnyRun,
+    /// `[abc]`, `[a-f]`, `[^aeiou]`
+    Class {
+        ranges: Vec<RangeInclusive<char>>,
+        negated: bool,
+    },
+}
+
+impl Token {
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            Token::Literal(literal) => *literal == c,
+            Token::AnyChar => true,
+            Token::AnyRun => false,
+            Token::Class { ranges, negated } => {
+                ranges.iter().any(|range| range.contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+/// A crossword-style headword pattern.
+///
+/// `?` matches one letter, `*` any run of letters and `[...]` one letter from a
+/// class (`[aeiou]`, `[a-m]`, `[^aeiou]`). A trailing `:N`, `:N-M` or `:N-`
+/// constrains the length, e.g. `c*t:5` or `*ing:8-10`. Patterns are matched
+/// against index keys, so the caller normalizes the input the same way.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    min_len: usize,
+    max_len: Option<usize>,
+}
+
+impl Pattern {
+    /// Whether `text` should be treated as a pattern rather than a plain word
+    pub fn looks_like_pattern(text: &str) -> bool {
+        text.contains(['?', '*'])
+    }
+
+    pub fn parse(input: &str) -> Result<Self, PatternError> {
+        // Only a suffix that looks like a length is one, so "re:*" still
+        // matches headwords with a colon in them
+        let (body, length) = match input.rsplit_once(':') {
+            Some((body, length)) if is_length_spec(length) => (body, Some(parse_length(length)?)),
+            _ => (input, None),
+        };
+
+        let mut tokens = Vec::new();
+        let mut chars = body.chars();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::AnyChar,
+                // Consecutive stars are equivalent to one
+                '*' if tokens.last() == Some(&Token::AnyRun) => continue,
+                '*' => Token::AnyRun,
+                '[' => parse_class(&mut chars)?,
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+        }
+
+        if tokens.is_empty() {
+            return Err(PatternError::Empty);
+        }
+
+        let fixed = tokens.iter().filter(|token| **token != Token::AnyRun).count();
+        let has_run = tokens.contains(&Token::AnyRun);
+        let (mut min_len, mut max_len) = (fixed, (!has_run).then_some(fixed));
+
+        if let Some((min, max)) = length {
+            min_len = min_len.max(min);
+            max_len = match (max_len, max) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+
+        Ok(Self {
+            tokens,
+            min_len,
+            max_len,
+        })
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        let chars: Vec<char> = key.chars().collect();
+        if chars.len() < self.min_len || self.max_len.is_some_and(|max| chars.len() > max) {
+            return false;
+        }
+
+        // reachable[j]: the tokens seen so far can consume exactly chars[..j]
+        let mut reachable = vec![false; chars.len() + 1];
+        reachable[0] = true;
+
+        for token in &self.tokens {
+            let mut next = vec![false; chars.len() + 1];
+            if *token == Token::AnyRun {
+                let mut seen = false;
+                for (j, slot) in next.iter_mut().enumerate() {
+                    seen |= reachable[j];
+                    *slot = seen;
+                }
+            } else {
+                for (j, c) in chars.iter().enumerate() {
+                    if reachable[j] && token.accepts(*c) {
+                        next[j + 1] = true;
+                    }
+                }
+            }
+            reachable = next;
+        }
+
+        reachable[chars.len()]
+    }
+
+    /// Length in characters when the pattern can only match one length
+    fn fixed_len(&self) -> Option<usize> {
+        self.max_len.filter(|&max| max == self.min_len)
+    }
+
+    fn literal_prefix(&self) -> String {
+        self.tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Literal(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn literal_suffix(&self) -> String {
+        let mut suffix: Vec<char> = self
+            .tokens
+            .iter()
+            .rev()
+            .map_while(|token| match token {
+                Token::Literal(c) => Some(*c),
+                _ => None,
+            })
+            .collect();
+        suffix.reverse();
+        suffix.into_iter().collect()
+    }
+
+    /// `(position, letter)` for every literal when no `*` precedes it
+    fn anchored_literals(&self) -> impl Iterator<Item = (usize, char)> + '_ {
+        self.tokens
+            .iter()
+            .take_while(|token| **token != Token::AnyRun)
+            .enumerate()
+            .filter_map(|(position, token)| match token {
+                Token::Literal(c) => Some((position, *c)),
+                _ => None,
+            })
+    }
+}
+
+/// Whether `spec` has the shape of `:N`, `:N-M` or `:N-` after the colon
+fn is_length_spec(spec: &str) -> bool {
+    spec.starts_with(|c: char| c.is_ascii_digit())
+        && spec.chars().all(|c| c.is_ascii_digit() || c == '-')
+}
+
+/// `5` → (5, Some(5)), `4-6` → (4, Some(6)), `4-` → (4, None)
+fn parse_length(spec: &str) -> Result<(usize, Option<usize>), PatternError> {
+    let invalid = || PatternError::InvalidLength(spec.to_string());
+    let number = |text: &str| text.trim().parse::<usize>().map_err(|_| invalid());
+
+    let (min, max) = match spec.split_once('-') {
+        Some((min, max)) if max.trim().is_empty() => (number(min)?, None),
+        Some((min, max)) => (number(min)?, Some(number(max)?)),
+        None => {
+            let exact = number(spec)?;
+            (exact, Some(exact))
+        }
+    };
+
+    if max.is_some_and(|max| max < min) {
+        return Err(invalid());
+    }
+    Ok((min, max))
+}
+
+/// Parse the rest of a `[...]` class; the opening bracket is already consumed
+fn parse_class(chars: &mut std::str::Chars) -> Result<Token, PatternError> {
+    let mut ranges = Vec::new();
+    let mut negated = false;
+    let mut first = true;
+
+    loop {
+        let c = chars.next().ok_or(PatternError::UnclosedClass)?;
+        match c {
+            ']' => break,
+            '^' if first => negated = true,
+            c => {
+                // "a-f" is a range; a '-' at either end is a literal dash
+                let mut lookahead = chars.clone();
+                match (lookahead.next(), lookahead.next()) {
+                    (Some('-'), Some(end)) if end != ']' => {
+                        chars.next();
+                        chars.next();
+                        ranges.push(c.min(end)..=c.max(end));
+                    }
+                    _ => ranges.push(c..=c),
+                }
+            }
+        }
+        first = false;
+    }
+
+    if ranges.is_empty() {
+        return Err(PatternError::EmptyClass);
+    }
+    Ok(Token::Class { ranges, negated })
+}
+
+/// Secondary indexes over a [`HeadwordIndex`]'s keys that narrow a pattern
+/// down to a small candidate set before any key is matched.
+///
+/// Key ids are positions in `HeadwordIndex::keys`.
+#[derive(Debug, Default)]
+pub struct PatternIndex {
+    /// Key ids ordered by their reversed key, for literal-suffix queries
+    by_suffix: Vec<u32>,
+    /// Key ids grouped by length in characters
+    by_length: HashMap<usize, Vec<u32>>,
+    /// Key ids by (length, position, letter), for fixed-length patterns
+    by_letter: HashMap<(usize, usize, char), Vec<u32>>,
+}
+
+impl PatternIndex {
+    pub fn build(index: &HeadwordIndex) -> Self {
+        let keys = index.keys();
+
+        let mut by_suffix: Vec<u32> = (0..keys.len() as u32).collect();
+        by_suffix.sort_unstable_by(|&a, &b| {
+            keys[a as usize].chars().rev().cmp(keys[b as usize].chars().rev())
+        });
+
+        let mut by_length: HashMap<usize, Vec<u32>> = HashMap::new();
+        let mut by_letter: HashMap<(usize, usize, char), Vec<u32>> = HashMap::new();
+        for (id, key) in keys.iter().enumerate() {
+            let len = key.chars().count();
+            by_length.entry(len).or_default().push(id as u32);
+
+            if len <= MAX_POSITIONAL_LEN {
+                for (position, c) in key.chars().enumerate() {
+                    by_letter.entry((len, position, c)).or_default().push(id as u32);
+                }
+            }
+        }
+
+        Self {
+            by_suffix,
+            by_length,
+            by_letter,
+        }
+    }
+
+    /// Keys matching `pattern`, in sorted order
+    pub fn search<'a>(
+        &self,
+        index: &'a HeadwordIndex,
+        pattern: &Pattern,
+        limit: usize,
+    ) -> Vec<&'a str> {
+        let keys = index.keys();
+
+        let mut ids: Vec<u32> = match self.candidates(index, pattern) {
+            Candidates::Range(range) => range.map(|id| id as u32).collect(),
+            Candidates::Slice(ids) => ids.to_vec(),
+            Candidates::Lists(lists) => lists.into_iter().flatten().copied().collect(),
+        };
+        ids.retain(|&id| pattern.matches(&keys[id as usize]));
+        ids.sort_unstable();
+
+        ids.into_iter()
+            .take(limit)
+            .map(|id| keys[id as usize].as_str())
+            .collect()
+    }
+
+    /// The smallest candidate set any index can offer for `pattern`
+    fn candidates<'a>(&'a self, index: &HeadwordIndex, pattern: &Pattern) -> Candidates<'a> {
+        let mut best = Candidates::Range(0..index.len());
+
+        let prefix = pattern.literal_prefix();
+        if !prefix.is_empty() {
+            best = best.min(Candidates::Range(index.prefix_range(&prefix)));
+        }
+
+        let suffix = pattern.literal_suffix();
+        if !suffix.is_empty() {
+            best = best.min(Candidates::Slice(self.suffix_ids(index, &suffix)));
+        }
+
+        if let Some(len) = pattern.fixed_len() {
+            let by_letter = pattern
+                .anchored_literals()
+                .filter(|_| len <= MAX_POSITIONAL_LEN)
+                .map(|(position, c)| {
+                    self.by_letter
+                        .get(&(len, position, c))
+                        .map(Vec::as_slice)
+                        .unwrap_or(&[])
+                })
+                .min_by_key(|ids| ids.len());
+
+            let ids = by_letter
+                .or_else(|| self.by_length.get(&len).map(Vec::as_slice))
+                .unwrap_or(&[]);
+            best = best.min(Candidates::Slice(ids));
+        } else if let Some(max) = pattern.max_len {
+            // Walk the lengths that exist rather than the range, which may be huge
+            let lists = self
+                .by_length
+                .iter()
+                .filter(|(len, _)| (pattern.min_len..=max).contains(len))
+                .map(|(_, ids)| ids.as_slice())
+                .collect();
+            best = best.min(Candidates::Lists(lists));
+        }
+
+        best
+    }
+
+    fn suffix_ids(&self, index: &HeadwordIndex, suffix: &str) -> &[u32] {
+        let keys = index.keys();
+        let start = self.by_suffix.partition_point(|&id| {
+            keys[id as usize].chars().rev().lt(suffix.chars().rev())
+        });
+        let len = self.by_suffix[start..]
+            .partition_point(|&id| keys[id as usize].ends_with(suffix));
+        &self.by_suffix[start..start + len]
+    }
+}
+
+enum Candidates<'a> {
+    Range(Range<usize>),
+    Slice(&'a [u32]),
+    Lists(Vec<&'a [u32]>),
+}
+
+impl<'a> Candidates<'a> {
+    fn len(&self) -> usize {
+        match self {
+            Candidates::Range(range) => range.len(),
+            Candidates::Slice(ids) => ids.len(),
+            Candidates::Lists(lists) => lists.iter().map(|ids| ids.len()).sum(),
+        }
+    }
+
+    fn min(self, other: Candidates<'a>) -> Candidates<'a> {
+        if other.len() < self.len() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, key: &str) -> bool {
+        Pattern::parse(pattern).unwrap().matches(key)
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        assert_eq!(Pattern::parse("").unwrap_err(), PatternError::Empty);
+        assert_eq!(Pattern::parse(":5").unwrap_err(), PatternError::Empty);
+        assert_eq!(Pattern::parse("c[ab").unwrap_err(), PatternError::UnclosedClass);
+        assert_eq!(Pattern::parse("c[]").unwrap_err(), PatternError::EmptyClass);
+        assert_eq!(
+            Pattern::parse("c*t:6-4").unwrap_err(),
+            PatternError::InvalidLength("6-4".to_string())
+        );
+    }
+
+    #[test]
+    fn matches_wildcards_and_classes() {
+        assert!(matches("c?t", "cat"));
+        assert!(!matches("c?t", "cart"));
+        assert!(matches("c*t", "ct"));
+        assert!(matches("c**t", "cart"));
+        assert!(matches("[a-c]at", "cat"));
+        assert!(!matches("[a-c]at", "dat"));
+        assert!(matches("[^aeiou]at", "bat"));
+        assert!(!matches("[^aeiou]at", "eat"));
+        assert!(matches("[a-]?", "-x"));
+    }
+
+    #[test]
+    fn applies_length_constraints() {
+        assert!(matches("c*t:4", "cart"));
+        assert!(!matches("c*t:4", "cat"));
+        assert!(matches("*ing:5-", "swing"));
+        assert!(!matches("*ing:5-", "sing"));
+        assert!(!matches("*:2-3", "cart"));
+        // Not a length, so the colon is part of the pattern
+        assert!(matches("re:*", "re:x"));
+    }
+
+    #[test]
+    fn searches_through_the_narrowest_index() {
+        let keys = ["cat", "cart", "coat", "dog", "catalog", "scat"];
+        let index = HeadwordIndex::build(keys.map(String::from));
+        let patterns = PatternIndex::build(&index);
+        let search = |pattern: &str, limit: usize| {
+            patterns.search(&index, &Pattern::parse(pattern).unwrap(), limit)
+        };
+
+        assert_eq!(search("c*t", 10), ["cart", "cat", "coat"]);
+        assert_eq!(search("*at", 10), ["cat", "coat", "scat"]);
+        assert_eq!(search("?a?", 10), ["cat"]);
+        assert_eq!(search("*:4", 10), ["cart", "coat", "scat"]);
+        assert_eq!(search("*:1-99999999999", 2), ["cart", "cat"]);
+    }
+}
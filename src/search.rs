@@ -0,0 +1,160 @@
+use crate::lemma;
+use crate::normalize;
+use std::collections::HashMap;
+
+/// BM25 term-frequency saturation
+const K1: f32 = 1.2;
+/// BM25 document-length normalization
+const B: f32 = 0.75;
+
+/// Function words that carry no meaning on their own in a definition query
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "into", "is", "it",
+    "its", "of", "on", "or", "that", "the", "to", "with",
+];
+
+/// Ranked inverted index over free text, one document per dictionary entry
+#[derive(Debug, Default)]
+pub struct FullTextIndex {
+    /// Term to (document id, term frequency), ordered by document id
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    doc_lengths: Vec<u32>,
+    avg_doc_length: f32,
+}
+
+impl FullTextIndex {
+    /// Index `documents`, where a document's id is its position in the iterator
+    pub fn build<I>(documents: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut postings: HashMap<String, Vec<(usize, u32)>> = HashMap::new();
+        let mut doc_lengths = Vec::new();
+
+        for (id, text) in documents.into_iter().enumerate() {
+            let terms = tokenize(&text);
+            doc_lengths.push(terms.len() as u32);
+
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for term in terms {
+                *counts.entry(term).or_default() += 1;
+            }
+            for (term, count) in counts {
+                postings.entry(term).or_default().push((id, count));
+            }
+        }
+
+        let total: u64 = doc_lengths.iter().map(|&len| u64::from(len)).sum();
+        let avg_doc_length = if doc_lengths.is_empty() {
+            0.0
+        } else {
+            total as f32 / doc_lengths.len() as f32
+        };
+
+        Self {
+            postings,
+            doc_lengths,
+            avg_doc_length,
+        }
+    }
+
+    /// Documents matching any query term, best BM25 score first
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(usize, f32)> {
+        let doc_count = self.doc_lengths.len() as f32;
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+
+        let mut terms: Vec<&str> = Vec::new();
+        for token in tokenize(query) {
+            if let Some((term, _)) = self.resolve_term(&token) {
+                if !terms.contains(&term) {
+                    terms.push(term);
+                }
+            }
+        }
+
+        for term in terms {
+            let postings = &self.postings[term];
+            let df = postings.len() as f32;
+            let idf = ((doc_count - df + 0.5) / (df + 0.5) + 1.0).ln();
+
+            for &(id, tf) in postings {
+                let tf = tf as f32;
+                let length_ratio = self.doc_lengths[id] as f32 / self.avg_doc_length.max(1.0);
+                let score = idf * (tf * (K1 + 1.0)) / (tf + K1 * (1.0 - B + B * length_ratio));
+                *scores.entry(id).or_default() += score;
+            }
+        }
+
+        let mut ranked: Vec<(usize, f32)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(limit);
+        ranked
+    }
+
+    /// The indexed form of a query token, falling back to its lemma so that
+    /// "heights" still matches definitions that only say "height"
+    fn resolve_term(&self, token: &str) -> Option<(&str, &Vec<(usize, u32)>)> {
+        if let Some((term, postings)) = self.postings.get_key_value(token) {
+            return Some((term.as_str(), postings));
+        }
+
+        lemma::candidates(token).into_iter().find_map(|lemma| {
+            self.postings
+                .get_key_value(&lemma)
+                .map(|(term, postings)| (term.as_str(), postings))
+        })
+    }
+}
+
+/// Split text into normalized, accent-insensitive terms without stop words
+fn tokenize(text: &str) -> Vec<String> {
+    normalize::loose(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty() && !STOP_WORDS.contains(token))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> FullTextIndex {
+        FullTextIndex::build(
+            [
+                "A large domesticated animal used for riding",
+                "A small domesticated feline animal",
+                "The act of riding a horse, riding for sport",
+                "A river",
+            ]
+            .map(String::from),
+        )
+    }
+
+    /// Document ids for `query`, best first
+    fn ids(index: &FullTextIndex, query: &str, limit: usize) -> Vec<usize> {
+        index
+            .search(query, limit)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn ranks_by_term_frequency_and_rarity() {
+        let index = index();
+        // Same length, but the third document mentions riding twice
+        assert_eq!(ids(&index, "riding", 10), [2, 0]);
+        // "feline" is rarer than "animal", so matching both wins
+        assert_eq!(ids(&index, "feline animal", 10), [1, 0]);
+        assert_eq!(ids(&index, "feline animal", 1), [1]);
+    }
+
+    #[test]
+    fn matches_lemmas_and_ignores_stop_words() {
+        let index = index();
+        assert_eq!(ids(&index, "Horses", 10), [2]);
+        assert!(ids(&index, "the of", 10).is_empty());
+        assert!(FullTextIndex::default().search("river", 10).is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+use crate::dict_client;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Settings file, relative to `$XDG_CONFIG_HOME`
+const SETTINGS_FILE: &str = "aynary/settings.json";
+
+/// User preferences that persist between runs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Require diacritics to match exactly in lookups
+    pub strict_diacritics: bool,
+    pub dict_server: DictServerSettings,
+}
+
+/// Where the DICT protocol source connects and which database it queries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DictServerSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// Database name, or `*` to search every database on the server
+    pub database: String,
+}
+
+impl Default for DictServerSettings {
+    fn default() -> Self {
+        Self {
+            // Off until the user sets up a server, so lookups stay offline
+            enabled: false,
+            host: String::from("localhost"),
+            port: dict_client::DEFAULT_PORT,
+            database: String::from("*"),
+        }
+    }
+}
+
+impl Settings {
+    /// Saved settings, or the defaults when none are saved or they can't be read
+    pub fn load() -> Self {
+        let Some(path) = settings_path() else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+                eprintln!("Ignoring invalid settings in {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = settings_path().context("No configuration directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create configuration directory")?;
+        }
+        let raw = serde_json::to_string_pretty(self)?;
+        fs::write(&path, raw).context("Failed to write settings")
+    }
+}
+
+/// `$XDG_CONFIG_HOME/aynary/settings.json`, defaulting to `~/.config`
+fn settings_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join(SETTINGS_FILE))
+}
@@ -0,0 +1,353 @@
+use crate::api::DictionaryEntry;
+use crate::fuzzy::BkTree;
+use crate::index::HeadwordIndex;
+use crate::normalize;
+use crate::pattern::{Pattern, PatternIndex};
+use crate::search::FullTextIndex;
+
+/// Descriptive information about a dictionary source
+#[derive(Debug, Clone, Default)]
+pub struct SourceMetadata {
+    /// Stable identifier, e.g. the file name without extension
+    pub id: String,
+    /// Human-readable title, used for attribution in results
+    pub name: String,
+    pub description: Option<String>,
+    /// BCP 47 language tag of the headwords, when known
+    pub language: Option<String>,
+    /// Number of entries, when the source knows it without a full scan
+    pub entry_count: Option<usize>,
+    /// Version of the dictionary's content, as its publisher numbers it
+    pub version: Option<String>,
+    /// Name or SPDX identifier of the license the content is under
+    pub license: Option<String>,
+    /// Credit the license asks for
+    pub attribution: Option<String>,
+}
+
+impl SourceMetadata {
+    pub fn new(id: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// ISO 639-1 codes and the three-letter ISO 639-2 codes of the same languages,
+/// which XDXF and ZIM name languages with. Codes after the first for a
+/// language are older bibliographic variants.
+pub const LANGUAGE_CODES: &[(&str, &str)] = &[
+    ("ar", "ara"),
+    ("cs", "ces"),
+    ("da", "dan"),
+    ("de", "deu"),
+    ("el", "ell"),
+    ("en", "eng"),
+    ("eo", "epo"),
+    ("es", "spa"),
+    ("fi", "fin"),
+    ("fr", "fra"),
+    ("he", "heb"),
+    ("hu", "hun"),
+    ("it", "ita"),
+    ("ja", "jpn"),
+    ("ko", "kor"),
+    ("la", "lat"),
+    ("nl", "nld"),
+    ("no", "nor"),
+    ("pl", "pol"),
+    ("pt", "por"),
+    ("ro", "ron"),
+    ("ru", "rus"),
+    ("sv", "swe"),
+    ("tr", "tur"),
+    ("uk", "ukr"),
+    ("zh", "zho"),
+    ("cs", "cze"),
+    ("de", "ger"),
+    ("el", "gre"),
+    ("fr", "fre"),
+    ("nl", "dut"),
+    ("ro", "rum"),
+    ("zh", "chi"),
+];
+
+/// The BCP 47 tag for an ISO 639 language code, which is the two-letter
+/// code when the language has one: `en` for `eng`
+pub fn language_tag(code: &str) -> String {
+    let code = code.to_ascii_lowercase();
+    LANGUAGE_CODES
+        .iter()
+        .find(|(_, three)| *three == code)
+        .map_or(code, |(two, _)| two.to_string())
+}
+
+/// A headword found by edit distance
+#[derive(Debug, Clone)]
+pub struct FuzzyMatch {
+    pub word: String,
+    pub distance: usize,
+    /// Relative usage frequency, 0 when unknown
+    pub frequency: u32,
+}
+
+/// A provider of dictionary entries that `DictionaryClient` can query.
+///
+/// Queries are passed already case folded with `normalize::fold`; `strict`
+/// asks the source to also match diacritics exactly. Only exact and prefix
+/// lookup are required; sources without fuzzy, full-text or pattern support
+/// simply contribute nothing to those searches.
+pub trait DictionarySource: Send + Sync {
+    fn metadata(&self) -> &SourceMetadata;
+
+    /// Entries whose headword equals `folded`
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry>;
+
+    /// Up to `limit` entries whose headword starts with `folded`, in headword order
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry>;
+
+    /// The headwords of [`DictionarySource::prefix`], which sources with a
+    /// headword index find without reading the entries
+    fn prefix_headwords(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.prefix(folded, strict, limit)
+            .into_iter()
+            .map(|entry| entry.word)
+            .collect()
+    }
+
+    /// Headwords within `max_distance` edits of the accent-insensitive key `loose`
+    fn fuzzy(&self, _loose: &str, _max_distance: usize) -> Vec<FuzzyMatch> {
+        Vec::new()
+    }
+
+    /// Entries whose definitions match `query`, best BM25 score first
+    fn search(&self, _query: &str, _limit: usize) -> Vec<(DictionaryEntry, f32)> {
+        Vec::new()
+    }
+
+    /// Headwords matching `pattern`, in headword order
+    fn pattern(&self, _pattern: &Pattern, _limit: usize) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Every headword, for sources that can enumerate them cheaply
+    fn headwords(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    /// Contents of a file bundled with the dictionary, such as an image named
+    /// in an entry's `images`
+    fn resource(&self, _name: &str) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Build now whatever indexes the source would otherwise build on first
+    /// use, so the first search needing one doesn't wait. Called on a
+    /// background thread once the dictionaries are loaded.
+    fn build_indexes(&self) {}
+}
+
+/// Headword indexes for a source, with ids being positions in the word list.
+///
+/// Sources that keep only their headwords in memory and read definitions on
+/// demand get exact, prefix, fuzzy and pattern search from this.
+pub struct Headwords {
+    words: Vec<String>,
+    index: HeadwordIndex,
+    fuzzy: BkTree,
+    patterns: PatternIndex,
+}
+
+impl Headwords {
+    pub fn new(words: Vec<String>) -> Self {
+        let index = HeadwordIndex::build(words.iter().map(|word| normalize::loose(word)));
+        let fuzzy = BkTree::build(index.keys().iter().cloned());
+        let patterns = PatternIndex::build(&index);
+
+        Self {
+            words,
+            index,
+            fuzzy,
+            patterns,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn word(&self, id: usize) -> &str {
+        &self.words[id]
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// Ids of the headwords equal to `folded`
+    pub fn exact(&self, folded: &str, strict: bool) -> Vec<usize> {
+        self.index
+            .exact(&normalize::strip_diacritics(folded))
+            .iter()
+            .copied()
+            .filter(|&id| self.accepts(id, folded, strict, false))
+            .collect()
+    }
+
+    /// Ids of up to `limit` headwords starting with `folded`, in headword order
+    pub fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<usize> {
+        self.index
+            .prefix(&normalize::strip_diacritics(folded))
+            .filter(|&id| self.accepts(id, folded, strict, true))
+            .take(limit)
+            .collect()
+    }
+
+    /// Up to `limit` headwords starting with `folded`, in headword order
+    pub fn prefix_words(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.prefix(folded, strict, limit)
+            .into_iter()
+            .map(|id| self.words[id].clone())
+            .collect()
+    }
+
+    /// Keys within `max_distance` edits of `loose`, as the ids stored under
+    /// each key together with its distance
+    pub fn fuzzy(&self, loose: &str, max_distance: usize) -> Vec<(&[usize], usize)> {
+        self.fuzzy
+            .find(loose, max_distance)
+            .into_iter()
+            .map(|(key, distance)| (self.index.exact(key), distance))
+            .filter(|(ids, _)| !ids.is_empty())
+            .collect()
+    }
+
+    /// Headwords matching `pattern`, one per key, in headword order
+    pub fn pattern(&self, pattern: &Pattern, limit: usize) -> Vec<String> {
+        self.patterns
+            .search(&self.index, pattern, limit)
+            .into_iter()
+            .filter_map(|key| self.index.exact(key).first())
+            .map(|&id| self.words[id].clone())
+            .collect()
+    }
+
+    /// The index ignores accents, so in strict mode candidates are re-checked with them
+    fn accepts(&self, id: usize, folded: &str, strict: bool, prefix: bool) -> bool {
+        if !strict {
+            return true;
+        }
+        let word = normalize::fold(&self.words[id]);
+        if prefix {
+            word.starts_with(folded)
+        } else {
+            word == folded
+        }
+    }
+}
+
+/// A dictionary held fully in memory with every index built over it
+pub struct MemorySource {
+    metadata: SourceMetadata,
+    entries: Vec<DictionaryEntry>,
+    headwords: Headwords,
+    definitions: FullTextIndex,
+}
+
+impl MemorySource {
+    pub fn new(mut metadata: SourceMetadata, entries: Vec<DictionaryEntry>) -> Self {
+        metadata.entry_count = Some(entries.len());
+        if metadata.language.is_none() {
+            metadata.language = entries.first().and_then(|entry| entry.language.clone());
+        }
+
+        let headwords = Headwords::new(entries.iter().map(|entry| entry.word.clone()).collect());
+        let definitions = FullTextIndex::build(entries.iter().map(definition_text));
+
+        Self {
+            metadata,
+            entries,
+            headwords,
+            definitions,
+        }
+    }
+
+    fn entries(&self, ids: Vec<usize>) -> Vec<DictionaryEntry> {
+        ids.into_iter().map(|id| self.entries[id].clone()).collect()
+    }
+}
+
+impl DictionarySource for MemorySource {
+    fn metadata(&self) -> &SourceMetadata {
+        &self.metadata
+    }
+
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        self.entries(self.headwords.exact(folded, strict))
+    }
+
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        self.entries(self.headwords.prefix(folded, strict, limit))
+    }
+
+    fn prefix_headwords(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.headwords.prefix_words(folded, strict, limit)
+    }
+
+    fn fuzzy(&self, loose: &str, max_distance: usize) -> Vec<FuzzyMatch> {
+        self.headwords
+            .fuzzy(loose, max_distance)
+            .into_iter()
+            .map(|(ids, distance)| FuzzyMatch {
+                word: self.headwords.word(ids[0]).to_string(),
+                distance,
+                // Highest frequency among the entries sharing the key
+                frequency: ids
+                    .iter()
+                    .filter_map(|&id| self.entries[id].frequency)
+                    .max()
+                    .unwrap_or(0),
+            })
+            .collect()
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<(DictionaryEntry, f32)> {
+        self.definitions
+            .search(query, limit)
+            .into_iter()
+            .map(|(id, score)| (self.entries[id].clone(), score))
+            .collect()
+    }
+
+    fn pattern(&self, pattern: &Pattern, limit: usize) -> Vec<String> {
+        self.headwords.pattern(pattern, limit)
+    }
+
+    fn headwords(&self) -> Vec<&str> {
+        self.headwords.words().iter().map(String::as_str).collect()
+    }
+}
+
+/// Every definition and example of an entry, as one searchable document
+pub fn definition_text(entry: &DictionaryEntry) -> String {
+    let mut text = String::new();
+    for definition in entry
+        .meanings
+        .iter()
+        .flat_map(|meaning| &meaning.definitions)
+    {
+        text.push_str(&definition.definition);
+        text.push('\n');
+        if let Some(example) = &definition.example {
+            text.push_str(example);
+            text.push('\n');
+        }
+    }
+    text
+}
@@ -0,0 +1,627 @@
+use crate::api::{Definition, DictionaryEntry, Meaning};
+use crate::markup;
+use crate::pattern::Pattern;
+use crate::source::{DictionarySource, FuzzyMatch, Headwords, SourceMetadata};
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::{Decompress, FlushDecompress};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// First line of every `.ifo` file
+const IFO_MAGIC: &str = "StarDict's dict ifo file";
+
+/// gzip header flags
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// A StarDict dictionary: `.ifo` metadata, `.idx` headword index, optional
+/// `.syn` synonyms and a plain or dictzip-compressed `.dict` payload.
+///
+/// Headwords are kept in memory; articles are read from the payload on
+/// demand, so large dictionaries open quickly. Definitions aren't indexed for
+/// reverse lookup.
+pub struct StarDictSource {
+    metadata: SourceMetadata,
+    /// Headwords from the `.idx` file followed by those from the `.syn` file
+    headwords: Headwords,
+    /// For each headword, the `.idx` entry holding its article
+    targets: Vec<usize>,
+    /// Article location in the uncompressed payload, per `.idx` entry
+    articles: Vec<(u64, u32)>,
+    same_type_sequence: Option<String>,
+    payload: Mutex<Payload>,
+}
+
+impl StarDictSource {
+    /// Open the dictionary described by the `.ifo` file at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let info = fs::read_to_string(path).context("Failed to read .ifo file")?;
+        let info = parse_ifo(&info)?;
+
+        let version = info.get("version").map(String::as_str).unwrap_or_default();
+        let offset_bits = match info.get("idxoffsetbits").map(String::as_str) {
+            Some("64") if version == "3.0.0" => 64,
+            Some("64") => bail!("64-bit offsets require StarDict 3.0.0, found {}", version),
+            Some("32") | None => 32,
+            Some(other) => bail!("Unsupported idxoffsetbits {}", other),
+        };
+
+        let idx = read_maybe_gzipped(&companion(path, "idx"), &companion(path, "idx.gz"))
+            .context("Failed to read .idx file")?;
+        let (mut words, articles): (Vec<String>, Vec<(u64, u32)>) =
+            parse_idx(&idx, offset_bits)?.into_iter().unzip();
+        let mut targets: Vec<usize> = (0..words.len()).collect();
+
+        let syn = companion(path, "syn");
+        if syn.is_file() {
+            let syn = fs::read(&syn).context("Failed to read .syn file")?;
+            for (word, target) in parse_syn(&syn)? {
+                if target < articles.len() {
+                    words.push(word);
+                    targets.push(target);
+                }
+            }
+        }
+
+        let payload = Payload::open(path)?;
+
+        let id = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = info.get("bookname").cloned().unwrap_or_else(|| id.clone());
+        let mut metadata = SourceMetadata::new(id, name);
+        metadata.description = info
+            .get("description")
+            .map(|description| markup::html_to_text(description))
+            .filter(|description| !description.is_empty());
+        metadata.entry_count = Some(articles.len());
+
+        Ok(Self {
+            metadata,
+            headwords: Headwords::new(words),
+            targets,
+            articles,
+            same_type_sequence: info.get("sametypesequence").cloned(),
+            payload: Mutex::new(payload),
+        })
+    }
+
+    /// Articles for the given headword ids, skipping ones that fail to read
+    fn entries(&self, ids: Vec<usize>) -> Vec<DictionaryEntry> {
+        // A query can match both a headword and one of its synonyms
+        let mut seen = HashSet::new();
+        ids.into_iter()
+            .map(|id| self.targets[id])
+            .filter(|&target| seen.insert(target))
+            .filter_map(|target| match self.entry(target) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    eprintln!(
+                        "Skipping StarDict article {}: {:#}",
+                        self.headwords.word(target),
+                        e
+                    );
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn entry(&self, target: usize) -> Result<DictionaryEntry> {
+        let (offset, size) = self.articles[target];
+        let data = self
+            .payload
+            .lock()
+            .map_err(|_| anyhow::anyhow!("StarDict payload lock poisoned"))?
+            .read(offset, size as usize)?;
+        let fields = parse_fields(&data, self.same_type_sequence.as_deref())?;
+        Ok(to_entry(self.headwords.word(target), fields))
+    }
+}
+
+impl DictionarySource for StarDictSource {
+    fn metadata(&self) -> &SourceMetadata {
+        &self.metadata
+    }
+
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        self.entries(self.headwords.exact(folded, strict))
+    }
+
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        self.entries(self.headwords.prefix(folded, strict, limit))
+    }
+
+    fn prefix_headwords(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.headwords.prefix_words(folded, strict, limit)
+    }
+
+    fn fuzzy(&self, loose: &str, max_distance: usize) -> Vec<FuzzyMatch> {
+        self.headwords
+            .fuzzy(loose, max_distance)
+            .into_iter()
+            .map(|(ids, distance)| FuzzyMatch {
+                word: self.headwords.word(ids[0]).to_string(),
+                distance,
+                frequency: 0,
+            })
+            .collect()
+    }
+
+    fn pattern(&self, pattern: &Pattern, limit: usize) -> Vec<String> {
+        self.headwords.pattern(pattern, limit)
+    }
+
+    fn headwords(&self) -> Vec<&str> {
+        self.headwords.words().iter().map(String::as_str).collect()
+    }
+}
+
+/// `name.ifo` → `name.<extension>`
+fn companion(ifo: &Path, extension: &str) -> PathBuf {
+    ifo.with_extension(extension)
+}
+
+fn parse_ifo(text: &str) -> Result<HashMap<String, String>> {
+    let mut lines = text.lines();
+    ensure!(
+        lines
+            .next()
+            .map(|line| line.trim_start_matches('\u{feff}').trim())
+            == Some(IFO_MAGIC),
+        "Not a StarDict .ifo file"
+    );
+
+    Ok(lines
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect())
+}
+
+/// Read `plain` if it exists, otherwise gunzip `gzipped`
+fn read_maybe_gzipped(plain: &Path, gzipped: &Path) -> Result<Vec<u8>> {
+    if plain.is_file() {
+        return Ok(fs::read(plain)?);
+    }
+    let mut data = Vec::new();
+    GzDecoder::new(File::open(gzipped)?).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Headwords and `(offset, size)` of their articles, in file order
+fn parse_idx(data: &[u8], offset_bits: u32) -> Result<Vec<(String, (u64, u32))>> {
+    let mut entries = Vec::new();
+    let mut rest = data;
+
+    while !rest.is_empty() {
+        let (word, tail) = split_nul(rest).context("Truncated .idx entry")?;
+        let (offset, tail) = if offset_bits == 64 {
+            let (offset, tail) = take::<8>(tail)?;
+            (u64::from_be_bytes(offset), tail)
+        } else {
+            let (offset, tail) = take::<4>(tail)?;
+            (u64::from(u32::from_be_bytes(offset)), tail)
+        };
+        let (size, tail) = take::<4>(tail)?;
+
+        entries.push((
+            String::from_utf8_lossy(word).into_owned(),
+            (offset, u32::from_be_bytes(size)),
+        ));
+        rest = tail;
+    }
+
+    Ok(entries)
+}
+
+/// Synonyms and the `.idx` entry each one refers to
+fn parse_syn(data: &[u8]) -> Result<Vec<(String, usize)>> {
+    let mut synonyms = Vec::new();
+    let mut rest = data;
+
+    while !rest.is_empty() {
+        let (word, tail) = split_nul(rest).context("Truncated .syn entry")?;
+        let (target, tail) = take::<4>(tail)?;
+        synonyms.push((
+            String::from_utf8_lossy(word).into_owned(),
+            u32::from_be_bytes(target) as usize,
+        ));
+        rest = tail;
+    }
+
+    Ok(synonyms)
+}
+
+fn split_nul(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((&data[..end], &data[end + 1..]))
+}
+
+fn take<const N: usize>(data: &[u8]) -> Result<([u8; N], &[u8])> {
+    ensure!(data.len() >= N, "Unexpected end of data");
+    let (head, tail) = data.split_at(N);
+    Ok((head.try_into()?, tail))
+}
+
+/// Split an article into `(type, data)` fields.
+///
+/// Lower-case types are NUL-terminated text and upper-case types are binary
+/// with a 32-bit size prefix. With `sametypesequence` the type markers are
+/// omitted and the last field runs to the end of the article.
+fn parse_fields<'a>(data: &'a [u8], types: Option<&str>) -> Result<Vec<(u8, &'a [u8])>> {
+    let mut fields = Vec::new();
+    let mut rest = data;
+
+    let field = |kind: u8, rest: &'a [u8], last: bool| -> Result<(&'a [u8], &'a [u8])> {
+        if last {
+            Ok((rest, &[]))
+        } else if kind.is_ascii_lowercase() {
+            Ok(split_nul(rest).unwrap_or((rest, &[])))
+        } else {
+            let (size, tail) = take::<4>(rest)?;
+            let size = u32::from_be_bytes(size) as usize;
+            ensure!(tail.len() >= size, "Field size exceeds article");
+            Ok(tail.split_at(size))
+        }
+    };
+
+    match types {
+        Some(types) => {
+            let types = types.as_bytes();
+            for (i, &kind) in types.iter().enumerate() {
+                let (value, tail) = field(kind, rest, i + 1 == types.len())?;
+                fields.push((kind, value));
+                rest = tail;
+            }
+        }
+        None => {
+            while let Some((&kind, tail)) = rest.split_first() {
+                let (value, tail) = field(kind, tail, false)?;
+                fields.push((kind, value));
+                rest = tail;
+            }
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Map article fields onto an entry: phonetic fields become the
+/// pronunciation and every text line of the other fields a definition.
+/// Resource fields (sounds, pictures) are ignored.
+fn to_entry(word: &str, fields: Vec<(u8, &[u8])>) -> DictionaryEntry {
+    let mut phonetic = None;
+    let mut definitions = Vec::new();
+
+    for (kind, value) in fields {
+        let value = String::from_utf8_lossy(value);
+        let text = match kind {
+            b'm' | b'l' => value.into_owned(),
+            b'g' | b'h' | b'x' | b'k' | b'w' | b'n' => markup::html_to_text(&value),
+            b't' | b'y' => {
+                phonetic = Some(value.trim().to_string()).filter(|text| !text.is_empty());
+                continue;
+            }
+            _ => continue,
+        };
+        definitions.extend(
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| Definition {
+                    definition: line.to_string(),
+                    ..Definition::default()
+                }),
+        );
+    }
+
+    DictionaryEntry {
+        word: word.to_string(),
+        phonetic,
+        meanings: vec![Meaning {
+            definitions,
+            ..Meaning::default()
+        }],
+        ..DictionaryEntry::default()
+    }
+}
+
+/// The `.dict` payload. dictzip files are read one chunk at a time; a `.dz`
+/// without a chunk table is an ordinary gzip file and is inflated up front.
+enum Payload {
+    Plain(File),
+    DictZip(DictZip),
+    Inflated(Vec<u8>),
+}
+
+impl Payload {
+    fn open(ifo: &Path) -> Result<Self> {
+        let plain = companion(ifo, "dict");
+        if plain.is_file() {
+            return Ok(Payload::Plain(File::open(plain)?));
+        }
+
+        let compressed = companion(ifo, "dict.dz");
+        let mut file = File::open(&compressed).context("Missing .dict or .dict.dz file")?;
+        match DictZip::open(&mut file)? {
+            Some(dictzip) => Ok(Payload::DictZip(dictzip)),
+            None => {
+                let mut data = Vec::new();
+                file.seek(SeekFrom::Start(0))?;
+                GzDecoder::new(file).read_to_end(&mut data)?;
+                Ok(Payload::Inflated(data))
+            }
+        }
+    }
+
+    fn read(&mut self, offset: u64, size: usize) -> Result<Vec<u8>> {
+        match self {
+            Payload::Plain(file) => {
+                // Sizes come from the .idx file, so read no further than the payload
+                let mut data = Vec::new();
+                file.seek(SeekFrom::Start(offset))?;
+                file.take(size as u64).read_to_end(&mut data)?;
+                ensure!(data.len() == size, "Article lies outside the .dict file");
+                Ok(data)
+            }
+            Payload::DictZip(dictzip) => dictzip.read(offset, size),
+            Payload::Inflated(data) => {
+                let start = usize::try_from(offset)?;
+                start
+                    .checked_add(size)
+                    .and_then(|end| data.get(start..end))
+                    .map(<[u8]>::to_vec)
+                    .context("Article lies outside the .dict file")
+            }
+        }
+    }
+}
+
+/// A gzip file whose deflate stream is flushed every `chunk_len` bytes of
+/// input, with the compressed chunk sizes recorded in an `RA` extra field so
+/// any chunk can be inflated on its own
+struct DictZip {
+    file: File,
+    chunk_len: usize,
+    /// File offset and compressed size of each chunk
+    chunks: Vec<(u64, usize)>,
+    /// Most recently inflated chunk; articles are often read in runs
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+impl DictZip {
+    /// Parse the gzip header, returning the chunk layout if the file is dictzip
+    fn open(file: &mut File) -> Result<Option<DictZip>> {
+        let mut fixed = [0u8; 10];
+        file.read_exact(&mut fixed)
+            .context("Truncated gzip header")?;
+        ensure!(fixed[..3] == [0x1f, 0x8b, 8], "Not a gzip file");
+        let flags = fixed[3];
+        let mut header_len = 10u64;
+
+        let mut layout = None;
+        if flags & FEXTRA != 0 {
+            let mut len = [0u8; 2];
+            file.read_exact(&mut len)?;
+            let mut extra = vec![0; u16::from_le_bytes(len) as usize];
+            file.read_exact(&mut extra)?;
+            header_len += 2 + extra.len() as u64;
+            layout = parse_random_access(&extra);
+        }
+
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                let mut byte = [0u8; 1];
+                loop {
+                    file.read_exact(&mut byte)?;
+                    header_len += 1;
+                    if byte[0] == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        if flags & FHCRC != 0 {
+            header_len += 2;
+        }
+
+        let Some((chunk_len, sizes)) = layout else {
+            return Ok(None);
+        };
+
+        let mut offset = header_len;
+        let chunks = sizes
+            .into_iter()
+            .map(|size| {
+                let chunk = (offset, size);
+                offset += size as u64;
+                chunk
+            })
+            .collect();
+
+        Ok(Some(DictZip {
+            file: file.try_clone()?,
+            chunk_len,
+            chunks,
+            cache: None,
+        }))
+    }
+
+    fn read(&mut self, offset: u64, size: usize) -> Result<Vec<u8>> {
+        let mut position = usize::try_from(offset)?;
+        let chunk_len = self.chunk_len;
+        ensure!(
+            position
+                .checked_add(size)
+                .is_some_and(|end| end <= chunk_len * self.chunks.len()),
+            "Article lies outside the .dict.dz file"
+        );
+        let mut data = Vec::with_capacity(size);
+
+        while data.len() < size {
+            let chunk = self.chunk(position / chunk_len)?;
+            let start = position % chunk_len;
+            ensure!(
+                start < chunk.len(),
+                "Article lies outside the .dict.dz file"
+            );
+
+            let end = chunk.len().min(start + size - data.len());
+            data.extend_from_slice(&chunk[start..end]);
+            position += end - start;
+        }
+
+        Ok(data)
+    }
+
+    fn chunk(&mut self, index: usize) -> Result<&[u8]> {
+        if self.cache.as_ref().map(|(cached, _)| *cached) != Some(index) {
+            let &(offset, size) = self
+                .chunks
+                .get(index)
+                .context("Article lies outside the .dict.dz file")?;
+
+            let mut compressed = vec![0; size];
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.read_exact(&mut compressed)?;
+
+            let mut inflated = Vec::with_capacity(self.chunk_len);
+            Decompress::new(false)
+                .decompress_vec(&compressed, &mut inflated, FlushDecompress::Sync)
+                .context("Corrupt .dict.dz chunk")?;
+            self.cache = Some((index, inflated));
+        }
+
+        Ok(self
+            .cache
+            .as_ref()
+            .map(|(_, data)| data.as_slice())
+            .unwrap_or_default())
+    }
+}
+
+/// Chunk length and compressed chunk sizes from a gzip extra field's `RA`
+/// subfield: version, chunk length and count, then one size per chunk, all
+/// 16-bit little-endian
+fn parse_random_access(extra: &[u8]) -> Option<(usize, Vec<usize>)> {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let id = &rest[..2];
+        let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let data = rest.get(4..4 + len)?;
+        rest = &rest[4 + len..];
+        if id != b"RA" || data.len() < 6 {
+            continue;
+        }
+
+        let word = |i: usize| u16::from_le_bytes([data[2 * i], data[2 * i + 1]]) as usize;
+        let (chunk_len, count) = (word(1), word(2));
+        if word(0) != 1 || chunk_len == 0 || data.len() < 6 + 2 * count {
+            return None;
+        }
+        return Some((chunk_len, (0..count).map(|i| word(3 + i)).collect()));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compress, Compression, FlushCompress};
+
+    /// A `.idx` entry with a 32-bit offset
+    fn idx_entry(word: &str, offset: u32, size: u32) -> Vec<u8> {
+        let mut entry = word.as_bytes().to_vec();
+        entry.push(0);
+        entry.extend(offset.to_be_bytes());
+        entry.extend(size.to_be_bytes());
+        entry
+    }
+
+    /// A dictzip file holding `text` in chunks of `chunk_len` bytes
+    fn dictzip(text: &[u8], chunk_len: usize) -> Vec<u8> {
+        let chunks: Vec<Vec<u8>> = text
+            .chunks(chunk_len)
+            .map(|chunk| {
+                let mut compressed = Vec::with_capacity(chunk.len() + 64);
+                Compress::new(Compression::default(), false)
+                    .compress_vec(chunk, &mut compressed, FlushCompress::Full)
+                    .unwrap();
+                compressed
+            })
+            .collect();
+
+        let mut extra = b"RA".to_vec();
+        extra.extend((6 + 2 * chunks.len() as u16).to_le_bytes());
+        for word in [1, chunk_len, chunks.len()] {
+            extra.extend((word as u16).to_le_bytes());
+        }
+        for chunk in &chunks {
+            extra.extend((chunk.len() as u16).to_le_bytes());
+        }
+
+        let mut file = vec![0x1f, 0x8b, 8, FEXTRA, 0, 0, 0, 0, 0, 3];
+        file.extend((extra.len() as u16).to_le_bytes());
+        file.extend(extra);
+        file.extend(chunks.concat());
+        file
+    }
+
+    #[test]
+    fn parses_index_and_synonyms() {
+        let idx = [idx_entry("cat", 0, 12), idx_entry("dog", 12, 9)].concat();
+        assert_eq!(
+            parse_idx(&idx, 32).unwrap(),
+            [("cat".to_string(), (0, 12)), ("dog".to_string(), (12, 9))]
+        );
+        assert!(parse_idx(&idx[..idx.len() - 2], 32).is_err());
+        assert!(parse_idx(b"cat", 32).is_err());
+
+        let mut wide = b"cat\0".to_vec();
+        wide.extend((1u64 << 40).to_be_bytes());
+        wide.extend(7u32.to_be_bytes());
+        assert_eq!(
+            parse_idx(&wide, 64).unwrap(),
+            [("cat".to_string(), (1 << 40, 7))]
+        );
+
+        let syn = [
+            &b"kitty\0"[..],
+            &0u32.to_be_bytes(),
+            b"hound\0",
+            &1u32.to_be_bytes(),
+        ]
+        .concat();
+        assert_eq!(
+            parse_syn(&syn).unwrap(),
+            [("kitty".to_string(), 0), ("hound".to_string(), 1)]
+        );
+        assert!(parse_syn(b"kitty\0\0").is_err());
+    }
+
+    #[test]
+    fn reads_dictzip_articles_across_chunks() {
+        let text = b"a small cat; a loyal dog";
+        let path =
+            std::env::temp_dir().join(format!("aynary-{}-stardict.dict.dz", std::process::id()));
+        fs::write(&path, dictzip(text, 8)).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let mut dictzip = DictZip::open(&mut file).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(dictzip.chunks.len(), 3);
+        assert_eq!(dictzip.read(2, 9).unwrap(), b"small cat");
+        assert_eq!(dictzip.read(0, 24).unwrap(), text);
+        // Sizes from a corrupt .idx fail instead of allocating them
+        assert!(dictzip.read(20, 5).is_err());
+        assert!(dictzip.read(2, u32::MAX as usize).is_err());
+        assert!(dictzip.read(u64::MAX, 1).is_err());
+    }
+}
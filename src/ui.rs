@@ -1,26 +1,87 @@
+use crate::diagnostics::{self, LoadProblem};
+use crate::markup::IMAGE_PLACEHOLDER;
 use adw::prelude::*;
-use adw::{ApplicationWindow, Clamp, WindowTitle};
+use adw::{ApplicationWindow, Banner, Clamp, MessageDialog, WindowTitle};
 use gtk4::prelude::*;
 use gtk4::{
     AboutDialog,
     Align,
     Box as GtkBox,
+    Button,
+    CheckButton,
+    DropDown,
     Entry,
     EntryIconPosition,
+    FlowBox,
     Image,
     Label,
+    MenuButton,
     Orientation,
+    Popover,
     ScrolledWindow,
+    SelectionMode,
+    Separator,
     Stack,
+    StringList,
     TextView,
     WrapMode,
 };
+use gtk4::gdk_pixbuf::{InterpType, PixbufLoader};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Embedded images wider than this are scaled down
+const MAX_IMAGE_WIDTH: i32 = 480;
+
+/// What the search entry searches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Look up a headword
+    Word,
+    /// Reverse dictionary: find words whose definitions match the text
+    Meaning,
+    /// Crossword-style pattern such as `c?t` or `*ology:7`
+    Pattern,
+}
+
+impl SearchMode {
+    const ALL: [SearchMode; 3] = [SearchMode::Word, SearchMode::Meaning, SearchMode::Pattern];
+
+    fn label(self) -> &'static str {
+        match self {
+            SearchMode::Word => "Word",
+            SearchMode::Meaning => "Meaning",
+            SearchMode::Pattern => "Pattern",
+        }
+    }
+
+    fn placeholder(self) -> &'static str {
+        match self {
+            SearchMode::Word => "Search words, phrases, and more...",
+            SearchMode::Meaning => "Describe a meaning, e.g. \"fear of heights\"...",
+            SearchMode::Pattern => "? for one letter, * for any run, e.g. c?t or *ing:7",
+        }
+    }
+}
 
 pub struct AppWindow {
     pub window: adw::ApplicationWindow,
     pub search_entry: Entry,
+    pub search_mode_dropdown: DropDown,
     pub definition_view: TextView,
+    pub strict_diacritics_check: CheckButton,
+    pub dict_enabled_check: CheckButton,
+    pub dict_host_entry: Entry,
+    pub dict_database_dropdown: DropDown,
+    pub dict_refresh_button: Button,
+    /// Database name behind each row of `dict_database_dropdown`
+    dict_databases: RefCell<Vec<String>>,
     content_stack: Stack,
+    suggestions_title: Label,
+    suggestions_flow: FlowBox,
+    /// Shown when dictionaries failed to load, with the details behind its button
+    load_banner: Banner,
+    load_details: Rc<RefCell<String>>,
 }
 
 impl AppWindow {
@@ -41,7 +102,7 @@ impl AppWindow {
         header.set_title_widget(Some(&title));
 
         let search_entry = Entry::builder()
-            .placeholder_text("Search words, phrases, and more...")
+            .placeholder_text(SearchMode::Word.placeholder())
             .hexpand(true)
             .build();
         search_entry.set_icon_from_icon_name(
@@ -50,6 +111,23 @@ impl AppWindow {
         );
         search_entry.add_css_class("search-entry");
 
+        let mode_labels: Vec<&str> = SearchMode::ALL.iter().map(|mode| mode.label()).collect();
+        let search_mode_dropdown = DropDown::from_strings(&mode_labels);
+        search_mode_dropdown.set_tooltip_text(Some("Search Mode"));
+        search_mode_dropdown.set_valign(Align::Center);
+        let entry_for_mode = search_entry.clone();
+        search_mode_dropdown.connect_selected_notify(move |dropdown| {
+            let mode = mode_at(dropdown.selected());
+            entry_for_mode.set_placeholder_text(Some(mode.placeholder()));
+        });
+
+        let search_row = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(8)
+            .build();
+        search_row.append(&search_entry);
+        search_row.append(&search_mode_dropdown);
+
         // Enable window controls (close, minimize, maximize buttons) - these appear automatically
         header.set_show_end_title_buttons(true);
         header.set_show_start_title_buttons(true);
@@ -64,6 +142,65 @@ impl AppWindow {
         });
         header.pack_end(&about_button);
 
+        // Search options menu
+        let strict_diacritics_check = CheckButton::with_label("Match accents exactly");
+        strict_diacritics_check
+            .set_tooltip_text(Some("When off, \"cafe\" also finds \"café\""));
+
+        let options_box = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(6)
+            .margin_top(6)
+            .margin_bottom(6)
+            .margin_start(6)
+            .margin_end(6)
+            .build();
+        options_box.append(&strict_diacritics_check);
+
+        // DICT protocol server
+        let dict_title = Label::new(Some("DICT server"));
+        dict_title.add_css_class("heading");
+        dict_title.set_halign(Align::Start);
+        dict_title.set_margin_top(6);
+
+        let dict_enabled_check = CheckButton::with_label("Look up words the dictionaries miss");
+        dict_enabled_check.set_tooltip_text(Some("Connects to the server below"));
+
+        let dict_host_entry = Entry::builder()
+            .placeholder_text("localhost")
+            .tooltip_text("Host name, optionally followed by :port")
+            .build();
+
+        let dict_database_dropdown = DropDown::from_strings(&["All databases"]);
+        dict_database_dropdown.set_tooltip_text(Some("Database to query"));
+        dict_database_dropdown.set_hexpand(true);
+
+        let dict_refresh_button = Button::from_icon_name("view-refresh-symbolic");
+        dict_refresh_button.set_tooltip_text(Some("Refresh Database List"));
+
+        let dict_database_row = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(6)
+            .build();
+        dict_database_row.append(&dict_database_dropdown);
+        dict_database_row.append(&dict_refresh_button);
+
+        options_box.append(&Separator::new(Orientation::Horizontal));
+        options_box.append(&dict_title);
+        options_box.append(&dict_enabled_check);
+        options_box.append(&dict_host_entry);
+        options_box.append(&dict_database_row);
+
+        let options_popover = Popover::new();
+        options_popover.set_child(Some(&options_box));
+
+        let options_button = MenuButton::builder()
+            .icon_name("emblem-system-symbolic")
+            .tooltip_text("Search Options")
+            .popover(&options_popover)
+            .build();
+        header.pack_end(&options_button);
+
         // Create definition display area
         let definition_view = TextView::builder()
             .editable(false)
@@ -109,12 +246,43 @@ impl AppWindow {
         placeholder_box.append(&placeholder_title);
         placeholder_box.append(&placeholder_subtitle);
 
+        let suggestions_title = Label::new(None);
+        suggestions_title.add_css_class("placeholder-title");
+        suggestions_title.set_halign(Align::Center);
+        suggestions_title.set_wrap(true);
+        suggestions_title.set_justify(gtk4::Justification::Center);
+
+        let suggestions_subtitle = Label::new(Some("Did you mean:"));
+        suggestions_subtitle.add_css_class("placeholder-subtitle");
+        suggestions_subtitle.set_halign(Align::Center);
+
+        let suggestions_flow = FlowBox::builder()
+            .selection_mode(SelectionMode::None)
+            .halign(Align::Center)
+            .max_children_per_line(4)
+            .column_spacing(6)
+            .row_spacing(6)
+            .build();
+
+        let suggestions_box = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(12)
+            .margin_start(12)
+            .margin_end(12)
+            .halign(Align::Center)
+            .valign(Align::Center)
+            .build();
+        suggestions_box.append(&suggestions_title);
+        suggestions_box.append(&suggestions_subtitle);
+        suggestions_box.append(&suggestions_flow);
+
         let content_stack = Stack::builder()
             .hexpand(true)
             .vexpand(true)
             .build();
         content_stack.add_named(&placeholder_box, Some("placeholder"));
         content_stack.add_named(&scrolled, Some("definition"));
+        content_stack.add_named(&suggestions_box, Some("suggestions"));
         content_stack.set_visible_child_name("placeholder");
 
         let definition_card = GtkBox::builder()
@@ -149,7 +317,7 @@ impl AppWindow {
             .margin_end(24)
             .build();
         content.append(&brand_box);
-        content.append(&search_entry);
+        content.append(&search_row);
         content.append(&definition_card);
 
         let clamp = Clamp::builder()
@@ -158,11 +326,27 @@ impl AppWindow {
             .build();
         clamp.set_child(Some(&content));
 
+        let load_banner = Banner::new("");
+        load_banner.set_button_label(Some("Details"));
+        let load_details = Rc::new(RefCell::new(String::new()));
+        let details_for_banner = Rc::clone(&load_details);
+        let window_for_banner = window.clone();
+        load_banner.connect_button_clicked(move |_| {
+            let dialog = MessageDialog::new(
+                Some(&window_for_banner),
+                Some("Dictionary Problems"),
+                Some(details_for_banner.borrow().as_str()),
+            );
+            dialog.add_response("close", "Close");
+            dialog.present();
+        });
+
         // Create main content container
         let main_box = GtkBox::builder()
             .orientation(Orientation::Vertical)
             .build();
         main_box.append(&header);
+        main_box.append(&load_banner);
         main_box.append(&clamp);
 
         window.set_content(Some(&main_box));
@@ -170,8 +354,37 @@ impl AppWindow {
         Self {
             window,
             search_entry,
+            search_mode_dropdown,
             definition_view,
+            strict_diacritics_check,
+            dict_enabled_check,
+            dict_host_entry,
+            dict_database_dropdown,
+            dict_refresh_button,
+            dict_databases: RefCell::new(vec![String::from("*")]),
             content_stack,
+            suggestions_title,
+            suggestions_flow,
+            load_banner,
+            load_details,
+        }
+    }
+
+    /// Say in a banner that dictionaries or entries failed to load, so an
+    /// empty result isn't mistaken for a missing word. The banner's button
+    /// lists the files and entries involved.
+    pub fn set_load_problems(&self, problems: &[LoadProblem]) {
+        match diagnostics::summarize(problems) {
+            Some(summary) => {
+                *self.load_details.borrow_mut() = problems
+                    .iter()
+                    .map(LoadProblem::describe)
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                self.load_banner.set_title(&summary);
+                self.load_banner.set_revealed(true);
+            }
+            None => self.load_banner.set_revealed(false),
         }
     }
 
@@ -187,6 +400,93 @@ impl AppWindow {
         }
     }
 
+    /// Like `set_definition`, but replacing each image placeholder in `text`
+    /// with the matching picture. Images that can't be decoded are left out.
+    pub fn set_rendered_definition(&self, text: &str, images: &[Option<Vec<u8>>]) {
+        let buffer = self.definition_view.buffer();
+        buffer.set_text("");
+        let mut end = buffer.end_iter();
+        let mut images = images.iter();
+        for (i, segment) in text.split(IMAGE_PLACEHOLDER).enumerate() {
+            if i > 0 {
+                let texture = images
+                    .next()
+                    .and_then(|data| image_texture(data.as_deref()?));
+                if let Some(texture) = texture {
+                    buffer.insert_paintable(&mut end, &texture);
+                }
+            }
+            buffer.insert(&mut end, segment);
+        }
+        if text.trim().is_empty() {
+            self.content_stack.set_visible_child_name("placeholder");
+        } else {
+            self.content_stack.set_visible_child_name("definition");
+        }
+    }
+
+    /// Show "did you mean" buttons for a query that had no match.
+    /// Clicking one searches for that word.
+    pub fn set_suggestions(&self, query: &str, suggestions: &[String]) {
+        while let Some(child) = self.suggestions_flow.first_child() {
+            self.suggestions_flow.remove(&child);
+        }
+
+        self.suggestions_title
+            .set_text(&format!("No entry found for '{}'", query.trim()));
+
+        for word in suggestions {
+            let button = Button::with_label(word);
+            button.add_css_class("pill");
+
+            let search_entry = self.search_entry.clone();
+            let word = word.clone();
+            button.connect_clicked(move |_| {
+                search_entry.set_text(&word);
+                search_entry.activate();
+            });
+
+            self.suggestions_flow.insert(&button, -1);
+        }
+
+        self.content_stack.set_visible_child_name("suggestions");
+    }
+
+    /// Fill the database list with `(name, description)` pairs and select
+    /// `selected`. The first row always searches every database (`*`).
+    pub fn set_dict_databases(&self, databases: &[(String, String)], selected: &str) {
+        let mut names = vec![String::from("*")];
+        let mut labels = vec![String::from("All databases")];
+        for (name, description) in databases {
+            names.push(name.clone());
+            labels.push(format!("{} ({})", description, name));
+        }
+        // Keep a saved choice selectable while the server is unreachable
+        if !names.iter().any(|name| name == selected) {
+            names.push(selected.to_string());
+            labels.push(selected.to_string());
+        }
+
+        let position = names.iter().position(|name| name == selected).unwrap_or(0);
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        *self.dict_databases.borrow_mut() = names;
+        self.dict_database_dropdown
+            .set_model(Some(&StringList::new(&labels)));
+        self.dict_database_dropdown.set_selected(position as u32);
+    }
+
+    /// Name of the database selected in the DICT options
+    pub fn selected_dict_database(&self) -> Option<String> {
+        self.dict_databases
+            .borrow()
+            .get(self.dict_database_dropdown.selected() as usize)
+            .cloned()
+    }
+
+    pub fn search_mode(&self) -> SearchMode {
+        mode_at(self.search_mode_dropdown.selected())
+    }
+
     pub fn get_search_text(&self) -> String {
         self.search_entry.text().to_string()
     }
@@ -207,6 +507,28 @@ impl AppWindow {
     }
 }
 
+/// Decode an embedded image, scaled down to fit the definition view
+fn image_texture(data: &[u8]) -> Option<gtk4::gdk::Texture> {
+    let loader = PixbufLoader::new();
+    if let Err(e) = loader.write(data).and_then(|_| loader.close()) {
+        eprintln!("Failed to decode image: {}", e);
+        return None;
+    }
+    let mut pixbuf = loader.pixbuf()?;
+    if pixbuf.width() > MAX_IMAGE_WIDTH {
+        let height = pixbuf.height() * MAX_IMAGE_WIDTH / pixbuf.width();
+        pixbuf = pixbuf.scale_simple(MAX_IMAGE_WIDTH, height.max(1), InterpType::Bilinear)?;
+    }
+    Some(gtk4::gdk::Texture::for_pixbuf(&pixbuf))
+}
+
+fn mode_at(position: u32) -> SearchMode {
+    SearchMode::ALL
+        .get(position as usize)
+        .copied()
+        .unwrap_or(SearchMode::Word)
+}
+
 fn show_about_dialog_ui(app: &adw::Application) {
     if let Some(win) = app.active_window() {
         let credits_text =
@@ -0,0 +1,624 @@
+use crate::api::{Definition, DictionaryEntry, License, Meaning, Relation, RelationKind};
+use crate::pattern::Pattern;
+use crate::source::{DictionarySource, FuzzyMatch, Headwords, SourceMetadata};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// File whose presence marks a directory as a WordNet database
+pub const MARKER_FILE: &str = "data.noun";
+
+/// Pointer source/target field of a relation between whole synsets
+const SEMANTIC_POINTER: &str = "0000";
+
+/// Words listed per relation, so a general noun's hundreds of hyponyms don't
+/// each cost a read of the data file
+const MAX_RELATED_WORDS: usize = 20;
+
+/// The four syntactic categories, each with its own `index.*` and `data.*` file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PartOfSpeech {
+    Noun,
+    Verb,
+    Adjective,
+    Adverb,
+}
+
+impl PartOfSpeech {
+    const ALL: [Self; 4] = [Self::Noun, Self::Verb, Self::Adjective, Self::Adverb];
+
+    /// Suffix of the `index.*`, `data.*` and `*.exc` files
+    fn file_suffix(self) -> &'static str {
+        match self {
+            Self::Noun => "noun",
+            Self::Verb => "verb",
+            Self::Adjective => "adj",
+            Self::Adverb => "adv",
+        }
+    }
+
+    /// Category of a synset type or pointer target; `s` is an adjective satellite
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "n" => Some(Self::Noun),
+            "v" => Some(Self::Verb),
+            "a" | "s" => Some(Self::Adjective),
+            "r" => Some(Self::Adverb),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Noun => "noun",
+            Self::Verb => "verb",
+            Self::Adjective => "adjective",
+            Self::Adverb => "adverb",
+        }
+    }
+}
+
+/// A synset location: its category and byte offset in that `data.*` file
+type SynsetId = (PartOfSpeech, u64);
+
+/// Princeton WordNet, read from the `index.*` and `data.*` files of a `dict`
+/// directory.
+///
+/// Each of a lemma's synsets becomes a [`Meaning`] carrying its gloss, examples,
+/// the other words in the synset as synonyms, and its relations to other
+/// synsets. Only the indexes are loaded; synsets are read from the data files
+/// on demand. Irregular forms from the `*.exc` files resolve to their lemmas.
+pub struct WordNetSource {
+    metadata: SourceMetadata,
+    /// Lemmas followed by irregular forms from the exception lists
+    headwords: Headwords,
+    /// For each headword, the lemma whose senses it shows
+    targets: Vec<usize>,
+    /// Synsets of each lemma, most frequent sense first within a category
+    senses: Vec<Vec<SynsetId>>,
+    /// Semantic concordance count summed over each lemma's senses
+    frequencies: Vec<u32>,
+    /// Open `data.*` files, indexed by [`PartOfSpeech`]
+    data: [Mutex<BufReader<File>>; 4],
+}
+
+impl WordNetSource {
+    /// Open the WordNet database in `dir`
+    pub fn open(dir: &Path) -> Result<Self> {
+        let mut words: Vec<String> = Vec::new();
+        let mut senses: Vec<Vec<SynsetId>> = Vec::new();
+        let mut frequencies: Vec<u32> = Vec::new();
+        let mut ids: HashMap<String, usize> = HashMap::new();
+
+        for pos in PartOfSpeech::ALL {
+            let path = dir.join(format!("index.{}", pos.file_suffix()));
+            let index = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            for line in index.lines().filter(|line| !is_license_line(line)) {
+                let (lemma, offsets, tagged) = parse_index_line(line)
+                    .with_context(|| format!("Invalid line in {}: {}", path.display(), line))?;
+                let id = *ids.entry(lemma.clone()).or_insert_with(|| {
+                    words.push(lemma);
+                    senses.push(Vec::new());
+                    frequencies.push(0);
+                    words.len() - 1
+                });
+                senses[id].extend(offsets.into_iter().map(|offset| (pos, offset)));
+                frequencies[id] += tagged;
+            }
+        }
+
+        let mut targets: Vec<usize> = (0..words.len()).collect();
+        for pos in PartOfSpeech::ALL {
+            // Exception lists are optional
+            let path = dir.join(format!("{}.exc", pos.file_suffix()));
+            let Ok(exceptions) = fs::read_to_string(&path) else {
+                continue;
+            };
+            for line in exceptions.lines() {
+                let mut fields = line.split_whitespace().map(display_word);
+                let Some(form) = fields.next() else {
+                    continue;
+                };
+                for lemma in fields {
+                    if let Some(&target) = ids.get(&lemma) {
+                        if !ids.contains_key(&form) {
+                            words.push(form.clone());
+                            targets.push(target);
+                        }
+                    }
+                }
+            }
+        }
+
+        let data = PartOfSpeech::ALL.map(|pos| {
+            let path = dir.join(format!("data.{}", pos.file_suffix()));
+            File::open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))
+                .map(|file| Mutex::new(BufReader::new(file)))
+        });
+        let [noun, verb, adjective, adverb] = data;
+
+        let mut metadata = SourceMetadata::new("wordnet", "WordNet");
+        metadata.description = Some(String::from(
+            "Princeton WordNet, a lexical database of English",
+        ));
+        metadata.language = Some(String::from("en"));
+        metadata.entry_count = Some(senses.len());
+
+        Ok(Self {
+            metadata,
+            headwords: Headwords::new(words),
+            targets,
+            senses,
+            frequencies,
+            data: [noun?, verb?, adjective?, adverb?],
+        })
+    }
+
+    /// The lemmas the given headword ids stand for, without repeats
+    fn lemmas(&self, ids: Vec<usize>) -> Vec<usize> {
+        let mut lemmas: Vec<usize> = Vec::new();
+        for id in ids {
+            let lemma = self.targets[id];
+            if !lemmas.contains(&lemma) {
+                lemmas.push(lemma);
+            }
+        }
+        lemmas
+    }
+
+    /// Entries for the given headword ids, skipping ones that fail to read
+    fn entries(&self, ids: Vec<usize>) -> Vec<DictionaryEntry> {
+        self.lemmas(ids)
+            .into_iter()
+            .filter_map(|lemma| match self.entry(lemma) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    eprintln!(
+                        "Skipping WordNet entry {}: {:#}",
+                        self.headwords.word(lemma),
+                        e
+                    );
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The lemma's entry, leaving out senses that fail to read
+    fn entry(&self, lemma: usize) -> Result<DictionaryEntry> {
+        let word = self.headwords.word(lemma);
+        let mut meanings = Vec::new();
+        let mut error = None;
+        for &synset in &self.senses[lemma] {
+            match self.meaning(word, synset) {
+                Ok(meaning) => meanings.push(meaning),
+                Err(e) => {
+                    eprintln!("Skipping WordNet sense of {}: {:#}", word, e);
+                    error = Some(e);
+                }
+            }
+        }
+        if meanings.is_empty() {
+            if let Some(e) = error {
+                return Err(e);
+            }
+        }
+
+        Ok(DictionaryEntry {
+            word: word.to_string(),
+            meanings,
+            license: Some(License {
+                name: String::from("WordNet 3.0 license"),
+                url: String::from("https://wordnet.princeton.edu/license-and-commercial-use"),
+            }),
+            frequency: Some(self.frequencies[lemma]).filter(|&count| count > 0),
+            ..DictionaryEntry::default()
+        })
+    }
+
+    /// `word`'s sense in the given synset
+    fn meaning(&self, word: &str, (pos, offset): SynsetId) -> Result<Meaning> {
+        let synset = self.synset(pos, offset)?;
+        // Lexical pointers name the word they start from by its position
+        let position = synset
+            .words
+            .iter()
+            .position(|member| member.eq_ignore_ascii_case(word))
+            .map(|index| index + 1);
+
+        // Antonyms are the relations without a kind
+        let mut wanted: Vec<(&Pointer, Option<RelationKind>)> = Vec::new();
+        for pointer in &synset.pointers {
+            let applies = pointer.source == 0 || Some(pointer.source) == position;
+            let kind = relation_kind(&pointer.symbol);
+            if !applies || (kind.is_none() && pointer.symbol != "!") {
+                continue;
+            }
+            if wanted.iter().filter(|(_, other)| *other == kind).count() < MAX_RELATED_WORDS {
+                wanted.push((pointer, kind));
+            }
+        }
+        let pointers: Vec<&Pointer> = wanted.iter().map(|&(pointer, _)| pointer).collect();
+        let targets = self.pointer_targets(&pointers);
+
+        let mut antonyms: Vec<String> = Vec::new();
+        let mut relations: Vec<Relation> = Vec::new();
+        for ((_, kind), target_word) in wanted.into_iter().zip(targets) {
+            let Some(target_word) = target_word else {
+                continue;
+            };
+
+            let words = match kind {
+                Some(kind) => match relations.iter_mut().find(|relation| relation.kind == kind) {
+                    Some(relation) => &mut relation.words,
+                    None => {
+                        relations.push(Relation {
+                            kind,
+                            words: Vec::new(),
+                        });
+                        &mut relations.last_mut().expect("just pushed").words
+                    }
+                },
+                None => &mut antonyms,
+            };
+            if !words.contains(&target_word) {
+                words.push(target_word);
+            }
+        }
+
+        let (definition, examples) = split_gloss(&synset.gloss);
+        Ok(Meaning {
+            part_of_speech: pos.name().to_string(),
+            definitions: vec![Definition {
+                definition,
+                example: (!examples.is_empty()).then(|| examples.join("; ")),
+                ..Definition::default()
+            }],
+            synonyms: synset
+                .words
+                .into_iter()
+                .filter(|member| !member.eq_ignore_ascii_case(word))
+                .collect(),
+            antonyms,
+            relations,
+        })
+    }
+
+    /// The words `pointers` lead to, locking each data file once and reading
+    /// it in offset order. Targets that fail to read are logged and left out.
+    fn pointer_targets(&self, pointers: &[&Pointer]) -> Vec<Option<String>> {
+        let mut words = vec![None; pointers.len()];
+        for pos in PartOfSpeech::ALL {
+            let mut order: Vec<usize> = (0..pointers.len())
+                .filter(|&i| pointers[i].pos == pos)
+                .collect();
+            if order.is_empty() {
+                continue;
+            }
+            order.sort_by_key(|&i| pointers[i].offset);
+
+            let Ok(mut data) = self.data[pos as usize].lock() else {
+                eprintln!("WordNet data file lock poisoned");
+                continue;
+            };
+            for i in order {
+                let pointer = pointers[i];
+                match read_synset(&mut data, pointer.offset) {
+                    Ok(target) => {
+                        words[i] = match pointer.target {
+                            0 => target.words.into_iter().next(),
+                            index => target.words.into_iter().nth(index - 1),
+                        }
+                    }
+                    Err(e) => eprintln!(
+                        "Skipping WordNet {} pointer to {} {}: {:#}",
+                        pointer.symbol,
+                        pos.name(),
+                        pointer.offset,
+                        e
+                    ),
+                }
+            }
+        }
+        words
+    }
+
+    fn synset(&self, pos: PartOfSpeech, offset: u64) -> Result<Synset> {
+        let mut data = self.data[pos as usize]
+            .lock()
+            .map_err(|_| anyhow!("WordNet data file lock poisoned"))?;
+        read_synset(&mut data, offset)
+    }
+}
+
+fn read_synset(data: &mut BufReader<File>, offset: u64) -> Result<Synset> {
+    data.seek(SeekFrom::Start(offset))?;
+    let mut line = String::new();
+    data.read_line(&mut line)?;
+    parse_data_line(&line, offset)
+}
+
+impl DictionarySource for WordNetSource {
+    fn metadata(&self) -> &SourceMetadata {
+        &self.metadata
+    }
+
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        self.entries(self.headwords.exact(folded, strict))
+    }
+
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        self.entries(self.headwords.prefix(folded, strict, limit))
+    }
+
+    fn prefix_headwords(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.lemmas(self.headwords.prefix(folded, strict, limit))
+            .into_iter()
+            .map(|lemma| self.headwords.word(lemma).to_string())
+            .collect()
+    }
+
+    fn fuzzy(&self, loose: &str, max_distance: usize) -> Vec<FuzzyMatch> {
+        self.headwords
+            .fuzzy(loose, max_distance)
+            .into_iter()
+            .map(|(ids, distance)| {
+                let lemma = self.targets[ids[0]];
+                FuzzyMatch {
+                    word: self.headwords.word(lemma).to_string(),
+                    distance,
+                    frequency: self.frequencies[lemma],
+                }
+            })
+            .collect()
+    }
+
+    fn pattern(&self, pattern: &Pattern, limit: usize) -> Vec<String> {
+        self.headwords.pattern(pattern, limit)
+    }
+
+    fn headwords(&self) -> Vec<&str> {
+        self.headwords.words().iter().map(String::as_str).collect()
+    }
+}
+
+/// One line of a `data.*` file
+struct Synset {
+    words: Vec<String>,
+    pointers: Vec<Pointer>,
+    gloss: String,
+}
+
+struct Pointer {
+    symbol: String,
+    pos: PartOfSpeech,
+    offset: u64,
+    /// 1-based position of the word the pointer starts from, or 0 for the synset
+    source: usize,
+    /// 1-based position of the word it points to, or 0 for the synset
+    target: usize,
+}
+
+/// The database files open with a license text, every line indented
+fn is_license_line(line: &str) -> bool {
+    line.starts_with("  ") || line.trim().is_empty()
+}
+
+/// `lemma pos synset_cnt p_cnt [ptr_symbol...] sense_cnt tagsense_cnt synset_offset...`
+fn parse_index_line(line: &str) -> Result<(String, Vec<u64>, u32)> {
+    let mut fields = line.split_whitespace();
+    let lemma = display_word(fields.next().context("Missing lemma")?);
+    let _pos = fields.next().context("Missing part of speech")?;
+    let synset_count: usize = fields.next().context("Missing synset count")?.parse()?;
+    let pointer_count: usize = fields.next().context("Missing pointer count")?.parse()?;
+    let mut fields = fields.skip(pointer_count);
+    let _sense_count = fields.next().context("Missing sense count")?;
+    let tagged: u32 = fields
+        .next()
+        .context("Missing tagged sense count")?
+        .parse()?;
+
+    let offsets = fields
+        .take(synset_count)
+        .map(|offset| offset.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    ensure!(
+        offsets.len() == synset_count,
+        "Expected {} synsets",
+        synset_count
+    );
+    Ok((lemma, offsets, tagged))
+}
+
+/// `offset lex_filenum ss_type w_cnt word lex_id... p_cnt [ptr...] [frames...] | gloss`
+fn parse_data_line(line: &str, offset: u64) -> Result<Synset> {
+    let (fields, gloss) = line.split_once(" | ").unwrap_or((line, ""));
+    let mut fields = fields.split_whitespace();
+
+    let found: u64 = fields.next().context("Empty synset")?.parse()?;
+    if found != offset {
+        bail!("Expected synset {} but found {}", offset, found);
+    }
+    let _lex_filenum = fields.next().context("Missing lexicographer file")?;
+    let _ss_type = fields.next().context("Missing synset type")?;
+
+    let word_count = usize::from_str_radix(fields.next().context("Missing word count")?, 16)?;
+    let mut words = Vec::with_capacity(word_count);
+    for _ in 0..word_count {
+        let word = fields.next().context("Missing word")?;
+        let _lex_id = fields.next().context("Missing lexical id")?;
+        words.push(display_word(strip_marker(word)));
+    }
+
+    let pointer_count: usize = fields.next().context("Missing pointer count")?.parse()?;
+    let mut pointers = Vec::with_capacity(pointer_count);
+    for _ in 0..pointer_count {
+        let symbol = fields.next().context("Missing pointer symbol")?;
+        let offset = fields.next().context("Missing pointer offset")?.parse()?;
+        let pos = fields.next().context("Missing pointer category")?;
+        let ends = fields.next().context("Missing pointer source/target")?;
+        // A malformed source/target loses only its own pointer
+        let Some((source, target)) = parse_pointer_ends(ends) else {
+            continue;
+        };
+        pointers.push(Pointer {
+            symbol: symbol.to_string(),
+            pos: PartOfSpeech::from_code(pos)
+                .with_context(|| format!("Unknown pointer category {}", pos))?,
+            offset,
+            source,
+            target,
+        });
+    }
+
+    Ok(Synset {
+        words,
+        pointers,
+        gloss: gloss.trim().to_string(),
+    })
+}
+
+/// Source and target word numbers of a pointer, two hex digits each
+fn parse_pointer_ends(ends: &str) -> Option<(usize, usize)> {
+    if ends == SEMANTIC_POINTER {
+        return Some((0, 0));
+    }
+    let source = usize::from_str_radix(ends.get(..2)?, 16).ok()?;
+    let target = usize::from_str_radix(ends.get(2..).filter(|rest| rest.len() == 2)?, 16).ok()?;
+    Some((source, target))
+}
+
+/// Database words use underscores for spaces
+fn display_word(word: &str) -> String {
+    word.replace('_', " ")
+}
+
+/// Drop an adjective's syntactic marker: `(a)`, `(p)` or `(ip)`
+fn strip_marker(word: &str) -> &str {
+    match word.find('(') {
+        Some(start) if word.ends_with(')') => &word[..start],
+        _ => word,
+    }
+}
+
+/// Split a gloss into its definition and the quoted examples that follow it,
+/// e.g. `a domesticated canine; "the dog barked all night"`
+fn split_gloss(gloss: &str) -> (String, Vec<String>) {
+    let mut definition: Vec<&str> = Vec::new();
+    let mut examples: Vec<String> = Vec::new();
+
+    let mut in_quote = false;
+    let mut start = 0;
+    for (index, ch) in gloss.char_indices().chain([(gloss.len(), ';')]) {
+        match ch {
+            '"' => in_quote = !in_quote,
+            ';' if !in_quote => {
+                let part = gloss[start..index].trim();
+                start = (index + 1).min(gloss.len());
+                if part.len() >= 2 && part.starts_with('"') {
+                    // Some examples carry an attribution after the closing quote
+                    let quoted = part.trim_start_matches('"');
+                    let quoted = quoted.rsplit_once('"').map_or(quoted, |(text, _)| text);
+                    examples.push(quoted.trim().to_string());
+                } else if !part.is_empty() {
+                    definition.push(part);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (definition.join("; "), examples)
+}
+
+/// Relations shown in the view; `!` (antonym) is kept separately
+fn relation_kind(symbol: &str) -> Option<RelationKind> {
+    match symbol {
+        "@" | "@i" => Some(RelationKind::Hypernym),
+        "~" | "~i" => Some(RelationKind::Hyponym),
+        "%m" | "%s" | "%p" => Some(RelationKind::Meronym),
+        "#m" | "#s" | "#p" => Some(RelationKind::Holonym),
+        "&" => Some(RelationKind::SimilarTo),
+        "+" => Some(RelationKind::Derivation),
+        "^" => Some(RelationKind::AlsoSee),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_data_lines_and_skips_bad_pointers() {
+        let line = "00001740 03 n 02 physical_entity 0 real(a) 1 003 \
+                    @ 00002137 n 0000 ! 00003000 n 0é1 ~ 00004000 n 0102 \
+                    | that which exists; \"an entity\"";
+        let synset = parse_data_line(line, 1740).unwrap();
+        assert_eq!(synset.words, ["physical entity", "real"]);
+        assert_eq!(synset.gloss, "that which exists; \"an entity\"");
+
+        let pointers: Vec<(&str, usize, usize)> = synset
+            .pointers
+            .iter()
+            .map(|pointer| (pointer.symbol.as_str(), pointer.source, pointer.target))
+            .collect();
+        assert_eq!(pointers, [("@", 0, 0), ("~", 1, 2)]);
+
+        assert!(parse_data_line(line, 0).is_err());
+        assert!(parse_data_line("00000000 03 n 02 entity 0", 0).is_err());
+    }
+
+    #[test]
+    fn looks_up_irregular_forms_through_exception_lists() {
+        let dir = std::env::temp_dir().join(format!("aynary-{}-wordnet", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for pos in PartOfSpeech::ALL {
+            fs::write(dir.join(format!("index.{}", pos.file_suffix())), "").unwrap();
+            fs::write(dir.join(format!("data.{}", pos.file_suffix())), "").unwrap();
+        }
+
+        let run = |walk: u64| {
+            format!(
+                "{:08} 38 v 01 run 0 001 ! {:08} v 0101 | move fast by using your feet\n",
+                0, walk
+            )
+        };
+        let walk = run(0).len() as u64;
+        let data = format!(
+            "{}{:08} 38 v 01 walk 0 000 | use your feet to advance\n",
+            run(walk),
+            walk
+        );
+        fs::write(dir.join("data.verb"), data).unwrap();
+        let index = format!(
+            "  1 license text\nrun v 1 1 ! 1 3 00000000\nwalk v 1 0 1 0 {:08}\n",
+            walk
+        );
+        fs::write(dir.join("index.verb"), index).unwrap();
+        fs::write(dir.join("verb.exc"), "ran run\nwalked walk\nfoo bar\n").unwrap();
+
+        let source = WordNetSource::open(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(source.metadata().entry_count, Some(2));
+        let entries = source.lookup("ran", false);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].word, "run");
+        assert_eq!(entries[0].frequency, Some(3));
+        let meaning = &entries[0].meanings[0];
+        assert_eq!(meaning.part_of_speech, "verb");
+        assert_eq!(
+            meaning.definitions[0].definition,
+            "move fast by using your feet"
+        );
+        assert_eq!(meaning.antonyms, ["walk"]);
+        // Exceptions for lemmas missing from the index are left out
+        assert!(source.lookup("foo", false).is_empty());
+    }
+}
@@ -0,0 +1,786 @@
+use crate::api::{Definition, DictionaryEntry, Meaning, Phonetic, Relation, RelationKind};
+use crate::article::{self, current_meaning, is_link_only};
+use crate::import;
+use crate::markup;
+use crate::source::{language_tag, MemorySource, SourceMetadata, LANGUAGE_CODES};
+use crate::xml::{Element, ElementReader, Node};
+use anyhow::Result;
+use quick_xml::escape::escape;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+/// `<kref type="...">` values for the relations XDXF can express. It has no
+/// "similar to" link, so those are exported as plain related words.
+const KREF_TYPES: &[(&str, RelationKind)] = &[
+    ("hpr", RelationKind::Hypernym),
+    ("hpn", RelationKind::Hyponym),
+    ("mer", RelationKind::Meronym),
+    ("hol", RelationKind::Holonym),
+    ("der", RelationKind::Derivation),
+    ("rel", RelationKind::AlsoSee),
+];
+
+/// Children of a logical `<def>` that aren't part of its text
+const NON_TEXT: &[&str] = &[
+    "abbr", "categ", "def", "etm", "ex", "gr", "rref", "sr", "tr",
+];
+
+/// ISO 639-2 code for an undetermined language, which XDXF files whose
+/// language isn't known are marked with
+const UNDETERMINED_LANGUAGE: &str = "und";
+
+/// Load an XDXF dictionary, streaming one `<ar>` article at a time.
+///
+/// Both layouts are read. In the logical format an article is a tree of
+/// `<def>`s with `<gr>` grammar, `<deftext>`, `<ex>` examples and `<sr>`
+/// cross-references. In the older visual format it is formatted text with
+/// `<abr>`, `<ex>` and `<kref>` marked up inline, read line by line. Each
+/// `<k>` key becomes an entry of its own.
+pub fn open(path: &Path) -> Result<MemorySource> {
+    let mut reader = ElementReader::with_names(
+        import::open_input(path)?,
+        &["ar", "meta_info", "full_name", "description"],
+    );
+
+    let mut title = None;
+    let mut description = None;
+    let mut abbreviations = HashMap::new();
+    let mut entries = Vec::new();
+    while let Some(element) = reader.next_element()? {
+        match element.name.as_str() {
+            "ar" => entries.extend(convert(&element, &abbreviations)),
+            "meta_info" => {
+                title = element
+                    .child("full_title")
+                    .or_else(|| element.child("title"))
+                    .map(Element::text);
+                description = element.child("description").map(Element::text);
+                abbreviations = abbreviation_table(&element);
+            }
+            // The visual format's header
+            "full_name" => title = Some(element.text()),
+            _ => description = Some(element.text()),
+        }
+    }
+
+    let root = reader.root();
+    let language = root
+        .and_then(|root| root.attribute("lang_from"))
+        .and_then(from_xdxf_language);
+    let target_language = root
+        .and_then(|root| root.attribute("lang_to"))
+        .and_then(from_xdxf_language);
+    for entry in &mut entries {
+        entry.language = language.clone();
+        entry.target_language = target_language.clone();
+    }
+
+    let id = source_id(path);
+    let name = title
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| id.clone());
+    let mut metadata = SourceMetadata::new(id, name);
+    metadata.description = description.filter(|description| !description.is_empty());
+    metadata.language = language;
+
+    Ok(MemorySource::new(metadata, entries))
+}
+
+/// The file name without extension; dictionaries distributed as a directory
+/// holding `dict.xdxf` are named after the directory
+fn source_id(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match path.parent().and_then(Path::file_name) {
+        Some(dir) if stem == "dict" => dir.to_string_lossy().into_owned(),
+        _ => stem,
+    }
+}
+
+fn from_xdxf_language(code: &str) -> Option<String> {
+    if code.eq_ignore_ascii_case(UNDETERMINED_LANGUAGE) {
+        return None;
+    }
+    Some(language_tag(code))
+}
+
+fn to_xdxf_language(code: &str) -> String {
+    LANGUAGE_CODES
+        .iter()
+        .find(|(two, _)| *two == code)
+        .map(|(_, three)| three)
+        .unwrap_or(&code)
+        .to_uppercase()
+}
+
+/// `<abbr_def>` entries of the logical format's header
+fn abbreviation_table(meta_info: &Element) -> HashMap<String, String> {
+    let mut table = HashMap::new();
+    for definition in meta_info.find_all("abbr_def") {
+        let Some(value) = definition.child("abbr_v").map(Element::text) else {
+            continue;
+        };
+        for key in definition.children_named("abbr_k") {
+            table.insert(key.text(), value.clone());
+        }
+    }
+    table
+}
+
+fn convert(article: &Element, abbreviations: &HashMap<String, String>) -> Vec<DictionaryEntry> {
+    let forms = article::unique_forms(article.children_named("k").flat_map(key_forms));
+    if forms.is_empty() {
+        return Vec::new();
+    }
+
+    let mut meanings = if article.child("def").is_some() {
+        logical_meanings(article, abbreviations)
+    } else {
+        visual_meanings(article, abbreviations)
+    };
+    meanings.retain(|meaning| !meaning.definitions.is_empty() || !meaning.relations.is_empty());
+    if meanings.is_empty() {
+        return Vec::new();
+    }
+
+    let phonetic = article
+        .find("tr")
+        .map(Element::text)
+        .filter(|text| !text.is_empty());
+    let etymology = article
+        .find("etm")
+        .map(Element::text)
+        .filter(|text| !text.is_empty());
+    let phonetics: Vec<Phonetic> = phonetic
+        .iter()
+        .map(|text| Phonetic {
+            text: Some(text.clone()),
+            ..Phonetic::default()
+        })
+        .collect();
+
+    article::entry_per_form(
+        &forms,
+        DictionaryEntry {
+            phonetic,
+            phonetics,
+            meanings,
+            etymology,
+            ..DictionaryEntry::default()
+        },
+    )
+}
+
+/// Lookup forms of a `<k>`: `<opt>` parts are optional, giving one form with
+/// them and one without, and `<nu>` parts aren't part of the key
+fn key_forms(key: &Element) -> Vec<String> {
+    let mut full = String::new();
+    let mut short = String::new();
+    for node in &key.children {
+        match node {
+            Node::Text(text) => {
+                full.push_str(text);
+                short.push_str(text);
+            }
+            Node::Element(element) if element.name == "opt" => full.push_str(&raw_text(element)),
+            Node::Element(element) if element.name == "nu" => {}
+            Node::Element(element) => {
+                full.push_str(&raw_text(element));
+                short.push_str(&raw_text(element));
+            }
+        }
+    }
+
+    article::unique_forms(
+        [full, short].map(|form| form.split_whitespace().collect::<Vec<_>>().join(" ")),
+    )
+}
+
+/// Text inside `element` with its spacing kept, unlike [`Element::text`]
+fn raw_text(element: &Element) -> String {
+    element
+        .children
+        .iter()
+        .map(|node| match node {
+            Node::Text(text) => text.clone(),
+            Node::Element(element) => raw_text(element),
+        })
+        .collect()
+}
+
+fn logical_meanings(article: &Element, abbreviations: &HashMap<String, String>) -> Vec<Meaning> {
+    let mut meanings = Vec::new();
+    for def in article.children_named("def") {
+        collect_definitions(def, None, abbreviations, &mut meanings);
+    }
+    meanings
+}
+
+/// Add the leaf definitions under `def` to a meaning. Each top-level `<def>`
+/// and each `<def>` with its own `<gr>` starts a meaning; nested ones inherit
+/// the part of speech of the `<def>` around them.
+fn collect_definitions(
+    def: &Element,
+    inherited: Option<&str>,
+    abbreviations: &HashMap<String, String>,
+    meanings: &mut Vec<Meaning>,
+) {
+    let grammar = def
+        .child("gr")
+        .map(|grammar| grammar_label(grammar, abbreviations))
+        .filter(|label| !label.is_empty());
+    let starts_meaning = grammar.is_some() || inherited.is_none();
+    let part_of_speech = match grammar {
+        Some(part_of_speech) => part_of_speech,
+        None => inherited.unwrap_or_default().to_string(),
+    };
+    if starts_meaning {
+        meanings.push(Meaning {
+            part_of_speech: part_of_speech.clone(),
+            ..Meaning::default()
+        });
+    }
+    let references = cross_references(def);
+
+    let text = definition_text(def);
+    let example = examples(def);
+    // Links on a group of definitions, or on a meaning without any, belong to
+    // the whole meaning
+    let group = def.child("def").is_some();
+    if group || (starts_meaning && text.is_empty() && example.is_none()) {
+        for nested in def.children_named("def") {
+            collect_definitions(nested, Some(&part_of_speech), abbreviations, meanings);
+        }
+        let meaning = meaning_for(meanings, &part_of_speech);
+        for (kind, word) in references {
+            match kind.as_str() {
+                "syn" => meaning.synonyms.push(word),
+                "ant" => meaning.antonyms.push(word),
+                _ => add_relation(meaning, relation_kind(&kind), word),
+            }
+        }
+        return;
+    }
+
+    let meaning = meaning_for(meanings, &part_of_speech);
+    let mut definition = Definition {
+        definition: text,
+        example,
+        tags: def
+            .children_named("abbr")
+            .map(|abbr| expand(abbreviations, abbr.text()))
+            .chain(def.children_named("categ").map(Element::text))
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        ..Definition::default()
+    };
+    for (kind, word) in references {
+        match kind.as_str() {
+            "syn" => definition.synonyms.push(word),
+            "ant" => definition.antonyms.push(word),
+            _ => add_relation(meaning, relation_kind(&kind), word),
+        }
+    }
+    if definition.definition.is_empty() {
+        match definition.example.take() {
+            // A definition given only by example
+            Some(example) => definition.definition = example,
+            None if definition.synonyms.is_empty() && definition.antonyms.is_empty() => return,
+            None => {}
+        }
+    }
+    meaning.definitions.push(definition);
+}
+
+/// A definition's `<dtrn>` translations, followed by the rest of its text in
+/// parentheses
+fn definition_text(def: &Element) -> String {
+    let translations: Vec<String> = def
+        .children_named("dtrn")
+        .map(Element::text)
+        .filter(|translation| !translation.is_empty())
+        .collect();
+    let mut skipped = NON_TEXT.to_vec();
+    skipped.push("dtrn");
+    let gloss = def.text_excluding(&skipped);
+    match (translations.is_empty(), gloss.is_empty()) {
+        (true, _) => gloss,
+        (false, true) => translations.join(", "),
+        (false, false) => format!("{} ({})", translations.join(", "), gloss),
+    }
+}
+
+/// Part of speech from a `<gr>`, with its abbreviations spelled out
+fn grammar_label(grammar: &Element, abbreviations: &HashMap<String, String>) -> String {
+    let labels: Vec<String> = grammar
+        .children_named("abbr")
+        .map(|abbr| expand(abbreviations, abbr.text()))
+        .collect();
+    if labels.is_empty() {
+        grammar.text()
+    } else {
+        labels.join(", ")
+    }
+}
+
+fn expand(abbreviations: &HashMap<String, String>, abbreviation: String) -> String {
+    abbreviations
+        .get(&abbreviation)
+        .cloned()
+        .unwrap_or(abbreviation)
+}
+
+/// `(type, word)` for the `<kref>`s of a definition: those grouped in `<sr>`
+/// and those inline in its text, which count as related words
+fn cross_references(def: &Element) -> Vec<(String, String)> {
+    let grouped = def
+        .children_named("sr")
+        .flat_map(|group| group.find_all("kref"));
+    let inline = def.children_named("kref").chain(
+        def.children_named("deftext")
+            .flat_map(|text| text.find_all("kref")),
+    );
+    grouped
+        .chain(inline)
+        .map(|kref| {
+            let kind = kref.attribute("type").unwrap_or("rel").to_string();
+            (kind, kref.text())
+        })
+        .filter(|(_, word)| !word.is_empty())
+        .collect()
+}
+
+fn relation_kind(kref_type: &str) -> RelationKind {
+    KREF_TYPES
+        .iter()
+        .find(|(name, _)| *name == kref_type)
+        .map_or(RelationKind::AlsoSee, |(_, kind)| *kind)
+}
+
+fn kref_type(kind: RelationKind) -> &'static str {
+    KREF_TYPES
+        .iter()
+        .find(|(_, known)| *known == kind)
+        .map_or("rel", |(name, _)| name)
+}
+
+/// Examples of a definition, each with its translations after a dash, joined with "; "
+fn examples(def: &Element) -> Option<String> {
+    let examples: Vec<String> = def
+        .children_named("ex")
+        .map(|example| {
+            let original = match example.child("ex_orig") {
+                Some(original) => original.text(),
+                None => example.text_excluding(&["ex_tran"]),
+            };
+            let translations: Vec<String> = example
+                .children_named("ex_tran")
+                .map(Element::text)
+                .collect();
+            if translations.is_empty() {
+                original
+            } else {
+                format!("{} — {}", original, translations.join(", "))
+            }
+        })
+        .filter(|example| !example.is_empty())
+        .collect();
+    (!examples.is_empty()).then(|| examples.join("; "))
+}
+
+/// The latest meaning with the given part of speech, added if there is none yet
+fn meaning_for<'a>(meanings: &'a mut Vec<Meaning>, part_of_speech: &str) -> &'a mut Meaning {
+    let index = match meanings
+        .iter()
+        .rposition(|meaning| meaning.part_of_speech == part_of_speech)
+    {
+        Some(index) => index,
+        None => {
+            meanings.push(Meaning {
+                part_of_speech: part_of_speech.to_string(),
+                ..Meaning::default()
+            });
+            meanings.len() - 1
+        }
+    };
+    &mut meanings[index]
+}
+
+fn add_relation(meaning: &mut Meaning, kind: RelationKind, word: String) {
+    match meaning
+        .relations
+        .iter_mut()
+        .find(|relation| relation.kind == kind)
+    {
+        Some(relation) => relation.words.push(word),
+        None => meaning.relations.push(Relation {
+            kind,
+            words: vec![word],
+        }),
+    }
+}
+
+/// One line of a visual-format article
+#[derive(Debug, Default)]
+struct VisualLine {
+    text: String,
+    /// `<abr>` labels
+    labels: Vec<String>,
+    examples: Vec<String>,
+    /// `<kref>` targets, which also stay in the text
+    refs: Vec<String>,
+}
+
+/// Read a visual-format article line by line: a line holding only an `<abr>`
+/// label starts a meaning with that part of speech, other lines are
+/// definitions with their examples, or "see also" links when they consist of
+/// `<kref>`s
+fn visual_meanings(article: &Element, abbreviations: &HashMap<String, String>) -> Vec<Meaning> {
+    let mut lines = vec![VisualLine::default()];
+    split_lines(article, &mut lines);
+
+    let mut meanings: Vec<Meaning> = Vec::new();
+    for line in lines {
+        let collapsed = line.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = markup::strip_sense_number(&collapsed);
+        let labels: Vec<String> = line
+            .labels
+            .into_iter()
+            .map(|label| expand(abbreviations, label))
+            .collect();
+
+        if text.is_empty() {
+            if !labels.is_empty() {
+                meanings.push(Meaning {
+                    part_of_speech: labels.join(", "),
+                    ..Meaning::default()
+                });
+            }
+        } else if !line.refs.is_empty() && is_link_only(text, &line.refs) {
+            let meaning = current_meaning(&mut meanings);
+            for word in line.refs {
+                add_relation(meaning, RelationKind::AlsoSee, word);
+            }
+        } else {
+            current_meaning(&mut meanings).definitions.push(Definition {
+                definition: text.to_string(),
+                tags: labels,
+                ..Definition::default()
+            });
+        }
+
+        if !line.examples.is_empty() {
+            article::add_example(&mut meanings, line.examples.join("; "));
+        }
+    }
+    meanings
+}
+
+fn split_lines(element: &Element, lines: &mut Vec<VisualLine>) {
+    for node in &element.children {
+        let line = lines.last_mut().expect("there is always a current line");
+        match node {
+            Node::Text(text) => {
+                let mut parts = text.split('\n');
+                line.text.push_str(parts.next().unwrap_or_default());
+                for part in parts {
+                    lines.push(VisualLine {
+                        text: part.to_string(),
+                        ..VisualLine::default()
+                    });
+                }
+            }
+            Node::Element(child) => match child.name.as_str() {
+                "k" | "tr" | "rref" => {}
+                "abr" | "abbr" => line.labels.push(child.text()),
+                "ex" => line.examples.push(child.text()),
+                "kref" => {
+                    let target = child.text();
+                    line.text.push_str(&target);
+                    line.refs.push(target);
+                }
+                "blockquote" => {
+                    lines.push(VisualLine::default());
+                    split_lines(child, lines);
+                    lines.push(VisualLine::default());
+                }
+                _ => split_lines(child, lines),
+            },
+        }
+    }
+}
+
+/// Header of an exported dictionary
+#[derive(Debug, Clone, Default)]
+pub struct ExportInfo {
+    pub title: String,
+    pub description: Option<String>,
+    /// Language codes as Aynary stores them, e.g. "en" or "fra"
+    pub language: Option<String>,
+    pub target_language: Option<String>,
+}
+
+/// Write `entries` as a logical-format XDXF document.
+///
+/// Each entry becomes an `<ar>` keyed by its word, with its transcription and
+/// etymology, a `<def>` per meaning holding its part of speech and a nested
+/// `<def>` per definition. Usage labels become `<abbr>`s, examples `<ex>`s,
+/// and synonyms, antonyms and related words `<kref>`s in `<sr>` groups.
+///
+/// XDXF can't hold everything an entry can, so reading the file back loses:
+/// inflected forms, audio, licenses and transcriptions after the first;
+/// "similar to" links, which come back as "see also"; and dictionary metadata
+/// other than the title, description and languages. A dictionary without a
+/// known language is marked `und`.
+pub fn export<W: Write>(entries: &[DictionaryEntry], info: &ExportInfo, mut out: W) -> Result<()> {
+    let lang_from = info
+        .language
+        .as_deref()
+        .map_or_else(|| UNDETERMINED_LANGUAGE.to_uppercase(), to_xdxf_language);
+    // A monolingual dictionary explains words in their own language
+    let lang_to = info
+        .target_language
+        .as_deref()
+        .map_or_else(|| lang_from.clone(), to_xdxf_language);
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<xdxf lang_from="{}" lang_to="{}" format="logical" revision="034">"#,
+        escape(&lang_from),
+        escape(&lang_to),
+    )?;
+    writeln!(out, "  <meta_info>")?;
+    writeln!(out, "    <title>{}</title>", escape(&info.title))?;
+    writeln!(out, "    <full_title>{}</full_title>", escape(&info.title))?;
+    if let Some(description) = &info.description {
+        writeln!(
+            out,
+            "    <description>{}</description>",
+            escape(description)
+        )?;
+    }
+    writeln!(out, "  </meta_info>")?;
+    writeln!(out, "  <lexicon>")?;
+    for entry in entries {
+        write_article(&mut out, entry)?;
+    }
+    writeln!(out, "  </lexicon>")?;
+    writeln!(out, "</xdxf>")?;
+    out.flush()?;
+    Ok(())
+}
+
+fn write_article<W: Write>(out: &mut W, entry: &DictionaryEntry) -> Result<()> {
+    writeln!(out, "    <ar>")?;
+    writeln!(out, "      <k>{}</k>", escape(&entry.word))?;
+    if let Some(phonetic) = &entry.phonetic {
+        writeln!(out, "      <tr>{}</tr>", escape(phonetic))?;
+    }
+    if let Some(etymology) = &entry.etymology {
+        writeln!(out, "      <etm>{}</etm>", escape(etymology))?;
+    }
+    for meaning in &entry.meanings {
+        writeln!(out, "      <def>")?;
+        if !meaning.part_of_speech.is_empty() {
+            writeln!(
+                out,
+                "        <gr><abbr>{}</abbr></gr>",
+                escape(&meaning.part_of_speech)
+            )?;
+        }
+        for definition in &meaning.definitions {
+            writeln!(out, "        <def>")?;
+            for tag in &definition.tags {
+                writeln!(out, "          <abbr>{}</abbr>", escape(tag))?;
+            }
+            writeln!(
+                out,
+                "          <deftext>{}</deftext>",
+                escape(&definition.definition)
+            )?;
+            if let Some(example) = &definition.example {
+                writeln!(
+                    out,
+                    r#"          <ex type="exm"><ex_orig>{}</ex_orig></ex>"#,
+                    escape(example)
+                )?;
+            }
+            let links = kref_list(&definition.synonyms, &definition.antonyms, &[]);
+            if !links.is_empty() {
+                writeln!(out, "          <sr>{}</sr>", links)?;
+            }
+            writeln!(out, "        </def>")?;
+        }
+        let links = kref_list(&meaning.synonyms, &meaning.antonyms, &meaning.relations);
+        if !links.is_empty() {
+            writeln!(out, "        <sr>{}</sr>", links)?;
+        }
+        writeln!(out, "      </def>")?;
+    }
+    writeln!(out, "    </ar>")?;
+    Ok(())
+}
+
+fn kref_list(synonyms: &[String], antonyms: &[String], relations: &[Relation]) -> String {
+    let typed = synonyms
+        .iter()
+        .map(|word| ("syn", word))
+        .chain(antonyms.iter().map(|word| ("ant", word)))
+        .chain(relations.iter().flat_map(|relation| {
+            let kind = kref_type(relation.kind);
+            relation.words.iter().map(move |word| (kind, word))
+        }));
+    typed
+        .map(|(kind, word)| format!(r#"<kref type="{}">{}</kref>"#, kind, escape(word)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::DictionarySource;
+    use std::fs;
+
+    fn definition(text: &str) -> Definition {
+        Definition {
+            definition: text.to_string(),
+            ..Definition::default()
+        }
+    }
+
+    /// Write `entries` out and read them back
+    fn round_trip(entries: &[DictionaryEntry], info: &ExportInfo) -> MemorySource {
+        let path = std::env::temp_dir().join(format!(
+            "aynary-xdxf-{}-{}.xdxf",
+            std::process::id(),
+            entries[0].word
+        ));
+        let mut out = Vec::new();
+        export(entries, info, &mut out).unwrap();
+        fs::write(&path, out).unwrap();
+        let source = open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        source
+    }
+
+    #[test]
+    fn export_round_trips_meanings() {
+        let entry = DictionaryEntry {
+            word: String::from("run"),
+            phonetic: Some(String::from("/rʌn/")),
+            phonetics: vec![Phonetic {
+                text: Some(String::from("/rʌn/")),
+                ..Phonetic::default()
+            }],
+            etymology: Some(String::from("Old English rinnan")),
+            meanings: vec![
+                // Two meanings without a part of speech stay apart
+                Meaning {
+                    definitions: vec![Definition {
+                        tags: vec![String::from("informal")],
+                        example: Some(String::from("a run on the bank")),
+                        synonyms: vec![String::from("rush")],
+                        ..definition("a sudden demand")
+                    }],
+                    ..Meaning::default()
+                },
+                Meaning {
+                    definitions: vec![definition("a score in cricket")],
+                    ..Meaning::default()
+                },
+                // Links without definitions stay on the meaning
+                Meaning {
+                    part_of_speech: String::from("verb"),
+                    synonyms: vec![String::from("sprint")],
+                    antonyms: vec![String::from("walk")],
+                    relations: vec![Relation {
+                        kind: RelationKind::Hypernym,
+                        words: vec![String::from("move")],
+                    }],
+                    ..Meaning::default()
+                },
+            ],
+            ..DictionaryEntry::default()
+        };
+
+        let info = ExportInfo {
+            title: String::from("Test"),
+            ..ExportInfo::default()
+        };
+        let source = round_trip(std::slice::from_ref(&entry), &info);
+        let back = source.lookup("run", true);
+        assert_eq!(back.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&back[0]).unwrap(),
+            serde_json::to_value(&entry).unwrap()
+        );
+        assert_eq!(source.metadata().language, None);
+    }
+
+    #[test]
+    fn export_keeps_languages() {
+        let entry = DictionaryEntry {
+            word: String::from("chat"),
+            meanings: vec![Meaning {
+                definitions: vec![definition("cat")],
+                ..Meaning::default()
+            }],
+            ..DictionaryEntry::default()
+        };
+        let info = ExportInfo {
+            title: String::from("Test"),
+            language: Some(String::from("fr")),
+            target_language: Some(String::from("en")),
+            ..ExportInfo::default()
+        };
+        let back = round_trip(&[entry], &info).lookup("chat", true);
+        assert_eq!(back[0].language.as_deref(), Some("fr"));
+        assert_eq!(back[0].target_language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn keys_give_one_entry_per_form() {
+        let mut reader = ElementReader::new(
+            "<ar><k>colo<opt>u</opt>r</k><k>hue</k><def><deftext>a tint</deftext></def></ar>"
+                .as_bytes(),
+            "ar",
+        );
+        let article = reader.next_element().unwrap().unwrap();
+        let entries = convert(&article, &HashMap::new());
+        let words: Vec<&str> = entries.iter().map(|entry| entry.word.as_str()).collect();
+        assert_eq!(words, ["colour", "color", "hue"]);
+        assert_eq!(entries[2].forms, ["colour", "color"]);
+    }
+
+    #[test]
+    fn articles_without_keys_or_text_are_skipped() {
+        let mut reader = ElementReader::new(
+            "<x><ar><def><deftext>orphan</deftext></def></ar><ar><k>empty</k></ar></x>".as_bytes(),
+            "ar",
+        );
+        while let Some(article) = reader.next_element().unwrap() {
+            assert!(convert(&article, &HashMap::new()).is_empty());
+        }
+    }
+
+    #[test]
+    fn visual_lines_become_meanings_and_links() {
+        let mut reader = ElementReader::new(
+            "<ar><k>dog</k>\n<abr>n</abr>\n1) a domestic animal <ex>the dog barked</ex>\nsee <kref>hound</kref></ar>"
+                .as_bytes(),
+            "ar",
+        );
+        let article = reader.next_element().unwrap().unwrap();
+        let meaning = &convert(&article, &HashMap::new())[0].meanings[0];
+        assert_eq!(meaning.part_of_speech, "n");
+        assert_eq!(meaning.definitions[0].definition, "a domestic animal");
+        assert_eq!(
+            meaning.definitions[0].example.as_deref(),
+            Some("the dog barked")
+        );
+        assert_eq!(meaning.relations[0].words, ["hound"]);
+    }
+}
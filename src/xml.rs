@@ -0,0 +1,362 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fmt;
+use std::io::BufRead;
+
+/// An XML element and everything inside it
+#[derive(Debug, Clone, Default)]
+pub struct Element {
+    /// Name without its namespace prefix
+    pub name: String,
+    /// Attributes by qualified name, such as `xml:lang`
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Child elements, skipping text
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |element| element.name == name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    /// The first element named `name` at any depth, searching depth first
+    pub fn find(&self, name: &str) -> Option<&Element> {
+        self.elements().find_map(|element| {
+            (element.name == name)
+                .then_some(element)
+                .or_else(|| element.find(name))
+        })
+    }
+
+    /// Every element named `name` at any depth, in document order
+    pub fn find_all<'a>(&'a self, name: &str) -> Vec<&'a Element> {
+        let mut found = Vec::new();
+        self.collect_named(name, &mut found);
+        found
+    }
+
+    fn collect_named<'a>(&'a self, name: &str, found: &mut Vec<&'a Element>) {
+        for element in self.elements() {
+            if element.name == name {
+                found.push(element);
+            }
+            element.collect_named(name, found);
+        }
+    }
+
+    /// All text inside the element, with runs of whitespace collapsed
+    pub fn text(&self) -> String {
+        let mut raw = String::new();
+        self.collect_text(&mut raw);
+        raw.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Like [`Element::text`], leaving out the elements named in `skipped`
+    pub fn text_excluding(&self, skipped: &[&str]) -> String {
+        let mut raw = String::new();
+        self.collect_text_excluding(skipped, &mut raw);
+        raw.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn collect_text(&self, out: &mut String) {
+        self.collect_text_excluding(&[], out);
+    }
+
+    fn collect_text_excluding(&self, skipped: &[&str], out: &mut String) {
+        for node in &self.children {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Element(element) if !skipped.contains(&element.name.as_str()) => {
+                    element.collect_text_excluding(skipped, out)
+                }
+                Node::Element(_) => {}
+            }
+        }
+    }
+}
+
+/// A streamed element that was read to its end tag but couldn't be decoded,
+/// for example because it uses an entity the document doesn't declare.
+/// Reading can carry on with the next element.
+#[derive(Debug)]
+pub struct InvalidElement {
+    pub name: String,
+    /// Byte offset of the element's end
+    pub position: u64,
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid <{}> before byte {}: {}",
+            self.name, self.position, self.reason
+        )
+    }
+}
+
+impl std::error::Error for InvalidElement {}
+
+/// Streams the elements with a given name out of a document, so record-based
+/// formats can be converted one record at a time
+pub struct ElementReader<R: BufRead> {
+    reader: Reader<R>,
+    names: Vec<String>,
+    root: Option<Element>,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> ElementReader<R> {
+    pub fn new(input: R, name: &str) -> Self {
+        Self::with_names(input, &[name])
+    }
+
+    /// Stream the elements with any of the given names, such as a format's
+    /// header as well as its records
+    pub fn with_names(input: R, names: &[&str]) -> Self {
+        Self {
+            reader: Reader::from_reader(input),
+            names: names.iter().map(|name| name.to_string()).collect(),
+            root: None,
+            buf: Vec::new(),
+        }
+    }
+
+    /// The document element, without its children, once reading has reached it
+    pub fn root(&self) -> Option<&Element> {
+        self.root.as_ref()
+    }
+
+    /// Byte offset reached in the input, for error messages
+    pub fn position(&self) -> u64 {
+        self.reader.buffer_position()
+    }
+
+    /// The next matching element, or `None` at the end of the document.
+    /// Matching elements nested inside one another are returned as the outer one.
+    ///
+    /// An element with undecodable text or attributes is an [`InvalidElement`]
+    /// error, after which the next call goes on with the following element.
+    pub fn next_element(&mut self) -> Result<Option<Element>> {
+        loop {
+            self.buf.clear();
+            let event = self
+                .reader
+                .read_event_into(&mut self.buf)
+                .with_context(|| format!("Invalid XML at byte {}", self.reader.error_position()))?;
+            match event {
+                Event::Start(start) if self.names.contains(&local_name(&start)) => {
+                    let (element, invalid) = match start_element(&start) {
+                        Ok(element) => (element, None),
+                        Err(e) => (placeholder(&start), Some(e)),
+                    };
+                    return self.read_children(element, invalid).map(Some);
+                }
+                Event::Empty(start) if self.names.contains(&local_name(&start)) => {
+                    let name = local_name(&start);
+                    return match start_element(&start) {
+                        Ok(element) => Ok(Some(element)),
+                        Err(e) => Err(self.invalid(&name, e)),
+                    };
+                }
+                Event::Start(start) if self.root.is_none() => {
+                    self.root = Some(start_element(&start)?);
+                }
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    /// Fill in `element`'s content, up to its end tag. Decoding errors are
+    /// kept in `invalid` until then, so the reader stays at an element boundary.
+    fn read_children(
+        &mut self,
+        mut element: Element,
+        mut invalid: Option<anyhow::Error>,
+    ) -> Result<Element> {
+        // Elements opened but not yet closed, innermost last
+        let mut open: Vec<Element> = Vec::new();
+        loop {
+            self.buf.clear();
+            let event = self
+                .reader
+                .read_event_into(&mut self.buf)
+                .with_context(|| format!("Invalid XML at byte {}", self.reader.error_position()))?;
+            let parent = open.last_mut().unwrap_or(&mut element);
+            match event {
+                Event::Start(start) => open.push(
+                    start_element(&start).unwrap_or_else(|e| {
+                        invalid.get_or_insert(e);
+                        placeholder(&start)
+                    }),
+                ),
+                Event::Empty(start) => match start_element(&start) {
+                    Ok(empty) => parent.children.push(Node::Element(empty)),
+                    Err(e) => {
+                        invalid.get_or_insert(e);
+                    }
+                },
+                Event::Text(text) => match text.unescape() {
+                    Ok(text) => parent.children.push(Node::Text(text.into_owned())),
+                    Err(e) => {
+                        invalid.get_or_insert(e.into());
+                    }
+                },
+                Event::CData(data) => parent
+                    .children
+                    .push(Node::Text(String::from_utf8_lossy(&data).into_owned())),
+                Event::End(_) => match open.pop() {
+                    Some(closed) => open
+                        .last_mut()
+                        .unwrap_or(&mut element)
+                        .children
+                        .push(Node::Element(closed)),
+                    None => {
+                        return match invalid {
+                            Some(e) => Err(self.invalid(&element.name, e)),
+                            None => Ok(element),
+                        }
+                    }
+                },
+                Event::Eof => anyhow::bail!("Unexpected end of document inside <{}>", element.name),
+                _ => {}
+            }
+        }
+    }
+
+    fn invalid(&self, name: &str, error: anyhow::Error) -> anyhow::Error {
+        InvalidElement {
+            name: name.to_string(),
+            position: self.reader.buffer_position(),
+            reason: error.to_string(),
+        }
+        .into()
+    }
+}
+
+/// Stands in for an element whose attributes couldn't be decoded, keeping
+/// the nesting intact
+fn placeholder(start: &BytesStart) -> Element {
+    Element {
+        name: local_name(start),
+        ..Element::default()
+    }
+}
+
+fn local_name(start: &BytesStart) -> String {
+    String::from_utf8_lossy(start.local_name().as_ref()).into_owned()
+}
+
+fn start_element(start: &BytesStart) -> Result<Element> {
+    let mut attributes = Vec::new();
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        attributes.push((
+            String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+            attribute.unescape_value()?.into_owned(),
+        ));
+    }
+    Ok(Element {
+        name: local_name(start),
+        attributes,
+        children: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Headwords read from `document`, with `None` for invalid entries
+    fn orths(document: &str) -> Vec<Option<String>> {
+        let mut reader = ElementReader::new(document.as_bytes(), "entry");
+        let mut orths = Vec::new();
+        loop {
+            match reader.next_element() {
+                Ok(Some(entry)) => orths.push(Some(
+                    entry.find("orth").map(Element::text).unwrap_or_default(),
+                )),
+                Ok(None) => return orths,
+                Err(e) => {
+                    assert!(e.is::<InvalidElement>(), "{:#}", e);
+                    orths.push(None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn streams_elements_with_their_content() {
+        let document = r#"<TEI xml:lang="en"><text><body>
+            <entry id="a"><form><orth> cat </orth><pron>kæt</pron></form><note>small <hi>feline</hi></note></entry>
+            <entry id="b"/>
+        </body></text></TEI>"#;
+        let mut reader = ElementReader::new(document.as_bytes(), "entry");
+
+        let first = reader.next_element().unwrap().unwrap();
+        assert_eq!(reader.root().unwrap().name, "TEI");
+        assert_eq!(reader.root().unwrap().attribute("xml:lang"), Some("en"));
+        assert_eq!(first.attribute("id"), Some("a"));
+        assert_eq!(first.find("orth").unwrap().text(), "cat");
+        assert_eq!(first.child("note").unwrap().text(), "small feline");
+        assert_eq!(first.text_excluding(&["pron", "hi"]), "cat small");
+
+        let second = reader.next_element().unwrap().unwrap();
+        assert_eq!(second.attribute("id"), Some("b"));
+        assert!(second.children.is_empty());
+        assert!(reader.next_element().unwrap().is_none());
+    }
+
+    #[test]
+    fn carries_on_after_invalid_elements() {
+        let document = r#"<TEI>
+            <entry><orth>one</orth></entry>
+            <entry><orth>&undeclared;</orth><sense><entry>nested</entry></sense></entry>
+            <entry type="&undeclared;"><orth>three</orth></entry>
+            <entry type="&undeclared;"/>
+            <entry><orth>five</orth></entry>
+        </TEI>"#;
+        assert_eq!(
+            orths(document),
+            [
+                Some("one".to_string()),
+                None,
+                None,
+                None,
+                Some("five".to_string())
+            ]
+        );
+
+        // Broken markup can't be recovered from
+        let mut reader =
+            ElementReader::new("<TEI><entry><orth>one</entry></TEI>".as_bytes(), "entry");
+        assert!(reader.next_element().is_err());
+    }
+}
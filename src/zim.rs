@@ -0,0 +1,905 @@
+use crate::api::{Definition, DictionaryEntry, License, Meaning};
+use crate::markup;
+use crate::normalize;
+use crate::source::{language_tag, DictionarySource, SourceMetadata};
+use anyhow::{bail, ensure, Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// "ZIM\x04" read as a little-endian number
+const MAGIC: u32 = 0x044D_495A;
+const HEADER_LEN: usize = 80;
+
+/// Directory entry MIME types that stand for something other than content
+const REDIRECT: u16 = 0xffff;
+const LINK_TARGET: u16 = 0xfffe;
+const DELETED: u16 = 0xfffd;
+
+/// Cluster compression, in the low bits of its first byte
+const UNCOMPRESSED: u8 = 1;
+const XZ: u8 = 4;
+const ZSTD: u8 = 5;
+/// Set in a cluster's first byte when its blob offsets are 64-bit
+const EXTENDED_CLUSTER: u8 = 0x10;
+
+/// Redirects followed before giving up, in case of a cycle
+const MAX_REDIRECTS: usize = 5;
+
+/// Decompressed clusters kept around; neighbouring articles often share one
+const CACHED_CLUSTERS: usize = 4;
+
+/// Paragraphs shorter than this are captions or headword lines, not a summary
+const MIN_SUMMARY_CHARS: usize = 40;
+const MAX_SUMMARY_CHARS: usize = 600;
+
+/// Senses taken from a dictionary-style page that starts with a list of them
+const MAX_SENSES: usize = 5;
+
+/// A Kiwix ZIM archive, such as an offline Wikipedia or Wiktionary.
+///
+/// Nothing is loaded up front: lookups binary search the title-ordered
+/// pointer list on disk, so archives with millions of articles open
+/// instantly. A match is shown as the start of its article, the first
+/// paragraph or, for Wiktionary-style pages, the first few senses.
+pub struct ZimSource {
+    metadata: SourceMetadata,
+    license: Option<String>,
+    header: Header,
+    /// Articles are in `A` before ZIM 6.1 and in `C` from then on
+    namespace: u8,
+    mime_types: Vec<String>,
+    file: Mutex<File>,
+    /// Most recently used first
+    clusters: Mutex<Vec<(u32, Arc<Cluster>)>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    entry_count: u32,
+    cluster_count: u32,
+    url_ptr_pos: u64,
+    title_ptr_pos: u64,
+    cluster_ptr_pos: u64,
+    checksum_pos: u64,
+}
+
+/// A directory entry: an article, a redirect or other content
+#[derive(Debug)]
+struct DirEntry {
+    mime_type: u16,
+    namespace: u8,
+    target: Target,
+    url: String,
+    title: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Blob {
+        cluster: u32,
+        blob: u32,
+    },
+    /// Index in the URL pointer list
+    Redirect(u32),
+    None,
+}
+
+impl DirEntry {
+    /// Entries without a title of their own use their URL
+    fn title(&self) -> &str {
+        if self.title.is_empty() {
+            &self.url
+        } else {
+            &self.title
+        }
+    }
+}
+
+/// A decompressed cluster: blobs stored back to back
+struct Cluster {
+    data: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl ZimSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut raw = [0; HEADER_LEN];
+        file.read_exact(&mut raw)
+            .context("File too short for a ZIM header")?;
+        ensure!(read_u32(&raw, 0) == MAGIC, "Not a ZIM file");
+        let major = u16::from_le_bytes([raw[4], raw[5]]);
+        let minor = u16::from_le_bytes([raw[6], raw[7]]);
+        ensure!(
+            major == 5 || major == 6,
+            "Unsupported ZIM version {}.{}",
+            major,
+            minor
+        );
+
+        let header = Header {
+            entry_count: read_u32(&raw, 24),
+            cluster_count: read_u32(&raw, 28),
+            url_ptr_pos: read_u64(&raw, 32),
+            title_ptr_pos: read_u64(&raw, 40),
+            cluster_ptr_pos: read_u64(&raw, 48),
+            checksum_pos: read_u64(&raw, 72),
+        };
+        let mime_list_pos = read_u64(&raw, 56);
+
+        let mut source = Self {
+            metadata: SourceMetadata::default(),
+            license: None,
+            header,
+            namespace: if major == 6 && minor >= 1 { b'C' } else { b'A' },
+            mime_types: Vec::new(),
+            file: Mutex::new(file),
+            clusters: Mutex::new(Vec::new()),
+        };
+        source.mime_types = source.read_mime_types(mime_list_pos)?;
+
+        let id = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = source
+            .metadata_value("Title")
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| id.clone());
+        let mut metadata = SourceMetadata::new(id, name);
+        metadata.description = source.metadata_value("Description");
+        // ISO 639-3, possibly several separated by commas
+        metadata.language = source.metadata_value("Language").and_then(|languages| {
+            let first = languages.split(',').next()?.trim();
+            (!first.is_empty()).then(|| language_tag(first))
+        });
+        source.license = source.metadata_value("License");
+        source.metadata = metadata;
+        Ok(source)
+    }
+
+    /// The MIME type list: NUL-terminated strings ending with an empty one
+    fn read_mime_types(&self, position: u64) -> Result<Vec<String>> {
+        let mut length = 1024;
+        loop {
+            let raw = self.read_up_to(position, length)?;
+            let mut types = Vec::new();
+            let mut rest = raw.as_slice();
+            while let Some(end) = rest.iter().position(|&byte| byte == 0) {
+                if end == 0 {
+                    return Ok(types);
+                }
+                types.push(String::from_utf8_lossy(&rest[..end]).into_owned());
+                rest = &rest[end + 1..];
+            }
+            ensure!(raw.len() == length, "Unterminated MIME type list");
+            length *= 4;
+        }
+    }
+
+    /// Value of a metadata entry such as `M/Title`
+    fn metadata_value(&self, name: &str) -> Option<String> {
+        let result = (|| {
+            let index = self.url_lower_bound(b'M', name)?;
+            if index >= self.header.entry_count {
+                return Ok(None);
+            }
+            let entry = self.entry(index)?;
+            if entry.namespace != b'M' || entry.url != name {
+                return Ok(None);
+            }
+            let value = String::from_utf8_lossy(&self.content(&entry)?)
+                .trim()
+                .to_string();
+            Ok::<_, anyhow::Error>(Some(value))
+        })();
+        result.unwrap_or_else(|e| {
+            eprintln!("Failed to read ZIM metadata {}: {:#}", name, e);
+            None
+        })
+    }
+
+    /// Up to `length` bytes from `position`. Lengths come from the file, so
+    /// the buffer grows with what is actually read rather than up front.
+    fn read_up_to(&self, position: u64, length: usize) -> Result<Vec<u8>> {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(position))?;
+        let mut buf = Vec::new();
+        file.by_ref().take(length as u64).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_exact_at(&self, position: u64, length: usize) -> Result<Vec<u8>> {
+        let buf = self.read_up_to(position, length)?;
+        ensure!(buf.len() == length, "ZIM file is truncated");
+        Ok(buf)
+    }
+
+    /// Directory entry at `index` in the URL pointer list
+    fn entry(&self, index: u32) -> Result<DirEntry> {
+        ensure!(
+            index < self.header.entry_count,
+            "Entry {} out of range",
+            index
+        );
+        let pointer = self.read_exact_at(self.header.url_ptr_pos + 8 * index as u64, 8)?;
+        self.entry_at(read_u64(&pointer, 0))
+    }
+
+    /// Index in the URL pointer list of the entry at `rank` in title order
+    fn title_rank_to_index(&self, rank: u32) -> Result<u32> {
+        let pointer = self.read_exact_at(self.header.title_ptr_pos + 4 * rank as u64, 4)?;
+        Ok(read_u32(&pointer, 0))
+    }
+
+    fn entry_at(&self, position: u64) -> Result<DirEntry> {
+        // URL and title are NUL-terminated; read more if they don't fit
+        let mut length = 512;
+        loop {
+            let raw = self.read_up_to(position, length)?;
+            if let Some(entry) = parse_entry(&raw)? {
+                return Ok(entry);
+            }
+            ensure!(raw.len() == length, "Truncated directory entry");
+            length *= 4;
+        }
+    }
+
+    /// First index in the URL pointer list at or after `(namespace, url)`
+    fn url_lower_bound(&self, namespace: u8, url: &str) -> Result<u32> {
+        let (mut low, mut high) = (0, self.header.entry_count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let entry = self.entry(middle)?;
+            if (entry.namespace, entry.url.as_bytes()) < (namespace, url.as_bytes()) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    /// First rank in title order at or after `title` in the article namespace
+    fn title_lower_bound(&self, title: &str) -> Result<u32> {
+        let (mut low, mut high) = (0, self.header.entry_count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let entry = self.entry(self.title_rank_to_index(middle)?)?;
+            if (entry.namespace, entry.title().as_bytes()) < (self.namespace, title.as_bytes()) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Up to `limit` articles and redirects whose title starts with `prefix`,
+    /// as URL pointer indexes, in title order
+    fn titles_starting_with(&self, prefix: &str, limit: usize) -> Result<Vec<(u32, DirEntry)>> {
+        let mut found = Vec::new();
+        let mut rank = self.title_lower_bound(prefix)?;
+        while found.len() < limit && rank < self.header.entry_count {
+            let index = self.title_rank_to_index(rank)?;
+            let entry = self.entry(index)?;
+            if entry.namespace != self.namespace || !entry.title().starts_with(prefix) {
+                break;
+            }
+            if self.is_article(&entry) {
+                found.push((index, entry));
+            }
+            rank += 1;
+        }
+        Ok(found)
+    }
+
+    /// HTML pages and redirects, as opposed to images, scripts and the like
+    fn is_article(&self, entry: &DirEntry) -> bool {
+        match entry.mime_type {
+            REDIRECT => true,
+            LINK_TARGET | DELETED => false,
+            mime_type => self
+                .mime_types
+                .get(mime_type as usize)
+                .is_some_and(|mime_type| mime_type.starts_with("text/html")),
+        }
+    }
+
+    /// Follow redirects from the entry at `index`, returning the final index
+    /// and entry
+    fn resolve(&self, mut index: u32, mut entry: DirEntry) -> Result<(u32, DirEntry)> {
+        for _ in 0..=MAX_REDIRECTS {
+            match entry.target {
+                Target::Redirect(target) => {
+                    index = target;
+                    entry = self.entry(target)?;
+                }
+                _ => return Ok((index, entry)),
+            }
+        }
+        bail!("Too many redirects")
+    }
+
+    fn content(&self, entry: &DirEntry) -> Result<Vec<u8>> {
+        let Target::Blob { cluster, blob } = entry.target else {
+            bail!("Entry has no content");
+        };
+        let cluster = self.cluster(cluster)?;
+        let blob = blob as usize;
+        ensure!(
+            blob + 1 < cluster.offsets.len(),
+            "Blob {} out of range",
+            blob
+        );
+        let (start, end) = (cluster.offsets[blob], cluster.offsets[blob + 1]);
+        ensure!(
+            start <= end && end <= cluster.data.len(),
+            "Corrupt cluster offsets"
+        );
+        Ok(cluster.data[start..end].to_vec())
+    }
+
+    fn cluster(&self, number: u32) -> Result<Arc<Cluster>> {
+        let mut cache = self.clusters.lock().unwrap();
+        if let Some(position) = cache.iter().position(|(cached, _)| *cached == number) {
+            let hit = cache.remove(position);
+            let cluster = Arc::clone(&hit.1);
+            cache.insert(0, hit);
+            return Ok(cluster);
+        }
+        drop(cache);
+
+        let cluster = Arc::new(self.read_cluster(number)?);
+        let mut cache = self.clusters.lock().unwrap();
+        cache.insert(0, (number, Arc::clone(&cluster)));
+        cache.truncate(CACHED_CLUSTERS);
+        Ok(cluster)
+    }
+
+    fn read_cluster(&self, number: u32) -> Result<Cluster> {
+        let header = &self.header;
+        ensure!(
+            number < header.cluster_count,
+            "Cluster {} out of range",
+            number
+        );
+        // The last cluster ends where the checksum starts
+        let last = number + 1 == header.cluster_count;
+        let pointers = self.read_exact_at(
+            header.cluster_ptr_pos + 8 * number as u64,
+            if last { 8 } else { 16 },
+        )?;
+        let start = read_u64(&pointers, 0);
+        let end = if last {
+            header.checksum_pos
+        } else {
+            read_u64(&pointers, 8)
+        };
+        ensure!(start < end, "Corrupt cluster pointer");
+        let raw = self.read_exact_at(start, (end - start) as usize)?;
+
+        let info = raw[0];
+        let body = &raw[1..];
+        let data = match info & 0x0f {
+            0 | UNCOMPRESSED => body.to_vec(),
+            XZ => {
+                let mut data = Vec::new();
+                xz2::read::XzDecoder::new(body)
+                    .read_to_end(&mut data)
+                    .context("Corrupt xz cluster")?;
+                data
+            }
+            ZSTD => {
+                let mut data = Vec::new();
+                zstd::stream::read::Decoder::new(body)?
+                    .single_frame()
+                    .read_to_end(&mut data)
+                    .context("Corrupt zstd cluster")?;
+                data
+            }
+            other => bail!("Unsupported cluster compression {}", other),
+        };
+
+        // Offsets of each blob and of the end of the last, relative to the data
+        let width = if info & EXTENDED_CLUSTER != 0 { 8 } else { 4 };
+        let offset = |i: usize| -> Result<usize> {
+            let bytes = data
+                .get(i * width..(i + 1) * width)
+                .context("Truncated cluster")?;
+            Ok(if width == 8 {
+                read_u64(bytes, 0) as usize
+            } else {
+                read_u32(bytes, 0) as usize
+            })
+        };
+        let count = offset(0)? / width;
+        ensure!(count >= 1, "Cluster without blobs");
+        let offsets = (0..count).map(offset).collect::<Result<Vec<_>>>()?;
+        Ok(Cluster { data, offsets })
+    }
+
+    /// Articles for the given entries, after following redirects and dropping
+    /// ones that several titles lead to
+    fn entries(&self, found: Vec<(u32, DirEntry)>) -> Vec<DictionaryEntry> {
+        let mut seen = Vec::new();
+        found
+            .into_iter()
+            .filter_map(|(index, entry)| {
+                let title = entry.title().to_string();
+                match self.resolve(index, entry) {
+                    Ok((index, entry)) if !seen.contains(&index) => {
+                        seen.push(index);
+                        Some(entry)
+                    }
+                    Ok(_) => None,
+                    Err(e) => {
+                        eprintln!("Skipping ZIM article {}: {:#}", title, e);
+                        None
+                    }
+                }
+            })
+            .filter_map(|entry| match self.content(&entry) {
+                Ok(html) => self.to_entry(&entry, &String::from_utf8_lossy(&html)),
+                Err(e) => {
+                    eprintln!("Skipping ZIM article {}: {:#}", entry.title(), e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn to_entry(&self, entry: &DirEntry, html: &str) -> Option<DictionaryEntry> {
+        let definitions: Vec<Definition> = summary(html)
+            .into_iter()
+            .map(|text| Definition {
+                definition: text,
+                ..Definition::default()
+            })
+            .collect();
+        if definitions.is_empty() {
+            return None;
+        }
+        Some(DictionaryEntry {
+            word: entry.title().to_string(),
+            meanings: vec![Meaning {
+                definitions,
+                ..Meaning::default()
+            }],
+            license: self.license.as_ref().map(|name| License {
+                name: name.clone(),
+                url: String::new(),
+            }),
+            language: self.metadata.language.clone(),
+            ..DictionaryEntry::default()
+        })
+    }
+
+    /// Up to `limit` title entries starting with `folded`, in title order
+    fn titles_with_prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<(u32, DirEntry)> {
+        let mut found: Vec<(u32, DirEntry)> = Vec::new();
+        for prefix in title_variants(folded) {
+            match self.titles_starting_with(&prefix, limit) {
+                Ok(matches) => {
+                    for (index, entry) in matches {
+                        if !found.iter().any(|(seen, _)| *seen == index) {
+                            found.push((index, entry));
+                        }
+                    }
+                }
+                Err(e) => eprintln!("ZIM lookup failed: {:#}", e),
+            }
+        }
+        found.retain(|(_, entry)| {
+            let title = normalize::fold(entry.title());
+            if strict {
+                title.starts_with(folded)
+            } else {
+                normalize::strip_diacritics(&title)
+                    .starts_with(&normalize::strip_diacritics(folded))
+            }
+        });
+        found.sort_by(|(_, a), (_, b)| a.title().cmp(b.title()));
+        found.truncate(limit);
+        found
+    }
+}
+
+impl DictionarySource for ZimSource {
+    fn metadata(&self) -> &SourceMetadata {
+        &self.metadata
+    }
+
+    fn lookup(&self, folded: &str, strict: bool) -> Vec<DictionaryEntry> {
+        let mut found = Vec::new();
+        for title in title_variants(folded) {
+            match self.titles_starting_with(&title, 1) {
+                Ok(matches) => found.extend(
+                    matches
+                        .into_iter()
+                        .filter(|(_, entry)| entry.title() == title),
+                ),
+                Err(e) => eprintln!("ZIM lookup failed: {:#}", e),
+            }
+        }
+        found.retain(|(_, entry)| headword_matches(entry.title(), folded, strict));
+        self.entries(found)
+    }
+
+    fn prefix(&self, folded: &str, strict: bool, limit: usize) -> Vec<DictionaryEntry> {
+        self.entries(self.titles_with_prefix(folded, strict, limit))
+    }
+
+    fn prefix_headwords(&self, folded: &str, strict: bool, limit: usize) -> Vec<String> {
+        self.titles_with_prefix(folded, strict, limit)
+            .into_iter()
+            .map(|(_, entry)| entry.title().to_string())
+            .collect()
+    }
+}
+
+fn headword_matches(title: &str, folded: &str, strict: bool) -> bool {
+    let title = normalize::fold(title);
+    if strict {
+        title == folded
+    } else {
+        normalize::strip_diacritics(&title) == normalize::strip_diacritics(folded)
+    }
+}
+
+/// Titles to try for a case-folded query. Titles are case sensitive and
+/// sorted by byte, so the usual capitalizations are searched one by one:
+/// "new york", "New york", "New York" and "NEW YORK".
+fn title_variants(folded: &str) -> Vec<String> {
+    let capitalize = |word: &str| {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+            None => String::new(),
+        }
+    };
+    let candidates = [
+        folded.to_string(),
+        capitalize(folded),
+        folded
+            .split(' ')
+            .map(capitalize)
+            .collect::<Vec<_>>()
+            .join(" "),
+        folded.to_uppercase(),
+    ];
+
+    let mut variants: Vec<String> = Vec::new();
+    for candidate in candidates {
+        if !candidate.is_empty() && !variants.contains(&candidate) {
+            variants.push(candidate);
+        }
+    }
+    variants
+}
+
+/// Parse a directory entry, or return `None` when `raw` ends before its title
+fn parse_entry(raw: &[u8]) -> Result<Option<DirEntry>> {
+    ensure!(raw.len() >= 12, "Truncated directory entry");
+    let mime_type = u16::from_le_bytes([raw[0], raw[1]]);
+    let namespace = raw[3];
+    let (target, strings_start) = match mime_type {
+        REDIRECT => (Target::Redirect(read_u32(raw, 8)), 12),
+        LINK_TARGET | DELETED => (Target::None, 8),
+        _ => {
+            ensure!(raw.len() >= 16, "Truncated directory entry");
+            let target = Target::Blob {
+                cluster: read_u32(raw, 8),
+                blob: read_u32(raw, 12),
+            };
+            (target, 16)
+        }
+    };
+
+    let rest = &raw[strings_start..];
+    let Some(url_end) = rest.iter().position(|&byte| byte == 0) else {
+        return Ok(None);
+    };
+    let Some(title_len) = rest[url_end + 1..].iter().position(|&byte| byte == 0) else {
+        return Ok(None);
+    };
+    Ok(Some(DirEntry {
+        mime_type,
+        namespace,
+        target,
+        url: String::from_utf8_lossy(&rest[..url_end]).into_owned(),
+        title: String::from_utf8_lossy(&rest[url_end + 1..url_end + 1 + title_len]).into_owned(),
+    }))
+}
+
+/// The start of an article: its first real paragraph, shortened, or for a
+/// dictionary-style page the first few senses. Senses are used when the list
+/// comes before any paragraph or right after one, which is then taken for a
+/// headword line such as "run (third-person singular runs)".
+fn summary(html: &str) -> Vec<String> {
+    let body = html.find("<body").map_or(html, |start| &html[start..]);
+    let first_list = find_tag(body, "ol", 0);
+
+    let mut paragraph = None;
+    let mut from = 0;
+    while let Some(start) = find_tag(body, "p", from) {
+        if first_list.is_some_and(|list| list < start) {
+            break;
+        }
+        let Some(content_start) = body[start..].find('>').map(|end| start + end + 1) else {
+            break;
+        };
+        let content_end = body[content_start..]
+            .find("</p>")
+            .map_or(body.len(), |end| content_start + end);
+        let text = remove_citations(&markup::html_to_text(&body[content_start..content_end]))
+            .replace('\n', " ");
+        if text.chars().count() >= MIN_SUMMARY_CHARS {
+            let after = body[content_end..].trim_start_matches("</p>").trim_start();
+            if !after.starts_with("<ol") {
+                paragraph = Some(text);
+            }
+            break;
+        }
+        from = content_end;
+    }
+
+    if let Some(text) = paragraph {
+        return vec![shorten(&text)];
+    }
+    first_list
+        .map(|list| list_items(&body[list..]))
+        .unwrap_or_default()
+}
+
+/// Position of the next `<name>` or `<name ...>` start tag at or after `from`
+fn find_tag(html: &str, name: &str, from: usize) -> Option<usize> {
+    let open = format!("<{}", name);
+    let mut position = from;
+    while let Some(found) = html[position..].find(&open) {
+        let start = position + found;
+        let after = html[start + open.len()..].chars().next();
+        if matches!(after, Some('>' | ' ' | '\t' | '\n' | '\r' | '/')) {
+            return Some(start);
+        }
+        position = start + open.len();
+    }
+    None
+}
+
+/// Text of the top-level items of the list starting at the beginning of
+/// `html`, leaving out nested lists such as quotations
+fn list_items(html: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current: Option<String> = None;
+    let mut depth = 0;
+    let mut rest = html;
+
+    while let Some(open) = rest.find('<') {
+        if depth == 1 {
+            if let Some(item) = &mut current {
+                item.push_str(&rest[..open]);
+            }
+        }
+        rest = &rest[open..];
+        let Some(close) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..close + 1];
+        rest = &rest[close + 1..];
+
+        let closing = tag.starts_with("</");
+        let name = tag[1..]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "ol" | "ul" | "dl" if closing => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            "ol" | "ul" | "dl" => depth += 1,
+            "li" if depth == 1 => {
+                items.extend(current.take());
+                if !closing {
+                    current = Some(String::new());
+                }
+            }
+            _ if depth == 1 => {
+                if let Some(item) = &mut current {
+                    item.push_str(tag);
+                }
+            }
+            _ => {}
+        }
+    }
+    items.extend(current);
+
+    items
+        .iter()
+        .map(|item| remove_citations(&markup::html_to_text(item)).replace('\n', " "))
+        .filter(|text| !text.is_empty())
+        .take(MAX_SENSES)
+        .collect()
+}
+
+/// Drop footnote markers such as "[1]"
+fn remove_citations(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        cleaned.push_str(&rest[..open]);
+        rest = &rest[open..];
+        match rest[1..].find(']') {
+            Some(len) if len > 0 && rest[1..=len].chars().all(|c| c.is_ascii_digit()) => {
+                rest = &rest[len + 2..];
+            }
+            _ => {
+                cleaned.push('[');
+                rest = &rest[1..];
+            }
+        }
+    }
+    cleaned.push_str(rest);
+    cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Cut a long paragraph after the last sentence that fits
+fn shorten(text: &str) -> String {
+    if text.chars().count() <= MAX_SUMMARY_CHARS {
+        return text.to_string();
+    }
+    let cut = text
+        .char_indices()
+        .nth(MAX_SUMMARY_CHARS)
+        .map_or(text.len(), |(index, _)| index);
+    let head = &text[..cut];
+    match head.rfind(". ") {
+        Some(end) => head[..=end].to_string(),
+        None => format!("{}…", head[..head.rfind(' ').unwrap_or(cut)].trim_end()),
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const ARTICLE: &str =
+        "<html><body><p>The cat is a small domesticated carnivorous mammal.</p></body></html>";
+
+    /// A ZIM 5 archive with the article `A/Cat` and the metadata `M/Language`
+    /// and `M/Title`, all in one uncompressed cluster
+    fn archive() -> Vec<u8> {
+        let blobs: [&[u8]; 3] = [ARTICLE.as_bytes(), b"eng,fra", b"Test Wiki"];
+        let entries: [(u8, &str, u32); 3] =
+            [(b'A', "Cat", 0), (b'M', "Language", 1), (b'M', "Title", 2)];
+
+        let mut file = vec![0; HEADER_LEN];
+        let mime_list_pos = file.len() as u64;
+        file.extend_from_slice(b"text/html\0\0");
+
+        let mut entry_positions = Vec::new();
+        for (namespace, url, blob) in entries {
+            entry_positions.push(file.len() as u64);
+            file.extend_from_slice(&[0, 0, 0, namespace]);
+            file.extend_from_slice(&0u32.to_le_bytes());
+            file.extend_from_slice(&0u32.to_le_bytes());
+            file.extend_from_slice(&blob.to_le_bytes());
+            file.extend_from_slice(url.as_bytes());
+            file.extend_from_slice(b"\0\0");
+        }
+
+        let url_ptr_pos = file.len() as u64;
+        for position in &entry_positions {
+            file.extend_from_slice(&position.to_le_bytes());
+        }
+        // Already in title order
+        let title_ptr_pos = file.len() as u64;
+        for index in 0..entries.len() as u32 {
+            file.extend_from_slice(&index.to_le_bytes());
+        }
+
+        let cluster_ptr_pos = file.len() as u64;
+        let cluster_pos = cluster_ptr_pos + 8;
+        file.extend_from_slice(&cluster_pos.to_le_bytes());
+        file.push(UNCOMPRESSED);
+        let mut offset = 4 * (blobs.len() as u32 + 1);
+        file.extend_from_slice(&offset.to_le_bytes());
+        for blob in blobs {
+            offset += blob.len() as u32;
+            file.extend_from_slice(&offset.to_le_bytes());
+        }
+        for blob in blobs {
+            file.extend_from_slice(blob);
+        }
+        let checksum_pos = file.len() as u64;
+        file.extend_from_slice(&[0; 16]);
+
+        file[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        file[4..6].copy_from_slice(&5u16.to_le_bytes());
+        file[24..28].copy_from_slice(&(entries.len() as u32).to_le_bytes());
+        file[28..32].copy_from_slice(&1u32.to_le_bytes());
+        file[32..40].copy_from_slice(&url_ptr_pos.to_le_bytes());
+        file[40..48].copy_from_slice(&title_ptr_pos.to_le_bytes());
+        file[48..56].copy_from_slice(&cluster_ptr_pos.to_le_bytes());
+        file[56..64].copy_from_slice(&mime_list_pos.to_le_bytes());
+        file[72..80].copy_from_slice(&checksum_pos.to_le_bytes());
+        file
+    }
+
+    fn write(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("aynary-{}-{}.zim", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn looks_up_articles_and_metadata() {
+        let path = write("valid", &archive());
+        let source = ZimSource::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(source.metadata().name, "Test Wiki");
+        // The first language, as a two-letter code like other sources use
+        assert_eq!(source.metadata().language.as_deref(), Some("en"));
+        let entries = source.lookup("cat", false);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].meanings[0].definitions[0].definition,
+            "The cat is a small domesticated carnivorous mammal."
+        );
+        assert_eq!(source.prefix_headwords("ca", false, 10), ["Cat"]);
+    }
+
+    #[test]
+    fn rejects_short_and_foreign_files() {
+        let path = write("short", &archive()[..40]);
+        assert!(ZimSource::open(&path).is_err());
+        fs::write(&path, [0; HEADER_LEN]).unwrap();
+        assert!(ZimSource::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_cluster_pointers_are_an_error() {
+        let mut bytes = archive();
+        // Claim a second cluster whose pointer would run past the end
+        bytes[28..32].copy_from_slice(&2u32.to_le_bytes());
+        let end = bytes.len() as u64;
+        bytes[48..56].copy_from_slice(&(end - 4).to_le_bytes());
+        let path = write("clusters", &bytes);
+        let source = ZimSource::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(source.read_cluster(0).is_err());
+        assert!(source.read_cluster(1).is_err());
+        assert!(source.lookup("cat", false).is_empty());
+    }
+
+    #[test]
+    fn reads_no_further_than_the_file() {
+        let path = write("long", &archive());
+        let source = ZimSource::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let all = source.read_up_to(0, usize::MAX).unwrap();
+        assert_eq!(all.len(), archive().len());
+        assert!(source.read_exact_at(0, usize::MAX).is_err());
+    }
+}